@@ -0,0 +1,117 @@
+use bevy::color::palettes::css::GOLD;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::camera_controls::ScreenSized;
+use crate::compare::ComparisonCamera;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// A shape drawn over the simulation in annotation mode (E key).
+enum Annotation {
+    Arrow { start: Vec2, end: Vec2 },
+    Circle { center: Vec2, radius: f32 },
+}
+
+/// Arrows, circles and numbered labels drawn over the simulation, for presentations.
+/// In annotation mode, left-drag draws an arrow, right-drag draws a circle, middle-click places a numbered label, and
+/// Backspace clears everything. The mouse doesn't interact with the fluid while annotating.
+#[derive(Resource, Default)]
+pub struct Annotations {
+    shapes: Vec<Annotation>,
+    /// Where the current drag started, if a button is held down.
+    drag_start: Option<Vec2>,
+    labels: usize,
+}
+
+#[derive(Component)]
+pub struct AnnotationLabel;
+
+#[allow(clippy::too_many_arguments)]
+pub fn annotate(
+    mut commands: Commands,
+    mut annotations: ResMut<Annotations>,
+    mut gizmos: Gizmos,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    labels: Query<Entity, With<AnnotationLabel>>,
+) {
+    if kb.just_pressed(KeyCode::Backspace) {
+        annotations.shapes.clear();
+        annotations.labels = 0;
+        for label in &labels {
+            commands.entity(label).despawn();
+        }
+    }
+
+    let cursor_point = windows
+        .single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .and_then(|cursor_position| {
+            let (camera, camera_transform) = cameras_query.iter().next()?;
+            camera.viewport_to_world_2d(camera_transform, cursor_position).ok()
+        });
+
+    if sim.debug.annotating
+        && let Some(point) = cursor_point
+    {
+        if buttons.just_pressed(MouseButton::Left) || buttons.just_pressed(MouseButton::Right) {
+            annotations.drag_start = Some(point);
+        }
+        if buttons.just_pressed(MouseButton::Middle) {
+            annotations.labels += 1;
+            commands.spawn((
+                Text2d::new(annotations.labels.to_string()),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(GOLD.into()),
+                Transform::from_translation(point.extend(2.0)),
+                AnnotationLabel,
+                ScreenSized,
+            ));
+        }
+
+        // The shape being dragged out, if any.
+        let dragged = annotations.drag_start.and_then(|start| {
+            if buttons.pressed(MouseButton::Left) || buttons.just_released(MouseButton::Left) {
+                Some(Annotation::Arrow { start, end: point })
+            } else if buttons.pressed(MouseButton::Right) || buttons.just_released(MouseButton::Right) {
+                Some(Annotation::Circle {
+                    center: start,
+                    radius: start.distance(point),
+                })
+            } else {
+                None
+            }
+        });
+        if let Some(shape) = dragged {
+            if buttons.just_released(MouseButton::Left) || buttons.just_released(MouseButton::Right) {
+                annotations.shapes.push(shape);
+                annotations.drag_start = None;
+            } else {
+                draw(&mut gizmos, &shape);
+            }
+        }
+    }
+
+    for shape in &annotations.shapes {
+        draw(&mut gizmos, shape);
+    }
+}
+
+fn draw(gizmos: &mut Gizmos, shape: &Annotation) {
+    match *shape {
+        Annotation::Arrow { start, end } => {
+            gizmos.arrow_2d(start, end, GOLD).with_tip_length(12.0);
+        }
+        Annotation::Circle { center, radius } => {
+            gizmos.circle_2d(center, radius, GOLD);
+        }
+    }
+}
@@ -1,12 +1,71 @@
+use bevy::log::Level;
+use bevy::math::Vec2;
 use clap::Parser;
 
-#[derive(Parser)]
+use std::str::FromStr;
+
+use crate::color_field::ColorField;
+use crate::colormap::Colormap;
+use crate::container::ContainerShape;
+use crate::field_export::ExportField;
+use crate::gpu::Backend;
+use crate::gravity::parse_point;
+use crate::neighbor_search::NeighborSearchKind;
+use crate::placement::InitialPlacement;
+use crate::scenario::Scenario;
+use crate::share_code::ParamsCode;
+use crate::solver::SolverKind;
+use crate::units::UnitSystem;
+use crate::walls::parse_restitution;
+use crate::wind::FlowField;
+use crate::window::{Vsync, parse_size};
+
+// The defaults of the parameters measured in units. They're in particle sizes (per second, ...) whatever --units is,
+// so that they're converted to meters for physical units rather than taken as meters.
+pub const DEFAULT_GRAVITY: f32 = 30.0;
+pub const DEFAULT_PRESSURE_MULTIPLIER: u32 = 250000;
+pub const DEFAULT_INTERACTION_INPUT_RADIUS: f32 = 40.0;
+pub const DEFAULT_EXPLOSION_RADIUS: f32 = 30.0;
+pub const DEFAULT_EXPLOSION_STRENGTH: f32 = 150.0;
+pub const DEFAULT_INTERACTION_SLOW_THRESHOLD: f32 = 20.0;
+pub const DEFAULT_WIND_SPEED: f32 = 20.0;
+pub const DEFAULT_INFLOW_SPEED: f32 = 20.0;
+pub const DEFAULT_OBSTACLE_RADIUS: f32 = 15.0;
+pub const DEFAULT_PADDLE_AMPLITUDE: f32 = 4.0;
+pub const DEFAULT_SOAK_MAX_ENERGY: f32 = 100000.0;
+
+#[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about = None)]
-/// A test client for Proactive Voice Moderation
+/// A 2D SPH fluid simulation
+///
+/// The options of the simulation, which the binary parses from its command line. Apps that embed the plugin build
+/// them in code instead, from Args::default(), and pass them in SimulationSettings.
 pub struct Args {
     /// Initial window size, as width,height
-    #[arg(long, default_value = "800,900")]
-    pub win: String,
+    #[arg(long, default_value = "800,900", value_parser = parse_size)]
+    pub win: (u32, u32),
+
+    /// Fixed size of the simulation domain, as width,height in world pixels. The tank no longer follows the window:
+    /// the view is scaled to fit it instead, with bars at the sides, so that resizing the window leaves the fluid
+    /// alone.
+    #[arg(long, value_parser = parse_size)]
+    pub domain: Option<(u32, u32)>,
+
+    /// Open the window fullscreen, at the monitor's current video mode.
+    #[arg(long, conflicts_with = "borderless")]
+    pub fullscreen: bool,
+
+    /// Open the window as a borderless window covering the whole monitor.
+    #[arg(long)]
+    pub borderless: bool,
+
+    /// The monitor to open the window on, by index, e.g. 0 for the first one.
+    #[arg(long)]
+    pub monitor: Option<usize>,
+
+    /// Wait for the display's vertical sync: on or off. Off shows the real frame rate.
+    #[arg(long, value_enum, default_value = "off")]
+    pub vsync: Vsync,
 
     /// Number of particles
     #[cfg(debug_assertions)]
@@ -17,53 +76,570 @@ pub struct Args {
     #[arg(long, default_value = "30000", visible_alias = "num")]
     pub num_particles: usize,
 
+    /// Unit system for the physical parameters (gravity, pressure, radii, speeds).
+    #[arg(long, value_enum, default_value_t = UnitSystem::Particle)]
+    pub units: UnitSystem,
+
+    /// Width of the window in meters, when using physical units.
+    #[arg(long, default_value = "1.0")]
+    pub world_width: f32,
+
+    /// Rest density of the fluid in kg/m², used to display densities and masses in physical units.
+    #[arg(long, default_value = "1000.0")]
+    pub rest_density: f32,
+
+    /// When the number of particles is changed at runtime, place all the particles from scratch instead of keeping the
+    /// existing ones and raining new ones in from the top.
+    #[arg(long)]
+    pub reset_on_count_change: bool,
+
+    /// Show the wall impulse heatmap at startup (toggle with K).
+    #[arg(long)]
+    pub wall_impulses: bool,
+
+    /// Add a layer of particles with its own parameters, drawn on top of the main fluid. May be repeated.
+    /// Format: `name:key=value,...`, where the keys are num_particles, smoothing_radius, gravity,
+    /// pressure_multiplier, viscosity_strength, collision_damping, sprite_size, and coupling (0..1, how strongly the
+    /// main fluid drags the layer along). E.g. `--layer foam:num_particles=3000,sprite_size=0.5,coupling=0.8`
+    #[arg(long = "layer")]
+    pub layers: Vec<String>,
+
+    /// Run a second simulation side by side with the main one, with the same input but some parameters changed, to
+    /// compare them. Format: `key=value,...`, where the keys are solver, neighbor_search, pbf_iterations,
+    /// pcisph_max_iterations, pcisph_tolerance, substeps, cfl, smoothing_radius, gravity, pressure_multiplier,
+    /// viscosity_strength and collision_damping.
+    /// E.g. `--compare neighbor_search=hash` or `--compare pressure_multiplier=500000`
+    #[arg(long)]
+    pub compare: Option<String>,
+
     /// Smoothing radius, as a multiple of particle size (e.g. 8.0 = 8x particle diameter).
     #[arg(short, long, default_value = "10.0")]
     pub smoothing_radius: f32,
 
-    /// Gravity strength
-    #[arg(short, long, default_value = "30.0")]
-    pub gravity: f32,
+    /// Target density of the fluid, in particles per square unit (see --units). By default, it's derived from the
+    /// spacing of the particles and the smoothing radius, and follows them when they change.
+    #[arg(long)]
+    pub target_density: Option<f32>,
+
+    /// Gravity strength, in units/s² (see --units). [default: 30 particle sizes/s²]
+    #[arg(short, long)]
+    pub gravity: Option<f32>,
+
+    /// Start with the gravity spinning around the tank at this many degrees per second, as if the tank were rotating
+    /// (Ctrl+Up toggles spinning at 20°/s by default).
+    #[arg(long, allow_negative_numbers = true)]
+    pub gravity_spin: Option<f32>,
+
+    /// Start in point gravity (planet) mode, with the gravity pulling toward this point, as x,y in units from the
+    /// middle of the window (M toggles it, and a middle click moves the point).
+    #[arg(long, value_parser = parse_point, allow_negative_numbers = true)]
+    pub point_gravity: Option<Vec2>,
 
     /// Speed multiplier
     #[arg(long, default_value = "3.0")]
     pub speed: f32,
 
-    /// Multiplier for the pressure calculations.
-    #[arg(short, long, default_value = "250000", visible_alias = "press")]
-    pub pressure_multiplier: u32,
+    /// Multiplier for the pressure calculations, which scales with the units like a length.
+    /// [default: 250000, in particle units]
+    #[arg(short, long, visible_alias = "press")]
+    pub pressure_multiplier: Option<u32>,
 
-    /// Multiplier for the viscosity calculations.
-    #[arg(short, long, default_value = "5.0")]
+    /// Kinematic viscosity, in particle sizes²/s: 0 is watery, and higher values are more like honey (V / shift+V).
+    #[arg(short, long, default_value = "50.0")]
     pub viscosity_strength: f32,
 
-    /// How much to slow down particles when they hit a wall.
+    /// Spring constant of the viscoelastic springs between neighboring particles, in 1/s²: 0 turns them off, and
+    /// higher values make the fluid stretch and wobble like goo.
+    #[arg(long, default_value = "0.0")]
+    pub elasticity: f32,
+
+    /// How quickly the springs give way when they're stretched or squeezed, per second: 0 is elastic, like jelly, and
+    /// higher values let the goo flow, like slime. Only with `--elasticity`.
+    #[arg(long, default_value = "0.3")]
+    pub plasticity: f32,
+
+    /// How much to slow down particles when they hit a wall (F8 / shift+F8).
     #[arg(long, default_value = "0.5", visible_alias = "cd")]
     pub collision_damping: f32,
 
-    /// Radius of the area-of-affect for mouse clicks, as a factor of particle size.
-    #[arg(long, default_value = "40", visible_alias = "ir")]
-    pub interaction_input_radius: u16,
+    /// How much of their speed particles keep when they bounce off the sides of the tank, instead of the collision
+    /// damping: one value for all of them, or four for the left, right, bottom and top walls (e.g. `1,1,0.1,1`).
+    #[arg(long, value_parser = parse_restitution)]
+    pub wall_restitution: Option<[f32; 4]>,
+
+    /// Friction between the particles and the walls: how much a bounce slows them down along the wall, relative to
+    /// the change in their speed across it. 0 is perfectly slippery, and a few times 1 makes the walls sticky.
+    #[arg(long, default_value = "0.0")]
+    pub wall_friction: f32,
+
+    /// Open the bottom of the tank: particles that fall out of the bottom are recycled to the top.
+    #[arg(long)]
+    pub bottomless: bool,
+
+    /// Wetting coefficient: how strongly particles are attracted to the walls, in units/s² like gravity.
+    /// Negative values repel particles from the walls, so that the fluid beads off them instead.
+    #[arg(long, default_value = "0.0", allow_negative_numbers = true)]
+    pub adhesion: f32,
+
+    /// Surface tension: how strongly the particles at the free surface are pulled toward their neighbors, in units/s²
+    /// like gravity, so that the surface smooths out and drops hold together.
+    #[arg(long, default_value = "0.0")]
+    pub surface_tension: f32,
+
+    /// Correct the density of the particles near the walls for their missing neighbors, as if the walls were lined
+    /// with fluid at rest, which pushes back with the particles' pressure. Stops the particles from sticking to the
+    /// walls and crawling along them.
+    #[arg(long)]
+    pub wall_density: bool,
+
+    /// Depth of the shallow-water heightfield at the bottom of the tank, as a fraction of the window height.
+    /// The heightfield is a cheap stand-in for a deep body of water, with SPH particles only simulated above it.
+    /// 0 disables the heightfield.
+    #[arg(long, default_value = "0.0", visible_alias = "hf")]
+    pub heightfield_depth: f32,
+
+    /// Radius of the area-of-affect for mouse clicks, in units (see --units). [default: 40 particle sizes]
+    #[arg(long, visible_alias = "ir")]
+    pub interaction_input_radius: Option<f32>,
 
     /// Strength of the attraction/repulsion when mouse is clicked.
     #[arg(long, default_value = "700", visible_alias = "is")]
     pub interaction_input_strength: f32,
 
+    /// What mouse clicks do with modifier keys held down, as a list of modifiers=mode.
+    /// Modifiers: shift, ctrl, alt. Modes: normal, double, vortex, slow-only, inspect, grab, explode.
+    #[arg(
+        long,
+        default_value = "shift=double,ctrl=vortex,alt=slow-only,ctrl+shift=inspect,alt+shift=grab,ctrl+alt=explode"
+    )]
+    pub interaction_modes: String,
+
+    /// Radius of the blast when double-clicking (or clicking in the explode mode), in units (see --units).
+    /// [default: 30 particle sizes]
+    #[arg(long)]
+    pub explosion_radius: Option<f32>,
+
+    /// Speed given to the particles at the center of the blast, in units/s. It falls off toward the edge.
+    /// [default: 150 particle sizes/s]
+    #[arg(long)]
+    pub explosion_strength: Option<f32>,
+
+    /// Speed threshold for the slow-only interaction mode, in units/s. [default: 20 particle sizes/s]
+    #[arg(long)]
+    pub interaction_slow_threshold: Option<f32>,
+
+    /// Strength of the wake that the mouse cursor leaves as it moves across the fluid, even without clicking.
+    /// 0 disables it.
+    #[arg(long, default_value = "0.0")]
+    pub cursor_wake: f32,
+
+    /// Start with a background current dragging the fluid along: uniform, gusts, or a file of flow vectors (one line
+    /// per row of a grid spanning the tank, top row first, each vector as x,y, relative to --wind-speed).
+    /// U toggles the wind (uniform, if this isn't given), and Y / shift+Y adjusts its speed.
+    #[arg(long, value_parser = FlowField::from_str)]
+    pub wind: Option<FlowField>,
+
+    /// Speed of the wind, in units/s (see --units). Negative speeds blow to the left. [default: 20 particle sizes/s]
+    #[arg(long, allow_negative_numbers = true)]
+    pub wind_speed: Option<f32>,
+
+    /// How quickly the wind brings the particles up to its speed, per second.
+    #[arg(long, default_value = "1.0")]
+    pub wind_drag: f32,
+
+    /// Shape of the tank: circle, hexagon, hourglass, u-tube, funnel, or an SVG file containing a <polygon>.
+    #[arg(long, value_parser = ContainerShape::from_str)]
+    pub container: Option<ContainerShape>,
+
+    /// How the particles start out (shift+R cycles through them): random-fill, grid, hex-lattice, dam-break-left,
+    /// double-dam-break, centered-droplet or top-rain. Containers and scenarios with regions place them themselves.
+    #[arg(long, value_enum, default_value_t = InitialPlacement::Grid)]
+    pub placement: InitialPlacement,
+
+    /// Key bindings file (RON): a map from action names to Bevy key names, e.g. {"pause": "KeyP", "reset": "End"}.
+    /// If it doesn't exist, it's written with the default bindings, to be edited.
+    #[arg(long)]
+    pub key_bindings: Option<String>,
+
+    /// Settings file (RON) with any of the tunable parameters, named like these options, e.g.
+    /// `#![enable(implicit_some)] (gravity: 20.0, pressure_multiplier: 300000)`. The options given on the command line
+    /// override it. It's reloaded into the running simulation whenever it's saved.
+    #[arg(long)]
+    pub settings: Option<String>,
+
+    /// Start with the parameters from a share code, which F9 shows and copies to the clipboard. They override the
+    /// number of particles, smoothing radius, gravity, pressure, viscosity, collision damping, wind speed and substeps.
+    #[arg(long, value_parser = ParamsCode::decode)]
+    pub params: Option<ParamsCode>,
+
+    /// Scenario file (RON, see scenarios/) describing where the particles start, obstacles, and parameters. Can be
+    /// given more than once; Shift+1 to Shift+9 switch between these scenarios and the built-in ones.
+    #[arg(long, value_parser = Scenario::load)]
+    pub scenario: Vec<Scenario>,
+
+    /// Minimum number of substeps per simulation step (the simulation runs at a fixed 60 steps per second). Steps are
+    /// split into more substeps when --max-dt or --cfl call for it.
+    #[arg(long, default_value = "1")]
+    pub substeps: u32,
+
+    /// CFL safety factor: the most of the smoothing radius that the fastest particle may move in a substep, before the
+    /// step is split into more of them. 0 turns the adaptive substeps off.
+    #[arg(long, default_value = "0.4")]
+    pub cfl: f32,
+
+    /// Longest substep, in seconds (before the speed multiplier).
+    #[arg(long, default_value = "0.0166667")]
+    pub max_dt: f32,
+
+    /// Compute the densities and pressures from the current positions, instead of the positions predicted half a step
+    /// ahead (toggle with O).
+    #[arg(long)]
+    pub no_prediction: bool,
+
+    /// Draw the region grid (X key) and the neighbor search (C key) only around the cursor or the watched particle.
+    #[arg(long)]
+    pub local_grid: bool,
+
+    /// Shrink the sprites of over-compressed particles and grow the ones in sparse regions (toggle with D).
+    #[arg(long)]
+    pub density_sprites: bool,
+
+    /// Draw the density field behind the particles, to show pressure waves moving through the fluid (toggle with F3).
+    #[arg(long)]
+    pub density_background: bool,
+
+    /// Size of the cells that the density background is sampled on, in pixels.
+    #[arg(long, default_value = "8.0")]
+    pub density_background_cell: f32,
+
+    /// Draw streamlines through the velocity field, to show the vortices and jets (toggle with F4).
+    #[arg(long)]
+    pub streamlines: bool,
+
+    /// Outline the particles at the free surface (toggle with F12).
+    #[arg(long)]
+    pub surface_outline: bool,
+
+    /// Add foam, spray and bubbles where the fluid splashes: cheap secondary particles that are only drawn, not
+    /// simulated (toggle with F11).
+    #[arg(long)]
+    pub foam: bool,
+
+    /// Display the kinetic and potential energy, momentum and average density, and warn when the energy keeps growing
+    /// on its own, a sign of an unstable solver (toggle with F6).
+    #[arg(long)]
+    pub energy_stats: bool,
+
     /// Size of the particle sprite, relative to particle size.
     #[arg(long, default_value = "3.0", visible_alias = "ss")]
     pub sprite_size: f32,
+
+    /// Spatial index used to find each particle's neighbors.
+    #[arg(long, value_enum, default_value_t = NeighborSearchKind::Grid)]
+    pub neighbor_search: NeighborSearchKind,
+
+    /// How the fluid is kept incompressible. PBF and PCISPH always run on the CPU, even with `--backend gpu`.
+    #[arg(long, value_enum, default_value_t = SolverKind::Sph)]
+    pub solver: SolverKind,
+
+    /// Number of constraint iterations per substep with `--solver pbf`. More iterations make the fluid stiffer.
+    #[arg(long, default_value = "4")]
+    pub pbf_iterations: u32,
+
+    /// Most pressure-correction iterations per substep with `--solver pcisph`.
+    #[arg(long, default_value = "8")]
+    pub pcisph_max_iterations: u32,
+
+    /// With `--solver pcisph`, the pressure is corrected until the mean compression (the density in excess of the
+    /// target density, relative to it) is below this, or for `--pcisph-max-iterations` iterations.
+    #[arg(long, default_value = "0.01")]
+    pub pcisph_tolerance: f32,
+
+    /// Where to compute the densities and pressure forces. The GPU backend only pays off with a lot of particles.
+    #[arg(long, value_enum, default_value_t = Backend::Cpu)]
+    pub backend: Backend,
+
+    /// Make every frame bit-exact reproducible: random numbers are derived from the frame and particle number, and
+    /// parallel reductions use fixed chunking and are combined in order.
+    #[arg(long)]
+    pub deterministic: bool,
+
+    /// Simulate this many frames headlessly before opening the window, and save the settled state to --settle-file
+    /// for use with --warm-start.
+    #[arg(long)]
+    pub settle: Option<u32>,
+
+    /// Where --settle saves the settled state.
+    #[arg(long, default_value = "settled.bin")]
+    pub settle_file: String,
+
+    /// Start from a settled state saved by --settle, instead of the initial grid of particles.
+    #[arg(long)]
+    pub warm_start: Option<String>,
+
+    /// Looping ambient track (under assets/) whose volume and speed follow the fluid.
+    #[cfg(feature = "music")]
+    #[arg(long)]
+    pub music: Option<String>,
+
+    /// Show a side panel with sliders for the simulation parameters.
+    #[cfg(feature = "egui")]
+    #[arg(long)]
+    pub panel: bool,
+
+    /// Wind tunnel scene: no gravity, periodic inflow from the left, and an obstacle in the middle whose drag is
+    /// measured and plotted.
+    #[arg(long)]
+    pub wind_tunnel: bool,
+
+    /// Inflow speed for the wind tunnel, in units/s (see --units). [default: 20 particle sizes/s]
+    #[arg(long)]
+    pub inflow_speed: Option<f32>,
+
+    /// Radius of the wind tunnel obstacle, in units (see --units). [default: 15 particle sizes]
+    #[arg(long)]
+    pub obstacle_radius: Option<f32>,
+
+    /// Wave paddle: the left wall moves in and out sinusoidally, making waves travel along the tank. Obstacles in a
+    /// scenario can serve as a slope for them to break on.
+    #[arg(long)]
+    pub paddle: bool,
+
+    /// How far the paddle moves into the tank, in units (see --units). [default: 4 particle sizes]
+    #[arg(long)]
+    pub paddle_amplitude: Option<f32>,
+
+    /// Paddle strokes per second.
+    #[arg(long, default_value = "0.5")]
+    pub paddle_frequency: f32,
+
+    /// Convection demo: a hot source at the bottom left and a cold source on the right, with the particles colored by
+    /// temperature.
+    #[arg(long)]
+    pub convection: bool,
+
+    /// Dye the fluid, red on the left and blue on the right, to show how it mixes. Hold ' and drag to inject more dye.
+    #[arg(long)]
+    pub dye: bool,
+
+    /// Draw the particles as splats of binned particles (see --lod-cell-size) when there are more than this many,
+    /// or when they're too small to see individually.
+    #[arg(long, default_value = "200000")]
+    pub lod_threshold: usize,
+
+    /// Size of the bins used for the LOD splats, in particle sizes.
+    #[arg(long, default_value = "4.0")]
+    pub lod_cell_size: f32,
+
+    /// Update every particle's sprite, even the ones that are off-screen (for comparing frame times).
+    #[arg(long)]
+    pub no_culling: bool,
+
+    /// Draw all the particles as a single mesh instead of a sprite entity per particle, which scales to hundreds of
+    /// thousands of particles. Particles can't be watched or inspected in this mode.
+    #[arg(long)]
+    pub particle_mesh: bool,
+
+    /// Colors for the speed, density and temperature (shift+H cycles through them). The classic colors show each fluid
+    /// in its own colors; the others are perceptually uniform, and cividis is colorblind-safe.
+    #[arg(long, value_enum, default_value_t = Colormap::Classic)]
+    pub colormap: Colormap,
+
+    /// What the particles are colored by (H cycles through them): speed, density, pressure, vorticity (the swirl of the
+    /// velocity), neighbor count or surface (whether they're at the free surface).
+    #[arg(long, value_enum, default_value_t = ColorField::Speed)]
+    pub color_field: ColorField,
+
+    /// Drop a single particle and compare its trajectory against the analytic free-fall trajectory.
+    #[arg(long)]
+    pub drop_test: bool,
+
+    /// Write a row of metrics for every frame to this file (FPS, particle count, density range, max speed, kinetic
+    /// energy and the time spent in each phase of the simulation): CSV, or JSON lines if it ends in .json or .jsonl.
+    #[arg(long)]
+    pub metrics_out: Option<String>,
+
+    /// The most verbose level of log messages to show (error, warn, info, debug or trace). RUST_LOG overrides it, and
+    /// can also filter by target, e.g. RUST_LOG=sim::density=info.
+    #[arg(long, default_value = "info")]
+    pub log_level: Level,
+
+    /// Export the interpolated velocity or density field as a PNG for every frame.
+    #[arg(long, value_enum)]
+    pub export_field: Option<ExportField>,
+
+    /// Directory for the exported field images.
+    #[arg(long, default_value = "field-export")]
+    pub export_dir: String,
+
+    /// Width of the exported field images, in pixels.
+    #[arg(long, default_value = "200")]
+    pub export_width: u32,
+
+    /// Export the field using the heatmap colors, instead of grayscale.
+    #[arg(long)]
+    pub export_heatmap: bool,
+
+    /// Record this many frames headlessly (starting from --warm-start, if given), write them to --loop-file as a
+    /// seamlessly looping animated PNG, and exit.
+    #[arg(long)]
+    pub export_loop: Option<usize>,
+
+    /// File for --export-loop.
+    #[arg(long, default_value = "loop.png")]
+    pub loop_file: String,
+
+    /// Width of the --export-loop frames, in pixels.
+    #[arg(long, default_value = "400")]
+    pub loop_width: u32,
+
+    /// Start recording the rendered frames right away (the T key toggles recording).
+    #[arg(long)]
+    pub record: bool,
+
+    /// Directory for the recorded frames.
+    #[arg(long, default_value = "frames")]
+    pub record_dir: String,
+
+    /// Record a frame only every Nth simulation step (of 60 per second).
+    #[arg(long, default_value = "1")]
+    pub record_every: u32,
+
+    /// Pipe the recorded frames to ffmpeg (which must be on the PATH) to make an MP4, instead of writing PNGs.
+    #[arg(long)]
+    pub record_ffmpeg: bool,
+
+    /// Run unattended for this many hours, periodically checking the solver invariants and logging violations.
+    #[arg(long)]
+    pub soak: Option<f32>,
+
+    /// Maximum mean kinetic energy per unit mass (in units²/s²) allowed during a soak test.
+    /// [default: 100000 particle sizes²/s²]
+    #[arg(long)]
+    pub soak_max_energy: Option<f32>,
 }
 
-impl Args {
-    pub fn win_size(&self) -> Result<(u32, u32), Box<dyn std::error::Error>> {
-        // Parse the window width and height from the "w,h" string.
-        let win_size: Vec<_> = self.win.split(',').collect();
-        if win_size.len() != 2 {
-            return Err("Incorrect window size".into());
+/// The same defaults as the command line, for embedding the simulation without parsing any arguments.
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            win: (800, 900),
+            domain: None,
+            fullscreen: false,
+            borderless: false,
+            monitor: None,
+            vsync: Vsync::Off,
+            num_particles: if cfg!(debug_assertions) { 5000 } else { 30000 },
+            units: UnitSystem::Particle,
+            world_width: 1.0,
+            rest_density: 1000.0,
+            reset_on_count_change: false,
+            wall_impulses: false,
+            layers: Vec::new(),
+            compare: None,
+            smoothing_radius: 10.0,
+            target_density: None,
+            gravity: None,
+            gravity_spin: None,
+            point_gravity: None,
+            speed: 3.0,
+            pressure_multiplier: None,
+            viscosity_strength: 50.0,
+            elasticity: 0.0,
+            plasticity: 0.3,
+            collision_damping: 0.5,
+            wall_restitution: None,
+            wall_friction: 0.0,
+            bottomless: false,
+            adhesion: 0.0,
+            surface_tension: 0.0,
+            wall_density: false,
+            heightfield_depth: 0.0,
+            interaction_input_radius: None,
+            interaction_input_strength: 700.0,
+            interaction_modes:
+                "shift=double,ctrl=vortex,alt=slow-only,ctrl+shift=inspect,alt+shift=grab,ctrl+alt=explode".into(),
+            explosion_radius: None,
+            explosion_strength: None,
+            interaction_slow_threshold: None,
+            cursor_wake: 0.0,
+            wind: None,
+            wind_speed: None,
+            wind_drag: 1.0,
+            container: None,
+            placement: InitialPlacement::Grid,
+            key_bindings: None,
+            settings: None,
+            params: None,
+            scenario: Vec::new(),
+            substeps: 1,
+            cfl: 0.4,
+            max_dt: 0.0166667,
+            no_prediction: false,
+            local_grid: false,
+            density_sprites: false,
+            density_background: false,
+            density_background_cell: 8.0,
+            streamlines: false,
+            surface_outline: false,
+            foam: false,
+            energy_stats: false,
+            sprite_size: 3.0,
+            neighbor_search: NeighborSearchKind::Grid,
+            solver: SolverKind::Sph,
+            pbf_iterations: 4,
+            pcisph_max_iterations: 8,
+            pcisph_tolerance: 0.01,
+            backend: Backend::Cpu,
+            deterministic: false,
+            settle: None,
+            settle_file: "settled.bin".into(),
+            warm_start: None,
+            #[cfg(feature = "music")]
+            music: None,
+            #[cfg(feature = "egui")]
+            panel: false,
+            wind_tunnel: false,
+            inflow_speed: None,
+            obstacle_radius: None,
+            paddle: false,
+            paddle_amplitude: None,
+            paddle_frequency: 0.5,
+            convection: false,
+            dye: false,
+            lod_threshold: 200000,
+            lod_cell_size: 4.0,
+            no_culling: false,
+            particle_mesh: false,
+            colormap: Colormap::Classic,
+            color_field: ColorField::Speed,
+            drop_test: false,
+            metrics_out: None,
+            log_level: Level::INFO,
+            export_field: None,
+            export_dir: "field-export".into(),
+            export_width: 200,
+            export_heatmap: false,
+            export_loop: None,
+            loop_file: "loop.png".into(),
+            loop_width: 400,
+            record: false,
+            record_dir: "frames".into(),
+            record_every: 1,
+            record_ffmpeg: false,
+            soak: None,
+            soak_max_energy: None,
         }
-        let width: u32 = win_size[0].parse()?;
-        let height: u32 = win_size[1].parse()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok((width, height))
+    /// The defaults are written out twice, in the clap attributes and in Default, so they have to be kept in step.
+    #[test]
+    fn default_matches_the_command_line_defaults() {
+        assert_eq!(format!("{:?}", Args::default()), format!("{:?}", Args::parse_from(["bevy-fluid-sim"])));
     }
 }
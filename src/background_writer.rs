@@ -0,0 +1,47 @@
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use bevy::prelude::{Resource, error};
+
+/// How many writes can be waiting before the app waits for the writer to catch up. Each one holds a whole image, so
+/// this bounds the memory used when encoding can't keep up with the frames.
+const QUEUE_LEN: usize = 8;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A single thread that encodes and writes the recorded frames and the exported fields, in the order they're queued.
+/// Encoding is slow compared to a frame, so it's done in the background, but a thread per image would pile up (along
+/// with the images) when the encoder can't keep up.
+#[derive(Resource, Default)]
+pub struct BackgroundWriter {
+    sender: Option<SyncSender<Job>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl BackgroundWriter {
+    /// Queues the job, starting the thread on first use. If the queue is full, this waits for a slot.
+    pub fn run(&mut self, job: impl FnOnce() + Send + 'static) {
+        let sender = self.sender.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::sync_channel::<Job>(QUEUE_LEN);
+            self.worker = Some(thread::spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            }));
+            sender
+        });
+        if sender.send(Box::new(job)).is_err() {
+            error!("The background writer has stopped");
+        }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        // Closing the channel lets the thread finish what's queued, and then end.
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
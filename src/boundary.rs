@@ -0,0 +1,200 @@
+use bevy::math::Vec2;
+
+use crate::sim_struct::Simulation;
+
+/// Number of distances from the wall at which the integrals are tabulated, from 0 to the smoothing radius.
+const SAMPLES: usize = 32;
+/// The integrals are evaluated on a grid of this many cells across the kernel's support.
+const INTEGRATION_CELLS: usize = 64;
+
+/// Integrals of the smoothing kernel over the half-plane beyond a wall, as a function of the distance to the wall,
+/// for the wall density correction (`--wall-density`).
+///
+/// A particle near a wall is missing the neighbors that would be on the other side, so its density is underestimated,
+/// and the pressure gradient draws it onto the wall, where it sticks and crawls along. The correction treats the far
+/// side of every wall as fluid at rest, so that the missing neighbors are added back analytically: as the fraction of
+/// the kernel's volume beyond the wall, times the rest density. The same fluid pushes back on the particle with the
+/// particle's own pressure (mirrored, as with boundary particles), but never pulls it onto the wall.
+#[derive(Default)]
+pub struct WallKernel {
+    step: f32,
+    /// The fraction of the kernel's volume beyond the wall.
+    density: Vec<f32>,
+    /// The sum of the pressure gradient terms of the fluid beyond the wall, along the wall's normal, per unit of
+    /// pressure.
+    pressure: Vec<f32>,
+}
+
+impl WallKernel {
+    pub fn new(smoothing_radius: f32, kernel: impl Fn(f32) -> f32, kernel_derivative: impl Fn(f32) -> f32) -> Self {
+        let step = smoothing_radius / (SAMPLES - 1) as f32;
+        let cell = smoothing_radius * 2.0 / INTEGRATION_CELLS as f32;
+        // The kernel's support around a particle at the origin, with the wall below it.
+        let points: Vec<Vec2> = (0..INTEGRATION_CELLS)
+            .flat_map(|row| (0..INTEGRATION_CELLS).map(move |col| Vec2::new(col as f32, row as f32)))
+            .map(|cell_index| (cell_index + 0.5) * cell - smoothing_radius)
+            .filter(|point| point.length() < smoothing_radius)
+            .collect();
+
+        let (density, pressure) = (0..SAMPLES)
+            .map(|sample| {
+                let distance = sample as f32 * step;
+                let beyond = points.iter().filter(|point| point.y < -distance);
+                beyond.fold((0.0, 0.0), |(density, pressure), point| {
+                    let r = point.length();
+                    (density + kernel(r) * cell * cell, pressure + -point.y / r * kernel_derivative(r) * cell * cell)
+                })
+            })
+            .unzip();
+
+        WallKernel {
+            step,
+            density,
+            pressure,
+        }
+    }
+
+    /// Linearly interpolates a table at the given distance from the wall, and returns the slope there too.
+    fn sample(&self, table: &[f32], distance: f32) -> (f32, f32) {
+        if table.len() < 2 || distance >= self.step * (table.len() - 1) as f32 {
+            return (0.0, 0.0);
+        }
+        let position = distance.max(0.0) / self.step;
+        let index = (position as usize).min(table.len() - 2);
+        let slope = (table[index + 1] - table[index]) / self.step;
+        (table[index] + slope * (position - index as f32) * self.step, slope)
+    }
+}
+
+impl Simulation {
+    /// The walls within the smoothing radius of the position, as the distance to each one and its inward normal.
+    /// The walls are the closest part of the container's boundary if there is one (it touches the sides of the tank,
+    /// which would otherwise be counted twice), or else the sides of the tank, apart from the ones that are open in
+    /// the wind tunnel or bottomless mode, with the heightfield's surface as the floor.
+    pub fn nearby_walls(&self, position: Vec2) -> [Option<(f32, Vec2)>; 4] {
+        let walls = match &self.container {
+            Some(container) => [Some(container.closest_wall(position)), None, None, None],
+            None => {
+                let half_size = self.half_bounds_size;
+                let side_walls = self.wind_tunnel.is_none();
+                let floor = !self.bottomless || self.heightfield.is_some();
+                [
+                    side_walls.then_some((half_size.x + position.x, Vec2::X)),
+                    side_walls.then_some((half_size.x - position.x, Vec2::NEG_X)),
+                    floor.then(|| (position.y - self.floor(position.x), Vec2::Y)),
+                    Some((half_size.y - position.y, Vec2::NEG_Y)),
+                ]
+            }
+        };
+
+        walls.map(|wall| wall.filter(|&(distance, _)| distance < self.smoothing_radius))
+    }
+
+    /// The density contributed by the fluid at rest beyond the walls, with `--wall-density`.
+    pub fn wall_density(&self, particle_id: usize) -> f32 {
+        if !self.wall_density {
+            return 0.0;
+        }
+
+        let fraction: f32 = self
+            .nearby_walls(self.solver_position(particle_id))
+            .into_iter()
+            .flatten()
+            .map(|(distance, _)| self.wall_kernel.sample(&self.wall_kernel.density, distance).0)
+            .sum();
+        fraction * self.rest_density(particle_id)
+    }
+
+    /// The gradient of wall_density() with respect to the particle's position, for the PBF constraints.
+    pub fn wall_density_gradient(&self, particle_id: usize) -> Vec2 {
+        if !self.wall_density {
+            return Vec2::ZERO;
+        }
+
+        self.wall_fraction_gradient(self.solver_position(particle_id)) * self.rest_density(particle_id)
+    }
+
+    /// The gradient of the fraction of the kernel's volume beyond the walls, with respect to the position. It points
+    /// toward the walls.
+    pub fn wall_fraction_gradient(&self, position: Vec2) -> Vec2 {
+        self.nearby_walls(position)
+            .into_iter()
+            .flatten()
+            .map(|(distance, normal)| normal * self.wall_kernel.sample(&self.wall_kernel.density, distance).1)
+            .sum()
+    }
+
+    /// The pressure force of the fluid beyond the walls, with `--wall-density`.
+    pub fn wall_pressure_force(&self, particle_id: usize) -> Vec2 {
+        let pressure = self.pressures[particle_id].max(0.0);
+        if !self.wall_density || pressure == 0.0 {
+            return Vec2::ZERO;
+        }
+
+        self.nearby_walls(self.solver_position(particle_id))
+            .into_iter()
+            .flatten()
+            .map(|(distance, normal)| normal * self.wall_kernel.sample(&self.wall_kernel.pressure, distance).0)
+            .sum::<Vec2>()
+            * pressure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn wall_kernel_covers_half_the_kernel_at_the_wall() {
+        let sim = Simulation::new(400.0, 400.0, &Args::default());
+        let wall_kernel = &sim.wall_kernel;
+        let density = |distance: f32| wall_kernel.sample(&wall_kernel.density, distance).0;
+
+        assert!((density(0.0) - 0.5).abs() < 0.02, "{}", density(0.0));
+        assert_eq!(density(sim.smoothing_radius), 0.0);
+        for sample in 1..wall_kernel.density.len() {
+            assert!(wall_kernel.density[sample] <= wall_kernel.density[sample - 1]);
+            assert!(wall_kernel.pressure[sample] <= wall_kernel.pressure[sample - 1]);
+        }
+    }
+
+    /// In a tank full of fluid at rest, the particles along the walls should have about the same density as the ones in
+    /// the middle.
+    #[test]
+    fn wall_density_fills_in_for_the_missing_neighbors() {
+        const SIDE: usize = 20;
+        let mut sim = Simulation::new(
+            200.0,
+            200.0,
+            &Args {
+                num_particles: SIDE * SIDE,
+                ..Args::default()
+            },
+        );
+        let spacing = sim.half_bounds_size.x * 2.0 / SIDE as f32;
+        for row in 0..SIDE {
+            for col in 0..SIDE {
+                let position = -sim.half_bounds_size + (Vec2::new(col as f32, row as f32) + 0.5) * spacing;
+                sim.positions[row * SIDE + col] = position;
+                sim.predicted_positions[row * SIDE + col] = position;
+            }
+        }
+        sim.update_regions();
+        let center = SIDE / 2 * SIDE + SIDE / 2;
+        let wall = SIDE / 2;
+
+        sim.calculate_densities();
+        let uncorrected = sim.densities[wall] / sim.densities[center];
+        assert!(uncorrected < 0.8, "uncorrected wall density ratio {uncorrected}");
+
+        // Make the lattice the fluid at rest.
+        sim.target_density = sim.densities[center];
+        sim.wall_density = true;
+        sim.calculate_densities();
+        let corrected = sim.densities[wall] / sim.densities[center];
+        assert!((corrected - 1.0).abs() < 0.1, "corrected wall density ratio {corrected}");
+    }
+}
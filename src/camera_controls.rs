@@ -0,0 +1,120 @@
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::compare::ComparisonCamera;
+use crate::components::{MainSimulation, Notifications};
+use crate::domain::world_per_pixel;
+use crate::messages::MESSAGE_POSITION;
+use crate::sim_struct::Simulation;
+
+/// Zoom factor per line of scrolling.
+const ZOOM_STEP: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.02;
+const MAX_ZOOM: f32 = 20.0;
+/// Arrow key panning speed, in screen pixels per second.
+const PAN_SPEED: f32 = 600.0;
+
+/// World-space text that keeps the same size on screen when zooming, like the region grid labels.
+#[derive(Component)]
+pub struct ScreenSized;
+
+/// Zooms with the scroll wheel (toward the cursor), pans with the middle mouse button or the arrow keys, and resets
+/// the view with 0. The letter keys are all taken by the keyboard commands, so panning doesn't use WASD.
+/// While annotating, the middle button places labels instead.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)] // ECS gonna ECS.
+pub fn control_camera(
+    camera: Single<
+        (&Camera, &GlobalTransform, &mut Transform, &mut Projection),
+        (With<Camera2d>, Without<ComparisonCamera>),
+    >,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    scroll: Res<AccumulatedMouseScroll>,
+    motion: Res<AccumulatedMouseMotion>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+) {
+    let (camera, camera_global_transform, mut transform, mut projection) = camera.into_inner();
+    let Projection::Orthographic(orthographic) = &mut *projection else {
+        return;
+    };
+
+    if kb.just_pressed(KeyCode::Digit0) {
+        transform.translation.x = 0.0;
+        transform.translation.y = 0.0;
+        orthographic.scale = 1.0;
+        return;
+    }
+
+    let lines = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        // Roughly one line per notch, on most platforms.
+        MouseScrollUnit::Pixel => scroll.delta.y / 20.0,
+    };
+    if lines != 0.0 {
+        let old_scale = orthographic.scale;
+        let new_scale = (old_scale / ZOOM_STEP.powf(lines)).clamp(MIN_ZOOM, MAX_ZOOM);
+        // Keep the point under the cursor where it is on screen.
+        let cursor_point = windows
+            .single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+            .and_then(|cursor_position| {
+                camera
+                    .viewport_to_world_2d(camera_global_transform, cursor_position)
+                    .ok()
+            });
+        if let Some(point) = cursor_point {
+            let camera_position = transform.translation.truncate();
+            let new_position = point - (point - camera_position) * new_scale / old_scale;
+            transform.translation.x = new_position.x;
+            transform.translation.y = new_position.y;
+        }
+        orthographic.scale = new_scale;
+    }
+
+    // Screen y points down, world y points up.
+    let mut pan = Vec2::ZERO;
+    if buttons.pressed(MouseButton::Middle) && !sim.debug.annotating {
+        pan -= motion.delta * Vec2::new(1.0, -1.0);
+    }
+    let arrows = [
+        (KeyCode::ArrowLeft, Vec2::NEG_X),
+        (KeyCode::ArrowRight, Vec2::X),
+        (KeyCode::ArrowDown, Vec2::NEG_Y),
+        (KeyCode::ArrowUp, Vec2::Y),
+    ];
+    // Ctrl+arrows steer the gravity instead.
+    let ctrl = kb.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    for (key, direction) in arrows {
+        if kb.pressed(key) && !ctrl {
+            pan += direction * PAN_SPEED * time.delta_secs();
+        }
+    }
+    let scale = world_per_pixel(camera, orthographic);
+    transform.translation.x += pan.x * scale;
+    transform.translation.y += pan.y * scale;
+}
+
+/// Scales the screen-sized text by the zoom, and keeps the notifications in the middle of the view.
+#[allow(clippy::type_complexity)]
+pub fn rescale_text(
+    camera: Single<(&Camera, &Transform, &Projection), (With<Camera2d>, Without<ComparisonCamera>)>,
+    mut texts: Query<(&mut Transform, Has<Notifications>), (With<ScreenSized>, Without<Camera2d>)>,
+) {
+    let (camera, camera_transform, projection) = *camera;
+    let zoom = match projection {
+        Projection::Orthographic(orthographic) => world_per_pixel(camera, orthographic),
+        _ => 1.0,
+    };
+    for (mut transform, is_notifications) in &mut texts {
+        transform.scale = Vec3::new(zoom, zoom, 1.0);
+        if is_notifications {
+            let position = camera_transform.translation.truncate() + MESSAGE_POSITION.truncate() * zoom;
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        }
+    }
+}
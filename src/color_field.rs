@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+use crate::sim_struct::Simulation;
+
+/// Which per-particle value the particles are colored by (`--color-field`, H).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorField {
+    #[default]
+    Speed,
+    Density,
+    /// The pressure from the density, centered on the rest density: the middle of the colormap is no pressure.
+    Pressure,
+    /// The curl of the velocity, centered on zero: one end of the colormap is clockwise, the other counterclockwise.
+    Vorticity,
+    /// The number of neighbors within the smoothing radius.
+    Neighbors,
+    /// Whether the particle is at the free surface (see update_surface()).
+    Surface,
+}
+
+impl ColorField {
+    /// The next field, wrapping around to the first.
+    pub fn next(self) -> Self {
+        let all = Self::value_variants();
+        let index = all.iter().position(|&field| field == self).unwrap_or(0);
+        all[(index + 1) % all.len()]
+    }
+
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map_or_else(|| format!("{self:?}"), |value| value.get_name().to_string())
+    }
+}
+
+impl Simulation {
+    /// The vorticity of the particle's neighborhood: the SPH estimate of the curl of the velocity field, positive for
+    /// counterclockwise swirls.
+    pub fn vorticity(&self, particle_id: usize) -> f32 {
+        let position = self.positions[particle_id];
+        let velocity = self.velocities[particle_id];
+        self.neighbor_particles(particle_id)
+            .filter(|&neighbor_id| neighbor_id != particle_id && self.densities[neighbor_id] > 0.0)
+            .map(|neighbor_id| {
+                let offset = position - self.positions[neighbor_id];
+                let distance = offset.length().max(0.000000001);
+                let gradient = offset / distance * self.smoothing_kernel_derivative(distance);
+                (self.velocities[neighbor_id] - velocity).perp_dot(gradient) / self.densities[neighbor_id]
+            })
+            .sum()
+    }
+
+    /// The color field's raw value for the particle. Speed and density are colored from the values the simulation
+    /// already tracks, so they aren't computed here.
+    fn color_field_value(&self, particle_id: usize) -> f32 {
+        match self.debug.color_field {
+            ColorField::Speed | ColorField::Density => 0.0,
+            ColorField::Pressure => {
+                self.pressure_from_density(self.densities[particle_id], self.rest_density(particle_id))
+            }
+            ColorField::Vorticity => self.vorticity(particle_id),
+            ColorField::Neighbors => self.neighbor_count(particle_id) as f32,
+            ColorField::Surface => self.is_surface(particle_id) as u8 as f32,
+        }
+    }
+
+    /// Computes the color field for every particle, scaled to 0..1 by the current extremes: signed fields are centered
+    /// on 0.5, and the neighbor counts and the surface start from zero.
+    pub fn update_color_field(&mut self) {
+        if matches!(self.debug.color_field, ColorField::Speed | ColorField::Density) {
+            self.debug.color_values.clear();
+            return;
+        }
+
+        let mut values: Vec<f32> = (0..self.num_particles)
+            .into_par_iter()
+            .map(|i| self.color_field_value(i))
+            .collect();
+        let largest = values.iter().fold(0f32, |largest, value| largest.max(value.abs()));
+        let scale = largest.max(f32::EPSILON);
+        if matches!(self.debug.color_field, ColorField::Neighbors | ColorField::Surface) {
+            values.iter_mut().for_each(|value| *value /= scale);
+        } else {
+            values.iter_mut().for_each(|value| *value = 0.5 + *value / scale * 0.5);
+        }
+        self.debug.color_values = values;
+    }
+}
+
+/// Refreshes the color field values of all the simulations before the particles are drawn.
+pub fn update_color_field(mut sims: Query<&mut Simulation>) {
+    for mut sim in &mut sims {
+        sim.update_color_field();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use clap::ValueEnum;
+
+    use super::ColorField;
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn vorticity_follows_the_direction_of_the_swirl() {
+        let mut sim = test_sim(400, 400.0, 400.0, Args::default());
+        sim.update_regions();
+        sim.calculate_densities();
+        // The middle of the fluid, where the particles have a full set of neighbors.
+        let center = sim.positions().iter().sum::<Vec2>() / sim.num_particles as f32;
+        let nearest = (0..sim.num_particles)
+            .min_by(|&a, &b| {
+                let distance = |i: usize| sim.positions()[i].distance(center);
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap();
+
+        // Solid-body rotation, counterclockwise.
+        for i in 0..sim.num_particles {
+            sim.velocities[i] = (sim.positions()[i] - center).perp();
+        }
+        sim.debug.color_field = ColorField::Vorticity;
+        sim.update_color_field();
+        assert!(sim.vorticity(nearest) > 0.0, "vorticity {}", sim.vorticity(nearest));
+        assert!(sim.debug.color_values[nearest] > 0.5);
+
+        sim.velocities.iter_mut().for_each(|velocity| *velocity = -*velocity);
+        sim.update_color_field();
+        assert!(sim.debug.color_values[nearest] < 0.5);
+
+        sim.debug.color_field = ColorField::Neighbors;
+        sim.update_color_field();
+        assert!(sim.debug.color_values.iter().all(|value| (0.0..=1.0).contains(value)));
+        assert!(sim.debug.color_values.contains(&1.0));
+
+        let mut field = ColorField::Speed;
+        for _ in ColorField::value_variants() {
+            field = field.next();
+        }
+        assert_eq!(field, ColorField::Speed);
+    }
+}
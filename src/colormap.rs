@@ -0,0 +1,95 @@
+use bevy::color::{Color, ColorToComponents, Srgba};
+use bevy::math::Vec3;
+use clap::ValueEnum;
+
+/// How the scalar fields (speed, density, temperature) are mapped to the particles' colors (`--colormap`, shift+H).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Colormap {
+    /// The original two-color blends: dark blue to yellow for the speed (or each fluid's own colors), and blue to red
+    /// for the density and temperature.
+    #[default]
+    Classic,
+    Viridis,
+    Plasma,
+    Turbo,
+    Grayscale,
+    /// Readable with the common forms of color blindness.
+    Cividis,
+}
+
+// Evenly spaced samples of the matplotlib colormaps, in sRGB.
+const VIRIDIS: [u32; 9] = [
+    0x440154, 0x472c7a, 0x3b518b, 0x2c718e, 0x21908d, 0x27ad81, 0x5cc863, 0xaadc32, 0xfde725,
+];
+const PLASMA: [u32; 8] = [
+    0x0d0887, 0x5302a3, 0x8b0aa5, 0xb83289, 0xdb5c68, 0xf48849, 0xfebd2a, 0xf0f921,
+];
+const TURBO: [u32; 13] = [
+    0x30123b, 0x4454c4, 0x4490fe, 0x1fc9dd, 0x2beb9a, 0x71fe5f, 0xb6f735, 0xe6d538, 0xfea431, 0xf56918, 0xd93806,
+    0xb01a01, 0x7a0403,
+];
+const GRAYSCALE: [u32; 2] = [0x000000, 0xffffff];
+const CIVIDIS: [u32; 10] = [
+    0x00224e, 0x123570, 0x3b496c, 0x575d6d, 0x707173, 0x8a8678, 0xa59c74, 0xc3b369, 0xe1cc55, 0xfee838,
+];
+
+impl Colormap {
+    /// The next colormap, wrapping around to the first.
+    pub fn next(self) -> Self {
+        let all = Self::value_variants();
+        let index = all.iter().position(|&colormap| colormap == self).unwrap_or(0);
+        all[(index + 1) % all.len()]
+    }
+
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map_or_else(|| format!("{self:?}"), |value| value.get_name().to_string())
+    }
+
+    /// The linear RGB color for a value from 0 to 1. The classic colormap blends between `low` and `high`.
+    pub fn color(self, value: f32, low: Vec3, high: Vec3) -> Vec3 {
+        let value = if value.is_finite() { value.clamp(0.0, 1.0) } else { 0.0 };
+        let stops: &[u32] = match self {
+            Colormap::Classic => return low.lerp(high, value),
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Plasma => &PLASMA,
+            Colormap::Turbo => &TURBO,
+            Colormap::Grayscale => &GRAYSCALE,
+            Colormap::Cividis => &CIVIDIS,
+        };
+
+        let position = value * (stops.len() - 1) as f32;
+        let index = (position as usize).min(stops.len() - 2);
+        let to_linear = |rgb: u32| {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            Color::from(Srgba::rgb_u8(r, g, b)).to_linear().to_vec3()
+        };
+        to_linear(stops[index]).lerp(to_linear(stops[index + 1]), position - index as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+    use clap::ValueEnum;
+
+    use super::Colormap;
+
+    #[test]
+    fn colormaps_cover_the_range_and_cycle() {
+        for &colormap in Colormap::value_variants() {
+            let low = colormap.color(0.0, Vec3::ZERO, Vec3::ONE);
+            let high = colormap.color(1.0, Vec3::ZERO, Vec3::ONE);
+            assert!(low.element_sum() < high.element_sum(), "{colormap:?} doesn't get brighter");
+            assert_eq!(colormap.color(-1.0, Vec3::ZERO, Vec3::ONE), low);
+            assert_eq!(colormap.color(f32::NAN, Vec3::ZERO, Vec3::ONE), low);
+        }
+        assert_eq!(Colormap::Classic.color(0.25, Vec3::ZERO, Vec3::ONE), Vec3::splat(0.25));
+
+        let mut colormap = Colormap::Classic;
+        for _ in Colormap::value_variants() {
+            colormap = colormap.next();
+        }
+        assert_eq!(colormap, Colormap::Classic);
+    }
+}
@@ -0,0 +1,239 @@
+use bevy::camera::Viewport;
+use bevy::color::palettes::css::GOLD;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::window::PrimaryWindow;
+use clap::ValueEnum;
+
+use crate::args::Args;
+use crate::camera_controls::ScreenSized;
+use crate::components::{MainSimulation, TankOffset};
+use crate::neighbor_search::NeighborSearchKind;
+use crate::sim_struct::Simulation;
+use crate::solver::SolverKind;
+
+/// Where the comparison tank is drawn, in world coordinates, far enough from the main tank that each camera only
+/// sees its own.
+const WORLD_OFFSET: Vec2 = Vec2::new(100_000.0, 0.0);
+
+/// The A/B comparison mode (`--compare`): a second simulation with some of the parameters changed, run side by side
+/// with the main one in the right half of the window.
+#[derive(Resource)]
+pub struct Comparison {
+    pub args: Args,
+    /// The parameters that differ from the main simulation, as given on the command line.
+    pub description: String,
+}
+
+impl Comparison {
+    /// Parses `key=value,key=value,...`. Any parameter that isn't given is taken from the main simulation's arguments.
+    pub fn parse(spec: &str, base: &Args) -> Result<Self, String> {
+        let mut args = base.clone();
+        for param in spec.split(',').map(str::trim).filter(|param| !param.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| format!("Comparison parameter '{param}' should be key=value"))?;
+            let bad_value = |e: &dyn std::fmt::Display| format!("Comparison parameter '{param}': {e}");
+            match key.trim() {
+                "solver" => args.solver = SolverKind::from_str(value, true).map_err(|e| bad_value(&e))?,
+                "neighbor_search" => {
+                    args.neighbor_search = NeighborSearchKind::from_str(value, true).map_err(|e| bad_value(&e))?
+                }
+                "pbf_iterations" => args.pbf_iterations = value.parse().map_err(|e| bad_value(&e))?,
+                "pcisph_max_iterations" => args.pcisph_max_iterations = value.parse().map_err(|e| bad_value(&e))?,
+                "pcisph_tolerance" => args.pcisph_tolerance = value.parse().map_err(|e| bad_value(&e))?,
+                "substeps" => args.substeps = value.parse().map_err(|e| bad_value(&e))?,
+                "cfl" => args.cfl = value.parse().map_err(|e| bad_value(&e))?,
+                "smoothing_radius" => args.smoothing_radius = value.parse().map_err(|e| bad_value(&e))?,
+                "gravity" => args.gravity = Some(value.parse().map_err(|e| bad_value(&e))?),
+                "pressure_multiplier" => args.pressure_multiplier = Some(value.parse().map_err(|e| bad_value(&e))?),
+                "viscosity_strength" => args.viscosity_strength = value.parse().map_err(|e| bad_value(&e))?,
+                "collision_damping" => args.collision_damping = value.parse().map_err(|e| bad_value(&e))?,
+                other => return Err(format!("Unknown comparison parameter '{other}'")),
+            }
+        }
+        // Both simulations place their particles identically.
+        args.deterministic = true;
+
+        Ok(Comparison {
+            args,
+            description: spec.trim().into(),
+        })
+    }
+
+    /// The width of each tank: half of the window, when comparing.
+    pub fn tank_width(comparison: Option<&Self>, window_width: f32) -> f32 {
+        if comparison.is_some() { window_width / 2.0 } else { window_width }
+    }
+}
+
+/// Marks the second Simulation entity of the comparison mode.
+#[derive(Component)]
+pub struct ComparisonTank;
+
+/// The camera showing the comparison tank. The systems that map the cursor to the world use the main camera.
+#[derive(Component)]
+pub struct ComparisonCamera;
+
+/// Labels a tank with the parameters that it's running with.
+#[derive(Component)]
+pub struct ComparisonLabel {
+    /// Where the tank is drawn, in world coordinates.
+    offset: Vec2,
+}
+
+impl Simulation {
+    /// Copies the mouse interaction and the direction of the gravity of the main simulation, and resets the particles
+    /// when it does, so that both simulations get the same input. Pausing applies to all the simulations anyway.
+    fn mirror_input(&mut self, main: &Simulation) {
+        self.interaction_input_point = main.interaction_input_point;
+        self.interaction_mode = main.interaction_mode;
+        self.interaction_input_strength = main.interaction_input_strength;
+        self.interaction_input_radius = main.interaction_input_radius;
+        self.pinch_point = main.pinch_point;
+        self.cursor_wake = main.cursor_wake;
+        self.gravity_angle = main.gravity_angle;
+        self.point_gravity = main.point_gravity;
+        self.gravity_center = main.gravity_center;
+        self.debug.color_field = main.debug.color_field;
+        self.debug.colormap = main.debug.colormap;
+        self.debug.show_arrows = main.debug.show_arrows;
+        if main.sim_time < self.sim_time {
+            self.place_particles();
+        }
+    }
+}
+
+pub fn spawn_comparison(commands: &mut Commands, tank_size: Vec2, comparison: &Comparison) {
+    let mut sim = Simulation::new(tank_size.x, tank_size.y, &comparison.args);
+    info!("Comparing with {}", comparison.description);
+
+    let entity = commands.spawn((TankOffset(WORLD_OFFSET), ComparisonTank)).id();
+    sim.spawn_particles(commands, entity);
+    commands.entity(entity).insert(sim);
+
+    // Drawn before the main camera, so that the main camera is the one that the UI goes to.
+    commands.spawn((
+        Camera2d,
+        Camera { order: -1, ..default() },
+        Transform::from_translation(WORLD_OFFSET.extend(0.0)),
+        ComparisonCamera,
+    ));
+
+    for (text, offset) in [
+        ("A".to_string(), Vec2::ZERO),
+        (format!("B: {}", comparison.description), WORLD_OFFSET),
+    ] {
+        commands.spawn((
+            Text2d::new(text),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(GOLD.into()),
+            Anchor::TOP_CENTER,
+            ScreenSized,
+            ComparisonLabel { offset },
+        ));
+    }
+}
+
+/// Gives the comparison simulation the same input as the main one, before the simulations are stepped.
+pub fn mirror_main_input(
+    main_sim: Single<&Simulation, With<MainSimulation>>,
+    mut comparisons: Query<&mut Simulation, (With<ComparisonTank>, Without<MainSimulation>)>,
+) {
+    for mut sim in &mut comparisons {
+        sim.mirror_input(&main_sim);
+    }
+}
+
+/// Labels both tanks at their top.
+pub fn label_tanks(
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut labels: Query<(&mut Transform, &ComparisonLabel)>,
+) {
+    let top = Vec2::new(0.0, sim.half_bounds_size.y);
+    for (mut transform, label) in &mut labels {
+        transform.translation = (label.offset + top).extend(2.0);
+    }
+}
+
+/// Splits the window between the main camera (left) and the comparison camera (right), which follows the main
+/// camera's zooming and panning.
+#[allow(clippy::type_complexity)]
+pub fn split_viewports(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut main_cameras: Query<(&mut Camera, &Transform, &Projection), (With<Camera2d>, Without<ComparisonCamera>)>,
+    comparison_camera: Single<(&mut Camera, &mut Transform, &mut Projection), With<ComparisonCamera>>,
+) {
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let size = window.physical_size();
+    let half_size = UVec2::new(size.x / 2, size.y);
+    if half_size.cmpeq(UVec2::ZERO).any() {
+        return;
+    }
+
+    let (mut camera, mut transform, mut projection) = comparison_camera.into_inner();
+    for (mut main_camera, main_transform, main_projection) in &mut main_cameras {
+        main_camera.viewport = Some(Viewport {
+            physical_position: UVec2::ZERO,
+            physical_size: half_size,
+            ..default()
+        });
+        transform.translation = main_transform.translation + WORLD_OFFSET.extend(0.0);
+        projection.clone_from(main_projection);
+    }
+    camera.viewport = Some(Viewport {
+        physical_position: UVec2::new(half_size.x, 0),
+        physical_size: half_size,
+        ..default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::Comparison;
+    use crate::args::Args;
+    use crate::neighbor_search::NeighborSearchKind;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn both_simulations_start_from_the_same_state() {
+        let base = Args {
+            num_particles: 200,
+            ..Args::default()
+        };
+        let comparison = Comparison::parse("neighbor_search=hash, pressure_multiplier=500000", &base).unwrap();
+        assert_eq!(comparison.args.neighbor_search, NeighborSearchKind::Hash);
+        assert_eq!(comparison.args.pressure_multiplier, Some(500000));
+        assert!(Comparison::parse("neighbor_search=octree", &base).is_err());
+        assert!(Comparison::parse("colour=red", &base).is_err());
+
+        let mut main_sim = Simulation::new(
+            200.0,
+            400.0,
+            &Args {
+                deterministic: true,
+                ..base
+            },
+        );
+        let mut sim = Simulation::new(200.0, 400.0, &comparison.args);
+        main_sim.place_particles();
+        sim.place_particles();
+        assert_eq!(main_sim.positions(), sim.positions());
+
+        // The comparison follows the main simulation's input, and its resets.
+        main_sim.step_n(1.0 / 60.0, 10);
+        sim.step_n(1.0 / 60.0, 10);
+        main_sim.place_particles();
+        main_sim.interaction_input_point = Some(Vec2::new(10.0, 20.0));
+        sim.mirror_input(&main_sim);
+        assert_eq!(sim.interaction_input_point, Some(Vec2::new(10.0, 20.0)));
+        assert_eq!(main_sim.positions(), sim.positions());
+    }
+}
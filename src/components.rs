@@ -3,15 +3,46 @@ use std::collections::BTreeMap;
 use bevy::prelude::*;
 
 use crate::args::Args;
+use crate::compare::Comparison;
+use crate::interaction::InteractionModes;
+use crate::key_bindings::KeyBindings;
 use crate::keyboard::KeyboardCommand;
+use crate::layers::LayerSpec;
 use crate::messages::MessageText;
 
-#[derive(Resource)]
-pub struct ArgsResource(pub Args);
+/// The simulation options, which are the same as the command line options of the binary. The default is the binary's
+/// defaults, without parsing any arguments.
+#[derive(Resource, Clone, Default)]
+pub struct SimulationSettings(pub Args);
+
+impl SimulationSettings {
+    /// Checks the options that are only parsed once the plugin is built, so that errors can be reported up front.
+    pub fn new(args: Args) -> Result<Self, String> {
+        InteractionModes::parse(&args.interaction_modes)?;
+        for spec in &args.layers {
+            LayerSpec::parse(spec, &args)?;
+        }
+        if let Some(spec) = &args.compare {
+            Comparison::parse(spec, &args)?;
+        }
+        if let Some(path) = &args.key_bindings {
+            KeyBindings::load_or_create(path)?;
+        }
+        Ok(Self(args))
+    }
+}
 
 #[derive(Component)]
 pub struct FpsText;
 
+/// Displays the number of particles, and the other conserved totals.
+#[derive(Component)]
+pub struct ParticleCountText;
+
+/// Displays the integration error in drop test mode.
+#[derive(Component)]
+pub struct DropTestText;
+
 /// Contains the collection of keyboard commands.
 #[derive(Component)]
 pub struct KeyboardCommands {
@@ -25,8 +56,20 @@ pub struct Notifications {
     pub messages: Vec<MessageText>,
 }
 
-#[derive(Component, Clone, Debug, Default)]
+/// Marks the simulation that the keyboard, the mouse and the UI act on. There can be other Simulation entities, which
+/// are stepped and drawn along with it, but only get its input if something copies it to them (like `--compare`).
+#[derive(Component)]
+pub struct MainSimulation;
+
+/// Where a simulation's tank is drawn in the world, for a Simulation entity that isn't centered on the origin. The
+/// simulation itself always works in coordinates centered on its tank.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct TankOffset(pub Vec2);
+
+#[derive(Component, Clone, Debug)]
 pub struct Particle {
+    /// The Simulation entity that the particle belongs to.
+    pub sim: Entity,
     pub id: usize,
     pub watched: bool,
 }
@@ -0,0 +1,192 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::components::{MainSimulation, Notifications, ParticleCountText};
+use crate::messages::MessageText;
+use crate::sim_struct::Simulation;
+
+/// Keeps track of the totals from the previous frame, to warn when they change unexpectedly.
+#[derive(Resource, Default)]
+pub struct Conservation {
+    num_particles: usize,
+    mass: f32,
+    non_finite: usize,
+    /// The total energy and the simulated time when it was measured, if the energy stats are turned on.
+    energy: Option<(f32, f32)>,
+    /// The number of steps in a row in which the total energy has grown.
+    growing_steps: u32,
+}
+
+impl Conservation {
+    /// Relative change in the total mass that's considered a change rather than rounding.
+    pub const MASS_TOLERANCE: f32 = 1e-3;
+    /// Warn when the total energy has grown for this many steps in a row with nothing adding energy to the fluid.
+    const GROWING_STEPS: u32 = 30;
+}
+
+/// The fluid's totals, in kg, meters (or particle sizes) and seconds.
+#[derive(Debug, Default, PartialEq)]
+pub struct EnergyStats {
+    pub kinetic: f32,
+    /// The potential energy in the uniform gravity (ignoring point gravity), relative to the bottom of the tank.
+    pub potential: f32,
+    pub momentum: Vec2,
+    /// The average density of the particles, in kg/m².
+    pub average_density: f32,
+}
+
+impl Simulation {
+    /// Sums the energy and momentum over the particles.
+    pub fn energy_stats(&self) -> EnergyStats {
+        let unit_mass = self.units.particle_mass(self.target_density);
+        let mass = |i: usize| unit_mass * self.particle_mass(i);
+        let velocity = |i: usize| self.velocities[i] / self.units.pixels_per_unit;
+        // Gravity points away from the way it pulls, so this is the height, times g.
+        let gravity = self.gravity_vector() / self.units.pixels_per_unit;
+        let height = |i: usize| (self.positions[i] + self.half_bounds_size) / self.units.pixels_per_unit;
+
+        EnergyStats {
+            kinetic: self.parallel_sum(|i| 0.5 * mass(i) * velocity(i).length_squared()),
+            potential: self.parallel_sum(|i| mass(i) * gravity.dot(height(i))),
+            momentum: Vec2::new(
+                self.parallel_sum(|i| mass(i) * velocity(i).x),
+                self.parallel_sum(|i| mass(i) * velocity(i).y),
+            ),
+            average_density: self.parallel_sum(|i| self.units.density(self.densities[i], self.target_density))
+                / self.num_particles.max(1) as f32,
+        }
+    }
+
+    /// Whether anything other than the solver is adding energy to the fluid: the mouse, the wind, or heating.
+    fn adding_energy(&self) -> bool {
+        self.interaction_input_point.is_some()
+            || self.grab_point.is_some()
+            || self.cursor_wake.is_some()
+            || self.wind.enabled
+            || self.convection.is_some()
+    }
+}
+
+/// Displays the particle count, total mass and fluid area, and warns when the particle count or mass changes without
+/// the user having asked for it (e.g. a bug in an emitter, or particles being lost to NaNs). With the energy stats
+/// turned on (F6), also displays the energy and momentum, and warns when the energy keeps growing on its own, which
+/// means that the solver is going unstable.
+pub fn update_conservation(
+    mut conservation: ResMut<Conservation>,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    mut count_text: Query<&mut Text, With<ParticleCountText>>,
+    mut messages: Single<&mut Notifications>,
+) {
+    let mass = sim.units.particle_mass(sim.target_density);
+    let total_mass = mass * sim.total_mass();
+    // Each particle occupies 1 / density of the area, in square pixels.
+    let area = sim.parallel_sum(|i| if sim.densities[i] > 0.0 { 1.0 / sim.densities[i] } else { 0.0 })
+        / (sim.units.pixels_per_unit * sim.units.pixels_per_unit);
+    let non_finite = (0..sim.num_particles)
+        .filter(|&i| !sim.positions[i].is_finite() || !sim.velocities[i].is_finite())
+        .count();
+
+    // Target density per square unit, rather than per square pixel.
+    let target_density = sim.target_density * sim.units.pixels_per_unit * sim.units.pixels_per_unit;
+    let mut text = format!(
+        "{} particles\nmass: {total_mass:.3} kg\narea: {area:.1} {}²\ntarget density: {target_density:.3}",
+        sim.num_particles,
+        sim.units.length_name(),
+    );
+    let energy_stats = sim.debug.show_energy.then(|| sim.energy_stats());
+    if let Some(stats) = &energy_stats {
+        text += &format!(
+            "\nkinetic energy: {:.3} J\npotential energy: {:.3} J\nmomentum: ({:.3}, {:.3}) kg·m/s",
+            stats.kinetic, stats.potential, stats.momentum.x, stats.momentum.y,
+        );
+        text += &format!("\naverage density: {:.3} kg/m²", stats.average_density);
+    }
+    for mut count_text in &mut count_text {
+        if **count_text != text {
+            **count_text = text.clone();
+        }
+    }
+
+    let mut warnings = vec![];
+    if conservation.num_particles != 0 && !sim.totals_changed {
+        if sim.num_particles != conservation.num_particles {
+            warnings.push(format!(
+                "Warning: particle count changed from {} to {}",
+                conservation.num_particles, sim.num_particles
+            ));
+        } else if (total_mass - conservation.mass).abs() > conservation.mass * Conservation::MASS_TOLERANCE {
+            warnings.push(format!("Warning: total mass changed from {:.3} to {total_mass:.3} kg", conservation.mass));
+        }
+    }
+    if non_finite > conservation.non_finite {
+        warnings.push(format!("Warning: {non_finite} particles have non-finite positions or velocities"));
+    }
+
+    // Only compare the energy after a step, since the render frames don't line up with them.
+    let energy = energy_stats.map(|stats| (stats.kinetic + stats.potential, sim.sim_time));
+    let mut growing_steps = conservation.growing_steps;
+    match (energy, conservation.energy) {
+        (Some((energy, time)), Some((previous_energy, previous_time))) if time != previous_time => {
+            if energy > previous_energy && !sim.adding_energy() && !sim.totals_changed {
+                growing_steps += 1;
+            } else {
+                growing_steps = 0;
+            }
+            if growing_steps == Conservation::GROWING_STEPS {
+                warnings.push(format!(
+                    "Warning: the energy has grown for {growing_steps} steps in a row, to {energy:.3} J \
+                     (the solver may be unstable)"
+                ));
+            }
+        }
+        (Some(_), Some(_)) => {}
+        _ => growing_steps = 0,
+    }
+    for warning in warnings {
+        warn!(target: "sim::energy", "{warning}");
+        messages.messages.push(MessageText {
+            text: warning,
+            start_time: Instant::now(),
+            duration: Duration::from_secs(3),
+        });
+    }
+
+    sim.totals_changed = false;
+    *conservation = Conservation {
+        num_particles: sim.num_particles,
+        mass: total_mass,
+        non_finite,
+        energy,
+        growing_steps,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn energy_stats_add_up_the_particles() {
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 2,
+                ..Args::default()
+            },
+        );
+        let pixels = sim.units.pixels_per_unit;
+        let mass = sim.units.particle_mass(sim.target_density) * sim.particle_mass(0);
+        sim.positions = vec![-sim.half_bounds_size, -sim.half_bounds_size + Vec2::new(0.0, pixels)];
+        sim.velocities = vec![Vec2::new(pixels, 0.0), Vec2::new(-pixels, 2.0 * pixels)];
+
+        let stats = sim.energy_stats();
+        assert!((stats.kinetic - mass * (0.5 + 2.5)).abs() < 1e-4 * mass, "{stats:?}");
+        assert!((stats.potential - mass * sim.gravity.y / pixels).abs() < 1e-4 * mass, "{stats:?}");
+        assert!((stats.momentum - Vec2::new(0.0, 2.0 * mass)).length() < 1e-4 * mass, "{stats:?}");
+    }
+}
@@ -0,0 +1,418 @@
+use std::fs;
+use std::str::FromStr;
+
+use bevy::asset::RenderAssetUsages;
+use bevy::color::palettes::basic::GRAY;
+use bevy::mesh::PrimitiveTopology;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+use crate::walls::apply_friction;
+
+/// The shape of the tank (`--container`, or a scenario's `container`), inside the window's rectangle. The shape is
+/// scaled to fill as much of the window as it can while keeping its aspect ratio, centered on the origin.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ContainerShape {
+    Circle,
+    Hexagon,
+    /// Two chambers joined by a narrow neck. The fluid starts in the top one.
+    Hourglass,
+    /// A U-shaped tube.
+    UTube,
+    /// A funnel above a basin. The fluid starts in the funnel.
+    Funnel,
+    /// A polygon, given by its vertices in any units, with y pointing up: listed in a scenario, or read from the
+    /// first `<polygon points="...">` element of an SVG file.
+    Polygon(Vec<(f32, f32)>),
+}
+
+impl FromStr for ContainerShape {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        match spec.to_lowercase().as_str() {
+            "circle" => Ok(ContainerShape::Circle),
+            "hexagon" => Ok(ContainerShape::Hexagon),
+            "hourglass" => Ok(ContainerShape::Hourglass),
+            "u-tube" => Ok(ContainerShape::UTube),
+            "funnel" => Ok(ContainerShape::Funnel),
+            _ if spec.ends_with(".svg") => {
+                let svg = fs::read_to_string(spec).map_err(|e| format!("{spec}: {e}"))?;
+                let shape = parse_svg_polygon(&svg)
+                    .map(ContainerShape::Polygon)
+                    .ok_or_else(|| format!("{spec}: no <polygon points=\"...\"> with at least 3 points"))?;
+                shape.validate().map_err(|e| format!("{spec}: {e}"))?;
+                Ok(shape)
+            }
+            _ => Err(format!(
+                "Unknown container '{spec}': expected circle, hexagon, hourglass, u-tube, funnel or a .svg file"
+            )),
+        }
+    }
+}
+
+impl ContainerShape {
+    /// Checks that a polygon has an inside for the particles to be placed in: at least 3 vertices, not all in a line.
+    pub fn validate(&self) -> Result<(), String> {
+        let ContainerShape::Polygon(vertices) = self else {
+            return Ok(());
+        };
+        if vertices.len() < 3 {
+            return Err(format!("the container polygon has {} vertices, expected at least 3", vertices.len()));
+        }
+        // The shoelace formula.
+        let area = vertices
+            .iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum::<f32>()
+            / 2.0;
+        if !area.is_normal() {
+            return Err("the container polygon has no area".into());
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the vertices of the first polygon in an SVG document. SVG's y axis points down, so it's flipped.
+fn parse_svg_polygon(svg: &str) -> Option<Vec<(f32, f32)>> {
+    let polygon = &svg[svg.find("<polygon")?..];
+    let points = &polygon[polygon.find("points=")? + "points=".len()..];
+    let quote = points.chars().next()?;
+    let points = &points[1..];
+    let points = &points[..points.find(quote)?];
+
+    let numbers = points
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let vertices: Vec<(f32, f32)> = numbers.chunks_exact(2).map(|xy| (xy[0], -xy[1])).collect();
+
+    (vertices.len() >= 3).then_some(vertices)
+}
+
+/// The container, as a polygon fitted to the window. The polygon can be concave, but not self-intersecting.
+pub struct Container {
+    pub vertices: Vec<Vec2>,
+    /// Place the particles at the top of the container rather than the bottom.
+    pub fill_from_top: bool,
+}
+
+impl Container {
+    /// Number of sides used to approximate a circle.
+    const CIRCLE_SEGMENTS: usize = 64;
+
+    pub fn new(shape: &ContainerShape, half_bounds_size: Vec2) -> Self {
+        let regular = |sides: usize| {
+            (0..sides)
+                .map(|i| Vec2::from_angle(i as f32 / sides as f32 * std::f32::consts::TAU))
+                .collect::<Vec<_>>()
+        };
+        let vertices = match shape {
+            ContainerShape::Circle => regular(Self::CIRCLE_SEGMENTS),
+            ContainerShape::Hexagon => regular(6),
+            ContainerShape::Hourglass => vec![
+                Vec2::new(-1.0, -1.0),
+                Vec2::new(1.0, -1.0),
+                Vec2::new(1.0, -0.8),
+                Vec2::new(0.1, -0.05),
+                Vec2::new(0.1, 0.05),
+                Vec2::new(1.0, 0.8),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(-1.0, 1.0),
+                Vec2::new(-1.0, 0.8),
+                Vec2::new(-0.1, 0.05),
+                Vec2::new(-0.1, -0.05),
+                Vec2::new(-1.0, -0.8),
+            ],
+            ContainerShape::UTube => vec![
+                Vec2::new(-1.0, -1.0),
+                Vec2::new(1.0, -1.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(0.5, 1.0),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(-0.5, 1.0),
+                Vec2::new(-1.0, 1.0),
+            ],
+            ContainerShape::Funnel => vec![
+                Vec2::new(-1.0, -1.0),
+                Vec2::new(1.0, -1.0),
+                Vec2::new(1.0, -0.4),
+                Vec2::new(0.08, -0.4),
+                Vec2::new(0.08, 0.0),
+                Vec2::new(1.0, 0.9),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(-1.0, 1.0),
+                Vec2::new(-1.0, 0.9),
+                Vec2::new(-0.08, 0.0),
+                Vec2::new(-0.08, -0.4),
+                Vec2::new(-1.0, -0.4),
+            ],
+            ContainerShape::Polygon(vertices) => vertices.iter().map(|&(x, y)| Vec2::new(x, y)).collect(),
+        };
+
+        // Center the shape on the origin, and scale it to fit the window.
+        let min = vertices.iter().copied().reduce(Vec2::min).unwrap_or_default();
+        let max = vertices.iter().copied().reduce(Vec2::max).unwrap_or_default();
+        let center = (min + max) / 2.0;
+        let scale = (half_bounds_size * 2.0 / (max - min).max(Vec2::splat(f32::EPSILON))).min_element();
+
+        Container {
+            vertices: vertices.iter().map(|&v| (v - center) * scale).collect(),
+            fill_from_top: matches!(shape, ContainerShape::Hourglass | ContainerShape::Funnel),
+        }
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(&a, &b)| (a, b))
+    }
+
+    /// Even-odd point-in-polygon test.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.edges()
+            .filter(|(a, b)| (a.y > point.y) != (b.y > point.y))
+            .filter(|(a, b)| point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x))
+            .count()
+            % 2
+            == 1
+    }
+
+    /// The point on the boundary closest to `point`.
+    fn closest_boundary_point(&self, point: Vec2) -> Vec2 {
+        self.edges()
+            .map(|(a, b)| {
+                let edge = b - a;
+                let t = ((point - a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+                a + edge * t
+            })
+            .min_by(|p, q| p.distance_squared(point).total_cmp(&q.distance_squared(point)))
+            .unwrap_or(point)
+    }
+
+    /// The distance from a point to the boundary: negative inside the container, and positive outside.
+    pub fn signed_distance(&self, point: Vec2) -> f32 {
+        let distance = self.closest_boundary_point(point).distance(point);
+        if self.contains(point) { -distance } else { distance }
+    }
+
+    /// The distance from a point inside the container to its boundary, and the inward normal of the boundary there.
+    pub fn closest_wall(&self, point: Vec2) -> (f32, Vec2) {
+        let boundary_point = self.closest_boundary_point(point);
+        let normal = (point - boundary_point).normalize_or(-point.normalize_or(Vec2::Y));
+        (-self.signed_distance(point), normal)
+    }
+
+    /// Moves a position outside the container back onto its boundary, and reflects the velocity off the boundary.
+    pub fn resolve_collision(
+        &self,
+        position: Vec2,
+        velocity: Vec2,
+        collision_damping: f32,
+        friction: f32,
+    ) -> (Vec2, Vec2) {
+        // Most particles are inside, so the edges are only searched for the closest one when the cheaper test fails.
+        if self.contains(position) {
+            return (position, velocity);
+        }
+        let boundary_point = self.closest_boundary_point(position);
+        if boundary_point == position {
+            return (position, velocity);
+        }
+
+        // The inward normal is the direction of the gradient of the signed distance, i.e. toward the closest point.
+        let normal = (boundary_point - position).normalize_or(-position.normalize_or(Vec2::Y));
+        let normal_speed = velocity.dot(normal);
+        let velocity_change =
+            if normal_speed < 0.0 { -normal * normal_speed * (1.0 + collision_damping) } else { Vec2::ZERO };
+        let velocity = apply_friction(velocity + velocity_change, normal, velocity_change, friction);
+
+        (boundary_point, velocity)
+    }
+
+    /// A mesh covering the area outside the container, out to `half_size` from the origin, for dimming it.
+    /// The area is split into horizontal bands, and each band is covered by rectangles between the points where the
+    /// container's edges cross it, so this works for concave shapes too.
+    pub fn outside_mesh(&self, half_size: Vec2) -> Mesh {
+        const BAND_HEIGHT: f32 = 2.0;
+        let mut positions: Vec<[f32; 3]> = vec![];
+        let mut rect = |min: Vec2, max: Vec2| {
+            for v in [min, Vec2::new(max.x, min.y), max, min, max, Vec2::new(min.x, max.y)] {
+                positions.push([v.x, v.y, 0.0]);
+            }
+        };
+
+        let (min, max) = self.bounds();
+        rect(-half_size, Vec2::new(half_size.x, min.y));
+        rect(Vec2::new(-half_size.x, max.y), half_size);
+        let bands = ((max.y - min.y) / BAND_HEIGHT).ceil() as usize;
+        for band in 0..bands {
+            let bottom = min.y + band as f32 * BAND_HEIGHT;
+            let top = (bottom + BAND_HEIGHT).min(max.y);
+            let y = (bottom + top) / 2.0;
+
+            let mut crossings: Vec<f32> = self
+                .edges()
+                .filter(|(a, b)| (a.y > y) != (b.y > y))
+                .map(|(a, b)| a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x))
+                .collect();
+            crossings.sort_by(f32::total_cmp);
+
+            // Outside the container from the left edge to the first crossing, between every other pair of
+            // crossings, and from the last crossing to the right edge.
+            let xs: Vec<f32> = std::iter::once(-half_size.x)
+                .chain(crossings)
+                .chain(std::iter::once(half_size.x))
+                .collect();
+            for pair in xs.chunks_exact(2) {
+                rect(Vec2::new(pair[0], bottom), Vec2::new(pair[1], top));
+            }
+        }
+
+        Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    }
+
+    fn bounds(&self) -> (Vec2, Vec2) {
+        let min = self.vertices.iter().copied().reduce(Vec2::min).unwrap_or_default();
+        let max = self.vertices.iter().copied().reduce(Vec2::max).unwrap_or_default();
+        (min, max)
+    }
+
+    /// The points of a lattice with the given spacing that are inside the container, from the bottom up (or from the
+    /// top down, if `fill_from_top` is set).
+    pub fn lattice(&self, spacing: f32) -> impl Iterator<Item = Vec2> + '_ {
+        let (min, max) = self.bounds();
+        let cells = ((max - min) / spacing).ceil().as_uvec2();
+
+        (0..cells.y)
+            .map(move |row| if self.fill_from_top { cells.y - 1 - row } else { row })
+            .flat_map(move |row| (0..cells.x).map(move |col| min + (Vec2::new(col as f32, row as f32) + 0.5) * spacing))
+            .filter(|&point| self.contains(point))
+    }
+}
+
+impl Simulation {
+    /// Fits the container to the tank: the scenario's, if it has one, and otherwise the one from --container.
+    pub fn update_container(&mut self) {
+        let shape = self
+            .scenario
+            .as_ref()
+            .and_then(|scenario| scenario.container.as_ref())
+            .or(self.container_shape.as_ref());
+        self.container = shape.map(|shape| Container::new(shape, self.half_bounds_size));
+    }
+}
+
+/// Dims the part of the window outside the container.
+#[derive(Component)]
+pub struct ContainerOverlay {
+    /// The container vertices that the mesh was built for, empty without a container.
+    vertices: Vec<Vec2>,
+}
+
+/// The overlay's mesh, or the default handle, which draws nothing, without a container.
+fn overlay_mesh(sim: &Simulation, meshes: &mut Assets<Mesh>) -> Handle<Mesh> {
+    sim.container
+        .as_ref()
+        .map(|container| meshes.add(container.outside_mesh(sim.half_bounds_size * 2.0)))
+        .unwrap_or_default()
+}
+
+/// Spawns the overlay even without a container, since a scenario can add one later.
+pub fn spawn_container_overlay(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    sim: &Simulation,
+) {
+    commands.spawn((
+        Mesh2d(overlay_mesh(sim, meshes)),
+        MeshMaterial2d(materials.add(Color::srgba(0.0, 0.0, 0.0, 0.6))),
+        Transform::from_xyz(0.0, 0.0, 1.0),
+        ContainerOverlay {
+            vertices: sim
+                .container
+                .as_ref()
+                .map(|container| container.vertices.clone())
+                .unwrap_or_default(),
+        },
+    ));
+}
+
+/// Outlines the container, and rebuilds the overlay when the container changes (i.e. when the window is resized, or
+/// a scenario with a different container is loaded).
+pub fn update_container_overlay(
+    mut gizmos: Gizmos,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    overlay: Single<(&mut Mesh2d, &mut ContainerOverlay)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let vertices: &[Vec2] = sim.container.as_ref().map_or(&[], |container| &container.vertices);
+    if let Some(&first) = vertices.first() {
+        gizmos.linestrip_2d(vertices.iter().copied().chain([first]), GRAY);
+    }
+
+    let (mut mesh, mut overlay) = overlay.into_inner();
+    if overlay.vertices != vertices {
+        mesh.0 = overlay_mesh(&sim, &mut meshes);
+        overlay.vertices = vertices.to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_polygon_is_parsed_and_flipped() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><polygon points="0,0 10,0 5, 8" fill="none"/></svg>"#;
+
+        assert_eq!(parse_svg_polygon(svg), Some(vec![(0.0, 0.0), (10.0, 0.0), (5.0, -8.0)]));
+    }
+
+    #[test]
+    fn polygons_without_an_inside_are_rejected() {
+        assert!(
+            ContainerShape::Polygon(vec![(0.0, 0.0), (10.0, 0.0), (5.0, 8.0)])
+                .validate()
+                .is_ok()
+        );
+        assert!(ContainerShape::Polygon(vec![]).validate().is_err());
+        assert!(
+            ContainerShape::Polygon(vec![(0.0, 0.0), (10.0, 0.0)])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            ContainerShape::Polygon(vec![(0.0, 0.0), (5.0, 5.0), (10.0, 10.0)])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn signed_distance_is_negative_only_inside_concave_shapes() {
+        let container = Container::new(&ContainerShape::UTube, Vec2::new(100.0, 100.0));
+
+        // The bottom of the U, one of its arms, and the gap between the arms.
+        assert!(container.signed_distance(Vec2::new(0.0, -90.0)) < 0.0);
+        assert!(container.signed_distance(Vec2::new(-75.0, 50.0)) < 0.0);
+        assert!((container.signed_distance(Vec2::new(0.0, 50.0)) - 50.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn collisions_push_particles_back_inside() {
+        let container = Container::new(&ContainerShape::Hexagon, Vec2::new(100.0, 100.0));
+        let (position, velocity) = container.resolve_collision(Vec2::new(0.0, 150.0), Vec2::new(0.0, 10.0), 0.5, 0.0);
+
+        assert!(container.contains(position * 0.999));
+        assert!(velocity.y < 0.0);
+    }
+}
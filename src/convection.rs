@@ -0,0 +1,99 @@
+use bevy::log::info_span;
+use bevy::math::Vec2;
+use rayon::prelude::*;
+
+use crate::sim_struct::Simulation;
+
+/// The convection demo (`--convection`): every particle carries a temperature, a hot source at the bottom left heats
+/// the particles that pass over it, and a cold source on the right cools them. Warm particles are buoyant and cold
+/// ones sink, and heat diffuses between neighbors, so the fluid keeps turning over.
+///
+/// Temperatures are relative to the ambient temperature: the hot source is at +1 and the cold one at -1.
+pub struct Convection {
+    pub temperatures: Vec<f32>,
+    /// Upward acceleration of a particle at temperature 1, as a fraction of gravity.
+    pub expansion: f32,
+    /// How quickly heat spreads between neighboring particles.
+    pub diffusion: f32,
+    /// Fraction of the difference from the source temperature that's removed per second.
+    pub source_rate: f32,
+}
+
+impl Convection {
+    /// The source positions, as fractions of the half bounds size.
+    const HOT_SOURCE: Vec2 = Vec2::new(-0.5, -1.0);
+    const COLD_SOURCE: Vec2 = Vec2::new(0.6, -0.4);
+    /// The source radius, as a fraction of the smaller half bounds dimension.
+    const SOURCE_RADIUS: f32 = 0.25;
+
+    pub fn new(num_particles: usize) -> Self {
+        Convection {
+            temperatures: vec![0.0; num_particles],
+            expansion: 0.5,
+            diffusion: 2.0,
+            source_rate: 4.0,
+        }
+    }
+
+    /// The centers, radius and temperature of the hot and cold sources.
+    pub fn sources(half_bounds_size: Vec2) -> [(Vec2, f32, f32); 2] {
+        let radius = half_bounds_size.min_element() * Self::SOURCE_RADIUS;
+        [
+            (Self::HOT_SOURCE * half_bounds_size, radius, 1.0),
+            (Self::COLD_SOURCE * half_bounds_size, radius, -1.0),
+        ]
+    }
+}
+
+impl Simulation {
+    /// Heats or cools the particles at the sources, and diffuses the heat between neighbors.
+    pub fn update_temperatures(&mut self, delta: f32) {
+        let Some(convection) = &self.convection else {
+            return;
+        };
+        let _span = info_span!("update_temperatures").entered();
+        let sources = Convection::sources(self.half_bounds_size);
+        let delta = delta * self.speed;
+
+        let temperatures = (0..self.num_particles)
+            .into_par_iter()
+            .map(|i| {
+                let position = self.positions[i];
+                let temperature = convection.temperatures[i];
+
+                let mut exchange = 0.0;
+                for neighbor_id in self.neighbor_search.candidates(position) {
+                    let distance = self.positions[neighbor_id].distance(position);
+                    if neighbor_id != i && distance < self.smoothing_radius {
+                        exchange +=
+                            (convection.temperatures[neighbor_id] - temperature) * self.viscosity_kernel(distance);
+                    }
+                }
+                let mut temperature = temperature + exchange * convection.diffusion * delta;
+
+                for (center, radius, source_temperature) in sources {
+                    if position.distance(center) < radius {
+                        temperature += (source_temperature - temperature) * (convection.source_rate * delta).min(1.0);
+                    }
+                }
+                temperature.clamp(-1.0, 1.0)
+            })
+            .collect();
+
+        if let Some(convection) = &mut self.convection {
+            convection.temperatures = temperatures;
+        }
+    }
+
+    /// The buoyancy of a particle: warm particles are pushed up, and cold ones down.
+    pub fn buoyancy_force(&self, particle_id: usize) -> Vec2 {
+        match &self.convection {
+            Some(convection) => {
+                self.gravity_at(self.positions[particle_id])
+                    * convection.expansion
+                    * convection.temperatures[particle_id]
+            }
+            None => Vec2::ZERO,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sim_struct::Simulation;
+use crate::warm_start::SettledState;
+
+/// The state from before the current step, kept between steps so that its buffers are reused rather than
+/// allocated for every step.
+#[derive(Default)]
+pub struct CrashDump {
+    before: SettledState,
+}
+
+impl CrashDump {
+    /// Steps the simulation, and if the step panics, dumps the state from before the step so that the crash can be
+    /// reproduced with --warm-start, and then lets the panic continue.
+    pub fn update_or_dump(&mut self, sim: &mut Simulation, delta: f32) {
+        // The step modifies the particles in place, so keep the state from before it. This costs a copy of the
+        // positions and velocities per step, which is small next to the step itself.
+        self.before.copy_from(sim);
+        let frame = sim.debug.current_frame;
+
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| sim.update_particles(delta))) {
+            match write_dump(&self.before, sim, frame) {
+                Ok(reproduction) => eprintln!("{reproduction}"),
+                Err(e) => eprintln!("The simulation panicked, and the crash dump couldn't be written: {e}"),
+            }
+            panic::resume_unwind(panic);
+        }
+    }
+}
+
+/// Writes the particle state and the parameters, and returns instructions for reproducing the crash.
+fn write_dump(state: &SettledState, sim: &Simulation, frame: u32) -> std::io::Result<String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let state_path = PathBuf::from(format!("crash-{timestamp}.bin"));
+    let params_path = PathBuf::from(format!("crash-{timestamp}.txt"));
+
+    // Replay with the original arguments, starting from the dumped state.
+    let mut args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--warm-start") {
+        args.drain(i..(i + 2).min(args.len()));
+    }
+    args.retain(|arg| !arg.starts_with("--warm-start="));
+    args.push("--warm-start".into());
+    args.push(state_path.display().to_string());
+    let command = args.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" ");
+
+    state.save(&state_path)?;
+    fs::write(&params_path, format!("Panicked in frame {frame}\nCommand: {command}\n\n{sim:?}"))?;
+
+    Ok(format!(
+        "The simulation panicked in frame {frame}. The state before the failing step was saved to {} and the \
+         parameters to {}.\nTo reproduce (if the particle count or parameters were changed at runtime, adjust the \
+         arguments to match {}):\n    {command}",
+        state_path.display(),
+        params_path.display(),
+        params_path.display(),
+    ))
+}
+
+/// Quotes an argument for a POSIX shell, if it has any characters that the shell would interpret.
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_are_quoted_only_when_needed() {
+        assert_eq!(shell_quote("--scenario=scenarios/dam-break.ron"), "--scenario=scenarios/dam-break.ron");
+        assert_eq!(shell_quote("my scenes/a.ron"), "'my scenes/a.ron'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
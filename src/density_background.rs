@@ -0,0 +1,160 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::image::ImageSampler;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use rayon::prelude::*;
+
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+use crate::update::{COLD, HOT};
+
+/// A texture of the continuous density field, drawn behind the particles, so that pressure waves can be seen moving
+/// through the bulk of the fluid. The field is sampled on a coarse grid and upsampled bilinearly by the sampler.
+#[derive(Resource)]
+pub struct DensityBackground {
+    /// Size of the grid cells, in pixels.
+    pub cell_size: f32,
+    image: Option<Handle<Image>>,
+    sprite: Option<Entity>,
+}
+
+/// Marks the sprite showing the density field.
+#[derive(Component)]
+pub struct DensityBackgroundSprite;
+
+impl DensityBackground {
+    pub fn new(cell_size: f32) -> Self {
+        DensityBackground {
+            cell_size: cell_size.max(1.0),
+            image: None,
+            sprite: None,
+        }
+    }
+}
+
+impl Simulation {
+    /// Samples the density at the center of each cell of a grid over the tank, from the top row down, divided by twice
+    /// the target density, so that 0.5 is the target.
+    pub fn density_grid(&self, cell_size: f32) -> (UVec2, Vec<f32>) {
+        let size = (self.half_bounds_size * 2.0 / cell_size)
+            .ceil()
+            .max(Vec2::ONE)
+            .as_uvec2();
+        let cell = self.half_bounds_size * 2.0 / size.as_vec2();
+        let top_left = Vec2::new(-self.half_bounds_size.x, self.half_bounds_size.y);
+        let scale = 0.5 / self.target_density.max(f32::EPSILON);
+        let values = (0..size.x * size.y)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = (i % size.x, i / size.x);
+                let point = top_left + Vec2::new(x as f32 + 0.5, -(y as f32 + 0.5)) * cell;
+                self.sample_density(point) * scale
+            })
+            .collect();
+        (size, values)
+    }
+}
+
+/// Redraws the density texture every frame while it's turned on (F3), and hides it otherwise.
+pub fn update_density_background(
+    mut commands: Commands,
+    mut background: ResMut<DensityBackground>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut images: ResMut<Assets<Image>>,
+    mut sprite_query: Query<(&mut Sprite, &mut Visibility), With<DensityBackgroundSprite>>,
+) {
+    let sprite = background.sprite.and_then(|entity| sprite_query.get_mut(entity).ok());
+    if !sim.debug.density_background {
+        if let Some((_, mut visibility)) = sprite {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+        return;
+    }
+
+    let (size, values) = sim.density_grid(background.cell_size);
+    // The empty parts of the tank are left transparent, and the color fades in with the density.
+    let data: Vec<u8> = values
+        .iter()
+        .flat_map(|&value| {
+            let rgb = Color::from(LinearRgba::from_vec3(sim.debug.colormap.color(value, COLD, HOT)));
+            let alpha = (value * 2.0).clamp(0.0, 1.0);
+            Srgba::from(rgb.with_alpha(alpha)).to_u8_array()
+        })
+        .collect();
+    let extent = Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    };
+
+    // The image is only recreated when the tank is resized.
+    let existing = background
+        .image
+        .as_ref()
+        .and_then(|handle| images.get_mut(handle))
+        .filter(|image| image.texture_descriptor.size == extent);
+    match existing {
+        Some(image) => image.data = Some(data),
+        None => {
+            let mut image = Image::new(
+                extent,
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default(),
+            );
+            image.sampler = ImageSampler::linear();
+            background.image = Some(images.add(image));
+        }
+    }
+
+    let Some(image) = background.image.clone() else {
+        return;
+    };
+    let custom_size = Some(sim.half_bounds_size * 2.0);
+    match sprite {
+        Some((mut sprite, mut visibility)) => {
+            visibility.set_if_neq(Visibility::Inherited);
+            if sprite.image != image {
+                sprite.image = image;
+            }
+            sprite.custom_size = custom_size;
+        }
+        None => {
+            // Behind the particles, which are at z = 0.
+            let entity = commands
+                .spawn((
+                    Sprite {
+                        image,
+                        custom_size,
+                        ..Default::default()
+                    },
+                    Transform::from_xyz(0.0, 0.0, -1.0),
+                    DensityBackgroundSprite,
+                ))
+                .id();
+            background.sprite = Some(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn density_grid_covers_the_tank() {
+        let mut sim = test_sim(400, 400.0, 400.0, Args::default());
+        sim.step_n(1.0 / 60.0, 60);
+
+        let cell_size = 8.0;
+        let (size, values) = sim.density_grid(cell_size);
+        assert_eq!(size.as_vec2(), (sim.half_bounds_size * 2.0 / cell_size).ceil());
+        assert_eq!(values.len(), (size.x * size.y) as usize);
+        // The fluid settles to the bottom, leaving the top row empty.
+        assert!(values[..size.x as usize].iter().all(|&value| value == 0.0));
+        let bottom_row = &values[values.len() - size.x as usize..];
+        assert!(bottom_row.iter().any(|&value| value > 0.25));
+    }
+}
@@ -0,0 +1,22 @@
+use bevy::prelude::KeyCode;
+
+/// The digit keys from 1 to 9, in order.
+pub const DIGIT_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// The number on a digit key from 1 to 9.
+pub fn key_number(key: KeyCode) -> Option<u32> {
+    DIGIT_KEYS
+        .iter()
+        .position(|&digit| digit == key)
+        .map(|index| index as u32 + 1)
+}
@@ -0,0 +1,59 @@
+use bevy::camera::ScalingMode;
+use bevy::prelude::*;
+
+use crate::compare::{Comparison, ComparisonCamera};
+use crate::components::SimulationSettings;
+
+/// The size of each tank, in world coordinates: the `--domain`, or else the window (or half of it, when comparing),
+/// which the tanks follow when it's resized.
+pub fn tank_size(domain: Option<(u32, u32)>, comparison: Option<&Comparison>, window_size: Vec2) -> Vec2 {
+    match domain {
+        Some((width, height)) => Vec2::new(width as f32, height as f32),
+        None => Vec2::new(Comparison::tank_width(comparison, window_size.x), window_size.y),
+    }
+}
+
+/// With a fixed `--domain`, scales the view so that the whole domain fits in it, leaving bars at the sides or at the
+/// top and bottom. The zoom applies on top of that.
+pub fn fit_domain(
+    settings: Res<SimulationSettings>,
+    mut projections: Query<&mut Projection, (With<Camera2d>, Without<ComparisonCamera>)>,
+) {
+    let Some((width, height)) = settings.0.domain else {
+        return;
+    };
+    for mut projection in &mut projections {
+        // The domain doesn't change, so the scaling mode only needs setting once, e.g. on a newly spawned camera.
+        if let Projection::Orthographic(orthographic) = &*projection
+            && !matches!(orthographic.scaling_mode, ScalingMode::AutoMin { .. })
+            && let Projection::Orthographic(orthographic) = &mut *projection
+        {
+            orthographic.scaling_mode = ScalingMode::AutoMin {
+                min_width: width as f32,
+                min_height: height as f32,
+            };
+        }
+    }
+}
+
+/// World units per screen pixel: the zoom, and the fitting of the domain to the view, if it's fixed.
+pub fn world_per_pixel(camera: &Camera, orthographic: &OrthographicProjection) -> f32 {
+    camera
+        .logical_viewport_size()
+        .filter(|size| size.x > 0.0)
+        .map_or(orthographic.scale, |size| orthographic.area.width() / size.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::tank_size;
+
+    #[test]
+    fn domain_overrides_the_window_size() {
+        let window_size = Vec2::new(1000.0, 600.0);
+        assert_eq!(tank_size(None, None, window_size), window_size);
+        assert_eq!(tank_size(Some((800, 900)), None, window_size), Vec2::new(800.0, 900.0));
+    }
+}
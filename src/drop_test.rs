@@ -0,0 +1,87 @@
+use bevy::color::palettes::basic::{AQUA, RED};
+use bevy::math::Vec2;
+use bevy::prelude::{Gizmos, Query, ResMut, Resource, Single, Text, With};
+
+use crate::components::{DropTestText, MainSimulation};
+use crate::sim_struct::Simulation;
+
+/// Records the trajectory of a single dropped particle (`--drop-test`), so that it can be compared against the
+/// analytic free-fall trajectory. The difference between the two is the integration error of the timestep scheme.
+#[derive(Resource, Default)]
+pub struct DropTest {
+    pub start_position: Vec2,
+    pub start_velocity: Vec2,
+    pub simulated: Vec<Vec2>,
+    pub analytic: Vec<Vec2>,
+    pub last_time: f32,
+    pub landed: bool,
+    pub max_error: f32,
+}
+
+impl DropTest {
+    /// The particle whose trajectory is tracked.
+    const PARTICLE_ID: usize = 0;
+
+    /// Moves the tracked particle to the top of the window, and starts recording from there.
+    fn restart(&mut self, sim: &mut Simulation) {
+        sim.positions[Self::PARTICLE_ID] = Vec2::new(0.0, sim.half_bounds_size.y * 0.9);
+        sim.predicted_positions[Self::PARTICLE_ID] = sim.positions[Self::PARTICLE_ID];
+        *self = DropTest {
+            start_position: sim.positions[Self::PARTICLE_ID],
+            start_velocity: sim.velocities[Self::PARTICLE_ID],
+            ..Default::default()
+        };
+    }
+
+    /// The analytic free-fall position at time t.
+    /// Positions are advanced by `velocity * speed`, so the speed multiplier scales the whole trajectory.
+    fn analytic_position(&self, sim: &Simulation, t: f32) -> Vec2 {
+        self.start_position + (self.start_velocity * t - 0.5 * sim.gravity_vector() * t * t) * sim.speed
+    }
+}
+
+/// Samples the tracked particle and draws both trajectories.
+/// This runs before the simulation step, so that the initial position of the particle is captured.
+pub fn update_drop_test(
+    mut drop_test: ResMut<DropTest>,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    mut gizmos: Gizmos,
+    mut text: Query<&mut Text, With<DropTestText>>,
+) {
+    // The particles have been reset (or this is the first frame).
+    if sim.sim_time < drop_test.last_time || drop_test.simulated.is_empty() {
+        drop_test.restart(&mut sim);
+    }
+
+    if !drop_test.landed && (sim.sim_time > drop_test.last_time || drop_test.simulated.is_empty()) {
+        let simulated = sim.positions[DropTest::PARTICLE_ID];
+        let analytic = drop_test.analytic_position(&sim, sim.sim_time);
+
+        drop_test.last_time = sim.sim_time;
+        drop_test.simulated.push(simulated);
+        drop_test.analytic.push(analytic);
+        drop_test.max_error = drop_test.max_error.max((simulated - analytic).length());
+        // Once the particle reaches the floor, the collision response takes over and the comparison is meaningless.
+        drop_test.landed = simulated.y <= -sim.half_bounds_size.y;
+    }
+
+    gizmos.linestrip_2d(drop_test.analytic.iter().copied(), RED);
+    gizmos.linestrip_2d(drop_test.simulated.iter().copied(), AQUA);
+    if let (Some(&analytic), Some(&simulated)) = (drop_test.analytic.last(), drop_test.simulated.last()) {
+        gizmos.circle_2d(analytic, sim.particle_size, RED);
+        gizmos.circle_2d(simulated, sim.particle_size, AQUA);
+
+        let error = (simulated - analytic).length();
+        let drop_height = (drop_test.start_position - analytic).length().max(f32::EPSILON);
+        for mut text in &mut text {
+            **text = format!(
+                "Drop test{}: t={:.3}s  error={:.3}px ({:.3}% of drop)  max error={:.3}px",
+                if drop_test.landed { " (landed)" } else { "" },
+                drop_test.last_time,
+                error,
+                100.0 * error / drop_height,
+                drop_test.max_error
+            );
+        }
+    }
+}
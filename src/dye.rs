@@ -0,0 +1,195 @@
+use bevy::log::info_span;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use rayon::prelude::*;
+
+use crate::compare::ComparisonCamera;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Hold down while dragging with the left button to inject dye at the cursor.
+pub const DYE_KEY: KeyCode = KeyCode::Quote;
+/// Radius of the dye brush, as a multiple of the smoothing radius.
+const BRUSH_RADIUS: f32 = 0.5;
+/// The colors that the fluid starts with, left half and right half.
+const LEFT: Vec3 = Vec3::new(0.8, 0.05, 0.05);
+const RIGHT: Vec3 = Vec3::new(0.05, 0.15, 0.8);
+/// The color of the particles that haven't been dyed, such as the ones added later.
+pub const UNDYED: Vec3 = Vec3::new(0.7, 0.75, 0.8);
+
+/// Dye carried by the particles (`--dye`), to show how the fluid mixes: the fluid starts out red on the left and blue on
+/// the right, and each stroke of the dye brush injects the next of a few colors. The colors diffuse slightly between
+/// neighbors, like heat in the convection demo, but are otherwise just carried along, so the vortices and the mixing
+/// show up in the particle colors.
+pub struct Dye {
+    /// Each particle's color, in linear RGB.
+    pub colors: Vec<Vec3>,
+    /// The fraction of the difference from the neighbors' average color that a particle takes on per second.
+    pub diffusion: f32,
+    /// The number of strokes of the dye brush so far, which picks the color of the next one.
+    pub strokes: usize,
+}
+
+impl Dye {
+    /// The colors of the dye brush, in turn.
+    const BRUSH_COLORS: [Vec3; 4] = [Vec3::new(0.9, 0.8, 0.05), Vec3::new(0.05, 0.7, 0.1), LEFT, RIGHT];
+
+    pub fn new(num_particles: usize) -> Self {
+        Dye {
+            colors: vec![UNDYED; num_particles],
+            diffusion: 0.2,
+            strokes: 0,
+        }
+    }
+
+    pub fn brush_color(&self) -> Vec3 {
+        Self::BRUSH_COLORS[self.strokes % Self::BRUSH_COLORS.len()]
+    }
+}
+
+impl Simulation {
+    /// Colors the particles left of the middle red and the rest blue.
+    pub fn reset_dye(&mut self) {
+        if let Some(dye) = &mut self.dye {
+            dye.colors = self
+                .positions
+                .iter()
+                .map(|position| if position.x < 0.0 { LEFT } else { RIGHT })
+                .collect();
+        }
+    }
+
+    /// Diffuses the dye between neighbors.
+    pub fn update_dye(&mut self, delta: f32) {
+        let Some(dye) = &self.dye else {
+            return;
+        };
+        let _span = info_span!("update_dye").entered();
+        let delta = delta * self.speed;
+
+        let colors = (0..self.num_particles)
+            .into_par_iter()
+            .map(|i| {
+                let position = self.positions[i];
+                let color = dye.colors[i];
+
+                // Toward the kernel-weighted average of the neighbors' colors, so that the rate doesn't depend on how
+                // many there are.
+                let mut exchange = Vec3::ZERO;
+                let mut total_weight = 0.0;
+                for neighbor_id in self.neighbor_search.candidates(position) {
+                    let distance = self.positions[neighbor_id].distance(position);
+                    if neighbor_id != i && distance < self.smoothing_radius {
+                        let weight = self.viscosity_kernel(distance);
+                        exchange += (dye.colors[neighbor_id] - color) * weight;
+                        total_weight += weight;
+                    }
+                }
+                if total_weight > 0.0 {
+                    exchange /= total_weight;
+                }
+                color + exchange * (dye.diffusion * delta).min(1.0)
+            })
+            .collect();
+
+        if let Some(dye) = &mut self.dye {
+            dye.colors = colors;
+        }
+    }
+
+    pub fn dye_brush_radius(&self) -> f32 {
+        self.smoothing_radius * BRUSH_RADIUS
+    }
+
+    /// Dyes the particles within the brush around the point the given color. Turns the dye on, with the other
+    /// particles undyed, if it isn't already.
+    pub fn inject_dye(&mut self, point: Vec2, color: Vec3) {
+        let radius = self.dye_brush_radius();
+        let dye = self.dye.get_or_insert_with(|| Dye::new(self.num_particles));
+        for neighbor_id in self.neighbor_search.candidates(point) {
+            if self.positions[neighbor_id].distance(point) < radius {
+                dye.colors[neighbor_id] = color;
+            }
+        }
+    }
+}
+
+/// While the dye key is held, dragging with the left button dyes the particles under the cursor, with a new color for
+/// each stroke.
+pub fn brush_dye(
+    mut gizmos: Gizmos,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+) {
+    let cursor_point = windows.single().ok().and_then(|window| {
+        let (camera, camera_transform) = cameras_query.iter().next()?;
+        camera
+            .viewport_to_world_2d(camera_transform, window.cursor_position()?)
+            .ok()
+    });
+    let (true, Some(point)) = (kb.pressed(DYE_KEY), cursor_point) else {
+        return;
+    };
+
+    let color = sim.dye.as_ref().map_or(Dye::BRUSH_COLORS[0], Dye::brush_color);
+    gizmos.circle_2d(point, sim.dye_brush_radius(), Color::linear_rgb(color.x, color.y, color.z));
+    if buttons.pressed(MouseButton::Left) {
+        sim.inject_dye(point, color);
+    }
+    if buttons.just_released(MouseButton::Left)
+        && let Some(dye) = &mut sim.dye
+    {
+        dye.strokes += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LEFT, RIGHT};
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn dye_mixes_slowly_and_can_be_injected() {
+        let mut sim = test_sim(
+            400,
+            400.0,
+            400.0,
+            Args {
+                dye: true,
+                ..Args::default()
+            },
+        );
+        let dye = sim.dye.as_ref().unwrap();
+        for i in 0..sim.num_particles {
+            assert_eq!(dye.colors[i], if sim.positions[i].x < 0.0 { LEFT } else { RIGHT });
+        }
+
+        // Only the particles near the middle mix.
+        sim.step_n(1.0 / 60.0, 30);
+        let dye = sim.dye.as_ref().unwrap();
+        let far_left = (0..sim.num_particles)
+            .min_by(|&a, &b| sim.positions[a].x.total_cmp(&sim.positions[b].x))
+            .unwrap();
+        assert!(dye.colors[far_left].distance(LEFT) < 0.1, "{}", dye.colors[far_left]);
+        assert!(
+            dye.colors
+                .iter()
+                .any(|color| color.distance(LEFT) > 0.1 && color.distance(RIGHT) > 0.1)
+        );
+
+        let color = dye.brush_color();
+        let point = sim.positions[far_left];
+        sim.inject_dye(point, color);
+        assert_eq!(sim.dye.as_ref().unwrap().colors[far_left], color);
+        assert!(
+            sim.positions
+                .iter()
+                .zip(&sim.dye.as_ref().unwrap().colors)
+                .all(|(position, &dyed)| { (position.distance(point) < sim.dye_brush_radius()) == (dyed == color) })
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::components::{MainSimulation, Notifications};
+use crate::messages::MessageText;
+use crate::sim_struct::Simulation;
+
+/// Warns when a large fraction of the particles is pressed up against one of the walls, which usually means that the
+/// target density is too high for the pressure, or gravity is too strong.
+#[derive(Resource, Default)]
+pub struct EdgeWarnings {
+    /// Number of particles within a particle size of the left, right, bottom and top walls, in the last frame.
+    pub pinned: [usize; 4],
+    last_warning: Option<Instant>,
+}
+
+impl EdgeWarnings {
+    /// Warn when more than this fraction of the particles is pinned against a single wall.
+    const MAX_FRACTION: f32 = 0.1;
+    /// Don't repeat the warning more often than this.
+    const INTERVAL: Duration = Duration::from_secs(10);
+    const WALL_NAMES: [&'static str; 4] = ["left", "right", "bottom", "top"];
+}
+
+pub fn warn_pinned_particles(
+    mut edge_warnings: ResMut<EdgeWarnings>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut messages: Single<&mut Notifications>,
+) {
+    let limit = sim.half_bounds_size - sim.particle_size;
+    edge_warnings.pinned = (0..sim.num_particles)
+        .into_par_iter()
+        .map(|i| {
+            let position = sim.positions[i];
+            [
+                position.x < -limit.x,
+                position.x > limit.x,
+                position.y < -limit.y,
+                position.y > limit.y,
+            ]
+            .map(|pinned| pinned as usize)
+        })
+        .reduce(|| [0; 4], |a, b| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]]);
+
+    let max_pinned = (sim.num_particles as f32 * EdgeWarnings::MAX_FRACTION) as usize;
+    let Some(wall) = (0..4).find(|&wall| edge_warnings.pinned[wall] > max_pinned.max(1)) else {
+        return;
+    };
+    if edge_warnings
+        .last_warning
+        .is_some_and(|time| time.elapsed() < EdgeWarnings::INTERVAL)
+    {
+        return;
+    }
+    edge_warnings.last_warning = Some(Instant::now());
+
+    let text = format!(
+        "{} of {} particles are pinned against the {} wall.\nTry more pressure (shift+P) or less gravity (G).",
+        edge_warnings.pinned[wall],
+        sim.num_particles,
+        EdgeWarnings::WALL_NAMES[wall]
+    );
+    warn!(target: "sim::boundary", "{text}");
+    messages.messages.push(MessageText {
+        text,
+        start_time: Instant::now(),
+        duration: Duration::from_secs(4),
+    });
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use bevy::math::Vec2;
+use rayon::prelude::*;
+
+use crate::sim_struct::Simulation;
+
+/// How far a spring can be stretched or squeezed before it yields, as a fraction of its rest length.
+const YIELD_RATIO: f32 = 0.1;
+
+/// Viscoelastic springs between neighboring particles (`--elasticity`), after Clavet, Beaudoin & Poulin, "Particle-based
+/// Viscoelastic Fluid Simulation" (2005). A spring is made between two particles when they come within the smoothing
+/// radius of each other, at their distance then, and pulls them back toward that distance, so the fluid stretches and
+/// wobbles like goo. Stretched or squeezed beyond the yield ratio, the spring's rest length gives way at the rate of
+/// the plasticity, so the goo slowly takes the shape it's pushed into instead of springing back. Springs break when
+/// the particles leave each other's neighborhood.
+#[derive(Default)]
+pub struct Springs {
+    /// The rest length of the spring between each pair of neighboring particles, lower id first.
+    pub rest_lengths: HashMap<(usize, usize), f32>,
+}
+
+impl Simulation {
+    /// The acceleration of a particle from its spring to a neighbor, at the given offset to it, or None if there's no
+    /// spring between them. The particle's springs are averaged (see apply_viscosity_to_particle()), so that the
+    /// stiffness doesn't depend on how many neighbors there are.
+    pub fn spring_acceleration(
+        &self,
+        particle_id: usize,
+        neighbor_id: usize,
+        offset: Vec2,
+        distance: f32,
+    ) -> Option<Vec2> {
+        if self.elasticity == 0.0 {
+            return None;
+        }
+        let pair = (particle_id.min(neighbor_id), particle_id.max(neighbor_id));
+        let rest_length = *self.springs.rest_lengths.get(&pair)?;
+        // Weaker for longer springs, so that the ones about to break don't snap.
+        let strength = self.elasticity * (1.0 - rest_length / self.smoothing_radius);
+        Some(-offset / distance * strength * (rest_length - distance))
+    }
+
+    /// Makes springs between the particles that have become neighbors, breaks the ones between those that have moved
+    /// apart, and lets the rest lengths yield.
+    pub fn update_springs(&mut self, delta: f32) {
+        if self.elasticity == 0.0 {
+            self.springs.rest_lengths.clear();
+            return;
+        }
+
+        let time_step = delta * self.speed;
+        let sim = &*self;
+        let rest_lengths = (0..sim.num_particles)
+            .into_par_iter()
+            .flat_map_iter(|particle_id| {
+                let position = sim.solver_position(particle_id);
+                sim.neighbor_particles(particle_id)
+                    .filter(move |&neighbor_id| neighbor_id > particle_id)
+                    .filter_map(move |neighbor_id| {
+                        let distance = sim.solver_position(neighbor_id).distance(position);
+                        if distance >= sim.smoothing_radius {
+                            return None;
+                        }
+                        let pair = (particle_id, neighbor_id);
+                        let mut rest_length = sim.springs.rest_lengths.get(&pair).copied().unwrap_or(distance);
+                        let tolerance = rest_length * YIELD_RATIO;
+                        if distance > rest_length + tolerance {
+                            rest_length += time_step * sim.plasticity * (distance - rest_length - tolerance);
+                        } else if distance < rest_length - tolerance {
+                            rest_length -= time_step * sim.plasticity * (rest_length - tolerance - distance);
+                        }
+                        (rest_length < sim.smoothing_radius).then_some((pair, rest_length))
+                    })
+            })
+            .collect();
+        self.springs.rest_lengths = rest_lengths;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn springs_pull_back_and_yield() {
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 2,
+                elasticity: 100.0,
+                plasticity: 1.0,
+                viscosity_strength: 0.0,
+                ..Args::default()
+            },
+        );
+        let spacing = sim.smoothing_radius / 2.0;
+        sim.positions = vec![Vec2::ZERO, Vec2::new(spacing, 0.0)];
+        sim.predicted_positions = sim.positions.clone();
+        sim.velocities = vec![Vec2::ZERO; 2];
+        sim.update_regions();
+        sim.update_springs(1.0 / 60.0);
+        assert_eq!(sim.springs.rest_lengths[&(0, 1)], spacing);
+
+        // Stretched well beyond the yield ratio, the spring pulls the particles together, and its rest length grows.
+        sim.positions[1].x = spacing * 1.5;
+        sim.predicted_positions[1].x = spacing * 1.5;
+        sim.update_regions();
+        sim.apply_viscosity(1.0 / 60.0);
+        assert!(sim.velocities[0].x > 0.0 && sim.velocities[1].x < 0.0, "{:?}", sim.velocities);
+        let rest_length = sim.springs.rest_lengths[&(0, 1)];
+        assert!(rest_length > spacing && rest_length < spacing * 1.5, "{rest_length}");
+
+        // Once they're out of each other's neighborhood, the spring breaks.
+        sim.positions[1].x = sim.smoothing_radius * 1.5;
+        sim.predicted_positions[1].x = sim.smoothing_radius * 1.5;
+        sim.update_regions();
+        sim.update_springs(1.0 / 60.0);
+        assert!(sim.springs.rest_lengths.is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::to_world;
+use crate::sim_struct::Simulation;
+
+/// A faucet for a scenario: it pours particles in from a line near the top of the tank, and a drain takes out the ones
+/// that fall below a line near the bottom, so the fluid keeps flowing. Like the rest of a scenario, positions are
+/// fractions of the tank.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Emitter {
+    /// The ends of the nozzle; the same point twice for a thin stream.
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    /// Downward speed of the new particles, in units/s.
+    pub speed: f32,
+    /// How many particles it pours in per second, while there are fewer than `live_particles`.
+    pub rate: f32,
+    pub live_particles: usize,
+    /// The height of the drain, as a fraction of the tank's height: particles below it are removed.
+    pub drain: f32,
+    /// The fraction of a particle that's due to be poured in, carried over to the next step.
+    #[serde(skip)]
+    pending: f32,
+}
+
+impl Simulation {
+    /// Drains the particles below the scenario's drain line, and pours in new ones to make up the live particle count.
+    pub fn update_emitter(&mut self, delta: f32) {
+        let half_size = self.half_bounds_size;
+        let Some(emitter) = self.scenario.as_mut().and_then(|scenario| scenario.emitter.as_mut()) else {
+            return;
+        };
+        let drain = to_world((0.0, emitter.drain), half_size).y;
+        let (from, to) = (to_world(emitter.from, half_size), to_world(emitter.to, half_size));
+        let speed = emitter.speed;
+        emitter.pending += emitter.rate * delta * self.speed;
+        let count = (emitter.pending as usize).min(emitter.live_particles.saturating_sub(self.num_particles));
+        emitter.pending -= emitter.pending.floor();
+
+        self.retain_particles(|position| position.y >= drain);
+        if count == 0 {
+            return;
+        }
+        let first = self.num_particles;
+        let positions: Vec<Vec2> = (first..first + count)
+            .map(|i| {
+                // Spread along the nozzle, and jittered by up to a particle size so that the new ones don't overlap.
+                let jitter = Vec2::new(self.random(i, 3), self.random(i, 4)) - 0.5;
+                from.lerp(to, self.random(i, 2)) + jitter * self.particle_size
+            })
+            .collect();
+        self.insert_particles(&positions, Vec2::new(0.0, -self.units.to_pixels(speed)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::scenario::Scenario;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn faucet_keeps_the_particles_flowing() {
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 200,
+                deterministic: true,
+                ..Args::default()
+            },
+        );
+        let faucet = Scenario::presets().find(|scenario| scenario.name == "Faucet").unwrap();
+        let live_particles = faucet.emitter.as_ref().unwrap().live_particles;
+        sim.load_scenario(faucet);
+
+        sim.step_n(1.0 / 60.0, 120);
+        assert!(sim.num_particles > 200, "{} particles", sim.num_particles);
+        assert!(sim.num_particles <= live_particles);
+        assert_eq!(sim.positions.len(), sim.num_particles);
+    }
+}
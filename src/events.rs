@@ -5,33 +5,115 @@ use bevy::camera::Camera;
 use bevy::input::ButtonInput;
 use bevy::math::Vec2;
 use bevy::prelude::{
-    Entity, GlobalTransform, KeyCode, MessageReader, MessageWriter, MouseButton, Query, Res, Single, Transform, Window,
-    With,
+    Entity, GlobalTransform, KeyCode, Local, MessageReader, MessageWriter, MouseButton, Query, Res, Single, Time,
+    Touches, Transform, Window, With, Without,
 };
 use bevy::window::{PrimaryWindow, WindowResized};
 
+use crate::compare::{Comparison, ComparisonCamera};
 use crate::components::*;
-use crate::messages::MessageText;
+use crate::dye::DYE_KEY;
+use crate::flow_meter::MEASURE_KEY;
+use crate::interaction::{InteractionMode, InteractionModes, Modifiers};
+use crate::key_bindings::KeyBindings;
+use crate::layers::SimLayer;
+use crate::obstacle_paint::PAINT_KEY;
+use crate::particle_brush::{DELETE_KEY, SPAWN_KEY};
 use crate::sim_struct::Simulation;
 
-/// Handles mouse clicks to attract/repel particles.
+/// The longest time between the clicks of a double-click, in seconds.
+const DOUBLE_CLICK_TIME: f32 = 0.3;
+
+/// Handles mouse clicks to attract/repel or grab particles, and the wake of the cursor when no button is pressed.
+#[allow(clippy::too_many_arguments)]
 pub fn handle_mouse_clicks(
     buttons: Res<ButtonInput<MouseButton>>,
-    mut sim: Single<&mut Simulation>,
+    kb: Res<ButtonInput<KeyCode>>,
+    interaction_modes: Res<InteractionModes>,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
     windows: Query<&Window, With<PrimaryWindow>>,
-    cameras_query: Query<(&Camera, &GlobalTransform)>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
     mut messages: Single<&mut Notifications>,
+    mut previous_cursor_point: Local<Option<Vec2>>,
+    mut last_click_time: Local<Option<f32>>,
+    touches: Res<Touches>,
+    time: Res<Time>,
 ) {
     if let Ok(window) = windows.single() {
         sim.interaction_input_point = None;
+        sim.pinch_point = None;
+        sim.cursor_wake = None;
+        if !buttons.any_pressed([MouseButton::Left, MouseButton::Right]) {
+            sim.release_grab();
+        }
+        // In annotation mode, the mouse draws over the simulation instead, the brush keys paint obstacles, particles
+        // or dye, and the measure key places measurement lines.
+        if sim.debug.annotating || kb.any_pressed([PAINT_KEY, SPAWN_KEY, DELETE_KEY, DYE_KEY, MEASURE_KEY]) {
+            return;
+        }
+        let to_world = |viewport_position: Vec2| {
+            let (camera, camera_transform) = cameras_query.iter().next()?;
+            camera.viewport_to_world_2d(camera_transform, viewport_position).ok()
+        };
+        let cursor_point = window.cursor_position().and_then(to_world);
+
+        // Two touches pinch the fluid between them.
+        let touch_points: Vec<Vec2> = touches.iter().filter_map(|touch| to_world(touch.position())).collect();
+        if let [first, second, ..] = touch_points[..] {
+            sim.interaction_input_strength = sim.interaction_input_strength.abs();
+            sim.interaction_input_point = Some(first);
+            sim.pinch_point = Some(second);
+            sim.interaction_mode = InteractionMode::Normal;
+            return;
+        }
+        let previous_point = std::mem::replace(&mut *previous_cursor_point, cursor_point);
+
+        // The grabbed particles follow the cursor until the button is released, whatever the modifiers.
+        if !sim.grab_constraints.is_empty() {
+            if let (Some(point), Some(previous_point)) = (cursor_point, previous_point) {
+                sim.drag_grabbed(point, (point - previous_point) / time.delta_secs().max(f32::EPSILON));
+            }
+            return;
+        }
+
+        // The modifier keys select the interaction mode. Inspection is handled by the particle inspector.
+        let mode = interaction_modes.mode(Modifiers::pressed(&kb));
+        if mode == InteractionMode::Inspect {
+            return;
+        }
+
+        // A double-click sets off an explosion under the cursor, as does any click in the explode mode.
+        if let Some(point) = cursor_point
+            && buttons.any_just_pressed([MouseButton::Left, MouseButton::Right])
+        {
+            let now = time.elapsed_secs();
+            let left_click = buttons.just_pressed(MouseButton::Left);
+            let double_click = left_click && last_click_time.is_some_and(|last| now - last < DOUBLE_CLICK_TIME);
+            *last_click_time = (left_click && !double_click).then_some(now);
+            if double_click || mode == InteractionMode::Explode {
+                sim.explode(point);
+            }
+        }
+        if mode == InteractionMode::Explode {
+            return;
+        }
 
         let left_click = buttons.pressed(MouseButton::Left);
         let right_click = buttons.pressed(MouseButton::Right);
-        if (left_click || right_click)
-            && let Some(cursor_position) = window.cursor_position()
-            && let Some((camera, camera_transform)) = cameras_query.iter().next()
-            && let Ok(point) = camera.viewport_to_world_2d(camera_transform, cursor_position)
-        {
+        if !left_click && !right_click {
+            if sim.cursor_wake_strength > 0.0
+                && let (Some(point), Some(previous_point)) = (cursor_point, previous_point)
+                && point != previous_point
+            {
+                sim.cursor_wake = Some((point, (point - previous_point) / time.delta_secs().max(f32::EPSILON)));
+            }
+        } else if mode == InteractionMode::Grab {
+            if let Some(point) = cursor_point
+                && buttons.any_just_pressed([MouseButton::Left, MouseButton::Right])
+            {
+                sim.grab_particles(point);
+            }
+        } else if let Some(point) = cursor_point {
             // Clear the welcome message, if it's still being displayed.
             if let Some(msg) = messages.messages.first()
                 && msg.duration == Duration::MAX
@@ -41,6 +123,8 @@ pub fn handle_mouse_clicks(
 
             sim.interaction_input_strength = sim.interaction_input_strength.abs() * if left_click { 1.0 } else { -1.0 };
             sim.interaction_input_point = Some(point);
+            sim.pinch_point = sim.pinch_anchor;
+            sim.interaction_mode = mode;
         }
     }
 }
@@ -50,11 +134,12 @@ pub fn handle_mouse_clicks(
 pub fn handle_keypress(
     kb: Res<ButtonInput<KeyCode>>,
     mut app_exit: MessageWriter<AppExit>,
-    mut sim: Single<&mut Simulation>,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
     mut particle_query: Query<(&mut Transform, &mut Particle)>,
     windows: Query<&Window, With<PrimaryWindow>>,
-    cameras_query: Query<(&Camera, &GlobalTransform)>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
     mut kb_cmds: Single<&mut KeyboardCommands>,
+    bindings: Res<KeyBindings>,
     mut messages: Single<&mut Notifications>,
 ) {
     if let Ok(window) = windows.single() {
@@ -81,34 +166,11 @@ pub fn handle_keypress(
             }
 
             match key {
-                // Esc / Q: quit the app
-                KeyCode::Escape | KeyCode::KeyQ => {
+                // Esc / Q (or its rebinding): quit the app
+                key if *key == KeyCode::Escape || *key == bindings.quit_key() => {
                     app_exit.write(AppExit::Success);
                 }
 
-                // ?: display help
-                KeyCode::Slash if kb.pressed(KeyCode::ShiftLeft) || kb.pressed(KeyCode::ShiftRight) => {
-                    let mut kb_help: String = "Keyboard commands:".into();
-                    // Are we already displaying it?
-                    for message in &messages.messages {
-                        if message.text.starts_with(&kb_help) {
-                            return;
-                        }
-                    }
-
-                    for (_key, cmd) in kb_cmds.commands.iter() {
-                        kb_help.push('\n');
-                        kb_help.push_str(&format!("{:5} - {}", cmd.key_text, cmd.description));
-                    }
-                    kb_help.push_str("\nEsc   - Quit");
-
-                    messages.messages.push(MessageText {
-                        text: kb_help,
-                        start_time: Instant::now(),
-                        duration: Duration::from_secs(5),
-                    });
-                }
-
                 // Other key: check the command map.
                 key => {
                     if let Some(command) = kb_cmds.commands.get_mut(key)
@@ -131,14 +193,27 @@ pub fn handle_keypress(
 
 pub fn on_resize(
     mut resize_reader: MessageReader<WindowResized>,
-    mut sim: Single<&mut Simulation>,
+    mut sims: Query<&mut Simulation>,
+    mut layers: Query<&mut SimLayer>,
+    comparison: Option<Res<Comparison>>,
+    settings: Res<SimulationSettings>,
     windows: Query<Entity, With<PrimaryWindow>>,
 ) {
+    // A fixed domain is scaled to fit the window instead (see fit_domain).
+    if settings.0.domain.is_some() {
+        return;
+    }
     if let Ok(primary) = windows.single() {
         for e in resize_reader.read() {
             // Only process resize for the primary window.
             if e.window == primary {
-                sim.on_resize(e.width, e.height);
+                let width = Comparison::tank_width(comparison.as_deref(), e.width);
+                for mut sim in &mut sims {
+                    sim.on_resize(width, e.height);
+                }
+                for mut layer in &mut layers {
+                    layer.sim.on_resize(width, e.height);
+                }
             }
         }
     }
@@ -0,0 +1,49 @@
+use bevy::math::Vec2;
+
+use crate::sim_struct::Simulation;
+
+impl Simulation {
+    /// Blasts the particles within the explosion radius away from the center, with a single kick to their velocities
+    /// rather than a force over time. The kick is strongest at the center and dies away to nothing at the edge of the
+    /// blast, falling off with the square of the distance from the edge, so the fluid splashes outward in a ring.
+    pub fn explode(&mut self, center: Vec2) {
+        let radius = self.explosion_radius;
+        if radius <= 0.0 {
+            return;
+        }
+
+        for (position, velocity) in self.positions.iter().zip(self.velocities.iter_mut()) {
+            let offset = *position - center;
+            let distance = offset.length();
+            if distance >= radius {
+                continue;
+            }
+            // A particle right at the center is blown straight up.
+            let direction = if distance > 0.0 { offset / distance } else { Vec2::Y };
+            let falloff = 1.0 - distance / radius;
+            *velocity += direction * self.explosion_strength * falloff * falloff;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn explosion_pushes_nearby_particles_outward() {
+        let mut sim = Simulation::new(400.0, 400.0, &Args::default());
+        sim.positions[0] = Vec2::ZERO;
+        sim.positions[1] = Vec2::new(sim.explosion_radius * 0.5, 0.0);
+        sim.positions[2] = Vec2::new(0.0, -sim.explosion_radius * 2.0);
+        sim.velocities.fill(Vec2::ZERO);
+
+        sim.explode(Vec2::ZERO);
+        assert_eq!(sim.velocities[0], Vec2::new(0.0, sim.explosion_strength));
+        assert_eq!(sim.velocities[1], Vec2::new(sim.explosion_strength * 0.25, 0.0));
+        assert_eq!(sim.velocities[2], Vec2::ZERO);
+    }
+}
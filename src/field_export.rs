@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::PathBuf;
+
+use bevy::color::{ColorToComponents, LinearRgba, Srgba};
+use bevy::math::{Vec2, Vec3};
+use bevy::prelude::{ResMut, Resource, Single, With, error};
+use clap::ValueEnum;
+use image::{ImageBuffer, Rgb};
+use rayon::prelude::*;
+
+use crate::background_writer::BackgroundWriter;
+use crate::colormap::Colormap;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+use crate::update::{COLD, FAST, HOT, STOPPED};
+
+/// The continuous field to export with `--export-field`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportField {
+    /// Interpolated velocity magnitude.
+    Velocity,
+    /// Interpolated density.
+    Density,
+}
+
+/// Rasterizes a field to a PNG for every simulated frame, for post-processed visualizations.
+#[derive(Resource)]
+pub struct FieldExport {
+    pub field: ExportField,
+    pub dir: PathBuf,
+    /// Width of the images, in pixels. The height is chosen to match the aspect ratio of the simulation.
+    pub width: u32,
+    /// Use the heatmap colors instead of grayscale.
+    pub heatmap: bool,
+    pub frame: u32,
+    pub last_time: f32,
+}
+
+impl FieldExport {
+    pub fn new(field: ExportField, dir: PathBuf, width: u32, heatmap: bool) -> Self {
+        FieldExport {
+            field,
+            dir,
+            width,
+            heatmap,
+            frame: 0,
+            last_time: -1.0,
+        }
+    }
+
+    /// Samples the field at the center of each pixel, normalized to [0..1].
+    fn rasterize(&self, sim: &Simulation, width: u32, height: u32) -> Vec<f32> {
+        let pixel_size = sim.half_bounds_size * 2.0 / Vec2::new(width as f32, height as f32);
+        let top_left = Vec2::new(-sim.half_bounds_size.x, sim.half_bounds_size.y);
+
+        (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let point = top_left + Vec2::new(x as f32 + 0.5, -(y as f32 + 0.5)) * pixel_size;
+                match self.field {
+                    ExportField::Velocity => sim.sample_velocity(point).length() / sim.max_velocity.max(f32::EPSILON),
+                    ExportField::Density => sim.sample_density(point) / sim.max_density.max(f32::EPSILON),
+                }
+                .clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// Writes the next image in the sequence, if the simulation has advanced since the last one.
+pub fn export_field(
+    mut export: ResMut<FieldExport>,
+    mut writer: ResMut<BackgroundWriter>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+) {
+    if sim.sim_time == export.last_time {
+        return;
+    }
+    export.last_time = sim.sim_time;
+
+    let width = export.width.max(1);
+    let height = ((width as f32 * sim.half_bounds_size.y / sim.half_bounds_size.x) as u32).max(1);
+    let values = export.rasterize(&sim, width, height);
+
+    let (low, high) = match export.field {
+        ExportField::Velocity => (STOPPED, FAST),
+        ExportField::Density => (COLD, HOT),
+    };
+    let heatmap = export.heatmap;
+    let colormap = sim.debug.colormap;
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        let value = values[(y * width + x) as usize];
+        let rgb = match (heatmap, colormap) {
+            (false, _) => Vec3::splat(value),
+            (true, Colormap::Classic) => low + value * (high - low),
+            // The image is written as sRGB.
+            (true, colormap) => Srgba::from(LinearRgba::from_vec3(colormap.color(value, low, high))).to_vec3(),
+        };
+        Rgb((rgb * 255.0).to_array().map(|c| c as u8))
+    });
+
+    let file_name = export
+        .dir
+        .join(format!("{:?}-{:05}.png", export.field, export.frame).to_lowercase());
+    export.frame += 1;
+
+    let dir = export.dir.clone();
+    writer.run(move || {
+        if let Err(e) = fs::create_dir_all(&dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| image.save(&file_name).map_err(|e| e.to_string()))
+        {
+            error!("Failed to write {}: {e}", file_name.display());
+        }
+    });
+}
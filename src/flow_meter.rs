@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+
+use bevy::color::palettes::css::{ORANGE, WHITE};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::camera_controls::ScreenSized;
+use crate::compare::ComparisonCamera;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Hold down and click two points to place a measurement line; right-click to remove them all.
+pub const MEASURE_KEY: KeyCode = KeyCode::Backslash;
+/// The flow rate is averaged over this much simulated time, in seconds.
+const RATE_WINDOW: f32 = 1.0;
+
+/// A line across the flow that counts the particles crossing it. Crossings to the right of the line, looking from its
+/// start to its end, count as positive, and crossings to the left as negative.
+pub struct FlowLine {
+    pub start: Vec2,
+    pub end: Vec2,
+    /// The net number of particles that have crossed the line since it was placed.
+    pub net_particles: i64,
+    /// The net mass that has crossed the line since it was placed.
+    pub net_mass: f32,
+    /// The net crossings in each recent step, with the simulated time of the step.
+    recent: VecDeque<(f32, i32)>,
+}
+
+/// Labels a measurement line with its flow rate.
+#[derive(Component)]
+pub struct FlowLabel(usize);
+
+impl FlowLine {
+    pub fn new(start: Vec2, end: Vec2) -> Self {
+        FlowLine {
+            start,
+            end,
+            net_particles: 0,
+            net_mass: 0.0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// +1 if a particle moving from one point to the other crosses the line to the right, -1 if it crosses to the left,
+    /// and 0 if it doesn't cross.
+    fn crossing(&self, from: Vec2, to: Vec2) -> i32 {
+        let direction = self.end - self.start;
+        let side_from = direction.perp_dot(from - self.start);
+        let side_to = direction.perp_dot(to - self.start);
+        // Half-open, so that a particle stopping exactly on the line isn't counted twice.
+        let sign = match (side_from > 0.0, side_to > 0.0) {
+            (true, false) => 1,
+            (false, true) => -1,
+            _ => return 0,
+        };
+
+        // Where the particle's path meets the line, as a fraction of the way along the line.
+        let point = from.lerp(to, side_from / (side_from - side_to));
+        let along = (point - self.start).dot(direction) / direction.length_squared().max(f32::EPSILON);
+        if (0.0..=1.0).contains(&along) { sign } else { 0 }
+    }
+
+    /// The net number of particles crossing per second, over the last second of simulated time.
+    pub fn particle_rate(&self) -> f32 {
+        self.recent.iter().map(|&(_, crossings)| crossings as f32).sum::<f32>() / RATE_WINDOW
+    }
+}
+
+impl Simulation {
+    pub fn add_flow_line(&mut self, start: Vec2, end: Vec2) {
+        self.flow_lines.push(FlowLine::new(start, end));
+    }
+
+    /// Counts the particles that crossed the measurement lines in the last step, moving from the given positions to
+    /// their current ones.
+    pub fn measure_flow(&mut self, before: &[Vec2]) {
+        let _span = info_span!("measure_flow").entered();
+        // Particles recycled from the bottom to the top (in bottomless mode) jump across the whole tank.
+        let max_move = self.half_bounds_size.y;
+        let mut flow_lines = std::mem::take(&mut self.flow_lines);
+        for line in &mut flow_lines {
+            let mut crossings = 0;
+            for (i, (&from, &to)) in before.iter().zip(&self.positions).enumerate() {
+                let crossing = if from.distance(to) < max_move { line.crossing(from, to) } else { 0 };
+                if crossing != 0 {
+                    crossings += crossing;
+                    line.net_mass += crossing as f32 * self.particle_mass(i);
+                }
+            }
+            line.net_particles += crossings as i64;
+
+            // The simulated time restarts when the particles are reset.
+            while let Some(&(time, _)) = line.recent.front()
+                && (time <= self.sim_time - RATE_WINDOW || time > self.sim_time)
+            {
+                line.recent.pop_front();
+            }
+            line.recent.push_back((self.sim_time, crossings));
+        }
+        self.flow_lines = flow_lines;
+    }
+}
+
+/// While the measure key is held, the first click starts a measurement line and the second one ends it, and a
+/// right-click removes all the lines. Draws the lines, and labels them with their flow rates.
+#[allow(clippy::too_many_arguments)] // ECS gonna ECS.
+pub fn flow_lines(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    mut labels: Query<(Entity, &FlowLabel, &mut Text2d, &mut Transform)>,
+    mut line_start: Local<Option<Vec2>>,
+) {
+    let cursor_point = windows.single().ok().and_then(|window| {
+        let (camera, camera_transform) = cameras_query.iter().next()?;
+        camera
+            .viewport_to_world_2d(camera_transform, window.cursor_position()?)
+            .ok()
+    });
+    if !kb.pressed(MEASURE_KEY) {
+        *line_start = None;
+    } else if let Some(point) = cursor_point {
+        if buttons.just_pressed(MouseButton::Right) {
+            sim.flow_lines.clear();
+            *line_start = None;
+        } else if buttons.just_pressed(MouseButton::Left) {
+            match line_start.take() {
+                Some(start) if start != point => sim.add_flow_line(start, point),
+                _ => *line_start = Some(point),
+            }
+        }
+        if let Some(start) = *line_start {
+            gizmos.line_2d(start, point, WHITE);
+        }
+    }
+
+    for line in &sim.flow_lines {
+        gizmos.line_2d(line.start, line.end, ORANGE);
+        // A tick showing which way counts as positive.
+        let middle = (line.start + line.end) / 2.0;
+        let right = -(line.end - line.start).perp().normalize_or_zero();
+        gizmos.arrow_2d(middle, middle + right * 12.0, ORANGE);
+    }
+
+    for (entity, label, mut text, mut transform) in &mut labels {
+        let Some(line) = sim.flow_lines.get(label.0) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        text.0 = format!("{:+.0} particles/s\n{:+.1} mass", line.particle_rate(), line.net_mass);
+        transform.translation = line.end.extend(2.0);
+    }
+    for index in labels.iter().count()..sim.flow_lines.len() {
+        commands.spawn((
+            Text2d::default(),
+            TextFont {
+                font_size: 12.0,
+                ..default()
+            },
+            TextColor(ORANGE.into()),
+            Transform::from_translation(sim.flow_lines[index].end.extend(2.0)),
+            ScreenSized,
+            FlowLabel(index),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::FlowLine;
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn crossings_are_counted_with_their_direction() {
+        // A horizontal line, drawn left to right: its right side is below it.
+        let line = FlowLine::new(Vec2::new(-10.0, 0.0), Vec2::new(10.0, 0.0));
+        assert_eq!(line.crossing(Vec2::new(0.0, 1.0), Vec2::new(0.0, -1.0)), 1);
+        assert_eq!(line.crossing(Vec2::new(0.0, -1.0), Vec2::new(0.0, 1.0)), -1);
+        assert_eq!(line.crossing(Vec2::new(0.0, 1.0), Vec2::new(0.0, 0.5)), 0);
+        // Past the end of the line.
+        assert_eq!(line.crossing(Vec2::new(20.0, 1.0), Vec2::new(20.0, -1.0)), 0);
+
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 10,
+                ..Args::default()
+            },
+        );
+        sim.add_flow_line(Vec2::new(-10.0, 0.0), Vec2::new(10.0, 0.0));
+        let before: Vec<Vec2> = (0..10).map(|i| Vec2::new(i as f32 - 5.0, 1.0)).collect();
+        sim.positions = before.iter().map(|&position| position - Vec2::new(0.0, 2.0)).collect();
+        sim.positions[0].y = 1.0;
+        sim.measure_flow(&before);
+        assert_eq!(sim.flow_lines[0].net_particles, 9);
+        assert_eq!(sim.flow_lines[0].net_mass, 9.0 * sim.particle_mass(0));
+        assert_eq!(sim.flow_lines[0].particle_rate(), 9.0);
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::math::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::sim_struct::Simulation;
+use crate::update::{FAST, STOPPED};
+
+/// A kind of fluid, for simulating several immiscible fluids at once (see the oil-and-water scenario).
+///
+/// All the particles are sized and spaced alike, but a denser fluid's particles are heavier, so the same pressure
+/// accelerates them less: the denser fluid sinks below the lighter one, and the pressure rises faster with depth in it.
+/// The density and the pressure are computed from the number of particles around each one (the kernel sum, as with a
+/// single fluid) rather than from their masses, so there's no spurious pressure jump at the interface between two
+/// fluids.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FluidType {
+    pub name: String,
+    /// Rest density, relative to the default fluid.
+    #[serde(default = "one")]
+    pub density: f32,
+    /// Particle mass, relative to the default fluid's particles. Defaults to the density, which packs the particles as
+    /// tightly as the default fluid's at rest.
+    pub mass: Option<f32>,
+    /// Linear RGB color of the slow particles.
+    #[serde(default = "stopped")]
+    pub slow_color: [f32; 3],
+    /// Linear RGB color of the fastest particles.
+    #[serde(default = "fast")]
+    pub fast_color: [f32; 3],
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn stopped() -> [f32; 3] {
+    STOPPED.to_array()
+}
+
+fn fast() -> [f32; 3] {
+    FAST.to_array()
+}
+
+impl Default for FluidType {
+    fn default() -> Self {
+        FluidType {
+            name: "Water".into(),
+            density: 1.0,
+            mass: None,
+            slow_color: stopped(),
+            fast_color: fast(),
+        }
+    }
+}
+
+impl FluidType {
+    pub fn mass(&self) -> f32 {
+        self.mass.unwrap_or(self.density)
+    }
+
+    /// The color of a particle moving at the given fraction of the maximum speed (after the same easing as with a
+    /// single fluid).
+    pub fn color(&self, speed_scale: f32) -> Vec3 {
+        let slow = Vec3::from_array(self.slow_color);
+        slow + speed_scale * (Vec3::from_array(self.fast_color) - slow)
+    }
+}
+
+impl Simulation {
+    pub fn fluid_type(&self, particle_id: usize) -> &FluidType {
+        &self.fluid_types[self.fluids[particle_id] as usize]
+    }
+
+    pub fn particle_mass(&self, particle_id: usize) -> f32 {
+        self.fluid_type(particle_id).mass()
+    }
+
+    /// The density (kernel sum) that the particle's fluid has at rest.
+    pub fn rest_density(&self, particle_id: usize) -> f32 {
+        let fluid = self.fluid_type(particle_id);
+        self.target_density * fluid.density / fluid.mass()
+    }
+}
@@ -0,0 +1,218 @@
+use bevy::color::palettes::css::{LIGHT_CYAN, WHITE};
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// The most secondary particles at a time. New ones aren't spawned while there are this many.
+const MAX_PARTICLES: usize = 20_000;
+/// How long a secondary particle lasts, in seconds of simulation time.
+const LIFETIME: f32 = 2.0;
+/// The expected number of secondary particles spawned per second by a fluid particle with the highest potential.
+const SPAWN_RATE: f32 = 40.0;
+/// A fluid particle is at the free surface if its neighbors' kernel-weighted center is further than this from it, as
+/// a fraction of the smoothing radius.
+const SURFACE_OFFSET: f32 = 0.1;
+/// How fast a fluid particle must be running into its neighbors, and moving, to start spawning secondary particles, and
+/// to spawn them at the full rate, relative to the speed that falling a smoothing radius under gravity gives.
+const MIN_COMPRESSION: f32 = 0.3;
+const MAX_COMPRESSION: f32 = 0.8;
+const MIN_SPEED: f32 = 0.4;
+const MAX_SPEED: f32 = 0.9;
+/// A secondary particle is spray where the fluid's density around it is below this fraction of the mean density, and
+/// a bubble where it's above the second one; in between, it's foam on the surface.
+const SPRAY_DENSITY: f32 = 0.2;
+const BUBBLE_DENSITY: f32 = 0.7;
+/// How quickly bubbles rise, as a multiple of the gravity, and how much of the difference from the fluid's velocity
+/// they lose per step.
+const BUBBLE_BUOYANCY: f32 = 2.0;
+const BUBBLE_DRAG: f32 = 0.5;
+
+/// Secondary particles for splashes (`--foam`, F11), after Ihmsen et al., "Unified Spray, Foam and Bubbles for
+/// Particle-Based Fluids" (2012). They aren't part of the SPH: they're spawned where the fluid at the free surface is
+/// fast and converging, as in a breaking wave or a drop hitting the pool, and are then carried along by the fluid's
+/// velocity (or fly on their own, as spray) until they fade away. They're only drawn, so thousands of them cost far
+/// less than the particles it would take to resolve the same splashes.
+#[derive(Default)]
+pub struct Foam {
+    pub particles: Vec<FoamParticle>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FoamParticle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    /// The simulation time left before it disappears.
+    pub life: f32,
+    pub kind: FoamKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FoamKind {
+    /// In the air: flies under gravity.
+    Spray,
+    /// On the surface: carried along by the fluid.
+    Foam,
+    /// Under the surface: carried along by the fluid, and rises.
+    Bubble,
+}
+
+/// How far `value` is between `min` and `max`, clamped to 0..1.
+fn ramp(value: f32, min: f32, max: f32) -> f32 {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+impl Simulation {
+    /// How strongly a fluid particle spawns secondary particles, from 0 to 1: only at the free surface, where it's
+    /// both fast and running into its neighbors.
+    pub fn foam_potential(&self, particle_id: usize) -> f32 {
+        let position = self.positions[particle_id];
+        let velocity = self.velocities[particle_id];
+        let mut center = Vec2::ZERO;
+        let mut convergence = 0.0;
+        let mut total_weight = 0.0;
+        for neighbor_id in self.neighbor_particles(particle_id) {
+            let offset = self.positions[neighbor_id] - position;
+            let distance = offset.length();
+            if distance <= 0.0 || distance >= self.smoothing_radius {
+                continue;
+            }
+            let weight = self.smoothing_kernel(distance);
+            center += offset * weight;
+            // How fast they're running into each other: the relative speed, counted double when it's head-on and not at
+            // all when they're moving apart.
+            let relative_velocity = velocity - self.velocities[neighbor_id];
+            convergence += (relative_velocity.length() + relative_velocity.dot(offset / distance)) * weight;
+            total_weight += weight;
+        }
+        if total_weight <= 0.0 || (center / total_weight).length() < self.smoothing_radius * SURFACE_OFFSET {
+            return 0.0;
+        }
+
+        // Relative to the speed that falling a smoothing radius under gravity gives (or a unit/s² of it, without).
+        let gravity = self.gravity.length().max(self.units.to_pixels(1.0));
+        let speed_scale = (gravity * self.smoothing_radius).sqrt();
+        let compression = convergence / total_weight / speed_scale;
+        ramp(compression, MIN_COMPRESSION, MAX_COMPRESSION)
+            * ramp(velocity.length() / speed_scale, MIN_SPEED, MAX_SPEED)
+    }
+
+    /// Spawns secondary particles where the potential is high, moves them, and removes the ones that have faded away
+    /// or left the tank.
+    pub fn update_foam(&mut self, delta: f32) {
+        let Some(mut foam) = self.foam.take() else {
+            return;
+        };
+        let time_step = delta * self.speed;
+        let mean_density = self.densities.iter().sum::<f32>() / self.num_particles.max(1) as f32;
+
+        let room = MAX_PARTICLES.saturating_sub(foam.particles.len());
+        let spawned: Vec<FoamParticle> = (0..self.num_particles)
+            .into_par_iter()
+            .filter(|&i| self.random(i, 5) < self.foam_potential(i) * SPAWN_RATE * time_step)
+            .map(|i| {
+                let jitter = Vec2::new(self.random(i, 6), self.random(i, 7)) - 0.5;
+                FoamParticle {
+                    position: self.positions[i] + jitter * self.particle_size,
+                    velocity: self.velocities[i],
+                    life: LIFETIME * (0.5 + self.random(i, 8) / 2.0),
+                    kind: FoamKind::Foam,
+                }
+            })
+            .collect();
+        foam.particles.extend(spawned.into_iter().take(room));
+
+        foam.particles.par_iter_mut().for_each(|particle| {
+            let fluid_velocity = self.sample_velocity(particle.position);
+            let density = self.density_at(particle.position);
+            particle.kind = if density < mean_density * SPRAY_DENSITY {
+                FoamKind::Spray
+            } else if density > mean_density * BUBBLE_DENSITY {
+                FoamKind::Bubble
+            } else {
+                FoamKind::Foam
+            };
+            let gravity = self.gravity_at(particle.position);
+            particle.velocity = match particle.kind {
+                FoamKind::Spray => particle.velocity - gravity * delta,
+                FoamKind::Foam => fluid_velocity,
+                FoamKind::Bubble => {
+                    particle.velocity
+                        + (fluid_velocity - particle.velocity) * BUBBLE_DRAG
+                        + gravity * BUBBLE_BUOYANCY * delta
+                }
+            };
+            particle.position += particle.velocity * time_step;
+            particle.life -= time_step;
+        });
+        let half_size = self.half_bounds_size;
+        foam.particles
+            .retain(|particle| particle.life > 0.0 && particle.position.abs().cmple(half_size).all());
+        self.foam = Some(foam);
+    }
+
+    /// The density at an arbitrary point: the sum of the smoothing kernel over the nearby particles.
+    fn density_at(&self, point: Vec2) -> f32 {
+        self.neighbor_search
+            .candidates(point)
+            .map(|neighbor_id| self.smoothing_kernel(self.positions[neighbor_id].distance(point)))
+            .sum()
+    }
+}
+
+/// Draws the secondary particles, fading out as they age: spray and foam in white, and bubbles in light cyan.
+pub fn draw_foam(mut gizmos: Gizmos, sim: Single<&Simulation, With<MainSimulation>>) {
+    let Some(foam) = &sim.foam else {
+        return;
+    };
+    for particle in &foam.particles {
+        let (color, size) = match particle.kind {
+            FoamKind::Spray => (WHITE, 0.15),
+            FoamKind::Foam => (WHITE, 0.25),
+            FoamKind::Bubble => (LIGHT_CYAN, 0.2),
+        };
+        let alpha = (particle.life / LIFETIME).clamp(0.0, 1.0);
+        gizmos
+            .circle_2d(particle.position, sim.particle_size * size, color.with_alpha(alpha))
+            .resolution(8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FoamKind, LIFETIME};
+    use crate::args::Args;
+    use crate::scenario::Scenario;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn splashes_make_foam_that_fades() {
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 600,
+                deterministic: true,
+                foam: true,
+                ..Args::default()
+            },
+        );
+        let droplet = Scenario::presets().find(|scenario| scenario.name == "Droplet").unwrap();
+        sim.load_scenario(droplet);
+
+        // The drop hits the pool.
+        sim.step_n(1.0 / 60.0, 60);
+        let foam = &sim.foam.as_ref().unwrap().particles;
+        assert!(!foam.is_empty());
+        for particle in foam {
+            assert!(particle.position.abs().cmple(sim.half_bounds_size).all(), "{particle:?}");
+            assert!(particle.life <= LIFETIME);
+        }
+        assert!(foam.iter().any(|particle| particle.kind != FoamKind::Bubble));
+
+        // Once the pool calms down, the foam fades away.
+        sim.step_n(1.0 / 60.0, (LIFETIME / sim.speed * 60.0) as usize + 1);
+        assert!(sim.foam.as_ref().unwrap().particles.is_empty());
+    }
+}
@@ -0,0 +1,384 @@
+use std::sync::mpsc;
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, MapMode,
+    PipelineLayoutDescriptor, PollType, RawComputePipelineDescriptor, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use clap::ValueEnum;
+
+use crate::components::{MainSimulation, SimulationSettings};
+use crate::sim_struct::Simulation;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Where the densities and pressure forces are computed, selectable with `--backend`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    #[default]
+    Cpu,
+    /// Compute shaders, through Bevy's wgpu device. The other forces stay on the CPU.
+    Gpu,
+}
+
+/// Runs the neighbor search, density and pressure force passes as compute shaders (see gpu.wgsl).
+///
+/// The particle positions are uploaded every step, and the results are read back synchronously, since the rest of the
+/// step (the other forces, collisions, rendering) runs on the CPU. That round trip is the main cost, so this only pays
+/// off with a lot of particles. The particles of a cell are in no particular order after the GPU's sort, so the sums
+/// can differ from the CPU's (and from run to run) in the last bits.
+pub struct GpuBackend {
+    device: RenderDevice,
+    queue: RenderQueue,
+    layout: BindGroupLayout,
+    clear_cells_pipeline: ComputePipeline,
+    count_cells_pipeline: ComputePipeline,
+    scan_cells_pipeline: ComputePipeline,
+    sort_particles_pipeline: ComputePipeline,
+    density_pipeline: ComputePipeline,
+    pressure_force_pipeline: ComputePipeline,
+    /// Kept from step to step, and only made again when the number of particles or cells changes.
+    buffers: Option<Buffers>,
+    /// Whether the particles have been sorted into the cells for this step, by the density pass. The pressure force
+    /// pass uses that sort, along with the positions and densities.
+    sorted: bool,
+}
+
+struct Buffers {
+    num_particles: u32,
+    num_cells: u32,
+    params: Buffer,
+    positions: Buffer,
+    densities: Buffer,
+    pressures: Buffer,
+    pressure_forces: Buffer,
+    /// Mappable, for reading back the densities or pressure forces.
+    readback: Buffer,
+    /// All the buffers, including the ones that are only used on the GPU.
+    bind_group: BindGroup,
+}
+
+impl Buffers {
+    fn new(device: &RenderDevice, layout: &BindGroupLayout, num_particles: u32, num_cells: u32) -> Self {
+        let buffer = |label: &str, size: u32, usage: BufferUsages| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                // wgpu doesn't allow empty bindings.
+                size: size.max(8) as u64,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let storage = |label: &str, size: u32| {
+            buffer(label, size, BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST)
+        };
+        let params = buffer("params", 32, BufferUsages::UNIFORM | BufferUsages::COPY_DST);
+        let positions = storage("positions", num_particles * 8);
+        let cell_particles = storage("cell particles", num_particles * 4);
+        let cell_start = storage("cell start", (num_cells + 1) * 4);
+        let densities = storage("densities", num_particles * 4);
+        let pressures = storage("pressures", num_particles * 4);
+        let pressure_forces = storage("pressure forces", num_particles * 8);
+        let cell_counts = storage("cell counts", num_cells * 4);
+        let particle_slots = storage("particle slots", num_particles * 8);
+        let readback = buffer("readback", num_particles * 8, BufferUsages::MAP_READ | BufferUsages::COPY_DST);
+
+        let bindings = [
+            &params,
+            &positions,
+            &cell_particles,
+            &cell_start,
+            &densities,
+            &pressures,
+            &pressure_forces,
+            &cell_counts,
+            &particle_slots,
+        ];
+        let entries: Vec<BindGroupEntry> = bindings
+            .iter()
+            .zip(0..)
+            .map(|(buffer, binding)| BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group("fluid sim", layout, &entries);
+
+        Buffers {
+            num_particles,
+            num_cells,
+            params,
+            positions,
+            densities,
+            pressures,
+            pressure_forces,
+            readback,
+            bind_group,
+        }
+    }
+}
+
+impl GpuBackend {
+    pub fn new(device: &RenderDevice, queue: &RenderQueue) -> Self {
+        let shader = device.create_and_validate_shader_module(ShaderModuleDescriptor {
+            label: Some("fluid sim"),
+            source: ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+        });
+        let entry = |binding: u32, ty: BufferBindingType| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let read_only = BufferBindingType::Storage { read_only: true };
+        let read_write = BufferBindingType::Storage { read_only: false };
+        let layout = device.create_bind_group_layout(
+            "fluid sim",
+            &[
+                entry(0, BufferBindingType::Uniform),
+                entry(1, read_only),
+                entry(2, read_write),
+                entry(3, read_write),
+                entry(4, read_write),
+                entry(5, read_only),
+                entry(6, read_write),
+                entry(7, read_write),
+                entry(8, read_write),
+            ],
+        );
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("fluid sim"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&RawComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            clear_cells_pipeline: pipeline("clear_cells"),
+            count_cells_pipeline: pipeline("count_cells"),
+            scan_cells_pipeline: pipeline("scan_cells"),
+            sort_particles_pipeline: pipeline("sort_particles"),
+            density_pipeline: pipeline("calculate_densities"),
+            pressure_force_pipeline: pipeline("calculate_pressure_forces"),
+            layout,
+            buffers: None,
+            sorted: false,
+        }
+    }
+
+    /// Uploads the positions, sorts the particles into the cells, and computes the densities.
+    fn densities(&mut self, sim: &Simulation) -> Vec<f32> {
+        let num_particles = sim.num_particles as u32;
+        let num_cells = (sim.region_cols * sim.region_rows) as u32;
+        if self
+            .buffers
+            .as_ref()
+            .is_none_or(|buffers| (buffers.num_particles, buffers.num_cells) != (num_particles, num_cells))
+        {
+            self.buffers = Some(Buffers::new(&self.device, &self.layout, num_particles, num_cells));
+        }
+        let buffers = self.buffers.as_ref().unwrap();
+
+        let params = [
+            num_particles,
+            sim.region_cols as u32,
+            sim.region_rows as u32,
+            sim.smoothing_radius.to_bits(),
+            (-sim.half_bounds_size.x).to_bits(),
+            (-sim.half_bounds_size.y).to_bits(),
+            sim.smoothing_scaling_factor.to_bits(),
+            sim.smoothing_derivative_scaling_factor.to_bits(),
+        ];
+        self.queue.write_buffer(&buffers.params, 0, &words(params));
+        let positions = (0..sim.num_particles).flat_map(|i| sim.solver_position(i).to_array());
+        self.queue.write_buffer(&buffers.positions, 0, &floats(positions));
+
+        let particle_workgroups = num_particles.div_ceil(WORKGROUP_SIZE);
+        let passes = [
+            (&self.clear_cells_pipeline, num_cells.div_ceil(WORKGROUP_SIZE)),
+            (&self.count_cells_pipeline, particle_workgroups),
+            (&self.scan_cells_pipeline, 1),
+            (&self.sort_particles_pipeline, particle_workgroups),
+            (&self.density_pipeline, particle_workgroups),
+        ];
+        let bytes = self.run(buffers, &passes, &buffers.densities);
+        self.sorted = true;
+        bytes
+            .chunks_exact(4)
+            .take(sim.num_particles)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+
+    /// Computes the pressure forces from the pressures, using the positions, cells and densities of densities().
+    fn pressure_forces(&mut self, pressures: &[f32]) -> Vec<Vec2> {
+        assert!(self.sorted, "The densities must be computed before the pressure forces");
+        self.sorted = false;
+        let buffers = self.buffers.as_ref().unwrap();
+        self.queue
+            .write_buffer(&buffers.pressures, 0, &floats(pressures.iter().copied()));
+
+        let passes = [(&self.pressure_force_pipeline, buffers.num_particles.div_ceil(WORKGROUP_SIZE))];
+        let bytes = self.run(buffers, &passes, &buffers.pressure_forces);
+        bytes
+            .chunks_exact(8)
+            .take(buffers.num_particles as usize)
+            .map(|b| {
+                Vec2::new(
+                    f32::from_le_bytes(b[0..4].try_into().unwrap()),
+                    f32::from_le_bytes(b[4..8].try_into().unwrap()),
+                )
+            })
+            .collect()
+    }
+
+    /// Runs the passes in order, with the given number of workgroups each, and reads back the output buffer.
+    fn run(&self, buffers: &Buffers, passes: &[(&ComputePipeline, u32)], output: &Buffer) -> Vec<u8> {
+        let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("fluid sim"),
+        });
+        {
+            // Each dispatch sees the writes of the ones before it.
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("fluid sim"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, Some(&*buffers.bind_group), &[]);
+            for &(pipeline, workgroups) in passes {
+                pass.set_pipeline(pipeline);
+                pass.dispatch_workgroups(workgroups, 1, 1);
+            }
+        }
+        encoder.copy_buffer_to_buffer(output, 0, &buffers.readback, 0, output.size());
+        self.queue.submit([encoder.finish()]);
+
+        let slice = buffers.readback.slice(..output.size());
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device
+            .poll(PollType::wait_indefinitely())
+            .expect("Failed to wait for the GPU");
+        receiver
+            .recv()
+            .expect("The readback was dropped")
+            .expect("Failed to read back from the GPU");
+        let bytes = slice.get_mapped_range().to_vec();
+        buffers.readback.unmap();
+        bytes
+    }
+}
+
+/// Little-endian bytes of u32 values, padded so that the buffer isn't empty.
+fn words(values: impl IntoIterator<Item = u32>) -> Vec<u8> {
+    let mut bytes: Vec<u8> = values.into_iter().flat_map(u32::to_le_bytes).collect();
+    bytes.resize(bytes.len().max(8), 0);
+    bytes
+}
+
+fn floats(values: impl IntoIterator<Item = f32>) -> Vec<u8> {
+    words(values.into_iter().map(f32::to_bits))
+}
+
+impl Simulation {
+    /// Computes the densities on the GPU, if the GPU backend is in use.
+    pub fn gpu_densities(&mut self) -> Option<Vec<f32>> {
+        let mut gpu = self.gpu.take()?;
+        let densities = gpu.densities(self);
+        self.gpu = Some(gpu);
+        Some(densities)
+    }
+
+    /// Computes the pressure forces on the GPU, if the GPU backend is in use.
+    pub fn gpu_pressure_forces(&mut self) -> Option<Vec<Vec2>> {
+        let gpu = self.gpu.as_mut()?;
+        Some(gpu.pressure_forces(&self.pressures))
+    }
+}
+
+/// Creates the GPU backend once the render device exists, if `--backend gpu` was given.
+pub fn init_gpu_backend(
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    settings: Res<SimulationSettings>,
+    device: Option<Res<RenderDevice>>,
+    queue: Option<Res<RenderQueue>>,
+) {
+    if settings.0.backend != Backend::Gpu {
+        return;
+    }
+    match (device, queue) {
+        (Some(device), Some(queue)) => {
+            sim.gpu = Some(GpuBackend::new(&device, &queue));
+            info!("Computing densities and pressures on the GPU.");
+        }
+        _ => warn!("No render device is available, so the simulation will run on the CPU."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bevy::render::renderer::WgpuWrapper;
+    use bevy::tasks::block_on;
+
+    use super::*;
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    /// A device on the first adapter that wgpu finds.
+    fn test_device() -> (RenderDevice, RenderQueue) {
+        let instance = wgpu::Instance::default();
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .expect("The GPU test needs a GPU adapter");
+        let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).unwrap();
+        (RenderDevice::from(device), RenderQueue(Arc::new(WgpuWrapper::new(queue))))
+    }
+
+    /// Run with `cargo test -- --ignored` on a machine with a GPU.
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn gpu_matches_the_cpu() {
+        let (device, queue) = test_device();
+        let mut sim = test_sim(1000, 400.0, 300.0, Args::default());
+        // Let the lattice break up, so that the particles are at all sorts of distances from each other.
+        sim.step_n(1.0 / 60.0, 20);
+        sim.update_regions();
+        sim.calculate_densities();
+        let cpu_densities = sim.densities.clone();
+        sim.calculate_pressure_values();
+        let cpu_forces: Vec<Vec2> = (0..sim.num_particles).map(|i| sim.pressure_force(i)).collect();
+
+        sim.gpu = Some(GpuBackend::new(&device, &queue));
+        sim.calculate_densities();
+        let gpu_forces = sim.gpu_pressure_forces().unwrap();
+
+        let max_density = cpu_densities.iter().copied().fold(0.0, f32::max);
+        for (i, (cpu, gpu)) in cpu_densities.iter().zip(&sim.densities).enumerate() {
+            assert!((cpu - gpu).abs() <= max_density * 1e-4, "density of {i}: CPU {cpu}, GPU {gpu}");
+        }
+        let max_force = cpu_forces.iter().map(|force| force.length()).fold(0.0, f32::max);
+        for (i, (cpu, gpu)) in cpu_forces.iter().zip(&gpu_forces).enumerate() {
+            assert!(cpu.distance(*gpu) <= max_force * 1e-3, "pressure force on {i}: CPU {cpu}, GPU {gpu}");
+        }
+    }
+}
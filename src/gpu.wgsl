@@ -0,0 +1,178 @@
+// Neighbor search, density and pressure force passes of the simulation (see gpu.rs).
+// The particles are sorted into the cells of a grid with the smoothing radius as its cell size, by a counting sort:
+// clear_cells, count_cells, scan_cells and sort_particles. Then each invocation of the density and pressure force
+// passes handles one particle, and searches the 3x3 block of cells around it for neighbors.
+
+struct Params {
+    num_particles: u32,
+    cols: u32,
+    rows: u32,
+    smoothing_radius: f32,
+    origin: vec2<f32>,
+    smoothing_scaling_factor: f32,
+    smoothing_derivative_scaling_factor: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> positions: array<vec2<f32>>;
+// The particle ids, sorted by cell, and the index of the first particle of each cell (plus one past the end).
+@group(0) @binding(2) var<storage, read_write> cell_particles: array<u32>;
+@group(0) @binding(3) var<storage, read_write> cell_start: array<u32>;
+@group(0) @binding(4) var<storage, read_write> densities: array<f32>;
+@group(0) @binding(5) var<storage, read> pressures: array<f32>;
+@group(0) @binding(6) var<storage, read_write> pressure_forces: array<vec2<f32>>;
+// The number of particles in each cell.
+@group(0) @binding(7) var<storage, read_write> cell_counts: array<atomic<u32>>;
+// Each particle's cell, and its place among the particles of that cell.
+@group(0) @binding(8) var<storage, read_write> particle_slots: array<vec2<u32>>;
+
+// The number of invocations that scan the cell counts, which is the size of the one workgroup of scan_cells.
+const SCAN_SIZE: u32 = 256u;
+var<workgroup> chunk_totals: array<u32, SCAN_SIZE>;
+
+// Positions outside the bounds are assigned to the nearest edge cell, as in CellLayout::cell_of().
+fn cell_of(position: vec2<f32>) -> vec2<i32> {
+    let cell = vec2<i32>(floor((position - params.origin) / params.smoothing_radius));
+    return clamp(cell, vec2<i32>(0), vec2<i32>(i32(params.cols) - 1, i32(params.rows) - 1));
+}
+
+fn in_grid(cell: vec2<i32>) -> bool {
+    return all(cell >= vec2<i32>(0)) && cell.x < i32(params.cols) && cell.y < i32(params.rows);
+}
+
+fn cell_index(cell: vec2<i32>) -> u32 {
+    return u32(cell.y) * params.cols + u32(cell.x);
+}
+
+fn num_cells() -> u32 {
+    return params.cols * params.rows;
+}
+
+@compute @workgroup_size(64)
+fn clear_cells(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x < num_cells() {
+        atomicStore(&cell_counts[id.x], 0u);
+    }
+}
+
+@compute @workgroup_size(64)
+fn count_cells(@builtin(global_invocation_id) id: vec3<u32>) {
+    let particle_id = id.x;
+    if particle_id >= params.num_particles {
+        return;
+    }
+    let cell = cell_index(cell_of(positions[particle_id]));
+    particle_slots[particle_id] = vec2<u32>(cell, atomicAdd(&cell_counts[cell], 1u));
+}
+
+// The starts of the cells are the running total of their counts. A single workgroup computes it: each invocation adds
+// up a contiguous chunk of the cells, the chunk totals are summed in workgroup memory, and then each invocation writes
+// the starts of the cells in its chunk.
+@compute @workgroup_size(256)
+fn scan_cells(@builtin(local_invocation_index) index: u32) {
+    let cells = num_cells();
+    let chunk = (cells + SCAN_SIZE - 1u) / SCAN_SIZE;
+    let first = min(index * chunk, cells);
+    let last = min(first + chunk, cells);
+    var total = 0u;
+    for (var cell = first; cell < last; cell++) {
+        total += atomicLoad(&cell_counts[cell]);
+    }
+    chunk_totals[index] = total;
+    workgroupBarrier();
+
+    // After this, each chunk's total includes the totals of all the chunks before it.
+    for (var offset = 1u; offset < SCAN_SIZE; offset *= 2u) {
+        var sum = chunk_totals[index];
+        if index >= offset {
+            sum += chunk_totals[index - offset];
+        }
+        workgroupBarrier();
+        chunk_totals[index] = sum;
+        workgroupBarrier();
+    }
+
+    var start = chunk_totals[index] - total;
+    for (var cell = first; cell < last; cell++) {
+        cell_start[cell] = start;
+        start += atomicLoad(&cell_counts[cell]);
+    }
+    if index == SCAN_SIZE - 1u {
+        cell_start[cells] = chunk_totals[index];
+    }
+}
+
+@compute @workgroup_size(64)
+fn sort_particles(@builtin(global_invocation_id) id: vec3<u32>) {
+    let particle_id = id.x;
+    if particle_id >= params.num_particles {
+        return;
+    }
+    let slot = particle_slots[particle_id];
+    cell_particles[cell_start[slot.x] + slot.y] = particle_id;
+}
+
+@compute @workgroup_size(64)
+fn calculate_densities(@builtin(global_invocation_id) id: vec3<u32>) {
+    let particle_id = id.x;
+    if particle_id >= params.num_particles {
+        return;
+    }
+    let position = positions[particle_id];
+    let center = cell_of(position);
+
+    var sum = 0.0;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let cell = center + vec2<i32>(dx, dy);
+            if !in_grid(cell) {
+                continue;
+            }
+            let index = cell_index(cell);
+            for (var i = cell_start[index]; i < cell_start[index + 1u]; i++) {
+                let neighbor_id = cell_particles[i];
+                let distance = length(positions[neighbor_id] - position);
+                if neighbor_id != particle_id && distance < params.smoothing_radius {
+                    let value = params.smoothing_radius - distance;
+                    sum += value * value * value * params.smoothing_scaling_factor;
+                }
+            }
+        }
+    }
+    densities[particle_id] = sum;
+}
+
+@compute @workgroup_size(64)
+fn calculate_pressure_forces(@builtin(global_invocation_id) id: vec3<u32>) {
+    let particle_id = id.x;
+    if particle_id >= params.num_particles {
+        return;
+    }
+    let position = positions[particle_id];
+    let center = cell_of(position);
+
+    var force = vec2<f32>(0.0);
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let cell = center + vec2<i32>(dx, dy);
+            if !in_grid(cell) {
+                continue;
+            }
+            let index = cell_index(cell);
+            for (var i = cell_start[index]; i < cell_start[index + 1u]; i++) {
+                let neighbor_id = cell_particles[i];
+                let offset = positions[neighbor_id] - position;
+                let distance = length(offset);
+                // Unlike the CPU version, particles at exactly the same position don't push each other apart.
+                if neighbor_id != particle_id && distance > 0.0 && distance < params.smoothing_radius {
+                    let direction = -offset / distance;
+                    let value = distance - params.smoothing_radius;
+                    let slope = 2.0 * value * value * params.smoothing_derivative_scaling_factor;
+                    let pressure = (pressures[particle_id] + pressures[neighbor_id]) / 2.0;
+                    force += pressure * direction * slope / densities[neighbor_id];
+                }
+            }
+        }
+    }
+    pressure_forces[particle_id] = force;
+}
@@ -0,0 +1,90 @@
+use bevy::math::Vec2;
+
+use crate::sim_struct::Simulation;
+
+/// Stiffness of the springs that attach the grabbed particles to the cursor, per second squared.
+const GRAB_STIFFNESS: f32 = 100.0;
+
+/// A particle held by the grab tool: it's pulled toward the cursor, plus the offset it had when it was grabbed, so
+/// that the grabbed blob keeps its shape.
+pub struct GrabConstraint {
+    pub particle_id: usize,
+    pub offset: Vec2,
+}
+
+impl Simulation {
+    /// Attaches the particles within the interaction radius of the point to it.
+    pub fn grab_particles(&mut self, point: Vec2) {
+        self.grab_constraints = (0..self.num_particles)
+            .filter(|&i| self.positions[i].distance(point) < self.interaction_input_radius)
+            .map(|i| GrabConstraint {
+                particle_id: i,
+                offset: self.positions[i] - point,
+            })
+            .collect();
+        self.grab_point = Some((point, Vec2::ZERO));
+    }
+
+    /// Moves the point that the grabbed particles are attached to. The velocity is in pixels per second.
+    pub fn drag_grabbed(&mut self, point: Vec2, velocity: Vec2) {
+        self.grab_point = Some((point, velocity));
+    }
+
+    pub fn release_grab(&mut self) {
+        self.grab_constraints.clear();
+        self.grab_point = None;
+    }
+
+    /// The spring force pulling a grabbed particle toward its place relative to the cursor.
+    ///
+    /// The springs are critically damped, relative to the cursor's velocity, so the particles follow the cursor without
+    /// overshooting or wobbling around it. Positions move `speed` times faster than the velocities are integrated, so
+    /// both the stiffness and the cursor's velocity are scaled to match.
+    pub fn grab_force(&self, particle_id: usize) -> Vec2 {
+        let Some((point, point_velocity)) = self.grab_point else {
+            return Vec2::ZERO;
+        };
+        let Ok(index) = self
+            .grab_constraints
+            .binary_search_by_key(&particle_id, |constraint| constraint.particle_id)
+        else {
+            return Vec2::ZERO;
+        };
+
+        let speed = self.speed.max(f32::EPSILON);
+        let damping = 2.0 * (GRAB_STIFFNESS * speed).sqrt();
+        let stretch = point + self.grab_constraints[index].offset - self.positions[particle_id];
+        stretch * GRAB_STIFFNESS + (point_velocity / speed - self.velocities[particle_id]) * damping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    /// The grabbed particles should follow the cursor, and the rest should be left behind.
+    #[test]
+    fn grabbed_particles_follow_the_cursor() {
+        let mut sim = test_sim(400, 400.0, 400.0, Args::default());
+        sim.step_n(1.0 / 60.0, 60);
+
+        let start = sim.positions()[0];
+        sim.interaction_input_radius = sim.smoothing_radius * 2.0;
+        sim.grab_particles(start);
+        let grabbed: Vec<usize> = sim.grab_constraints.iter().map(|c| c.particle_id).collect();
+        assert!(!grabbed.is_empty() && grabbed.len() < 400, "grabbed {}", grabbed.len());
+
+        let target = Vec2::new(0.0, sim.half_bounds_size.y * 0.5);
+        sim.drag_grabbed(target, Vec2::ZERO);
+        sim.step_n(1.0 / 60.0, 120);
+        let distance = sim.positions()[0].distance(target);
+        assert!(distance < sim.interaction_input_radius, "the grabbed particle is {distance} from the cursor");
+
+        sim.release_grab();
+        assert!(sim.grab_constraints.is_empty());
+        assert_eq!(sim.grab_force(0), Vec2::ZERO);
+    }
+}
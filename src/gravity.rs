@@ -0,0 +1,226 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::time::{Duration, Instant};
+
+use bevy::color::palettes::basic::LIME;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::compare::ComparisonCamera;
+use crate::components::{MainSimulation, Notifications};
+use crate::messages::MessageText;
+use crate::sim_struct::Simulation;
+
+/// How fast Ctrl+Left/Right tilt the gravity, in radians per second.
+const TILT_SPEED: f32 = 1.5;
+/// How far the gamepad's left stick has to be pushed before it steers the gravity.
+const STICK_DEADZONE: f32 = 0.5;
+/// The radius of the planet in point gravity mode, as a fraction of the smaller half of the tank.
+const PLANET_RADIUS: f32 = 0.25;
+/// How far the mouse can move between pressing and releasing the middle button for it to count as a click rather
+/// than a pan, in screen pixels.
+const CLICK_TOLERANCE: f32 = 4.0;
+
+/// Parses `--point-gravity`, as x,y.
+pub fn parse_point(arg: &str) -> Result<Vec2, String> {
+    let values = arg
+        .split(',')
+        .map(|value| value.trim().parse::<f32>().map_err(|e| format!("{value}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [x, y] => Ok(Vec2::new(x, y)),
+        _ => Err(format!("Expected x,y, got {} values", values.len())),
+    }
+}
+
+impl Simulation {
+    /// The gravity, tilted by gravity_angle. Like `gravity`, it points up: the acceleration is its negative.
+    pub fn gravity_vector(&self) -> Vec2 {
+        Vec2::from_angle(self.gravity_angle).rotate(self.gravity)
+    }
+
+    /// The gravity at a position, pointing away from the way it pulls, like `gravity`.
+    ///
+    /// In point gravity mode, it pulls toward gravity_center with a 1/r² falloff, and is as strong as the uniform
+    /// gravity at the planet's surface. Inside the planet, it falls off linearly to zero at the center, as it would in a
+    /// planet of uniform density, so that the particles that fall in aren't flung out again.
+    pub fn gravity_at(&self, position: Vec2) -> Vec2 {
+        if !self.point_gravity {
+            return self.gravity_vector();
+        }
+
+        let planet_radius = self.planet_radius();
+        let offset = position - self.gravity_center;
+        let distance = offset.length();
+        if distance <= 0.0 {
+            return Vec2::ZERO;
+        }
+        let falloff =
+            if distance < planet_radius { distance / planet_radius } else { (planet_radius / distance).powi(2) };
+        offset / distance * self.gravity.y * falloff
+    }
+
+    pub fn planet_radius(&self) -> f32 {
+        self.half_bounds_size.min_element() * PLANET_RADIUS
+    }
+
+    pub fn toggle_point_gravity(&mut self) {
+        self.point_gravity = !self.point_gravity;
+    }
+
+    pub fn toggle_gravity_spin(&mut self) {
+        self.gravity_spinning = !self.gravity_spinning;
+    }
+
+    /// Points the gravity straight down again, and stops it spinning.
+    pub fn reset_gravity_direction(&mut self) {
+        self.gravity_angle = 0.0;
+        self.gravity_spinning = false;
+    }
+
+    /// Turns the gravity as if the tank were rotating, when spinning is on. Called once per substep.
+    pub fn spin_gravity(&mut self, delta: f32) {
+        if self.gravity_spinning {
+            self.gravity_angle = (self.gravity_angle + self.gravity_spin_speed * delta).rem_euclid(TAU);
+        }
+    }
+}
+
+/// Tilts the gravity with Ctrl+Left/Right or the gamepad's left stick, toggles spinning with Ctrl+Up or the gamepad's
+/// north button, and resets the gravity with Ctrl+Down. Without Ctrl, the arrow keys pan the view.
+pub fn control_gravity(
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    time: Res<Time>,
+    mut messages: Single<&mut Notifications>,
+) {
+    let mut notify = |text: String| {
+        messages.messages.push(MessageText {
+            text,
+            start_time: Instant::now(),
+            duration: Duration::from_secs(1),
+        });
+    };
+
+    let mut toggle_spin = gamepads
+        .iter()
+        .any(|gamepad| gamepad.just_pressed(GamepadButton::North));
+    if kb.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        // A positive angle turns the (downward) acceleration toward the right.
+        if kb.pressed(KeyCode::ArrowLeft) {
+            sim.gravity_angle = (sim.gravity_angle - TILT_SPEED * time.delta_secs()).rem_euclid(TAU);
+        }
+        if kb.pressed(KeyCode::ArrowRight) {
+            sim.gravity_angle = (sim.gravity_angle + TILT_SPEED * time.delta_secs()).rem_euclid(TAU);
+        }
+        toggle_spin |= kb.just_pressed(KeyCode::ArrowUp);
+        if kb.just_pressed(KeyCode::ArrowDown) {
+            sim.reset_gravity_direction();
+            notify("Gravity: straight down".into());
+        }
+    }
+    if toggle_spin {
+        sim.toggle_gravity_spin();
+        notify(format!("Gravity spinning: {}", if sim.gravity_spinning { "on" } else { "off" }));
+    }
+
+    // The stick points the way the fluid should fall.
+    if let Some(stick) = gamepads
+        .iter()
+        .map(|gamepad| gamepad.left_stick())
+        .find(|stick| stick.length() > STICK_DEADZONE)
+    {
+        sim.gravity_angle = (stick.to_angle() + FRAC_PI_2).rem_euclid(TAU);
+    }
+}
+
+/// Moves the center of the point gravity to where the middle mouse button is clicked. Dragging with the middle button
+/// still pans the view, and while annotating, the middle button places labels instead.
+pub fn place_gravity_center(
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    mut press_position: Local<Option<Vec2>>,
+) {
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let cursor_position = window.cursor_position();
+    if buttons.just_pressed(MouseButton::Middle) {
+        *press_position = cursor_position;
+    }
+    if !buttons.just_released(MouseButton::Middle) || !sim.point_gravity || sim.debug.annotating {
+        return;
+    }
+
+    let (Some(pressed), Some(released)) = (press_position.take(), cursor_position) else {
+        return;
+    };
+    if pressed.distance(released) > CLICK_TOLERANCE {
+        return;
+    }
+    let Some((camera, camera_transform)) = cameras_query.iter().next() else {
+        return;
+    };
+    if let Ok(point) = camera.viewport_to_world_2d(camera_transform, released) {
+        sim.gravity_center = point;
+    }
+}
+
+/// Draws the direction of the gravity from the middle of the tank, while it isn't straight down, or the planet in
+/// point gravity mode.
+pub fn draw_gravity(mut gizmos: Gizmos, sim: Single<&Simulation, With<MainSimulation>>) {
+    if sim.point_gravity {
+        gizmos.circle_2d(sim.gravity_center, sim.planet_radius(), LIME);
+        gizmos.cross_2d(sim.gravity_center, sim.particle_size * 2.0, LIME);
+        return;
+    }
+    if (sim.gravity_angle == 0.0 && !sim.gravity_spinning) || sim.gravity == Vec2::ZERO {
+        return;
+    }
+
+    let length = sim.half_bounds_size.min_element() * 0.2;
+    let direction = -sim.gravity_vector().normalize();
+    gizmos
+        .arrow_2d(Vec2::ZERO, direction * length, LIME)
+        .with_tip_length(length * 0.2);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+    use crate::sim_struct::Simulation;
+
+    /// With point gravity, the fluid should gather around the planet rather than on the floor, without blowing up.
+    #[test]
+    fn point_gravity_gathers_the_fluid_around_the_planet() {
+        let mut sim = test_sim(
+            400,
+            400.0,
+            400.0,
+            Args {
+                point_gravity: Some(Vec2::ZERO),
+                ..Args::default()
+            },
+        );
+        let surface = sim.gravity_at(Vec2::new(sim.planet_radius(), 0.0));
+        assert!((surface.x - sim.gravity.y).abs() < 1e-3, "gravity at the surface {surface}");
+
+        let mean_distance = |sim: &Simulation| sim.positions().iter().map(|p| p.length()).sum::<f32>() / 400.0;
+        let start = mean_distance(&sim);
+        sim.step_n(1.0 / 60.0, 180);
+
+        for (i, position) in sim.positions().iter().enumerate() {
+            assert!(
+                position.is_finite() && position.abs().cmple(sim.half_bounds_size).all(),
+                "particle {i} is at {position}"
+            );
+        }
+        let end = mean_distance(&sim);
+        assert!(end < start * 0.85, "the fluid hasn't gathered: mean distance {start} -> {end}");
+    }
+}
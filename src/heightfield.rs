@@ -0,0 +1,123 @@
+/// A cheap 1D shallow-water heightfield used to simulate the deep, calm part of the tank.
+/// SPH particles sit on top of the heightfield surface: they collide with it (and are carried by its vertical motion),
+/// and their weight pushes the surface down, which generates waves that propagate through the heightfield.
+///
+/// The heightfield is a staggered grid: water heights are stored at the center of each column, and horizontal
+/// velocities are stored on the faces between columns. The outer faces are walls, so the volume of water is conserved.
+pub struct Heightfield {
+    /// Rest depth of the water, in pixels.
+    pub depth: f32,
+    /// Width of each column, in pixels.
+    pub column_width: f32,
+    /// X coordinate of the left edge of the first column.
+    pub left: f32,
+    /// Water height of each column.
+    pub heights: Vec<f32>,
+    /// Rate of change of each column's height, i.e. the vertical velocity of the surface.
+    pub surface_velocities: Vec<f32>,
+    /// Horizontal water velocity on the face between column i-1 and column i.
+    face_velocities: Vec<f32>,
+}
+
+impl Heightfield {
+    /// Fraction of the face velocity that's retained each step, to stop waves sloshing forever.
+    const DAMPING: f32 = 0.995;
+    /// Maximum distance a wave may travel in one substep, as a fraction of the column width.
+    const MAX_CFL: f32 = 0.5;
+
+    pub fn new(width: f32, depth: f32, column_width: f32) -> Self {
+        let columns = ((width / column_width) as usize).max(2);
+        let column_width = width / columns as f32;
+
+        Heightfield {
+            depth,
+            column_width,
+            left: -width / 2.0,
+            heights: vec![depth; columns],
+            surface_velocities: vec![0.0; columns],
+            face_velocities: vec![0.0; columns + 1],
+        }
+    }
+
+    pub fn column(&self, x: f32) -> usize {
+        (((x - self.left) / self.column_width) as usize).min(self.heights.len() - 1)
+    }
+
+    /// Height of the water surface above the floor at x, linearly interpolated between the column centers.
+    pub fn surface_height(&self, x: f32) -> f32 {
+        let position = ((x - self.left) / self.column_width - 0.5).clamp(0.0, (self.heights.len() - 1) as f32);
+        let column = (position as usize).min(self.heights.len() - 2);
+        let t = position - column as f32;
+
+        self.heights[column] * (1.0 - t) + self.heights[column + 1] * t
+    }
+
+    pub fn surface_velocity(&self, x: f32) -> f32 {
+        self.surface_velocities[self.column(x)]
+    }
+
+    /// Advances the heightfield by `delta` seconds.
+    /// `loads` is the additional water height that each column has to support, due to the particles resting on it.
+    pub fn step(&mut self, delta: f32, gravity: f32, loads: &[f32]) {
+        let previous = self.heights.clone();
+
+        // Split the step up if the waves would otherwise travel too far per step.
+        let max_height = self.heights.iter().cloned().fold(self.depth, f32::max);
+        let wave_speed = (gravity * max_height).sqrt();
+        let substeps = ((wave_speed * delta / (self.column_width * Self::MAX_CFL)).ceil() as usize).max(1);
+        let dt = delta / substeps as f32;
+
+        for _ in 0..substeps {
+            // Accelerate the water from the high (or heavily loaded) columns toward the low ones.
+            for face in 1..self.heights.len() {
+                let head_left = self.heights[face - 1] + loads[face - 1];
+                let head_right = self.heights[face] + loads[face];
+                self.face_velocities[face] += -gravity * dt * (head_right - head_left) / self.column_width;
+                self.face_velocities[face] *= Self::DAMPING;
+            }
+
+            // Move the water across the faces, taking it from the upwind column.
+            let fluxes: Vec<f32> = (0..self.face_velocities.len())
+                .map(|face| {
+                    let velocity = self.face_velocities[face];
+                    let upwind = if velocity > 0.0 { face.wrapping_sub(1) } else { face };
+                    self.heights.get(upwind).map_or(0.0, |&height| velocity * height)
+                })
+                .collect();
+            for column in 0..self.heights.len() {
+                self.heights[column] -= dt * (fluxes[column + 1] - fluxes[column]) / self.column_width;
+                self.heights[column] = self.heights[column].max(0.0);
+            }
+        }
+
+        for ((surface_velocity, height), previous) in
+            self.surface_velocities.iter_mut().zip(&self.heights).zip(previous)
+        {
+            *surface_velocity = (height - previous) / delta;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drop a load on one side of the heightfield, and verify that the water sloshes around without any being
+    /// created or destroyed.
+    #[test]
+    fn heightfield_conserves_volume() {
+        let mut heightfield = Heightfield::new(800.0, 200.0, 20.0);
+        let initial_volume: f32 = heightfield.heights.iter().sum();
+        let mut loads = vec![0f32; heightfield.heights.len()];
+        loads[..5].fill(50.0);
+
+        for _ in 0..600 {
+            heightfield.step(1.0 / 60.0, 300.0, &loads);
+        }
+
+        let volume: f32 = heightfield.heights.iter().sum();
+        assert!((volume - initial_volume).abs() / initial_volume < 1e-3);
+        // The load should have pushed the water away from the left side.
+        assert!(heightfield.heights[0] < heightfield.heights[heightfield.heights.len() - 1]);
+    }
+}
@@ -0,0 +1,180 @@
+use bevy::color::palettes::css::GOLD;
+use bevy::prelude::*;
+
+use crate::components::{KeyboardCommands, MainSimulation};
+use crate::key_bindings::KeyBindings;
+use crate::keyboard::key_to_string;
+use crate::sim_struct::Simulation;
+
+/// Rows of the help panel per page.
+const ROWS_PER_PAGE: usize = 18;
+
+/// The keyboard help panel, toggled with ?. PageUp and PageDown turn its pages.
+#[derive(Component, Default)]
+pub struct HelpPanel {
+    page: usize,
+}
+
+/// One of the help panel's columns, which hold a line for each row.
+#[derive(Component, Clone, Copy)]
+pub enum HelpColumn {
+    Key,
+    Description,
+    Value,
+}
+
+#[derive(Component)]
+pub struct HelpFooter;
+
+pub fn spawn_help_panel(commands: &mut Commands) {
+    let column = |column: HelpColumn, color: Color| {
+        (
+            Text::default(),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(color),
+            column,
+        )
+    };
+
+    commands.spawn((
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            top: Val::Px(30.0),
+            left: Val::Px(5.0),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(8.0)),
+            row_gap: Val::Px(6.0),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.85)),
+        HelpPanel::default(),
+        children![
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(16.0),
+                    ..default()
+                },
+                children![
+                    column(HelpColumn::Key, GOLD.into()),
+                    column(HelpColumn::Description, Color::WHITE),
+                    column(HelpColumn::Value, GOLD.into()),
+                ],
+            ),
+            (
+                Text::default(),
+                TextFont {
+                    font_size: 12.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.6, 0.6, 0.6)),
+                HelpFooter,
+            ),
+        ],
+    ));
+}
+
+/// The rows of the help panel: the key, what it does, and the current value of what it changes, if anything. The
+/// commands are sorted by key, followed by the keys that are handled outside the command table.
+fn help_rows(kb_cmds: &KeyboardCommands, bindings: &KeyBindings, sim: &Simulation) -> Vec<[String; 3]> {
+    let mut rows: Vec<[String; 3]> = kb_cmds
+        .commands
+        .values()
+        .map(|command| {
+            [
+                command.key_text.clone(),
+                command.description.clone(),
+                command.value.map_or_else(String::new, |value| value(sim)),
+            ]
+        })
+        .collect();
+    let pause = key_to_string(bindings.pause_key());
+    let quit = format!("Esc / {}", key_to_string(bindings.quit_key()));
+    for (key, description) in [
+        (pause.as_str(), "Pause"),
+        ("1..9", "Advance 1..9 frames"),
+        ("Arrows", "Pan (also middle-drag; scroll to zoom)"),
+        ("0", "Reset the view"),
+        ("Tab+drag", "Paint obstacles (right button: erase)"),
+        ("=/-+drag", "Add / delete particles"),
+        ("'+drag", "Inject dye"),
+        ("Ctrl+Left/Right", "Tilt gravity (Ctrl+Up: spin, Ctrl+Down: reset)"),
+        (quit.as_str(), "Quit"),
+    ] {
+        rows.push([key.into(), description.into(), String::new()]);
+    }
+    rows
+}
+
+/// ? shows and hides the help panel, and PageUp and PageDown turn its pages. While it's shown, it's refreshed every
+/// frame, so that the values follow the commands.
+#[allow(clippy::type_complexity)]
+pub fn update_help_panel(
+    kb: Res<ButtonInput<KeyCode>>,
+    kb_cmds: Single<&KeyboardCommands>,
+    bindings: Res<KeyBindings>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    panel: Single<(&mut HelpPanel, &mut Node)>,
+    mut columns: Query<(&mut Text, &HelpColumn)>,
+    mut footer: Single<&mut Text, (With<HelpFooter>, Without<HelpColumn>)>,
+) {
+    let (mut panel, mut node) = panel.into_inner();
+    let shown = node.display != Display::None;
+    if kb.just_pressed(KeyCode::Slash) && kb.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        node.display = if shown { Display::None } else { Display::Flex };
+        panel.page = 0;
+    } else if !shown {
+        return;
+    }
+
+    let rows = help_rows(&kb_cmds, &bindings, &sim);
+    let pages = rows.len().div_ceil(ROWS_PER_PAGE);
+    if kb.just_pressed(KeyCode::PageDown) {
+        panel.page = (panel.page + 1).min(pages - 1);
+    }
+    if kb.just_pressed(KeyCode::PageUp) {
+        panel.page = panel.page.saturating_sub(1);
+    }
+
+    let page_rows = rows.chunks(ROWS_PER_PAGE).nth(panel.page).unwrap_or_default();
+    for (mut text, &column) in &mut columns {
+        text.0 = page_rows
+            .iter()
+            .map(|row| row[column as usize].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    footer.0 = format!("Page {}/{pages} (PageUp/PageDown)   ? to close", panel.page + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::help_rows;
+    use crate::args::Args;
+    use crate::components::KeyboardCommands;
+    use crate::key_bindings::KeyBindings;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn help_lists_the_commands_by_key_with_their_values() {
+        let sim = Simulation::new(
+            200.0,
+            200.0,
+            &Args {
+                num_particles: 10,
+                ..Args::default()
+            },
+        );
+        let kb_cmds = KeyboardCommands::create(&["Dam break".into()], &KeyBindings::default());
+        let rows = help_rows(&kb_cmds, &KeyBindings::default(), &sim);
+        assert_eq!(rows[0][0], "Shift+1");
+        assert_eq!(rows[1][0], "A");
+        let particles = rows.iter().find(|row| row[0] == "N").unwrap();
+        assert_eq!(particles[2], "10");
+        assert_eq!(rows.last().unwrap()[0], "Esc / Q");
+    }
+}
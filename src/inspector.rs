@@ -0,0 +1,224 @@
+use bevy::color::palettes::css::GOLD;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::compare::ComparisonCamera;
+use crate::components::{MainSimulation, Particle};
+use crate::interaction::{InteractionMode, InteractionModes, Modifiers};
+use crate::sim_struct::Simulation;
+
+/// A popup showing the full state of a single particle, opened by clicking near the particle in the Inspect
+/// interaction mode (ctrl+shift+click by default).
+#[derive(Component, Default)]
+pub struct ParticleInspector {
+    pub particle_id: Option<usize>,
+}
+
+#[derive(Component)]
+pub struct InspectorText;
+
+/// A panel in the corner of the window showing the live state of the watched particles (W key), updated every frame.
+#[derive(Component)]
+pub struct WatchPanel;
+
+#[derive(Component, Clone, Copy)]
+pub enum InspectorButton {
+    Watch,
+    Unwatch,
+    Close,
+}
+
+pub fn spawn_inspector(commands: &mut Commands) {
+    let button = |label: &str, action: InspectorButton| {
+        (
+            Button,
+            Node {
+                padding: UiRect::axes(Val::Px(8.0), Val::Px(2.0)),
+                margin: UiRect::right(Val::Px(4.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.2, 0.2, 0.35)),
+            action,
+            children![(
+                Text::new(label),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+            )],
+        )
+    };
+
+    commands.spawn((
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(6.0)),
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        ParticleInspector::default(),
+        children![
+            (
+                Text::default(),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(GOLD.into()),
+                InspectorText,
+            ),
+            (
+                Node {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                },
+                children![
+                    button("Watch", InspectorButton::Watch),
+                    button("Unwatch", InspectorButton::Unwatch),
+                    button("Close", InspectorButton::Close),
+                ],
+            ),
+        ],
+    ));
+}
+
+pub fn spawn_watch_panel(commands: &mut Commands) {
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(GOLD.into()),
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            right: Val::Px(5.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        WatchPanel,
+    ));
+}
+
+/// The state of a particle, one value per line.
+fn particle_state(sim: &Simulation, particle_id: usize) -> String {
+    format!(
+        "position: ({:.1}, {:.1})\nvelocity: ({:.1}, {:.1})\ndensity:  {:.5}\npressure: {:.3}\nneighbors: {}",
+        sim.positions[particle_id].x,
+        sim.positions[particle_id].y,
+        sim.velocities[particle_id].x,
+        sim.velocities[particle_id].y,
+        sim.densities[particle_id],
+        sim.pressures[particle_id],
+        sim.neighbor_count(particle_id),
+    )
+}
+
+/// Select the particle nearest the cursor, and open the inspector next to it.
+pub fn select_inspected_particle(
+    buttons: Res<ButtonInput<MouseButton>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    interaction_modes: Res<InteractionModes>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    mut inspector: Single<(&mut ParticleInspector, &mut Node)>,
+) {
+    if !buttons.just_pressed(MouseButton::Left)
+        || interaction_modes.mode(Modifiers::pressed(&kb)) != InteractionMode::Inspect
+    {
+        return;
+    }
+
+    if let Ok(window) = windows.single()
+        && let Some(cursor_position) = window.cursor_position()
+        && let Some((camera, camera_transform)) = cameras_query.iter().next()
+        && let Ok(point) = camera.viewport_to_world_2d(camera_transform, cursor_position)
+        && let Some(particle_id) = sim.nearest_particle(point)
+    {
+        let (inspector, node) = &mut *inspector;
+        inspector.particle_id = Some(particle_id);
+        node.display = Display::Flex;
+        node.left = Val::Px(cursor_position.x + 10.0);
+        node.top = Val::Px(cursor_position.y + 10.0);
+    }
+}
+
+/// Refreshes the inspector text every frame, and handles its buttons.
+pub fn update_inspector(
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut inspector: Single<(&mut ParticleInspector, &mut Node)>,
+    mut text: Single<&mut Text, With<InspectorText>>,
+    interactions: Query<(&Interaction, &InspectorButton), Changed<Interaction>>,
+    mut particle_query: Query<&mut Particle>,
+) {
+    let (inspector, node) = &mut *inspector;
+    let Some(particle_id) = inspector.particle_id.filter(|&id| id < sim.num_particles) else {
+        return;
+    };
+
+    for (interaction, button) in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            InspectorButton::Watch | InspectorButton::Unwatch => {
+                let watched = matches!(button, InspectorButton::Watch);
+                particle_query
+                    .iter_mut()
+                    .filter(|particle| particle.id == particle_id)
+                    .for_each(|mut particle| particle.watched = watched);
+            }
+            InspectorButton::Close => {
+                inspector.particle_id = None;
+                node.display = Display::None;
+                return;
+            }
+        }
+    }
+
+    let watched = particle_query
+        .iter()
+        .any(|particle| particle.id == particle_id && particle.watched);
+    let cell = sim.particle_cell(particle_id);
+    text.0 = format!(
+        "Particle {particle_id}{}{}\n{}\ncell:     ({}, {})",
+        if watched { " (watched)" } else { "" },
+        if sim.fluid_types.len() > 1 { format!(" ({})", sim.fluid_type(particle_id).name) } else { String::new() },
+        particle_state(&sim, particle_id),
+        cell.x,
+        cell.y,
+    );
+}
+
+/// Refreshes the watch panel every frame, hiding it when no particles are being watched.
+pub fn update_watch_panel(
+    sim: Single<&Simulation, With<MainSimulation>>,
+    particle_query: Query<&Particle>,
+    panel: Single<(&mut Text, &mut Node), With<WatchPanel>>,
+) {
+    let (mut text, mut node) = panel.into_inner();
+    let mut watched: Vec<usize> = particle_query
+        .iter()
+        .filter(|particle| particle.watched && particle.id < sim.num_particles)
+        .map(|particle| particle.id)
+        .collect();
+    if watched.is_empty() {
+        node.display = Display::None;
+        return;
+    }
+
+    watched.sort_unstable();
+    node.display = Display::Flex;
+    text.0 = watched
+        .iter()
+        .map(|&particle_id| format!("Watching particle {particle_id}\n{}", particle_state(&sim, particle_id)))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+}
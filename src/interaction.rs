@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use bevy::color::palettes::css::ORANGE;
+use bevy::input::ButtonInput;
+use bevy::math::Vec2;
+use bevy::prelude::{
+    Camera, Gizmos, GlobalTransform, KeyCode, MouseButton, Query, Res, Resource, Single, State, Window, With, Without,
+};
+use bevy::window::PrimaryWindow;
+use clap::ValueEnum;
+
+use crate::compare::ComparisonCamera;
+use crate::components::MainSimulation;
+use crate::sim_state::SimState;
+use crate::sim_struct::Simulation;
+
+/// What a mouse click does, depending on the modifier keys held down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InteractionMode {
+    /// Left-click attracts, right-click repels.
+    #[default]
+    Normal,
+    /// Like Normal, but twice as strong.
+    Double,
+    /// Swirl the particles around the cursor: left-click counter-clockwise, right-click clockwise.
+    Vortex,
+    /// Like Normal, but only affects particles slower than the threshold.
+    SlowOnly,
+    /// Open the particle inspector for the particle nearest the cursor.
+    Inspect,
+    /// Attach the particles within the interaction radius to the cursor with springs, and drag them along until the
+    /// button is released.
+    Grab,
+    /// Blast the particles away from the cursor with a single impulse, as a double-click does in any mode.
+    Explode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub fn pressed(kb: &ButtonInput<KeyCode>) -> Self {
+        Modifiers {
+            shift: kb.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+            ctrl: kb.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+            alt: kb.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]),
+        }
+    }
+}
+
+/// Maps modifier key combinations to interaction modes.
+#[derive(Resource)]
+pub struct InteractionModes {
+    pub table: HashMap<Modifiers, InteractionMode>,
+}
+
+impl InteractionModes {
+    /// Parses a table of the form "shift=double,ctrl=vortex,ctrl+shift=inspect".
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut table = HashMap::new();
+
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (keys, mode) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Interaction mode entry '{entry}' should be modifiers=mode"))?;
+            let mut modifiers = Modifiers::default();
+            for key in keys.split('+') {
+                match key.trim().to_lowercase().as_str() {
+                    "shift" => modifiers.shift = true,
+                    "ctrl" | "control" => modifiers.ctrl = true,
+                    "alt" | "option" => modifiers.alt = true,
+                    other => return Err(format!("Unknown modifier key '{other}'")),
+                }
+            }
+            let mode = InteractionMode::from_str(mode.trim(), true)?;
+            table.insert(modifiers, mode);
+        }
+
+        Ok(InteractionModes { table })
+    }
+
+    pub fn mode(&self, modifiers: Modifiers) -> InteractionMode {
+        self.table.get(&modifiers).copied().unwrap_or_default()
+    }
+}
+
+/// While paused, draws the acceleration that the interaction would apply around the cursor (in addition to gravity) as
+/// a field of arrows, so that the effect can be positioned before stepping the simulation.
+/// The arrows show a left-click, unless the right button is held down.
+#[allow(clippy::too_many_arguments)] // ECS gonna ECS.
+pub fn draw_interaction_preview(
+    mut gizmos: Gizmos,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    interaction_modes: Res<InteractionModes>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    state: Res<State<SimState>>,
+) {
+    let mode = interaction_modes.mode(Modifiers::pressed(&kb));
+    if *state.get() != SimState::Paused
+        || matches!(mode, InteractionMode::Inspect | InteractionMode::Grab | InteractionMode::Explode)
+    {
+        return;
+    }
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let Some(point) = window.cursor_position().and_then(|cursor_position| {
+        let (camera, camera_transform) = cameras_query.iter().next()?;
+        camera.viewport_to_world_2d(camera_transform, cursor_position).ok()
+    }) else {
+        return;
+    };
+
+    let strength = sim.interaction_input_strength.abs() * if buttons.pressed(MouseButton::Right) { -1.0 } else { 1.0 };
+    const STEPS: i32 = 6;
+    let spacing = sim.interaction_input_radius / STEPS as f32;
+    let arrows: Vec<(Vec2, Vec2)> = (-STEPS..=STEPS)
+        .flat_map(|row| (-STEPS..=STEPS).map(move |col| point + Vec2::new(col as f32, row as f32) * spacing))
+        .filter_map(|pos| {
+            let accel = sim.interaction_acceleration(point, mode, strength, pos, Vec2::ZERO)?;
+            Some((pos, accel + sim.gravity_at(pos)))
+        })
+        .collect();
+
+    // Scale the arrows so that the strongest one is as long as the grid spacing.
+    let max_accel = arrows.iter().map(|(_, accel)| accel.length()).fold(0.0, f32::max);
+    if max_accel <= 0.0 {
+        return;
+    }
+    gizmos.circle_2d(point, sim.interaction_input_radius, ORANGE);
+    for (pos, accel) in arrows {
+        gizmos
+            .arrow_2d(pos, pos + accel / max_accel * spacing, ORANGE)
+            .with_tip_length(spacing / 4.0);
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::components::KeyboardCommands;
+
+/// The action names of the keys that aren't in the keyboard command table.
+pub const PAUSE: &str = "pause";
+pub const QUIT: &str = "quit";
+
+/// The keys that can be used in a key bindings file, which are named as in Bevy's KeyCode.
+const NAMED_KEYS: [KeyCode; 79] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Backquote,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Backslash,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Enter,
+    KeyCode::Backspace,
+    KeyCode::Delete,
+    KeyCode::Insert,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+];
+
+/// The keys of the commands, by action name, as rebound by the `--key-bindings` file. Actions that aren't in it keep
+/// their default keys.
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct KeyBindings(pub BTreeMap<String, KeyCode>);
+
+impl KeyBindings {
+    /// The key for the action.
+    pub fn key(&self, action: &str, default: KeyCode) -> KeyCode {
+        self.0.get(action).copied().unwrap_or(default)
+    }
+
+    /// Pauses and resumes the simulations (see control_sim_state).
+    pub fn pause_key(&self) -> KeyCode {
+        self.key(PAUSE, KeyCode::Space)
+    }
+
+    /// Quits, as well as Esc.
+    pub fn quit_key(&self) -> KeyCode {
+        self.key(QUIT, KeyCode::KeyQ)
+    }
+
+    /// The default key for every action that can be rebound.
+    pub fn defaults() -> Self {
+        let unbound = KeyBindings::default();
+        let kb_cmds = KeyboardCommands::create(&[], &unbound);
+        let mut keys: BTreeMap<String, KeyCode> = kb_cmds
+            .commands
+            .iter()
+            .filter_map(|(&key, command)| Some((command.action_name?.to_string(), key)))
+            .collect();
+        keys.insert(PAUSE.into(), unbound.pause_key());
+        keys.insert(QUIT.into(), unbound.quit_key());
+        KeyBindings(keys)
+    }
+
+    /// Loads a key bindings file, first writing it with the default bindings if it doesn't exist yet, so that there's
+    /// something to edit.
+    pub fn load_or_create(path: &str) -> Result<Self, String> {
+        if !Path::new(path).exists() {
+            let defaults = Self::defaults();
+            fs::write(path, defaults.to_ron()?).map_err(|e| format!("Couldn't write {path}: {e}"))?;
+            return Ok(defaults);
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {path}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("Couldn't parse {path}: {e}"))
+    }
+
+    /// Parses a map from action names to key names, e.g. `{"pause": "KeyP", "reset": "Backspace"}`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let names: BTreeMap<String, String> = ron::from_str(text).map_err(|e| e.to_string())?;
+        let actions = Self::defaults();
+        let mut keys = BTreeMap::new();
+        for (action, key_name) in names {
+            if !actions.0.contains_key(&action) {
+                return Err(format!("Unknown action '{action}'"));
+            }
+            let key = NAMED_KEYS
+                .into_iter()
+                .find(|key| format!("{key:?}") == key_name)
+                .ok_or_else(|| format!("Unknown key '{key_name}' for '{action}'"))?;
+            keys.insert(action, key);
+        }
+        Ok(KeyBindings(keys))
+    }
+
+    fn to_ron(&self) -> Result<String, String> {
+        let names: BTreeMap<&str, String> = self
+            .0
+            .iter()
+            .map(|(action, key)| (action.as_str(), format!("{key:?}")))
+            .collect();
+        ron::ser::to_string_pretty(&names, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::KeyCode;
+
+    use super::KeyBindings;
+    use crate::components::KeyboardCommands;
+
+    #[test]
+    fn commands_can_be_rebound() {
+        let defaults = KeyBindings::defaults();
+        assert_eq!(KeyBindings::parse(&defaults.to_ron().unwrap()).unwrap(), defaults);
+        assert!(KeyBindings::parse(r#"{"reset": "Hyper"}"#).is_err());
+        assert!(KeyBindings::parse(r#"{"self_destruct": "KeyR"}"#).is_err());
+
+        let bindings = KeyBindings::parse(r#"{"reset": "End", "pause": "Enter"}"#).unwrap();
+        assert_eq!(bindings.pause_key(), KeyCode::Enter);
+        let kb_cmds = KeyboardCommands::create(&[], &bindings);
+        assert_eq!(kb_cmds.commands[&KeyCode::End].action_name, Some("reset"));
+        assert!(!kb_cmds.commands.contains_key(&KeyCode::KeyR));
+        assert_eq!(kb_cmds.commands[&KeyCode::End].key_text, "End");
+    }
+}
@@ -1,10 +1,13 @@
 use std::collections::BTreeMap;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use bevy::prelude::*;
 
 use crate::MessageText;
 use crate::components::*;
+use crate::digit_keys::DIGIT_KEYS;
+use crate::key_bindings::{KeyBindings, PAUSE, QUIT};
+use crate::share_code::{ParamsCode, copy_to_clipboard};
 use crate::sim_struct::Simulation;
 
 /// Defines a keyboard command to associate with a keypress.
@@ -15,6 +18,17 @@ pub struct KeyboardCommand {
     pub last_action_time: Instant,
     pub interval: Duration,
     pub action: KeyboardAction,
+    /// The name that the command is rebound by in the key bindings file, if it can be.
+    pub action_name: Option<&'static str>,
+    /// The current value of what the command changes, for the help panel.
+    pub value: Option<fn(&Simulation) -> String>,
+}
+
+impl KeyboardCommand {
+    /// Shows the value in the help panel.
+    pub fn showing(&mut self, value: fn(&Simulation) -> String) {
+        self.value = Some(value);
+    }
 }
 
 /// The function that invokes the keyboard action.
@@ -30,68 +44,393 @@ type KeyboardAction = fn(
 );
 
 impl KeyboardCommands {
-    pub fn create() -> Self {
+    /// `scenario_names` are the names of the simulation's scenarios, in order, for the digit keys' descriptions.
+    /// `bindings` rebinds the commands by their action names.
+    pub fn create(scenario_names: &[String], bindings: &KeyBindings) -> Self {
         let mut kb_cmds = KeyboardCommands {
             commands: BTreeMap::new(),
         };
 
-        // Space: freeze / unfreeze particle motion.
-        kb_cmds.add_command(KeyCode::Space, "Pause", 250, pause);
-
-        // 1: advance 1 frames.
-        kb_cmds.add_command(KeyCode::Digit1, "Advance 1 frame", 50, |sim, _, _, _, _| sim.set_frames_to_show(1));
+        // Space (pause) and the digits without Shift (advance N frames) are handled by control_sim_state, as they
+        // change the SimState.
+        // Shift+1-9: load one of the scenarios.
+        let scenario_actions: [KeyboardAction; 9] = [
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 0, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 1, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 2, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 3, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 4, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 5, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 6, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 7, msgs),
+            |sim, shift, _, _, msgs| load_scenario(sim, shift, 8, msgs),
+        ];
+        for ((key, action), name) in DIGIT_KEYS.into_iter().zip(scenario_actions).zip(scenario_names) {
+            kb_cmds.add_command(key, &format!("Load scenario: {name}"), 500, action);
+            if let Some(command) = kb_cmds.commands.get_mut(&key) {
+                command.key_text = format!("Shift+{}", command.key_text);
+            }
+        }
         // A: toggle velocity arrows
-        kb_cmds.add_command(KeyCode::KeyA, "Toggle velocity arrows", 250, |sim, _, _, _, _| sim.toggle_arrows());
+        kb_cmds
+            .bind_command(
+                bindings,
+                "velocity_arrows",
+                KeyCode::KeyA,
+                "Toggle velocity arrows",
+                250,
+                |sim, _, _, _, _| sim.toggle_arrows(),
+            )
+            .showing(|sim| on_off(sim.debug.show_arrows));
+        // B: toggle bottomless mode.
+        kb_cmds
+            .bind_command(bindings, "bottomless", KeyCode::KeyB, "Toggle bottomless mode", 500, toggle_bottomless)
+            .showing(|sim| on_off(sim.bottomless));
         // C: toggle display of smoothing radius circle.
-        kb_cmds.add_command(KeyCode::KeyC, "Show smoothing radius around particle 0", 250, |sim, _, _, _, _| {
-            sim.toggle_smoothing_radius()
-        });
+        kb_cmds
+            .bind_command(
+                bindings,
+                "smoothing_radius_circle",
+                KeyCode::KeyC,
+                "Show smoothing radius around particle 0",
+                250,
+                |sim, _, _, _, _| sim.toggle_smoothing_radius(),
+            )
+            .showing(|sim| on_off(sim.debug.show_smoothing_radius));
+        // D: toggle density-scaled sprites
+        kb_cmds
+            .bind_command(
+                bindings,
+                "density_scaled_sprites",
+                KeyCode::KeyD,
+                "Toggle density-scaled sprites",
+                500,
+                |sim, _, _, _, _| sim.toggle_density_scaled_sprites(),
+            )
+            .showing(|sim| on_off(sim.debug.density_scaled_sprites));
+        // E: toggle annotation mode
+        kb_cmds
+            .bind_command(
+                bindings,
+                "annotate",
+                KeyCode::KeyE,
+                "Toggle annotation mode (Backspace: clear)",
+                250,
+                toggle_annotating,
+            )
+            .showing(|sim| on_off(sim.debug.annotating));
         // F: toggle FPS
-        kb_cmds.add_command(KeyCode::KeyF, "Toggle FPS", 500, toggle_fps);
+        kb_cmds
+            .bind_command(bindings, "fps", KeyCode::KeyF, "Toggle FPS", 500, toggle_fps)
+            .showing(|sim| on_off(sim.debug.show_fps));
         // G: increase/decrease gravity
-        kb_cmds.add_command(KeyCode::KeyG, "Decrease gravity (shift: inc)", 50, adj_gravity);
-        // H: toggle heat map
-        kb_cmds.add_command(KeyCode::KeyH, "Toggle heatmap", 500, toggle_heatmap);
-        // I: toggle inertia
-        kb_cmds.add_command(KeyCode::KeyI, "Reset inertia", 250, reset_inertia);
+        kb_cmds
+            .bind_command(bindings, "gravity", KeyCode::KeyG, "Decrease gravity (shift: inc)", 50, adj_gravity)
+            .showing(|sim| format!("{:.2}", sim.units.to_units(sim.gravity.y)));
+        // H: cycle the field the particles are colored by
+        kb_cmds
+            .bind_command(
+                bindings,
+                "color_field",
+                KeyCode::KeyH,
+                "Color by next field (shift: next colormap)",
+                500,
+                toggle_heatmap,
+            )
+            .showing(|sim| format!("{} ({})", sim.debug.color_field.name(), sim.debug.colormap.name()));
+        // I: zero the velocities, keeping the positions
+        kb_cmds.bind_command(
+            bindings,
+            "zero_velocities",
+            KeyCode::KeyI,
+            "Zero velocities, keeping positions",
+            250,
+            reset_inertia,
+        );
+        // J: drop (or remove) a pinch anchor at the cursor.
+        kb_cmds.bind_command(
+            bindings,
+            "pinch_anchor",
+            KeyCode::KeyJ,
+            "Toggle pinch anchor at cursor",
+            250,
+            toggle_pinch_anchor,
+        );
+        // K: toggle the wall impulse heatmap.
+        kb_cmds
+            .bind_command(
+                bindings,
+                "wall_impulses",
+                KeyCode::KeyK,
+                "Toggle wall impulse heatmap",
+                500,
+                |sim, _, _, _, _| sim.toggle_wall_impulses(),
+            )
+            .showing(|sim| on_off(sim.debug.show_wall_impulses));
         // L: log debug info in the next frame
-        kb_cmds.add_command(KeyCode::KeyL, "Log debug info", 250, |sim, _, _, _, _| sim.log_next_frame());
+        kb_cmds.bind_command(bindings, "log_debug_info", KeyCode::KeyL, "Log debug info", 250, |sim, _, _, _, _| {
+            sim.log_next_frame()
+        });
         // P: toggle use of predicted positions
-        kb_cmds.add_command(KeyCode::KeyP, "Decrease pressure multiplier (shift: inc)", 100, adj_pressure);
+        kb_cmds
+            .bind_command(
+                bindings,
+                "pressure",
+                KeyCode::KeyP,
+                "Decrease pressure multiplier (shift: inc)",
+                100,
+                adj_pressure,
+            )
+            .showing(|sim| format!("{:.1}", sim.units.to_units(sim.pressure_multiplier)));
+        // M: toggle point gravity (planet) mode.
+        kb_cmds
+            .bind_command(
+                bindings,
+                "point_gravity",
+                KeyCode::KeyM,
+                "Toggle point gravity (middle click: move)",
+                500,
+                toggle_point_gravity,
+            )
+            .showing(|sim| on_off(sim.point_gravity));
+        // N: decrease/increase the number of particles.
+        kb_cmds
+            .bind_command(
+                bindings,
+                "num_particles",
+                KeyCode::KeyN,
+                "Decrease number of particles (shift: inc)",
+                100,
+                adj_num_particles,
+            )
+            .showing(|sim| sim.num_particles.to_string());
         // O: toggle use of predicted positions
-        kb_cmds.add_command(KeyCode::KeyO, "Toggle use of predicted positions", 500, toggle_predicted);
+        kb_cmds
+            .bind_command(
+                bindings,
+                "predicted_positions",
+                KeyCode::KeyO,
+                "Toggle use of predicted positions",
+                500,
+                toggle_predicted,
+            )
+            .showing(|sim| on_off(sim.debug.use_predicted_positions));
         // R: reset the simulation
-        kb_cmds.add_command(KeyCode::KeyR, "Reset particles", 250, |sim, _, _, _, _| sim.reset());
+        // Shift-R: reset with the next initial placement
+        kb_cmds
+            .bind_command(
+                bindings,
+                "reset",
+                KeyCode::KeyR,
+                "Reset particles (shift: next placement)",
+                250,
+                reset_particles,
+            )
+            .showing(|sim| sim.placement.name());
         // S: increase/decrease smoothing radius.
-        kb_cmds.add_command(KeyCode::KeyS, "Decrease smoothing radius (shift: inc)", 250, adj_smoothing_radius);
+        kb_cmds
+            .bind_command(
+                bindings,
+                "smoothing_radius",
+                KeyCode::KeyS,
+                "Decrease smoothing radius (shift: inc)",
+                250,
+                adj_smoothing_radius,
+            )
+            .showing(|sim| format!("{:.2}", sim.smoothing_radius / sim.particle_size));
+        // T: start/stop recording the rendered frames.
+        kb_cmds
+            .bind_command(bindings, "record", KeyCode::KeyT, "Toggle frame recording", 500, toggle_recording)
+            .showing(|sim| on_off(sim.debug.recording));
+        // U: toggle the wind.
+        kb_cmds
+            .bind_command(bindings, "wind", KeyCode::KeyU, "Toggle wind", 500, toggle_wind)
+            .showing(|sim| on_off(sim.wind.enabled));
         // V: increase/decrease viscosity strength.
-        kb_cmds.add_command(KeyCode::KeyV, "Decrease smoothing radius (shift: inc)", 50, adj_viscosity);
+        kb_cmds
+            .bind_command(bindings, "viscosity", KeyCode::KeyV, "Decrease viscosity (shift: inc)", 50, adj_viscosity)
+            .showing(|sim| format!("{:.2}", sim.viscosity_strength));
         // W: "watch" the particle(s) under the cursor (color them yellow).
         // Shift-W: clear all watched particles.
-        kb_cmds.add_command(KeyCode::KeyW, "Watch (highlight) particle under cursor", 250, watch_particle);
+        kb_cmds.bind_command(
+            bindings,
+            "watch",
+            KeyCode::KeyW,
+            "Watch (highlight) particle under cursor",
+            250,
+            watch_particle,
+        );
         // X: toggle region grid
-        kb_cmds.add_command(KeyCode::KeyX, "Display region grid", 500, |sim, _, _, _, _| sim.toggle_region_grid());
+        kb_cmds
+            .bind_command(bindings, "region_grid", KeyCode::KeyX, "Display region grid", 500, |sim, _, _, _, _| {
+                sim.toggle_region_grid()
+            })
+            .showing(|sim| on_off(sim.debug.show_region_grid));
+
+        // Y: increase/decrease the wind speed.
+        kb_cmds
+            .bind_command(bindings, "wind_speed", KeyCode::KeyY, "Decrease wind speed (shift: inc)", 50, adj_wind)
+            .showing(|sim| format!("{:.2}", sim.units.to_units(sim.wind.speed)));
+
+        // Z: "surprise me", randomize some parameters.
+        kb_cmds.bind_command(bindings, "randomize", KeyCode::KeyZ, "Randomize parameters", 500, randomize);
+
+        // F2: save the scenario, with any painted obstacles.
+        kb_cmds.bind_command(
+            bindings,
+            "save_scenario",
+            KeyCode::F2,
+            "Save scenario (with painted obstacles)",
+            1000,
+            save_scenario,
+        );
+        // F3: toggle the density field background.
+        kb_cmds
+            .bind_command(
+                bindings,
+                "density_background",
+                KeyCode::F3,
+                "Toggle density background",
+                250,
+                toggle_density_background,
+            )
+            .showing(|sim| on_off(sim.debug.density_background));
+        // F4: toggle the streamlines.
+        kb_cmds
+            .bind_command(bindings, "streamlines", KeyCode::F4, "Toggle streamlines", 250, toggle_streamlines)
+            .showing(|sim| on_off(sim.debug.show_streamlines));
+        // F11: toggle the foam, spray and bubbles.
+        kb_cmds
+            .bind_command(bindings, "foam", KeyCode::F11, "Toggle foam, spray and bubbles", 250, toggle_foam)
+            .showing(|sim| on_off(sim.foam.is_some()));
+        // F12: toggle the surface outline.
+        kb_cmds
+            .bind_command(bindings, "surface", KeyCode::F12, "Toggle surface outline", 250, toggle_surface)
+            .showing(|sim| on_off(sim.debug.show_surface));
+        // F5: toggle the probe, which shows the fluid's state under the cursor.
+        kb_cmds
+            .bind_command(
+                bindings,
+                "probe",
+                KeyCode::F5,
+                "Toggle probe (density, pressure, velocity at cursor)",
+                250,
+                toggle_probing,
+            )
+            .showing(|sim| on_off(sim.debug.probing));
+        // F6: toggle the energy and momentum stats.
+        kb_cmds
+            .bind_command(bindings, "energy_stats", KeyCode::F6, "Toggle energy stats", 250, toggle_energy_stats)
+            .showing(|sim| on_off(sim.debug.show_energy));
+        // F7: decrease/increase the target density.
+        kb_cmds
+            .bind_command(
+                bindings,
+                "target_density",
+                KeyCode::F7,
+                "Decrease target density (shift: inc)",
+                50,
+                adj_target_density,
+            )
+            .showing(|sim| format!("{:.3}", target_density_per_unit(sim)));
+        // F8: decrease/increase the collision damping.
+        kb_cmds
+            .bind_command(
+                bindings,
+                "collision_damping",
+                KeyCode::F8,
+                "Decrease collision damping (shift: inc)",
+                100,
+                adj_collision_damping,
+            )
+            .showing(|sim| format!("{:.2}", sim.collision_damping));
+
+        // F9: show the share code for the current parameters, and copy it to the clipboard.
+        kb_cmds.bind_command(
+            bindings,
+            "share_params",
+            KeyCode::F9,
+            "Copy share code for the parameters",
+            1000,
+            share_params,
+        );
+        // F10: reset the positions, but keep the velocities.
+        kb_cmds.bind_command(
+            bindings,
+            "reset_keeping_velocities",
+            KeyCode::F10,
+            "Reset positions, keeping velocities",
+            250,
+            reset_keeping_velocities,
+        );
+
+        for action in [PAUSE, QUIT] {
+            if let Some(key) = bindings.0.get(action)
+                && let Some(command) = kb_cmds.commands.get(key)
+            {
+                warn!("{action} is bound to {}, which is also bound to \"{}\"", command.key_text, command.description);
+            }
+        }
 
         kb_cmds
     }
 
-    pub fn add_command(&mut self, key: KeyCode, description: &str, interval_millis: u64, action: KeyboardAction) {
-        self.commands.insert(
-            key,
-            KeyboardCommand {
+    pub fn add_command(
+        &mut self,
+        key: KeyCode,
+        description: &str,
+        interval_millis: u64,
+        action: KeyboardAction,
+    ) -> &mut KeyboardCommand {
+        self.commands
+            .entry(key)
+            .insert_entry(KeyboardCommand {
                 key_text: key_to_string(key),
                 description: description.into(),
                 last_action_time: Instant::now(),
                 interval: Duration::from_millis(interval_millis),
                 action,
-            },
-        );
+                action_name: None,
+                value: None,
+            })
+            .into_mut()
+    }
+
+    /// Adds a command that can be rebound, on the key that `bindings` gives it, or `default_key`.
+    pub fn bind_command(
+        &mut self,
+        bindings: &KeyBindings,
+        action_name: &'static str,
+        default_key: KeyCode,
+        description: &str,
+        interval_millis: u64,
+        action: KeyboardAction,
+    ) -> &mut KeyboardCommand {
+        let key = bindings.key(action_name, default_key);
+        if let Some(replaced) = self.commands.get(&key) {
+            warn!("{action_name} is bound to {}, replacing \"{}\"", replaced.key_text, replaced.description);
+        }
+        let command = self.add_command(key, description, interval_millis, action);
+        command.action_name = Some(action_name);
+        command
     }
 }
 
-fn key_to_string(key: KeyCode) -> String {
+fn on_off(on: bool) -> String {
+    if on { "on" } else { "off" }.into()
+}
+
+pub fn key_to_string(key: KeyCode) -> String {
     match key {
         KeyCode::Digit1 => "1".into(),
+        KeyCode::Digit2 => "2".into(),
+        KeyCode::Digit3 => "3".into(),
+        KeyCode::Digit4 => "4".into(),
+        KeyCode::Digit5 => "5".into(),
+        KeyCode::Digit6 => "6".into(),
+        KeyCode::Digit7 => "7".into(),
+        KeyCode::Digit8 => "8".into(),
+        KeyCode::Digit9 => "9".into(),
         KeyCode::KeyA => "A".into(),
         KeyCode::KeyB => "B".into(),
         KeyCode::KeyC => "C".into(),
@@ -123,49 +462,87 @@ fn key_to_string(key: KeyCode) -> String {
     }
 }
 
-fn pause(
+fn toggle_fps(
     sim: &mut Simulation,
     _shift: bool,
     _cursor_pos: &Vec2,
     _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
     _msgs: &mut Single<&mut Notifications>,
 ) {
-    if sim.frames_to_advance() == 0 {
-        sim.set_frames_to_show(u32::MAX);
+    sim.toggle_fps();
+}
+
+fn adj_gravity(
+    sim: &mut Simulation,
+    shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    if shift {
+        sim.adj_gravity(true);
     } else {
-        sim.set_frames_to_show(0);
+        sim.adj_gravity(false);
     }
+    msgs.messages.push(MessageText {
+        text: format!("Gravity: {:.2}", sim.units.to_units(sim.gravity.y)),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
 }
 
-fn toggle_fps(
+fn toggle_wind(
     sim: &mut Simulation,
     _shift: bool,
     _cursor_pos: &Vec2,
     _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
-    _msgs: &mut Single<&mut Notifications>,
+    msgs: &mut Single<&mut Notifications>,
 ) {
-    sim.toggle_fps();
+    sim.toggle_wind();
+    msgs.messages.push(MessageText {
+        text: format!("Wind {}", if sim.wind.enabled { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
 }
 
-fn adj_gravity(
+fn adj_wind(
     sim: &mut Simulation,
     shift: bool,
     _cursor_pos: &Vec2,
     _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
     msgs: &mut Single<&mut Notifications>,
 ) {
-    if shift {
-        sim.adj_gravity(true);
-    } else {
-        sim.adj_gravity(false);
-    }
+    sim.adj_wind(shift);
     msgs.messages.push(MessageText {
-        text: format!("Gravity: {:.1}", sim.gravity.y / sim.particle_size),
+        text: format!("Wind speed: {:.2}", sim.units.to_units(sim.wind.speed)),
         start_time: Instant::now(),
         duration: Duration::from_secs(1),
     });
 }
 
+fn toggle_pinch_anchor(
+    sim: &mut Simulation,
+    _shift: bool,
+    cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.pinch_anchor = match sim.pinch_anchor {
+        Some(_) => None,
+        None => Some(*cursor_pos),
+    };
+    msgs.messages.push(MessageText {
+        text: if sim.pinch_anchor.is_some() {
+            "Pinch anchor set: click to pull the fluid toward both the anchor and the cursor".into()
+        } else {
+            "Pinch anchor removed".into()
+        },
+        start_time: Instant::now(),
+        duration: Duration::from_secs(2),
+    });
+}
+
 fn adj_pressure(
     sim: &mut Simulation,
     shift: bool,
@@ -179,33 +556,322 @@ fn adj_pressure(
         sim.adj_pressure(false);
     }
     msgs.messages.push(MessageText {
-        text: format!("Pressure multiplier: {:.1}", sim.pressure_multiplier / sim.particle_size),
+        text: format!("Pressure multiplier: {:.1}", sim.units.to_units(sim.pressure_multiplier)),
         start_time: Instant::now(),
         duration: Duration::from_secs(1),
     });
 }
 
-fn toggle_heatmap(
+fn adj_num_particles(
     sim: &mut Simulation,
-    _shift: bool,
+    shift: bool,
     _cursor_pos: &Vec2,
     _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
     msgs: &mut Single<&mut Notifications>,
 ) {
-    sim.toggle_heatmap();
-    if sim.debug.density_heatmap {
-        msgs.messages.push(MessageText {
-            text: "Density heatmap".into(),
-            start_time: Instant::now(),
-            duration: Duration::from_secs(1),
-        });
+    let num_particles = if shift {
+        (sim.num_particles as f32 * 1.10).ceil() as usize
     } else {
+        (sim.num_particles as f32 / 1.10) as usize
+    };
+    let preserve = !sim.reset_on_count_change;
+    sim.change_num_particles(num_particles, preserve);
+    msgs.messages.push(MessageText {
+        text: format!("Particles: {}", sim.num_particles),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn randomize(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    let text = sim.randomize();
+    info!("Randomized parameters:\n{text}");
+    msgs.messages.push(MessageText {
+        text,
+        start_time: Instant::now(),
+        duration: Duration::from_secs(3),
+    });
+}
+
+fn toggle_recording(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_recording();
+    msgs.messages.push(MessageText {
+        text: if sim.debug.recording { "Recording frames".into() } else { "Recording stopped".into() },
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_heatmap(
+    sim: &mut Simulation,
+    shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    if shift {
+        sim.debug.colormap = sim.debug.colormap.next();
         msgs.messages.push(MessageText {
-            text: "Velocity heatmap".into(),
+            text: format!("Colormap: {}", sim.debug.colormap.name()),
             start_time: Instant::now(),
             duration: Duration::from_secs(1),
         });
+        return;
+    }
+    sim.next_color_field();
+    msgs.messages.push(MessageText {
+        text: format!("Coloring by {}", sim.debug.color_field.name()),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_bottomless(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_bottomless();
+    msgs.messages.push(MessageText {
+        text: format!("Bottomless mode {}", if sim.bottomless { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_density_background(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_density_background();
+    msgs.messages.push(MessageText {
+        text: format!("Density background {}", if sim.debug.density_background { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_streamlines(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_streamlines();
+    msgs.messages.push(MessageText {
+        text: format!("Streamlines {}", if sim.debug.show_streamlines { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_foam(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_foam();
+    msgs.messages.push(MessageText {
+        text: format!("Foam {}", if sim.foam.is_some() { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_surface(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_surface();
+    msgs.messages.push(MessageText {
+        text: format!("Surface outline {}", if sim.debug.show_surface { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_probing(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_probing();
+    msgs.messages.push(MessageText {
+        text: format!("Probe {}", if sim.debug.probing { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_energy_stats(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_energy_stats();
+    msgs.messages.push(MessageText {
+        text: format!("Energy stats {}", if sim.debug.show_energy { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_point_gravity(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_point_gravity();
+    msgs.messages.push(MessageText {
+        text: format!("Point gravity {}", if sim.point_gravity { "on" } else { "off" }),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn toggle_annotating(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.toggle_annotating();
+    msgs.messages.push(MessageText {
+        text: if sim.debug.annotating {
+            "Annotating: drag to draw arrows (left) or circles (right), middle-click for a label".into()
+        } else {
+            "Annotation mode off".into()
+        },
+        start_time: Instant::now(),
+        duration: Duration::from_secs(2),
+    });
+}
+
+/// Only with Shift: the digits on their own advance the simulation (see control_sim_state).
+fn load_scenario(sim: &mut Simulation, shift: bool, index: usize, msgs: &mut Single<&mut Notifications>) {
+    if !shift {
+        return;
     }
+    let scenario = sim.scenarios[index].clone();
+    msgs.messages.push(MessageText {
+        text: format!("Scenario: {}", scenario.name),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(2),
+    });
+    sim.load_scenario(scenario);
+}
+
+fn reset_particles(
+    sim: &mut Simulation,
+    shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    if shift {
+        sim.placement = sim.placement.next();
+    }
+    sim.reset();
+    msgs.messages.push(MessageText {
+        text: if shift { format!("Placement: {}", sim.placement.name()) } else { "Particles reset".into() },
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn reset_keeping_velocities(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.reset_keeping_velocities();
+    msgs.messages.push(MessageText {
+        text: "Positions reset, velocities kept".into(),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn save_scenario(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = format!("scenario-{timestamp}.ron");
+    let text = match sim.current_scenario().save(&path) {
+        Ok(()) => format!("Saved {path}"),
+        Err(e) => e,
+    };
+    info!("{text}");
+    msgs.messages.push(MessageText {
+        text,
+        start_time: Instant::now(),
+        duration: Duration::from_secs(2),
+    });
+}
+
+fn share_params(
+    sim: &mut Simulation,
+    _shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    let code = ParamsCode::from_sim(sim).encode();
+    info!("Parameters: --params {code}");
+    let copied = match copy_to_clipboard(&code) {
+        Ok(()) => "copied to the clipboard",
+        Err(e) => {
+            warn!("Couldn't copy the parameters to the clipboard: {e}");
+            "see the log"
+        }
+    };
+    msgs.messages.push(MessageText {
+        text: format!(
+            "--params {code}
+({copied})"
+        ),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(5),
+    });
 }
 
 fn reset_inertia(
@@ -217,7 +883,7 @@ fn reset_inertia(
 ) {
     sim.reset_inertia();
     msgs.messages.push(MessageText {
-        text: "Inertia reset".into(),
+        text: "Velocities zeroed, positions kept".into(),
         start_time: Instant::now(),
         duration: Duration::from_secs(1),
     });
@@ -258,6 +924,41 @@ fn adj_smoothing_radius(
     });
 }
 
+/// The target density per square unit, rather than per square pixel.
+fn target_density_per_unit(sim: &Simulation) -> f32 {
+    sim.target_density * sim.units.pixels_per_unit * sim.units.pixels_per_unit
+}
+
+fn adj_target_density(
+    sim: &mut Simulation,
+    shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.adj_target_density(shift);
+    msgs.messages.push(MessageText {
+        text: format!("Target density: {:.3}", target_density_per_unit(sim)),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
+fn adj_collision_damping(
+    sim: &mut Simulation,
+    shift: bool,
+    _cursor_pos: &Vec2,
+    _particle_query: &mut Query<(&mut Transform, &mut Particle)>,
+    msgs: &mut Single<&mut Notifications>,
+) {
+    sim.adj_collision_damping(shift);
+    msgs.messages.push(MessageText {
+        text: format!("Collision damping: {:.2}", sim.collision_damping),
+        start_time: Instant::now(),
+        duration: Duration::from_secs(1),
+    });
+}
+
 fn adj_viscosity(
     sim: &mut Simulation,
     shift: bool,
@@ -291,7 +992,7 @@ fn watch_particle(
     } else {
         particle_query.par_iter_mut().for_each(|(transform, mut particle)| {
             if (transform.translation.xy() - cursor_pos).length() <= sim.particle_size / 2.0 {
-                println!(
+                info!(
                     "Watching particle {} @({},{}) density={}, velocity={:?}",
                     particle.id,
                     transform.translation.x,
@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+
+use crate::SpriteImage;
+use crate::args::Args;
+use crate::components::MainSimulation;
+use crate::sim_state::SimState;
+use crate::sim_struct::Simulation;
+use crate::update::{FAST, STOPPED};
+
+/// A layer specification from the command line: `name:key=value,key=value,...`.
+/// Any parameter that isn't given is taken from the main simulation's arguments.
+pub struct LayerSpec {
+    pub name: String,
+    pub args: Args,
+    /// How strongly the layer's particles are dragged along by the main fluid, from 0 (not at all) to 1 (they simply
+    /// follow it).
+    pub coupling: f32,
+}
+
+impl LayerSpec {
+    pub fn parse(spec: &str, base: &Args) -> Result<Self, String> {
+        let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+        if name.trim().is_empty() {
+            return Err(format!("Layer '{spec}' needs a name"));
+        }
+
+        let mut args = base.clone();
+        let mut coupling = 0.0;
+        for param in params.split(',').map(str::trim).filter(|param| !param.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| format!("Layer parameter '{param}' should be key=value"))?;
+            let bad_value = |e: &dyn std::fmt::Display| format!("Layer parameter '{param}': {e}");
+            match key.trim() {
+                "num_particles" | "num" => args.num_particles = value.parse().map_err(|e| bad_value(&e))?,
+                "smoothing_radius" => args.smoothing_radius = value.parse().map_err(|e| bad_value(&e))?,
+                "gravity" => args.gravity = Some(value.parse().map_err(|e| bad_value(&e))?),
+                "pressure_multiplier" => args.pressure_multiplier = Some(value.parse().map_err(|e| bad_value(&e))?),
+                "viscosity_strength" => args.viscosity_strength = value.parse().map_err(|e| bad_value(&e))?,
+                "collision_damping" => args.collision_damping = value.parse().map_err(|e| bad_value(&e))?,
+                "sprite_size" => args.sprite_size = value.parse().map_err(|e| bad_value(&e))?,
+                "coupling" => coupling = value.parse::<f32>().map_err(|e| bad_value(&e))?.clamp(0.0, 1.0),
+                other => return Err(format!("Unknown layer parameter '{other}'")),
+            }
+        }
+        // The layers sit on top of the main fluid rather than under it.
+        args.heightfield_depth = 0.0;
+
+        Ok(LayerSpec {
+            name: name.trim().into(),
+            args,
+            coupling,
+        })
+    }
+}
+
+#[derive(Resource)]
+pub struct LayerSpecs(pub Vec<LayerSpec>);
+
+/// An additional set of particles, simulated independently of the main fluid but in the same domain.
+/// Layers only interact one-way: the main fluid drags the layer's particles along, but not vice versa.
+/// Keyboard and mouse input only affect the main fluid.
+/// The layer entity also has a `Name`.
+#[derive(Component)]
+pub struct SimLayer {
+    pub sim: Simulation,
+    pub coupling: f32,
+    /// Layers are drawn on top of the main fluid, in the order they were specified.
+    pub depth: f32,
+}
+
+#[derive(Component)]
+pub struct LayerParticle {
+    pub layer: Entity,
+    pub id: usize,
+}
+
+pub fn spawn_layers(commands: &mut Commands, width: f32, height: f32, specs: &LayerSpecs) {
+    for (i, spec) in specs.0.iter().enumerate() {
+        let mut sim = Simulation::new(width, height, &spec.args);
+        info!("Layer '{}': {} particles, coupling {}", spec.name, sim.num_particles, spec.coupling);
+
+        let layer = commands.spawn(Name::new(spec.name.clone())).id();
+        for id in 0..sim.num_particles {
+            commands.spawn((Sprite::default(), LayerParticle { layer, id }));
+        }
+        sim.place_particles();
+        commands.entity(layer).insert(SimLayer {
+            sim,
+            coupling: spec.coupling,
+            depth: (i + 1) as f32,
+        });
+    }
+}
+
+/// Steps each layer along with the main simulation, and draws its particles.
+pub fn update_layers(
+    main_sim: Single<&Simulation, With<MainSimulation>>,
+    mut layers: Query<&mut SimLayer>,
+    mut particle_query: Query<(&mut Transform, &mut Sprite, &LayerParticle)>,
+    sprite_image: Single<&SpriteImage>,
+    state: Res<State<SimState>>,
+) {
+    for mut layer in &mut layers {
+        let layer = &mut *layer;
+        // Pause and single-step along with the main simulation.
+        let advancing = *state.get() != SimState::Paused;
+        if advancing {
+            layer.sim.update_particles(1.0 / 60.0);
+        } else {
+            layer.sim.refresh(1.0 / 60.0);
+        }
+
+        if advancing && layer.coupling > 0.0 {
+            let sim = &mut layer.sim;
+            for i in 0..sim.num_particles {
+                let fluid_velocity = main_sim.sample_velocity(sim.positions[i]);
+                sim.velocities[i] = sim.velocities[i].lerp(fluid_velocity, layer.coupling);
+            }
+        }
+        layer.sim.end_frame();
+    }
+
+    for (mut transform, mut sprite, particle) in &mut particle_query {
+        let Ok(layer) = layers.get(particle.layer) else {
+            continue;
+        };
+        let sim = &layer.sim;
+        if particle.id >= sim.num_particles {
+            continue;
+        }
+
+        transform.translation = sim.positions[particle.id].extend(layer.depth);
+
+        let speed_ratio = sim.velocities[particle.id].length() / sim.max_velocity;
+        let rgb = STOPPED + speed_ratio.powf(1.0 / 4.0) * (FAST - STOPPED);
+        let custom_size = Some(Vec2::splat(sim.particle_size * sim.sprite_size));
+        if sprite.image != sprite_image.handle {
+            sprite.image = sprite_image.handle.clone();
+        }
+        if sprite.custom_size != custom_size {
+            sprite.custom_size = custom_size;
+        }
+        sprite.color = Color::linear_rgb(rgb.x, rgb.y, rgb.z);
+    }
+}
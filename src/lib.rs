@@ -0,0 +1,452 @@
+//! A 2D SPH fluid simulation, as a Bevy plugin.
+//!
+//! Add [`FluidSimPlugin`] to an app (along with `DefaultPlugins` and a 2D camera) to fill the primary window with
+//! fluid. The simulation is configured with the [`SimulationSettings`] resource, which takes the same options as the
+//! command line of the bevy-fluid-sim binary (with the same defaults in `Args::default()`); insert it before adding
+//! the plugin. While the app is running, the [`Simulation`] component holds the particles and the live parameters.
+//! Every `Simulation` entity is stepped and drawn (offset by its [`TankOffset`], if it has one), and the one marked
+//! [`MainSimulation`] gets the input.
+
+mod annotations;
+pub mod args;
+mod background_writer;
+mod boundary;
+mod camera_controls;
+mod color_field;
+mod colormap;
+mod compare;
+mod components;
+mod conservation;
+mod container;
+mod convection;
+mod crash_dump;
+mod density_background;
+mod digit_keys;
+mod domain;
+mod drop_test;
+mod dye;
+mod edge_warnings;
+mod elasticity;
+mod emitter;
+mod events;
+mod explosion;
+mod field_export;
+mod flow_meter;
+mod fluids;
+mod foam;
+mod gpu;
+mod grab;
+mod gravity;
+mod heightfield;
+mod help;
+mod inspector;
+mod interaction;
+mod key_bindings;
+mod keyboard;
+mod layers;
+mod lod;
+pub mod loop_export;
+mod messages;
+mod metrics;
+mod motion;
+#[cfg(feature = "music")]
+mod music;
+mod neighbor_search;
+mod obstacle_paint;
+mod paddle;
+#[cfg(feature = "egui")]
+mod param_panel;
+mod particle_brush;
+mod particle_mesh;
+mod pbf;
+mod pcisph;
+mod placement;
+mod probe;
+mod recorder;
+mod region_grid;
+mod rigid_body;
+mod scenario;
+pub mod settings_file;
+mod share_code;
+mod sim_impl;
+mod sim_settings;
+mod sim_state;
+mod sim_struct;
+mod soak;
+mod solver;
+mod streamlines;
+mod surface;
+mod time_step;
+mod units;
+mod update;
+mod wall_impulse;
+mod walls;
+pub mod warm_start;
+pub mod wind;
+mod wind_tunnel;
+mod window;
+
+use bevy::asset::RenderAssetUsages;
+use bevy::color::palettes::css::GOLD;
+use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::prelude::*;
+
+use crate::annotations::{Annotations, annotate};
+use crate::background_writer::BackgroundWriter;
+use crate::camera_controls::{control_camera, rescale_text};
+use crate::color_field::update_color_field;
+use crate::compare::{Comparison, label_tanks, mirror_main_input, spawn_comparison, split_viewports};
+use crate::components::*;
+use crate::conservation::{Conservation, update_conservation};
+use crate::container::{spawn_container_overlay, update_container_overlay};
+use crate::density_background::{DensityBackground, update_density_background};
+use crate::domain::{fit_domain, tank_size};
+use crate::drop_test::{DropTest, update_drop_test};
+use crate::dye::brush_dye;
+use crate::edge_warnings::{EdgeWarnings, warn_pinned_particles};
+use crate::events::{handle_keypress, handle_mouse_clicks, on_resize};
+use crate::field_export::{FieldExport, export_field};
+use crate::flow_meter::flow_lines;
+use crate::foam::draw_foam;
+use crate::gpu::init_gpu_backend;
+use crate::gravity::{control_gravity, draw_gravity, place_gravity_center};
+use crate::help::{spawn_help_panel, update_help_panel};
+use crate::inspector::{
+    select_inspected_particle, spawn_inspector, spawn_watch_panel, update_inspector, update_watch_panel,
+};
+use crate::interaction::{InteractionModes, draw_interaction_preview};
+use crate::key_bindings::KeyBindings;
+use crate::layers::{LayerSpec, LayerSpecs, spawn_layers, update_layers};
+use crate::lod::{Lod, update_lod};
+use crate::messages::{MessageText, display_messages, spawn_messages};
+use crate::metrics::{MetricsExport, export_metrics};
+#[cfg(feature = "music")]
+use crate::music::{MusicIntensity, spawn_music, update_music};
+use crate::obstacle_paint::paint_obstacles;
+#[cfg(feature = "egui")]
+use crate::param_panel::add_param_panel;
+use crate::particle_brush::brush_particles;
+use crate::particle_mesh::{spawn_particle_mesh, update_particle_mesh};
+use crate::probe::{spawn_probe_label, update_probe};
+use crate::recorder::{Recorder, record_frames};
+use crate::region_grid::{draw_local_region_grid, spawn_grid_labels};
+use crate::settings_file::{SettingsWatcher, reload_settings};
+use crate::sim_state::{SimState, advancing, control_sim_state, count_single_steps, refresh_paused};
+use crate::soak::{SoakTest, run_soak_test};
+use crate::solver::{SimEvent, emit_solver_events, log_sim_events};
+use crate::streamlines::draw_streamlines;
+use crate::surface::draw_surface;
+use crate::time_step::emit_throttle_events;
+use crate::update::{
+    draw_debug_info, highlight_watched_neighbors, step_simulation, sync_particle_entities, update_fps, update_particles,
+};
+use crate::warm_start::SettledState;
+
+pub use crate::components::{MainSimulation, SimulationSettings, TankOffset};
+pub use crate::sim_struct::Simulation;
+
+/// Runs the fluid simulation in the primary window, with the keyboard and mouse controls, using the
+/// [`SimulationSettings`] resource (or the defaults, if there isn't one). To start from a settled state, insert a
+/// [`SettledState`] resource too.
+pub struct FluidSimPlugin;
+
+impl Plugin for FluidSimPlugin {
+    fn build(&self, app: &mut App) {
+        let mut settings = app.world_mut().get_resource_or_init::<SimulationSettings>();
+        if settings.0.compare.is_some() {
+            // Both simulations of a comparison place their particles identically.
+            settings.0.deterministic = true;
+        }
+        let args = settings.0.clone();
+        // The binary validates the settings before adding the plugin, but a plugin can't return an error.
+        let interaction_modes = InteractionModes::parse(&args.interaction_modes)
+            .unwrap_or_else(|e| panic!("Invalid interaction modes: {e}"));
+        let layer_specs = args
+            .layers
+            .iter()
+            .map(|spec| LayerSpec::parse(spec, &args))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| panic!("Invalid layer: {e}"));
+        let comparison = args
+            .compare
+            .as_ref()
+            .map(|spec| Comparison::parse(spec, &args).unwrap_or_else(|e| panic!("Invalid comparison: {e}")));
+        let key_bindings = args.key_bindings.as_ref().map_or_else(KeyBindings::default, |path| {
+            KeyBindings::load_or_create(path).unwrap_or_else(|e| panic!("Invalid key bindings: {e}"))
+        });
+
+        app
+            // Add our startup function, setup().
+            .add_systems(Startup, setup)
+            .add_systems(Startup, init_gpu_backend.after(setup))
+            // The simulation runs at a fixed rate, regardless of the framerate.
+            .insert_resource(Time::<Fixed>::from_hz(60.0))
+            .init_state::<SimState>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    (step_simulation, count_single_steps).chain().run_if(advancing),
+                    refresh_paused.run_if(not(advancing)),
+                ),
+            )
+            .add_systems(Update, control_sim_state)
+            // Add the functions that will be called once per update.
+            .add_systems(
+                Update,
+                (
+                    update_particles,
+                    draw_debug_info,
+                    handle_keypress,
+                    handle_mouse_clicks,
+                    on_resize,
+                    update_fps,
+                    display_messages,
+                    select_inspected_particle,
+                    update_inspector,
+                    update_watch_panel,
+                    update_help_panel,
+                ),
+            )
+            .add_systems(Update, brush_particles.before(sync_particle_entities))
+            .add_systems(Update, brush_dye.before(update_particles))
+            .add_systems(Update, sync_particle_entities.before(update_particles))
+            .add_systems(
+                Update,
+                update_color_field
+                    .after(sync_particle_entities)
+                    .before(update_particles),
+            )
+            .add_systems(Update, update_layers.before(update_particles))
+            .add_systems(Update, highlight_watched_neighbors.before(update_particles))
+            .add_systems(Update, draw_interaction_preview)
+            .add_systems(Update, (fit_domain, control_camera, rescale_text).chain())
+            .add_systems(Update, update_probe.after(control_camera))
+            .add_systems(Update, annotate)
+            .add_systems(Update, paint_obstacles)
+            .add_systems(Update, flow_lines)
+            .add_systems(Update, (control_gravity.before(update_particles), place_gravity_center, draw_gravity))
+            .add_systems(Update, update_container_overlay.after(update_particles))
+            .add_systems(Update, draw_local_region_grid.after(update_particles))
+            .add_systems(Update, draw_streamlines.after(update_particles))
+            .add_systems(Update, draw_foam.after(update_particles))
+            .add_systems(Update, draw_surface.after(update_particles))
+            .add_systems(Update, update_lod.after(update_particles))
+            .add_systems(Update, update_density_background.after(update_particles))
+            .add_systems(Update, update_particle_mesh.after(update_lod))
+            .add_systems(Update, update_conservation.after(update_particles))
+            .add_systems(Update, record_frames.after(update_particle_mesh))
+            .add_systems(Update, warn_pinned_particles.after(update_particles))
+            .add_message::<SimEvent>()
+            .add_systems(
+                Update,
+                (emit_solver_events, emit_throttle_events, log_sim_events)
+                    .chain()
+                    .after(update_particles),
+            )
+            .add_systems(
+                Update,
+                // Before update_conservation, which clears Simulation::totals_changed.
+                run_soak_test
+                    .run_if(resource_exists::<SoakTest>)
+                    .after(update_particles)
+                    .before(update_conservation),
+            )
+            .add_systems(
+                Update,
+                update_drop_test
+                    .before(update_particles)
+                    .run_if(resource_exists::<DropTest>),
+            )
+            .add_systems(
+                Update,
+                export_field
+                    .after(update_particles)
+                    .run_if(resource_exists::<FieldExport>),
+            )
+            .add_systems(
+                Update,
+                export_metrics
+                    .after(update_particles)
+                    .run_if(resource_exists::<MetricsExport>),
+            );
+
+        app.insert_resource(interaction_modes);
+        app.insert_resource(key_bindings);
+        app.insert_resource(Conservation::default());
+        app.insert_resource(EdgeWarnings::default());
+        #[cfg(feature = "music")]
+        if args.music.is_some() {
+            app.insert_resource(MusicIntensity::default())
+                .add_systems(Update, update_music.after(update_particles));
+        }
+        #[cfg(feature = "egui")]
+        if args.panel {
+            add_param_panel(app);
+        }
+        app.insert_resource(Annotations::default());
+        app.init_resource::<BackgroundWriter>();
+        app.insert_resource(Recorder::new(args.record_dir.clone().into(), args.record_every, args.record_ffmpeg));
+        app.insert_resource(Lod::new(args.lod_threshold, args.lod_cell_size));
+        app.insert_resource(DensityBackground::new(args.density_background_cell));
+        if args.drop_test {
+            app.insert_resource(DropTest::default());
+        }
+        if let Some(field) = args.export_field {
+            app.insert_resource(FieldExport::new(
+                field,
+                args.export_dir.clone().into(),
+                args.export_width,
+                args.export_heatmap,
+            ));
+        }
+        if let Some(path) = &args.metrics_out {
+            match MetricsExport::create(path) {
+                Ok(export) => {
+                    app.insert_resource(export);
+                }
+                Err(e) => error!("Can't write the metrics to {path}: {e}"),
+            }
+        }
+        if let Some(path) = &args.settings {
+            app.insert_resource(SettingsWatcher::new(path.clone()))
+                .add_systems(Update, reload_settings);
+        }
+        if let Some(hours) = args.soak {
+            app.insert_resource(SoakTest::new(hours, args.soak_max_energy));
+        }
+
+        app.insert_resource(LayerSpecs(layer_specs));
+        if let Some(comparison) = comparison {
+            app.insert_resource(comparison)
+                .add_systems(FixedUpdate, mirror_main_input.before(step_simulation).before(refresh_paused))
+                .add_systems(Update, (label_tanks, split_viewports.after(control_camera)));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)] // ECS gonna ECS.
+fn setup(
+    mut commands: Commands,
+    window: Single<&Window>,
+    #[cfg(feature = "music")] asset_server: Res<AssetServer>,
+    settings: Res<SimulationSettings>,
+    layer_specs: Res<LayerSpecs>,
+    comparison: Option<Res<Comparison>>,
+    key_bindings: Res<KeyBindings>,
+    settled_state: Option<Res<SettledState>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // Load the image for the sprites. It's embedded in the library, so that games using the plugin don't need a copy
+    // of it in their assets.
+    let sprite_image = images.add(
+        Image::from_buffer(
+            include_bytes!("../assets/blurred-circle-pow-2.0.png"),
+            ImageType::Extension("png"),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::default(),
+        )
+        .expect("The embedded sprite image is a valid PNG"),
+    );
+
+    // Create the simulation and add it to ECS.
+    // Note: the simulation isn't well-integrated into Bevy ECS at all. Perhaps I will try, at some point,
+    // to move the many buffers inside the Simulation struct (e.g. positions, velocities, densities, and so on)
+    // into ECS, but it was easier to just stick them inside Simulation while developing.
+    // It would be interesting to see what, if any, impact moving them to ECS has on performance.
+    // When comparing, each simulation gets half of the window.
+    let size = tank_size(settings.0.domain, comparison.as_deref(), window.size());
+    let mut sim = Simulation::new(size.x, size.y, &settings.0);
+    if settings.0.drop_test {
+        // Keep the particle size that the requested number of particles would have, but only drop one of them.
+        sim.set_num_particles(1);
+    }
+
+    let sim_entity = commands.spawn(MainSimulation).id();
+    sim.spawn_particles(&mut commands, sim_entity);
+    if let Some(settled_state) = settled_state {
+        settled_state.apply(&mut sim);
+    }
+    let sim_num_particles = sim.num_particles;
+    let scenario_names: Vec<String> = sim.scenarios.iter().map(|scenario| scenario.name.clone()).collect();
+    spawn_container_overlay(&mut commands, &mut meshes, &mut materials, &sim);
+    spawn_particle_mesh(&mut commands, &mut meshes, &mut materials, &sim, sprite_image.clone());
+    commands.entity(sim_entity).insert(sim);
+    spawn_layers(&mut commands, size.x, size.y, &layer_specs);
+    if let Some(comparison) = comparison {
+        spawn_comparison(&mut commands, size, &comparison);
+    }
+    #[cfg(feature = "music")]
+    if let Some(path) = &settings.0.music {
+        spawn_music(&mut commands, &asset_server, path);
+    }
+
+    // FPS display.
+    commands.spawn((
+        Text::default(),
+        (
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+            TextColor(GOLD.into()),
+        ),
+        FpsText,
+    ));
+
+    // Add a text display of the number of particles.
+    commands.spawn((
+        Text::new(format!("{} particles", sim_num_particles)),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextLayout::new_with_justify(Justify::Right),
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(5.0),
+            ..default()
+        },
+        ParticleCountText,
+    ));
+
+    // Drop test error display.
+    if settings.0.drop_test {
+        commands.spawn((
+            Text::default(),
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+            TextColor(GOLD.into()),
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(5.0),
+                left: Val::Px(5.0),
+                ..default()
+            },
+            DropTestText,
+        ));
+    }
+
+    // Spawn the notification message component.
+    spawn_messages(&mut commands);
+
+    // Particle inspector popup (ctrl+click).
+    spawn_inspector(&mut commands);
+    // Live state of the watched particles (W).
+    spawn_watch_panel(&mut commands);
+    // Keyboard help (?).
+    spawn_help_panel(&mut commands);
+    // Label for the probe (F5).
+    spawn_probe_label(&mut commands);
+    spawn_grid_labels(&mut commands);
+
+    // Keyboard commands component
+    commands.spawn(KeyboardCommands::create(&scenario_names, &key_bindings));
+
+    commands.spawn(SpriteImage { handle: sprite_image });
+}
@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+
+use crate::SpriteImage;
+use crate::compare::ComparisonCamera;
+use crate::components::{MainSimulation, Particle};
+use crate::domain::world_per_pixel;
+use crate::sim_struct::Simulation;
+use crate::update::{FAST, STOPPED};
+
+/// Level-of-detail rendering: when there are too many particles, or they're too small on screen to be seen
+/// individually, the particles are binned into a coarse grid and each non-empty cell is drawn as a single larger
+/// splat. This keeps the rendering cost roughly constant regardless of the number of particles.
+#[derive(Resource)]
+pub struct Lod {
+    /// Use the splats when there are more particles than this.
+    pub threshold: usize,
+    /// Size of the grid cells, in particle sizes.
+    pub cell_size: f32,
+    /// Whether the splats are currently being drawn instead of the particles.
+    pub active: bool,
+    splats: Vec<Entity>,
+}
+
+/// Marks the sprites used to draw the grid cells.
+#[derive(Component)]
+pub struct LodSplat;
+
+impl Lod {
+    /// Use the splats when the particles would be smaller than this on screen, in pixels.
+    const MIN_PARTICLE_PIXELS: f32 = 1.0;
+
+    pub fn new(threshold: usize, cell_size: f32) -> Self {
+        Lod {
+            threshold,
+            cell_size: cell_size.max(1.0),
+            active: false,
+            splats: vec![],
+        }
+    }
+}
+
+/// Switches between drawing the individual particles and drawing the splats, and draws the splats.
+#[allow(clippy::type_complexity)]
+pub fn update_lod(
+    mut commands: Commands,
+    mut lod: ResMut<Lod>,
+    sim: Single<(Entity, &Simulation), With<MainSimulation>>,
+    camera: Single<(&Camera, &Projection), (With<Camera2d>, Without<ComparisonCamera>)>,
+    mut particle_query: Query<(&mut Visibility, &Particle), Without<LodSplat>>,
+    mut splat_query: Query<(&mut Transform, &mut Visibility), With<LodSplat>>,
+    sprite_image: Single<&SpriteImage>,
+) {
+    let (sim_entity, sim) = *sim;
+    let (camera, projection) = *camera;
+    let zoom = match projection {
+        Projection::Orthographic(orthographic) => world_per_pixel(camera, orthographic),
+        _ => 1.0,
+    };
+    let active =
+        sim.num_particles > lod.threshold || sim.particle_size * sim.sprite_size / zoom < Lod::MIN_PARTICLE_PIXELS;
+
+    lod.active = active;
+
+    // Particles may have been added since the last frame, so check all of them rather than only on a switch.
+    let particle_visibility = if active { Visibility::Hidden } else { Visibility::Inherited };
+    particle_query
+        .iter_mut()
+        .filter(|(_, particle)| particle.sim == sim_entity)
+        .for_each(|(mut visibility, _)| {
+            visibility.set_if_neq(particle_visibility);
+        });
+    if !active {
+        for (_, mut visibility) in &mut splat_query {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+        return;
+    }
+
+    // Bin the particles: sum of positions, sum of speeds, and count for each cell.
+    let cell_size = sim.particle_size * lod.cell_size;
+    let cols = (sim.half_bounds_size.x * 2.0 / cell_size) as usize + 1;
+    let rows = (sim.half_bounds_size.y * 2.0 / cell_size) as usize + 1;
+    let mut cells = vec![(Vec2::ZERO, 0.0, 0u32); cols * rows];
+    for i in 0..sim.num_particles {
+        let cell = ((sim.positions[i] + sim.half_bounds_size) / cell_size).floor();
+        let col = (cell.x.max(0.0) as usize).min(cols - 1);
+        let row = (cell.y.max(0.0) as usize).min(rows - 1);
+        let (position_sum, speed_sum, count) = &mut cells[row * cols + col];
+        *position_sum += sim.positions[i];
+        *speed_sum += sim.velocities[i].length();
+        *count += 1;
+    }
+
+    // The cells are drawn with the same total area as the particles in them.
+    let particle_area = (sim.particle_size * sim.sprite_size).powi(2);
+    let mut num_splats = 0;
+    for &(position_sum, speed_sum, count) in cells.iter().filter(|(_, _, count)| *count > 0) {
+        let position = position_sum / count as f32;
+        let speed_ratio = speed_sum / count as f32 / sim.max_velocity;
+        let rgb = sim.debug.colormap.color(speed_ratio.powf(1.0 / 4.0), STOPPED, FAST);
+        let sprite = Sprite {
+            image: sprite_image.handle.clone(),
+            custom_size: Some(Vec2::splat((particle_area * count as f32).sqrt().min(cell_size * 1.5))),
+            color: Color::linear_rgb(rgb.x, rgb.y, rgb.z),
+            ..Default::default()
+        };
+
+        if let Some(&splat) = lod.splats.get(num_splats) {
+            if let Ok((mut transform, mut visibility)) = splat_query.get_mut(splat) {
+                transform.translation = position.extend(0.0);
+                *visibility = Visibility::Inherited;
+            }
+            commands.entity(splat).insert(sprite);
+        } else {
+            let splat = commands
+                .spawn((sprite, Transform::from_translation(position.extend(0.0)), LodSplat))
+                .id();
+            lod.splats.push(splat);
+        }
+        num_splats += 1;
+    }
+
+    // Hide the splats that aren't needed this frame.
+    for &splat in &lod.splats[num_splats..] {
+        if let Ok((_, mut visibility)) = splat_query.get_mut(splat) {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
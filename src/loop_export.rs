@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use bevy::math::{Vec2, Vec3};
+use rayon::prelude::*;
+
+use crate::args::Args;
+use crate::sim_struct::Simulation;
+use crate::update::{FAST, STOPPED};
+use crate::warm_start::SettledState;
+
+/// Background color of the exported frames, matching the app's ClearColor.
+const BACKGROUND: Vec3 = Vec3::new(0.0, 0.0, 0.05);
+
+/// Records `frames` frames of the simulation, starting from the settled state (if any), and writes them to `path` as
+/// a looping animated PNG.
+///
+/// The frames are simulated twice from the same snapshot: the first pass only records the particle positions, and the
+/// second one renders them, and checks that the simulation is reproducing the first pass exactly. Any difference
+/// means something in the step isn't deterministic, and the clip wouldn't match a recording made from the same state.
+///
+/// A clip of a fluid doesn't end where it started, so an extra `frames / 5` frames are simulated, and the last of the
+/// exported frames are cross-faded into the beginning of the clip.
+pub fn export_loop(
+    args: &Args,
+    window_width: f32,
+    window_height: f32,
+    settled_state: Option<&SettledState>,
+    frames: usize,
+    path: &Path,
+) -> io::Result<()> {
+    let mut sim = Simulation::new(window_width, window_height, args);
+    sim.deterministic = true;
+    sim.place_particles();
+    if let Some(state) = settled_state {
+        state.apply(&mut sim);
+    }
+    let snapshot = SettledState::from_sim(&sim);
+
+    let frames = frames.max(1);
+    let fade_frames = frames / 5;
+    let total_frames = frames + fade_frames;
+    let mut recorded = Vec::with_capacity(total_frames);
+    for _ in 0..total_frames {
+        sim.step_n(1.0 / 60.0, 1);
+        recorded.push(sim.positions().to_vec());
+    }
+
+    snapshot.apply(&mut sim);
+    sim.sim_time = 0.0;
+    let width = args.loop_width.max(1);
+    let height = ((width as f32 * window_height / window_width) as u32).max(1);
+    let mut images = Vec::with_capacity(total_frames);
+    let mut max_divergence = 0f32;
+    for (frame, positions) in recorded.iter().enumerate() {
+        sim.step_n(1.0 / 60.0, 1);
+        let divergence = sim
+            .positions()
+            .iter()
+            .zip(positions)
+            .map(|(a, b)| a.distance(*b))
+            .fold(0.0, f32::max);
+        if divergence > max_divergence {
+            println!("Frame {frame} diverged from the recording by {divergence} pixels");
+            max_divergence = divergence;
+        }
+        images.push(render(&sim, width, height));
+    }
+
+    // The last frames fade into the ones before the start of the exported clip, so that it wraps seamlessly.
+    let clip: Vec<Vec<u8>> = (0..frames)
+        .map(|i| {
+            let frame = &images[i + fade_frames];
+            if i + fade_frames < frames {
+                return frame.clone();
+            }
+            let wrapped = &images[i + fade_frames - frames];
+            let t = (i + fade_frames + 1 - frames) as f32 / (fade_frames + 1) as f32;
+            frame
+                .iter()
+                .zip(wrapped)
+                .map(|(&a, &b)| (a as f32 + (b as f32 - a as f32) * t).round() as u8)
+                .collect()
+        })
+        .collect();
+
+    write_apng(path, width, height, &clip)?;
+    println!("Wrote {frames} frames to {}", path.display());
+    Ok(())
+}
+
+/// Draws each particle as a disc, colored by speed like the sprites, into an RGB8 buffer.
+fn render(sim: &Simulation, width: u32, height: u32) -> Vec<u8> {
+    let scale = width as f32 / (sim.half_bounds_size.x * 2.0);
+    let radius = (sim.particle_size * sim.sprite_size * scale / 2.0).max(0.5);
+    let mut pixels = vec![BACKGROUND; (width * height) as usize];
+
+    for i in 0..sim.num_particles {
+        let center = (sim.positions[i] * Vec2::new(1.0, -1.0) + sim.half_bounds_size) * scale;
+        let speed_scale = (sim.velocities[i].length() / sim.max_velocity.max(f32::EPSILON)).powf(1.0 / 4.0);
+        let color = STOPPED + speed_scale.min(1.0) * (FAST - STOPPED);
+
+        let min = (center - radius).max(Vec2::ZERO);
+        let max = (center + radius).min(Vec2::new(width as f32 - 1.0, height as f32 - 1.0));
+        for y in min.y as u32..=max.y as u32 {
+            for x in min.x as u32..=max.x as u32 {
+                if Vec2::new(x as f32 + 0.5, y as f32 + 0.5).distance(center) <= radius {
+                    pixels[(y * width + x) as usize] = color;
+                }
+            }
+        }
+    }
+
+    pixels
+        .par_iter()
+        .flat_map_iter(|color| (color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).to_array().map(|c| c as u8))
+        .collect()
+}
+
+fn write_apng(path: &Path, width: u32, height: u32, frames: &[Vec<u8>]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0).map_err(io::Error::other)?;
+    encoder.set_frame_delay(1, 60).map_err(io::Error::other)?;
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    for frame in frames {
+        writer.write_image_data(frame).map_err(io::Error::other)?;
+    }
+    writer.finish().map_err(io::Error::other)
+}
@@ -1,117 +1,77 @@
-mod args;
-mod components;
-mod events;
-mod keyboard;
-mod messages;
-mod sim_impl;
-mod sim_settings;
-mod sim_struct;
-mod update;
-
-use bevy::color::palettes::css::GOLD;
+use bevy::log::LogPlugin;
 use bevy::prelude::*;
-use bevy::window::{PresentMode, WindowResolution};
-use bevy_embedded_assets::EmbeddedAssetPlugin;
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
 
-use crate::args::Args;
-use crate::components::*;
-use crate::events::{handle_keypress, handle_mouse_clicks, on_resize};
-use crate::messages::{MessageText, display_messages, spawn_messages};
-use crate::sim_struct::Simulation;
-use crate::update::{draw_debug_info, update_fps, update_particles};
+use bevy_fluid_sim::args::Args;
+use bevy_fluid_sim::loop_export::export_loop;
+use bevy_fluid_sim::settings_file::SettingsFile;
+use bevy_fluid_sim::warm_start::{SettledState, settle};
+use bevy_fluid_sim::{FluidSimPlugin, SimulationSettings};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    let (width, height) = args.win_size()?;
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(path) = args.settings.clone() {
+        SettingsFile::load(&path)?
+            .apply_to_args(&mut args, |id| matches.value_source(id) == Some(ValueSource::CommandLine));
+    }
+    if let Some(params) = args.params.clone() {
+        params.apply(&mut args);
+    }
+    let (width, height) = args.win;
+
+    let settled_state = if let Some(frames) = args.settle {
+        let state = settle(&args, width as f32, height as f32, frames);
+        state.save(args.settle_file.as_ref())?;
+        println!("Saved the settled state to {}", args.settle_file);
+        Some(state)
+    } else if let Some(path) = &args.warm_start {
+        Some(SettledState::load(path.as_ref())?)
+    } else {
+        None
+    };
+    if let Some(frames) = args.export_loop {
+        let path = args.loop_file.as_ref();
+        export_loop(&args, width as f32, height as f32, settled_state.as_ref(), frames, path)?;
+        return Ok(());
+    }
 
     // Create and run the Bevy App.
-    App::new()
+    let mut app = App::new();
+    app
         // Background color
         .insert_resource(ClearColor(Color::linear_rgb(0.0, 0.0, 0.05)))
-        .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    present_mode: PresentMode::AutoNoVsync,
-                    resolution: WindowResolution::new(width, height),
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(args.window()),
+                    ..default()
+                })
+                .set(LogPlugin {
+                    level: args.log_level,
                     ..default()
                 }),
-                ..default()
-            }),
-            // The EmbeddedAssetPlugin loads all files under assets/ (at compile time) and makes them available via
-            // asset_server.load("embedded://filename").
-            EmbeddedAssetPlugin::default(),
-        ))
-        // Add our startup function, setup().
-        .add_systems(Startup, setup)
-        // Add the functions that will be called once per update.
-        .add_systems(
-            Update,
-            (
-                update_particles,
-                draw_debug_info,
-                handle_keypress,
-                handle_mouse_clicks,
-                on_resize,
-                update_fps,
-                display_messages,
-            ),
         )
-        .insert_resource(ArgsResource(args))
-        .run();
-
-    Ok(())
-}
+        .add_systems(Startup, |mut commands: Commands| {
+            commands.spawn(Camera2d);
+        });
 
-fn setup(mut commands: Commands, window: Single<&Window>, asset_server: Res<AssetServer>, args: Res<ArgsResource>) {
-    commands.spawn(Camera2d);
+    if let Some(state) = settled_state {
+        if state.positions.len() != args.num_particles {
+            return Err(format!(
+                "The settled state has {} particles, but --num-particles is {}",
+                state.positions.len(),
+                args.num_particles
+            )
+            .into());
+        }
+        app.insert_resource(state);
+    }
 
-    // Create the simulation and add it to ECS.
-    // Note: the simulation isn't well-integrated into Bevy ECS at all. Perhaps I will try, at some point,
-    // to move the many buffers inside the Simulation struct (e.g. positions, velocities, densities, and so on)
-    // into ECS, but it was easier to just stick them inside Simulation while developing.
-    // It would be interesting to see what, if any, impact moving them to ECS has on performance.
-    let mut sim = Simulation::new(window.width(), window.height(), &args.0);
-
-    sim.spawn_particles(&mut commands);
-    commands.spawn(sim);
-
-    // FPS display.
-    commands.spawn((
-        Text::default(),
-        (
-            TextFont {
-                font_size: 16.0,
-                ..default()
-            },
-            TextColor(GOLD.into()),
-        ),
-        FpsText,
-    ));
-
-    // Add a text display of the number of particles.
-    commands.spawn((
-        Text::new(format!("{} particles", args.0.num_particles)),
-        TextFont {
-            font_size: 16.0,
-            ..default()
-        },
-        TextLayout::new_with_justify(Justify::Right),
-        Node {
-            position_type: PositionType::Absolute,
-            right: Val::Px(5.0),
-            ..default()
-        },
-    ));
-
-    // Spawn the notification message component.
-    spawn_messages(&mut commands);
-
-    // Keyboard commands component
-    commands.spawn(KeyboardCommands::create());
+    app.insert_resource(SimulationSettings::new(args)?)
+        .add_plugins(FluidSimPlugin)
+        .run();
 
-    // Load the image for the sprites.
-    commands.spawn(SpriteImage {
-        handle: asset_server.load("embedded://blurred-circle-pow-2.0.png"),
-    });
+    Ok(())
 }
@@ -3,8 +3,12 @@ use std::time::{Duration, Instant};
 use bevy::prelude::*;
 use bevy::sprite::Text2dShadow;
 
+use crate::camera_controls::ScreenSized;
 use crate::components::Notifications;
 
+/// Where the notifications are drawn, relative to the middle of the view.
+pub const MESSAGE_POSITION: Vec3 = Vec3::new(0.0, 2.0, 1.0);
+
 #[derive(Clone)]
 pub struct MessageText {
     pub text: String,
@@ -38,12 +42,13 @@ pub fn spawn_messages(commands: &mut Commands) {
             ..default()
         },
         TextLayout::new_with_justify(Justify::Left),
-        Transform::from_translation(Vec3::new(0.0, 2.0, 1.0)),
+        Transform::from_translation(MESSAGE_POSITION),
         Text2dShadow {
             offset: Vec2::new(2., -2.),
             color: Color::BLACK,
         },
         messages,
+        ScreenSized,
     ));
 }
 
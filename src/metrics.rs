@@ -0,0 +1,160 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use bevy::prelude::{Res, ResMut, Resource, Single, Time, With, error};
+
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Time spent in each phase of the simulation since the last frame was exported, summed over the steps.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PhaseTimings {
+    /// The whole of the steps, including the phases below.
+    pub step: Duration,
+    pub neighbor_search: Duration,
+    pub densities: Duration,
+    /// The pressure forces, or the PBF constraint projection.
+    pub pressures: Duration,
+    pub viscosity: Duration,
+}
+
+/// The columns of the metrics export, in order.
+const COLUMNS: [&str; 13] = [
+    "frame",
+    "fps",
+    "particles",
+    "min_density",
+    "avg_density",
+    "max_density",
+    "max_speed",
+    "kinetic_energy",
+    "step_ms",
+    "neighbor_search_ms",
+    "densities_ms",
+    "pressures_ms",
+    "viscosity_ms",
+];
+
+/// Writes a row of metrics for every frame to the `--metrics-out` file: CSV, or JSON lines if the file name ends in
+/// .json or .jsonl.
+#[derive(Resource)]
+pub struct MetricsExport {
+    writer: BufWriter<File>,
+    json: bool,
+    frame: u64,
+}
+
+impl MetricsExport {
+    pub fn create(path: &str) -> io::Result<Self> {
+        let json = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension == "json" || extension == "jsonl");
+        let mut writer = BufWriter::new(File::create(path)?);
+        if !json {
+            writeln!(writer, "{}", COLUMNS.join(","))?;
+        }
+        Ok(MetricsExport { writer, json, frame: 0 })
+    }
+
+    fn write_row(&mut self, values: &[f64]) -> io::Result<()> {
+        if self.json {
+            // JSON has no NaN or infinity.
+            let json_value = |value: &f64| if value.is_finite() { value.to_string() } else { "null".into() };
+            let fields: Vec<String> = COLUMNS
+                .iter()
+                .zip(values)
+                .map(|(column, value)| format!("\"{column}\":{}", json_value(value)))
+                .collect();
+            writeln!(self.writer, "{{{}}}", fields.join(","))
+        } else {
+            let fields: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            writeln!(self.writer, "{}", fields.join(","))
+        }
+    }
+}
+
+impl Simulation {
+    /// The metrics for the current state, in the order of COLUMNS, after the frame number and FPS.
+    fn frame_metrics(&self) -> [f64; 6] {
+        let count = self.num_particles.max(1) as f32;
+        let min_density = self.densities.iter().copied().fold(f32::MAX, f32::min);
+        let max_density = self.densities.iter().copied().fold(0.0, f32::max);
+        let avg_density = self.parallel_sum(|i| self.densities[i]) / count;
+        let max_speed = self.velocities.iter().map(|v| v.length()).fold(0.0, f32::max);
+        [
+            self.num_particles as f32,
+            min_density,
+            avg_density,
+            max_density,
+            max_speed,
+            self.energy_stats().kinetic,
+        ]
+        .map(f64::from)
+    }
+}
+
+/// Appends the metrics for the frame, and starts timing the phases of the next one.
+pub fn export_metrics(
+    mut export: ResMut<MetricsExport>,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    time: Res<Time>,
+) {
+    let fps = if time.delta_secs() > 0.0 { 1.0 / time.delta_secs_f64() } else { 0.0 };
+    let timings = std::mem::take(&mut sim.phase_timings);
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let row: Vec<f64> = [export.frame as f64, fps]
+        .into_iter()
+        .chain(sim.frame_metrics())
+        .chain(
+            [
+                timings.step,
+                timings.neighbor_search,
+                timings.densities,
+                timings.pressures,
+                timings.viscosity,
+            ]
+            .map(ms),
+        )
+        .collect();
+    export.frame += 1;
+    if let Err(e) = export.write_row(&row).and_then(|()| export.writer.flush()) {
+        error!("Failed to write the metrics: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{COLUMNS, MetricsExport};
+
+    #[test]
+    fn metrics_are_written_as_csv_or_json() {
+        let dir = std::env::temp_dir().join(format!("bevy-fluid-sim-metrics-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut row = [0.0; COLUMNS.len()];
+        row[1] = 60.0;
+        row[3] = f64::NAN;
+
+        let csv_path = dir.join("metrics.csv");
+        let mut export = MetricsExport::create(csv_path.to_str().unwrap()).unwrap();
+        export.write_row(&row).unwrap();
+        drop(export);
+        let csv = fs::read_to_string(&csv_path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], COLUMNS.join(","));
+        assert_eq!(lines[1].split(',').nth(1), Some("60"));
+
+        let json_path = dir.join("metrics.jsonl");
+        let mut export = MetricsExport::create(json_path.to_str().unwrap()).unwrap();
+        export.write_row(&row).unwrap();
+        drop(export);
+        let json = fs::read_to_string(&json_path).unwrap();
+        assert!(json.starts_with("{\"frame\":0,\"fps\":60,\"particles\":0,\"min_density\":null,"), "{json}");
+        assert_eq!(json.lines().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
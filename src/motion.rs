@@ -0,0 +1,103 @@
+use std::f32::consts::TAU;
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::to_world;
+
+/// How long the central difference for the velocity spans, in seconds.
+const VELOCITY_STEP: f32 = 0.001;
+
+/// Scripted motion for an obstacle in a scenario, starting from the obstacle's center. Like the rest of a scenario,
+/// positions are fractions of the tank.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Motion {
+    /// Moves back and forth along `amplitude` (a fraction of the tank's width and height) and the opposite way, like
+    /// a piston.
+    Oscillate { amplitude: (f32, f32), frequency: f32 },
+    /// Goes around `pivot`, counterclockwise for a positive frequency.
+    Orbit { pivot: (f32, f32), frequency: f32 },
+    /// Visits the points in turn and then heads back to the start, at `speed` tank widths per second.
+    Waypoints { points: Vec<(f32, f32)>, speed: f32 },
+}
+
+impl Motion {
+    /// How far the obstacle has moved from `start`, in world coordinates, at the given time.
+    pub fn offset(&self, start: Vec2, half_size: Vec2, time: f32) -> Vec2 {
+        match self {
+            Motion::Oscillate { amplitude, frequency } => {
+                Vec2::new(amplitude.0, amplitude.1) * half_size * 2.0 * (TAU * frequency * time).sin()
+            }
+            Motion::Orbit { pivot, frequency } => {
+                let arm = start - to_world(*pivot, half_size);
+                Vec2::from_angle(TAU * frequency * time).rotate(arm) - arm
+            }
+            Motion::Waypoints { points, speed } => {
+                let path: Vec<Vec2> = std::iter::once(start)
+                    .chain(points.iter().map(|&point| to_world(point, half_size)))
+                    .chain(std::iter::once(start))
+                    .collect();
+                let length: f32 = path.windows(2).map(|leg| leg[0].distance(leg[1])).sum();
+                if length <= 0.0 {
+                    return Vec2::ZERO;
+                }
+                let mut distance = (speed * half_size.x * 2.0 * time).rem_euclid(length);
+                for leg in path.windows(2) {
+                    let leg_length = leg[0].distance(leg[1]);
+                    if distance <= leg_length {
+                        return leg[0].lerp(leg[1], distance / leg_length.max(f32::EPSILON)) - start;
+                    }
+                    distance -= leg_length;
+                }
+                Vec2::ZERO
+            }
+        }
+    }
+
+    /// The obstacle's velocity, in pixels per second, by central difference.
+    pub fn velocity(&self, start: Vec2, half_size: Vec2, time: f32) -> Vec2 {
+        let before = self.offset(start, half_size, time - VELOCITY_STEP);
+        let after = self.offset(start, half_size, time + VELOCITY_STEP);
+        (after - before) / (2.0 * VELOCITY_STEP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::Motion;
+
+    #[test]
+    fn motions_start_from_the_center() {
+        let half_size = Vec2::new(200.0, 100.0);
+        let start = Vec2::new(-100.0, 0.0);
+        let motions = [
+            Motion::Oscillate {
+                amplitude: (0.1, 0.0),
+                frequency: 0.5,
+            },
+            Motion::Orbit {
+                pivot: (0.5, 0.5),
+                frequency: 0.5,
+            },
+            Motion::Waypoints {
+                points: vec![(0.25, 1.0), (0.75, 0.5)],
+                speed: 0.5,
+            },
+        ];
+        for motion in &motions {
+            assert!(motion.offset(start, half_size, 0.0).length() < 1e-3, "{motion:?}");
+        }
+
+        // A quarter of the way around the orbit, counterclockwise, it's below the pivot, moving right.
+        let orbit = &motions[1];
+        assert!((start + orbit.offset(start, half_size, 0.5)).distance(Vec2::new(0.0, -100.0)) < 1e-2);
+        let velocity = orbit.velocity(start, half_size, 0.5);
+        assert!(velocity.x > 0.0 && velocity.y.abs() < 1.0, "{velocity}");
+
+        // At 200 pixels per second, it takes half a second to reach the first waypoint, 100 pixels above the start.
+        let waypoints = &motions[2];
+        assert!(waypoints.offset(start, half_size, 0.5).distance(Vec2::new(0.0, 100.0)) < 1e-2);
+    }
+}
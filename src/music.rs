@@ -0,0 +1,55 @@
+use bevy::audio::Volume;
+use bevy::prelude::*;
+
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Plays a looping ambient track (`--music`) whose volume and playback speed follow the fluid: the volume rises with
+/// the total kinetic energy, and splashes (sudden increases in energy) briefly speed the track up.
+/// bevy_audio doesn't have filters, so the playback speed stands in for a filter cutoff.
+#[derive(Resource, Default)]
+pub struct MusicIntensity {
+    /// Smoothed mean kinetic energy.
+    energy: f32,
+    /// The highest smoothed energy seen so far, used to normalize the volume.
+    max_energy: f32,
+    /// Splash intensity, from 0 to 1. Decays over time.
+    splash: f32,
+}
+
+impl MusicIntensity {
+    /// Fraction of the new value that's blended into the smoothed energy each frame.
+    const SMOOTHING: f32 = 0.05;
+    /// Fraction of the splash intensity that's retained each frame.
+    const SPLASH_DECAY: f32 = 0.97;
+    const MIN_VOLUME: f32 = 0.2;
+    const MAX_SPEED_CHANGE: f32 = 0.15;
+}
+
+#[derive(Component)]
+pub struct MusicTrack;
+
+pub fn spawn_music(commands: &mut Commands, asset_server: &AssetServer, path: &str) {
+    commands.spawn((AudioPlayer::new(asset_server.load(path.to_string())), PlaybackSettings::LOOP, MusicTrack));
+}
+
+pub fn update_music(
+    mut intensity: ResMut<MusicIntensity>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut sinks: Query<&mut AudioSink, With<MusicTrack>>,
+) {
+    let energy = sim.mean_kinetic_energy();
+    let previous_energy = intensity.energy;
+    intensity.energy += (energy - intensity.energy) * MusicIntensity::SMOOTHING;
+    intensity.max_energy = intensity.max_energy.max(intensity.energy);
+
+    // A splash is a sudden jump in energy, relative to the usual level.
+    let jump = (energy - previous_energy) / intensity.max_energy.max(f32::EPSILON);
+    intensity.splash = (intensity.splash * MusicIntensity::SPLASH_DECAY).max(jump.clamp(0.0, 1.0));
+
+    let level = intensity.energy / intensity.max_energy.max(f32::EPSILON);
+    for mut sink in &mut sinks {
+        sink.set_volume(Volume::Linear(MusicIntensity::MIN_VOLUME + (1.0 - MusicIntensity::MIN_VOLUME) * level));
+        sink.set_speed(1.0 + intensity.splash * MusicIntensity::MAX_SPEED_CHANGE);
+    }
+}
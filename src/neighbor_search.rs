@@ -0,0 +1,332 @@
+use bevy::math::{IVec2, UVec2, Vec2};
+use clap::ValueEnum;
+
+const OFFSETS_2D: [(i32, i32); 9] = [
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (-1, 0),
+    (0, 0),
+    (1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// A spatial index used to find the particles near a given position.
+/// The space is divided into square cells the size of the smoothing radius, so that every particle within the
+/// smoothing radius of a position is in the 3x3 block of cells around it.
+pub trait NeighborSearch: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Rebuilds the index from the particle positions.
+    /// `origin` is the bottom-left corner of the simulation bounds, and `cells` is the number of columns and rows.
+    fn update(&mut self, positions: &[Vec2], origin: Vec2, cells: UVec2, cell_size: f32);
+
+    /// Returns every particle in the 3x3 block of cells around the position. Some of them will be further away than
+    /// the smoothing radius, so callers still need to check the distance.
+    fn candidates(&self, position: Vec2) -> Candidates<'_>;
+
+    /// The number of particles in the given cell.
+    fn cell_len(&self, cell: IVec2) -> usize;
+
+    /// The particles in the given cell.
+    fn cell_particles(&self, cell: IVec2) -> Candidates<'_>;
+}
+
+/// The particles in up to nine cells, as returned by the NeighborSearch queries. This is called for every particle
+/// several times a step, so it's a plain struct over the index's own slices rather than a boxed iterator.
+pub struct Candidates<'a> {
+    /// The particle ids listed for each cell, in order.
+    runs: [&'a [usize]; 9],
+    /// For a hash, where a run can also hold particles from other cells with the same hash: every particle's cell, and
+    /// the cell that each run is meant to hold, so that the others can be skipped.
+    cells: Option<(&'a [IVec2], [IVec2; 9])>,
+    run: usize,
+    index: usize,
+}
+
+impl<'a> Candidates<'a> {
+    fn new(runs: [&'a [usize]; 9], cells: Option<(&'a [IVec2], [IVec2; 9])>) -> Self {
+        Self {
+            runs,
+            cells,
+            run: 0,
+            index: 0,
+        }
+    }
+}
+
+impl Iterator for Candidates<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let run = self.runs.get(self.run)?;
+            let Some(&particle_id) = run.get(self.index) else {
+                self.run += 1;
+                self.index = 0;
+                continue;
+            };
+            self.index += 1;
+            match self.cells {
+                Some((particle_cells, cells)) if particle_cells[particle_id] != cells[self.run] => {}
+                _ => return Some(particle_id),
+            }
+        }
+    }
+}
+
+/// The available NeighborSearch implementations, selectable with `--neighbor-search`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum NeighborSearchKind {
+    /// A 2D grid of cells, each holding a list of particles.
+    #[default]
+    Grid,
+    /// Sebastian Lague's compact spatial hash: particles sorted by cell hash, with a start index per hash.
+    Hash,
+}
+
+impl NeighborSearchKind {
+    pub fn create(self) -> Box<dyn NeighborSearch> {
+        match self {
+            NeighborSearchKind::Grid => Box::new(RegionGrid::default()),
+            NeighborSearchKind::Hash => Box::new(CompactHash::default()),
+        }
+    }
+}
+
+/// Cell layout shared by the implementations, so that they agree on which cell a position is in.
+#[derive(Default)]
+struct CellLayout {
+    origin: Vec2,
+    cells: UVec2,
+    cell_size: f32,
+}
+
+impl CellLayout {
+    /// The cell containing the position. Positions outside the bounds (e.g. while the window is being resized) are
+    /// assigned to the nearest edge cell.
+    fn cell_of(&self, position: Vec2) -> IVec2 {
+        let cell = ((position - self.origin) / self.cell_size).floor();
+        IVec2::new((cell.x as i32).clamp(0, self.cells.x as i32 - 1), (cell.y as i32).clamp(0, self.cells.y as i32 - 1))
+    }
+
+    fn in_bounds(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && (cell.x as u32) < self.cells.x && (cell.y as u32) < self.cells.y
+    }
+}
+
+/// This is my simplistic alternative to the funky "spatial hash" code.
+/// I just divide the space up into regions the size of the smoothing radius, and
+/// keep track of the particles in each region. Wasteful of memory, but it's simple and
+/// it works.
+#[derive(Default)]
+pub struct RegionGrid {
+    layout: CellLayout,
+    regions: Vec<Vec<Vec<usize>>>,
+}
+
+impl NeighborSearch for RegionGrid {
+    fn name(&self) -> &'static str {
+        "region grid"
+    }
+
+    fn update(&mut self, positions: &[Vec2], origin: Vec2, cells: UVec2, cell_size: f32) {
+        let rows = cells.y as usize;
+        let cols = cells.x as usize;
+        let num_regions = rows * cols;
+
+        // If window size or smoothing radius has changed, need to resize the regions vector.
+        if self.layout.cells != cells {
+            self.regions.clear();
+            for row in 0..rows {
+                self.regions.push(vec![]);
+                for _ in 0..cols {
+                    let region = Vec::with_capacity(positions.len() / num_regions * 4);
+                    self.regions[row].push(region);
+                }
+            }
+        } else {
+            for row in self.regions.iter_mut() {
+                for region in row.iter_mut() {
+                    region.clear();
+                }
+            }
+        }
+        self.layout = CellLayout {
+            origin,
+            cells,
+            cell_size,
+        };
+
+        for (i, &position) in positions.iter().enumerate() {
+            let cell = self.layout.cell_of(position);
+            self.regions[cell.y as usize][cell.x as usize].push(i);
+        }
+    }
+
+    fn candidates(&self, position: Vec2) -> Candidates<'_> {
+        let cell = self.layout.cell_of(position);
+        let runs = OFFSETS_2D.map(|offset| self.region(cell + IVec2::new(offset.0, offset.1)));
+        Candidates::new(runs, None)
+    }
+
+    fn cell_len(&self, cell: IVec2) -> usize {
+        self.region(cell).len()
+    }
+
+    fn cell_particles(&self, cell: IVec2) -> Candidates<'_> {
+        let mut runs = [&[][..]; 9];
+        runs[0] = self.region(cell);
+        Candidates::new(runs, None)
+    }
+}
+
+impl RegionGrid {
+    fn region(&self, cell: IVec2) -> &[usize] {
+        if self.layout.in_bounds(cell) { &self.regions[cell.y as usize][cell.x as usize] } else { &[] }
+    }
+}
+
+/// The spatial hash from Sebastian Lague's video: each particle is tagged with the hash of its cell, the particles are
+/// sorted by hash, and the index of the first particle with each hash is recorded. All the particles in a cell are
+/// then adjacent in the sorted list (along with particles from any other cells with the same hash, which have to be
+/// filtered out).
+#[derive(Default)]
+pub struct CompactHash {
+    layout: CellLayout,
+    /// The hash key of each entry, sorted.
+    keys: Vec<usize>,
+    /// The particle id of each entry, in the same order as `keys`.
+    particle_ids: Vec<usize>,
+    /// Index into the entries of the first entry with each key, or usize::MAX if there are none.
+    start_indices: Vec<usize>,
+    /// The cell of each particle.
+    particle_cells: Vec<IVec2>,
+}
+
+impl CompactHash {
+    fn key(&self, cell: IVec2) -> usize {
+        let hash = (cell.x as u32).wrapping_mul(15823) ^ (cell.y as u32).wrapping_mul(9737333);
+        hash as usize % self.start_indices.len().max(1)
+    }
+
+    /// The particles whose cells have the same key as the given cell (including the cell's own particles).
+    fn bucket(&self, cell: IVec2) -> &[usize] {
+        if !self.layout.in_bounds(cell) {
+            return &[];
+        }
+        let key = self.key(cell);
+        let Some(&start) = self.start_indices.get(key).filter(|&&start| start != usize::MAX) else {
+            return &[];
+        };
+        let len = self.keys[start..]
+            .iter()
+            .take_while(|&&entry_key| entry_key == key)
+            .count();
+        &self.particle_ids[start..start + len]
+    }
+}
+
+impl NeighborSearch for CompactHash {
+    fn name(&self) -> &'static str {
+        "compact hash"
+    }
+
+    fn update(&mut self, positions: &[Vec2], origin: Vec2, cells: UVec2, cell_size: f32) {
+        self.layout = CellLayout {
+            origin,
+            cells,
+            cell_size,
+        };
+        self.start_indices.clear();
+        self.start_indices.resize(positions.len(), usize::MAX);
+
+        self.particle_cells = positions
+            .iter()
+            .map(|&position| self.layout.cell_of(position))
+            .collect();
+        let mut entries: Vec<(usize, usize)> = self
+            .particle_cells
+            .iter()
+            .enumerate()
+            .map(|(i, &cell)| (self.key(cell), i))
+            .collect();
+        entries.sort_unstable();
+        (self.keys, self.particle_ids) = entries.into_iter().unzip();
+
+        for (index, &key) in self.keys.iter().enumerate().rev() {
+            self.start_indices[key] = index;
+        }
+    }
+
+    fn candidates(&self, position: Vec2) -> Candidates<'_> {
+        let cell = self.layout.cell_of(position);
+        let cells = OFFSETS_2D.map(|offset| cell + IVec2::new(offset.0, offset.1));
+        Candidates::new(cells.map(|cell| self.bucket(cell)), Some((&self.particle_cells, cells)))
+    }
+
+    fn cell_len(&self, cell: IVec2) -> usize {
+        self.cell_particles(cell).count()
+    }
+
+    fn cell_particles(&self, cell: IVec2) -> Candidates<'_> {
+        let mut runs = [&[][..]; 9];
+        runs[0] = self.bucket(cell);
+        Candidates::new(runs, Some((&self.particle_cells, [cell; 9])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::random;
+
+    use super::*;
+
+    /// Every NeighborSearch implementation must return exactly the same neighbors (i.e. the candidates within the
+    /// cell size) as a brute-force search, for random particle configurations.
+    #[test]
+    fn implementations_return_identical_neighbor_sets() {
+        let cell_size = 10.0;
+        let cells = UVec2::new(17, 11);
+        let origin = Vec2::new(-85.0, -55.0);
+        let bounds = cells.as_vec2() * cell_size;
+
+        for num_particles in [1, 10, 500, 2000] {
+            // Include a few particles slightly outside the bounds, as happens while the window is being resized.
+            let positions: Vec<Vec2> = (0..num_particles)
+                .map(|_| origin - 5.0 + Vec2::new(random::<f32>(), random::<f32>()) * (bounds + 10.0))
+                .collect();
+
+            let brute_force = |position: Vec2| -> Vec<usize> {
+                (0..num_particles)
+                    .filter(|&i| (positions[i] - position).length() < cell_size)
+                    .collect()
+            };
+
+            for kind in NeighborSearchKind::value_variants() {
+                let mut search = kind.create();
+                search.update(&positions, origin, cells, cell_size);
+
+                let total: usize = (0..cells.y as i32)
+                    .flat_map(|row| (0..cells.x as i32).map(move |col| IVec2::new(col, row)))
+                    .map(|cell| {
+                        assert_eq!(search.cell_particles(cell).count(), search.cell_len(cell));
+                        search.cell_len(cell)
+                    })
+                    .sum();
+                assert_eq!(total, num_particles, "{}: cell counts", search.name());
+
+                for &position in positions.iter().take(200) {
+                    let mut neighbors: Vec<usize> = search
+                        .candidates(position)
+                        .filter(|&i| (positions[i] - position).length() < cell_size)
+                        .collect();
+                    neighbors.sort_unstable();
+                    assert_eq!(neighbors, brute_force(position), "{}: neighbors of {position}", search.name());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::color::palettes::css::{RED, WHITE};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::compare::ComparisonCamera;
+use crate::components::MainSimulation;
+use crate::scenario::{Obstacle, Scenario};
+use crate::sim_struct::Simulation;
+
+/// Radius of the painted obstacles, as a fraction of the tank's width.
+const BRUSH_RADIUS: f32 = 0.015;
+/// Spacing of the circles along a stroke, as a fraction of their radius, so that they overlap into a solid wall.
+const BRUSH_SPACING: f32 = 0.5;
+/// The key to hold down while dragging to paint (left button) or erase (right button) obstacles.
+pub const PAINT_KEY: KeyCode = KeyCode::Tab;
+
+impl Simulation {
+    fn brush_radius(&self) -> f32 {
+        BRUSH_RADIUS * self.half_bounds_size.x * 2.0
+    }
+
+    /// Paints a stroke of circular obstacles from one point to another. The obstacles are added to the scenario (an
+    /// empty one, if there isn't one), so that they stay in place when the particles are reset, and are saved with it.
+    pub fn paint_obstacles(&mut self, from: Vec2, to: Vec2) {
+        let half_size = self.half_bounds_size;
+        let radius = self.brush_radius();
+        let spacing = radius * BRUSH_SPACING;
+        let scenario = self.scenario.get_or_insert_with(|| Scenario {
+            name: "Painted".into(),
+            ..Default::default()
+        });
+
+        let steps = (from.distance(to) / spacing).ceil().max(1.0) as usize;
+        for step in 0..=steps {
+            let point = from.lerp(to, step as f32 / steps as f32);
+            let covered = scenario
+                .obstacles
+                .iter()
+                .any(|obstacle| obstacle.circle(half_size).0.distance(point) < spacing);
+            if !covered {
+                scenario.obstacles.push(Obstacle::new(point, radius, half_size));
+            }
+        }
+    }
+
+    /// Removes the obstacles that the brush touches at the point, painted or not.
+    pub fn erase_obstacles(&mut self, point: Vec2) {
+        let half_size = self.half_bounds_size;
+        let time = self.sim_time;
+        let radius = self.brush_radius();
+        if let Some(scenario) = &mut self.scenario {
+            scenario.obstacles.retain(|obstacle| {
+                let (center, obstacle_radius) = obstacle.circle_at(half_size, time);
+                center.distance(point) >= obstacle_radius + radius
+            });
+        }
+    }
+}
+
+/// While the paint key is held down, dragging with the left button paints obstacles, and with the right button
+/// erases them. The mouse doesn't interact with the fluid meanwhile.
+pub fn paint_obstacles(
+    mut gizmos: Gizmos,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    mut previous_point: Local<Option<Vec2>>,
+) {
+    let cursor_point = windows.single().ok().and_then(|window| {
+        let (camera, camera_transform) = cameras_query.iter().next()?;
+        camera
+            .viewport_to_world_2d(camera_transform, window.cursor_position()?)
+            .ok()
+    });
+    let (true, Some(point)) = (kb.pressed(PAINT_KEY), cursor_point) else {
+        *previous_point = None;
+        return;
+    };
+
+    let erasing = buttons.pressed(MouseButton::Right);
+    gizmos.circle_2d(point, sim.brush_radius(), if erasing { RED } else { WHITE });
+    if erasing {
+        sim.erase_obstacles(point);
+        *previous_point = None;
+    } else if buttons.pressed(MouseButton::Left) {
+        sim.paint_obstacles(previous_point.unwrap_or(point), point);
+        *previous_point = Some(point);
+    } else {
+        *previous_point = None;
+    }
+}
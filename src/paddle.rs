@@ -0,0 +1,81 @@
+use std::f32::consts::TAU;
+
+use bevy::math::Vec2;
+
+use crate::walls::apply_friction;
+
+/// A wave paddle (`--paddle`): the left wall of the tank moves in and out sinusoidally, pushing the fluid, which makes
+/// gravity waves travel along the tank.
+#[derive(Clone, Debug)]
+pub struct Paddle {
+    /// How far the paddle moves into the tank at its furthest, in pixels.
+    pub amplitude: f32,
+    /// Strokes per second.
+    pub frequency: f32,
+}
+
+impl Paddle {
+    /// How far the paddle is from the left wall at the given time. It starts at the wall, at rest, so that it eases
+    /// into its first stroke.
+    pub fn offset(&self, time: f32) -> f32 {
+        self.amplitude * (1.0 - (TAU * self.frequency * time).cos()) / 2.0
+    }
+
+    /// The paddle's speed to the right, in pixels per second.
+    pub fn velocity(&self, time: f32) -> f32 {
+        self.amplitude * TAU * self.frequency * (TAU * self.frequency * time).sin() / 2.0
+    }
+
+    /// Pushes a position behind the paddle back out in front of it, and bounces the velocity off it, relative to the
+    /// paddle's own velocity, so a paddle moving into the fluid pushes it along. `speed` is the simulation speed,
+    /// since the particles' velocities are in its time.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resolve_collision(
+        &self,
+        mut position: Vec2,
+        mut velocity: Vec2,
+        left: f32,
+        time: f32,
+        speed: f32,
+        restitution: f32,
+        friction: f32,
+    ) -> (Vec2, Vec2) {
+        let x = left + self.offset(time);
+        if position.x >= x {
+            return (position, velocity);
+        }
+
+        let paddle_velocity = self.velocity(time) / speed.max(f32::EPSILON);
+        let before = velocity;
+        position.x = x;
+        let relative = velocity.x - paddle_velocity;
+        if relative < 0.0 {
+            velocity.x = paddle_velocity - relative * restitution;
+        }
+        velocity = apply_friction(velocity, Vec2::X, velocity - before, friction);
+        (position, velocity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::Paddle;
+
+    #[test]
+    fn paddle_pushes_the_particles_in_front_of_it() {
+        let paddle = Paddle {
+            amplitude: 40.0,
+            frequency: 0.5,
+        };
+        assert_eq!(paddle.offset(0.0), 0.0);
+        assert!((paddle.offset(1.0) - 40.0).abs() < 1e-3);
+
+        // Half way through its stroke, it's moving into the tank at its fastest.
+        let (position, velocity) =
+            paddle.resolve_collision(Vec2::new(-100.0, 0.0), Vec2::ZERO, -100.0, 0.5, 1.0, 0.5, 0.0);
+        assert!((position.x - (-80.0)).abs() < 1e-3);
+        assert!(velocity.x > paddle.velocity(0.5), "{velocity}");
+    }
+}
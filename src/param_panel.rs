@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiGlobalSettings, EguiPlugin, EguiPrimaryContextPass, egui};
+
+use crate::components::MainSimulation;
+use crate::sim_state::SimState;
+use crate::sim_struct::Simulation;
+
+/// Adds a side panel (`--panel`) with sliders for the main simulation parameters, and buttons for pausing and
+/// resetting. Unlike the keyboard shortcuts, the sliders show the current values and can be set to any value.
+pub fn add_param_panel(app: &mut App) {
+    app.add_plugins(EguiPlugin::default())
+        .add_systems(Startup, absorb_input)
+        .add_systems(EguiPrimaryContextPass, param_panel);
+}
+
+/// Keeps clicks and drags on the panel from also interacting with the fluid.
+fn absorb_input(mut settings: ResMut<EguiGlobalSettings>) {
+    settings.enable_absorb_bevy_input_system = true;
+}
+
+fn param_panel(
+    mut contexts: EguiContexts,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    state: Res<State<SimState>>,
+    mut next_state: ResMut<NextState<SimState>>,
+) -> Result {
+    egui::SidePanel::left("parameters").show(contexts.ctx_mut()?, |ui| {
+        ui.heading("Parameters");
+
+        let mut smoothing_radius = sim.smoothing_radius / sim.particle_size;
+        if ui
+            .add(egui::Slider::new(&mut smoothing_radius, 1.0..=20.0).text("Smoothing radius"))
+            .changed()
+        {
+            sim.set_smoothing_radius(smoothing_radius);
+        }
+
+        // The target density is derived from the particle spacing, and its scale depends on it, so a slider range
+        // wouldn't fit every particle count. Once it's set here, it's kept instead of being derived.
+        let pixels_per_unit = sim.units.pixels_per_unit;
+        let mut target_density = sim.target_density * pixels_per_unit * pixels_per_unit;
+        let speed = target_density * 0.005;
+        ui.horizontal(|ui| {
+            if ui
+                .add(
+                    egui::DragValue::new(&mut target_density)
+                        .speed(speed)
+                        .range(0.0..=f32::MAX),
+                )
+                .changed()
+            {
+                sim.set_target_density(target_density);
+            }
+            ui.label("Target density");
+        });
+
+        let units = sim.units;
+        let mut pressure_multiplier = units.to_units(sim.pressure_multiplier);
+        if ui
+            .add(
+                egui::Slider::new(&mut pressure_multiplier, 1000.0..=2_000_000.0)
+                    .logarithmic(true)
+                    .text("Pressure multiplier"),
+            )
+            .changed()
+        {
+            sim.pressure_multiplier = units.to_pixels(pressure_multiplier);
+        }
+
+        let mut gravity = units.to_units(sim.gravity.y);
+        if ui
+            .add(egui::Slider::new(&mut gravity, -100.0..=100.0).text("Gravity"))
+            .changed()
+        {
+            sim.gravity.y = units.to_pixels(gravity);
+        }
+
+        ui.add(egui::Slider::new(&mut sim.viscosity_strength, 0.0..=500.0).text("Viscosity"));
+        ui.add(egui::Slider::new(&mut sim.collision_damping, 0.0..=1.0).text("Collision damping"));
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let paused = *state.get() == SimState::Paused;
+            if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                next_state.set(if paused { SimState::Running } else { SimState::Paused });
+            }
+            if ui.button("Reset").clicked() {
+                sim.reset();
+            }
+        });
+    });
+    Ok(())
+}
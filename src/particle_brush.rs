@@ -0,0 +1,201 @@
+use std::f32::consts::TAU;
+
+use bevy::color::palettes::css::{RED, SKY_BLUE};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::compare::ComparisonCamera;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Hold down while dragging with the left button to pour new particles in at the cursor.
+pub const SPAWN_KEY: KeyCode = KeyCode::Equal;
+/// Hold down while dragging with the left button to delete the particles under the cursor.
+pub const DELETE_KEY: KeyCode = KeyCode::Minus;
+/// Radius of the brush, as a multiple of the smoothing radius.
+const BRUSH_RADIUS: f32 = 1.0;
+/// How many particles the spawn brush adds per second.
+const SPAWN_RATE: f32 = 400.0;
+
+/// Keeps only the values whose entry in `keep` is true, in order.
+fn retain_indices<T>(values: &mut Vec<T>, keep: &[bool]) {
+    let mut index = 0;
+    values.retain(|_| {
+        index += 1;
+        keep[index - 1]
+    });
+}
+
+impl Simulation {
+    pub fn particle_brush_radius(&self) -> f32 {
+        self.smoothing_radius * BRUSH_RADIUS
+    }
+
+    /// Adds `count` particles of the first fluid at random spots within the brush around the point, all moving with
+    /// the given velocity. Unlike changing the number of particles with N, this leaves the target density alone.
+    pub fn add_particles(&mut self, point: Vec2, velocity: Vec2, count: usize) {
+        let radius = self.particle_brush_radius();
+        let first = self.num_particles;
+        let positions: Vec<Vec2> = (first..first + count)
+            .map(|i| {
+                // Uniform over the disc.
+                point + Vec2::from_angle(self.random(i, 2) * TAU) * self.random(i, 3).sqrt() * radius
+            })
+            .collect();
+        self.insert_particles(&positions, velocity);
+    }
+
+    /// Adds particles of the first fluid at the given positions (clamped to the tank), all moving with the given
+    /// velocity, leaving the target density alone.
+    pub fn insert_particles(&mut self, positions: &[Vec2], velocity: Vec2) {
+        let first = self.num_particles;
+        self.set_num_particles(first + positions.len());
+        for (i, &position) in (first..).zip(positions) {
+            let position = self.clamp_to_bounds(position);
+            self.positions[i] = position;
+            self.predicted_positions[i] = position;
+            self.previous_positions[i] = position;
+            self.velocities[i] = velocity;
+        }
+        self.totals_changed = true;
+        self.update_regions();
+    }
+
+    /// Deletes the particles within the brush around the point, keeping the order of the others (and at least one
+    /// particle). Returns the number deleted.
+    pub fn delete_particles(&mut self, point: Vec2) -> usize {
+        let radius = self.particle_brush_radius();
+        self.retain_particles(|position| position.distance(point) >= radius)
+    }
+
+    /// Deletes the particles whose positions don't pass `keep`, keeping the order of the others (and at least one
+    /// particle). Returns the number deleted.
+    pub fn retain_particles(&mut self, keep: impl Fn(Vec2) -> bool) -> usize {
+        let mut keep: Vec<bool> = self.positions.iter().map(|&position| keep(position)).collect();
+        let mut num_kept = keep.iter().filter(|&&keep| keep).count();
+        if num_kept == 0 {
+            keep[0] = true;
+            num_kept = 1;
+        }
+        let deleted = self.num_particles - num_kept;
+        if deleted == 0 {
+            return 0;
+        }
+
+        retain_indices(&mut self.positions, &keep);
+        retain_indices(&mut self.predicted_positions, &keep);
+        retain_indices(&mut self.previous_positions, &keep);
+        retain_indices(&mut self.velocities, &keep);
+        retain_indices(&mut self.densities, &keep);
+        retain_indices(&mut self.pressures, &keep);
+        retain_indices(&mut self.fluids, &keep);
+        if let Some(convection) = &mut self.convection {
+            retain_indices(&mut convection.temperatures, &keep);
+        }
+        if let Some(dye) = &mut self.dye {
+            retain_indices(&mut dye.colors, &keep);
+        }
+        self.num_particles = num_kept;
+        // The particle ids have shifted.
+        self.release_grab();
+        self.springs.rest_lengths.clear();
+        self.surface.clear();
+        self.totals_changed = true;
+        self.update_regions();
+        deleted
+    }
+
+    fn clamp_to_bounds(&self, position: Vec2) -> Vec2 {
+        position.clamp(-self.half_bounds_size, self.half_bounds_size)
+    }
+}
+
+/// While the spawn key is held, dragging with the left button pours particles in at the cursor, moving with it; while
+/// the delete key is held, it deletes the particles under the cursor. The entities for the particles are spawned and
+/// despawned to match by sync_particle_entities().
+#[allow(clippy::too_many_arguments)] // ECS gonna ECS.
+pub fn brush_particles(
+    mut gizmos: Gizmos,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    kb: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    time: Res<Time>,
+    mut previous_point: Local<Option<Vec2>>,
+    mut pending: Local<f32>,
+) {
+    let spawning = kb.pressed(SPAWN_KEY);
+    let deleting = kb.pressed(DELETE_KEY);
+    let cursor_point = windows.single().ok().and_then(|window| {
+        let (camera, camera_transform) = cameras_query.iter().next()?;
+        camera
+            .viewport_to_world_2d(camera_transform, window.cursor_position()?)
+            .ok()
+    });
+    let point = match cursor_point {
+        Some(point) if spawning || deleting => point,
+        _ => {
+            *previous_point = None;
+            return;
+        }
+    };
+    let last_point = previous_point.replace(point).unwrap_or(point);
+
+    gizmos.circle_2d(point, sim.particle_brush_radius(), if deleting { RED } else { SKY_BLUE });
+    if !buttons.pressed(MouseButton::Left) {
+        *pending = 0.0;
+        return;
+    }
+
+    if deleting {
+        sim.delete_particles(point);
+    } else {
+        // The new particles move with the cursor. Their velocities are in the simulation's time, which runs `speed`
+        // times faster.
+        let cursor_velocity = (point - last_point) / time.delta_secs().max(f32::EPSILON);
+        let velocity = cursor_velocity / sim.speed.max(f32::EPSILON);
+        *pending += SPAWN_RATE * time.delta_secs();
+        let count = *pending as usize;
+        *pending -= count as f32;
+        if count > 0 {
+            sim.add_particles(point, velocity, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn brush_adds_and_deletes_particles_under_the_cursor() {
+        let mut sim = test_sim(100, 400.0, 400.0, Args::default());
+        let point = Vec2::new(0.0, sim.half_bounds_size.y * 0.8);
+        let target_density = sim.target_density;
+
+        sim.add_particles(point, Vec2::X, 20);
+        assert_eq!(sim.num_particles, 120);
+        assert_eq!(sim.positions.len(), 120);
+        assert_eq!(sim.target_density, target_density);
+        for i in 100..120 {
+            assert!(sim.positions[i].distance(point) <= sim.particle_brush_radius() * 1.001);
+            assert_eq!(sim.velocities[i], Vec2::X);
+        }
+
+        let survivor = sim.positions[0];
+        let deleted = sim.delete_particles(point);
+        assert!(deleted >= 20, "deleted {deleted}");
+        assert_eq!(sim.num_particles, 120 - deleted);
+        assert_eq!(sim.velocities.len(), sim.num_particles);
+        assert_eq!(sim.positions[0], survivor);
+        assert!(
+            sim.positions()
+                .iter()
+                .all(|p| p.distance(point) >= sim.particle_brush_radius())
+        );
+    }
+}
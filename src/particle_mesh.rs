@@ -0,0 +1,108 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::camera::visibility::NoFrustumCulling;
+use bevy::color::ColorToComponents;
+use bevy::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::components::MainSimulation;
+use crate::lod::Lod;
+use crate::sim_struct::Simulation;
+use crate::update::{particle_color, particle_sprite_size};
+
+/// The corners of a particle's quad, relative to its center, and their texture coordinates.
+const CORNERS: [(Vec2, [f32; 2]); 4] = [
+    (Vec2::new(-0.5, -0.5), [0.0, 1.0]),
+    (Vec2::new(0.5, -0.5), [1.0, 1.0]),
+    (Vec2::new(0.5, 0.5), [1.0, 0.0]),
+    (Vec2::new(-0.5, 0.5), [0.0, 0.0]),
+];
+
+/// Draws every particle as a textured quad in a single mesh (`--particle-mesh`), so that the renderer has one draw
+/// call and no per-particle entities to deal with.
+#[derive(Component)]
+pub struct ParticleMesh;
+
+pub fn spawn_particle_mesh(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    sim: &Simulation,
+    image: Handle<Image>,
+) {
+    if !sim.debug.particle_mesh {
+        return;
+    }
+    commands.spawn((
+        Mesh2d(meshes.add(particle_mesh(sim, 0.0))),
+        // The vertex colors are multiplied by the texture.
+        MeshMaterial2d(materials.add(ColorMaterial {
+            texture: Some(image),
+            ..default()
+        })),
+        Transform::default(),
+        // The mesh's bounds change every frame.
+        NoFrustumCulling,
+        ParticleMesh,
+    ));
+}
+
+/// Updates the particle mesh from the positions, interpolated between the last two simulation steps. Only the
+/// positions and colors are written, into the mesh's own buffers, unless the number of particles has changed.
+pub fn update_particle_mesh(
+    sim: Single<&Simulation, With<MainSimulation>>,
+    particle_mesh_query: Single<(&Mesh2d, &mut Visibility), With<ParticleMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    time: Res<Time<Fixed>>,
+    lod: Res<Lod>,
+) {
+    let (mesh, mut visibility) = particle_mesh_query.into_inner();
+    // The LOD splats are drawn instead, when zoomed out.
+    visibility.set_if_neq(if lod.active { Visibility::Hidden } else { Visibility::Inherited });
+    if lod.active {
+        return;
+    }
+    let Some(mesh) = meshes.get_mut(&mesh.0) else {
+        return;
+    };
+    if mesh.count_vertices() == sim.num_particles * 4 {
+        write_vertices(mesh, &sim, time.overstep_fraction());
+    } else {
+        *mesh = particle_mesh(&sim, time.overstep_fraction());
+    }
+}
+
+/// A mesh with a quad for each particle. The texture coordinates and indices only depend on the number of particles.
+fn particle_mesh(sim: &Simulation, alpha: f32) -> Mesh {
+    let vertices = sim.num_particles * 4;
+    let uvs: Vec<[f32; 2]> = CORNERS.map(|(_, uv)| uv).repeat(sim.num_particles);
+    let indices = (0..sim.num_particles as u32)
+        .flat_map(|i| [0, 1, 2, 0, 2, 3].map(|corner| i * 4 + corner))
+        .collect();
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0; 3]; vertices])
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, vec![[0.0; 4]; vertices])
+        .with_inserted_indices(Indices::U32(indices));
+    write_vertices(&mut mesh, sim, alpha);
+    mesh
+}
+
+/// Overwrites the positions and colors of the particles' quads in place.
+fn write_vertices(mesh: &mut Mesh, sim: &Simulation, alpha: f32) {
+    if let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION) {
+        positions.par_chunks_mut(4).enumerate().for_each(|(i, quad)| {
+            let center = sim.render_position(i, alpha);
+            let size = particle_sprite_size(sim, i);
+            for (vertex, (corner, _)) in quad.iter_mut().zip(CORNERS) {
+                *vertex = (center + corner * size).extend(0.0).to_array();
+            }
+        });
+    }
+    if let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR) {
+        colors.par_chunks_mut(4).enumerate().for_each(|(i, quad)| {
+            quad.fill(particle_color(sim, i, false).to_linear().to_f32_array());
+        });
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::Instant;
+
+use bevy::log::info_span;
+use bevy::math::Vec2;
+use rayon::prelude::*;
+
+use crate::sim_struct::Simulation;
+use crate::solver::Solver;
+
+/// Softens the density constraints (the paper's ε), so that particles with few neighbors, whose constraint gradients
+/// are tiny, don't get huge corrections. Divided by the square of the smoothing radius, to match the gradients' units.
+const RELAXATION: f32 = 0.1;
+/// Limit on how far a particle can be moved in one iteration, as a fraction of the smoothing radius. The Jacobi
+/// iterations overshoot where many constraints push the same way, as in a corner that the particles are crammed into.
+const MAX_CORRECTION: f32 = 0.1;
+
+/// Position-Based Fluids (Macklin & Müller 2013, "Position Based Fluids"), selected with `--solver pbf`.
+///
+/// Instead of turning the density error into pressure forces, each particle's density is treated as a constraint
+/// (density / rest density = 1), and the predicted positions are moved to satisfy the constraints over a few Jacobi
+/// iterations. The velocities are then whatever it takes to get from the old positions to the corrected ones, which
+/// is unconditionally stable no matter how stiff the fluid is.
+///
+/// Only compression is corrected: the constraints are clamped to C ≥ 0. Particles at the free surface are always
+/// short of neighbors, and pulling them together is what the paper's artificial pressure term is there to fight.
+/// Heavier particles (see FluidType) are moved less by the corrections, so that they still sink through the lighter
+/// fluids. As in the paper, the neighbors are found once per step, at the predicted positions.
+pub struct PbfSolver;
+
+impl Solver for PbfSolver {
+    fn name(&self) -> &'static str {
+        "pbf"
+    }
+
+    fn update(&mut self, sim: &mut Simulation, delta: f32) {
+        // Apply the external forces, and predict the positions (bouncing them off the walls as usual).
+        sim.velocities = (0..sim.num_particles)
+            .into_par_iter()
+            .map(|i| sim.calculate_pressure(i, Vec2::ZERO, delta))
+            .collect();
+        sim.apply_velocities(delta);
+        // Both are kept in step, so that the density calculation doesn't depend on the prediction toggle.
+        sim.predicted_positions.clone_from(&sim.positions);
+        let start = Instant::now();
+        sim.update_regions();
+        sim.phase_timings.neighbor_search += start.elapsed();
+
+        // The total correction of each particle, which becomes part of its velocity. The positions themselves can
+        // jump (in the wind tunnel, or falling out of the bottom), so the velocities can't be derived from them.
+        let mut displacements = vec![Vec2::ZERO; sim.num_particles];
+        let iterations = sim.pbf_iterations.max(1);
+        for _ in 0..iterations {
+            let _span = info_span!("pbf_iteration").entered();
+            let start = Instant::now();
+            sim.densities = sim.par_map_by_cell(|i| sim.calculate_density(i));
+            sim.phase_timings.densities += start.elapsed();
+            let start = Instant::now();
+            let lambdas = sim.par_map_by_cell(|i| sim.constraint_lambda(i));
+            let corrections = sim.par_map_by_cell(|i| sim.position_correction(i, &lambdas));
+
+            let positions: Vec<Vec2> = (0..sim.num_particles)
+                .into_par_iter()
+                .map(|i| {
+                    sim.resolve_collisions(sim.positions[i] + corrections[i], sim.velocities[i])
+                        .0
+                })
+                .collect();
+            for (i, position) in positions.iter().enumerate() {
+                displacements[i] += *position - sim.positions[i];
+            }
+            sim.positions = positions;
+            sim.predicted_positions.clone_from(&sim.positions);
+            sim.phase_timings.pressures += start.elapsed();
+        }
+
+        let time_step = delta * sim.speed;
+        if time_step > 0.0 {
+            for (velocity, displacement) in sim.velocities.iter_mut().zip(&displacements) {
+                *velocity += *displacement / time_step;
+            }
+        }
+        let start = Instant::now();
+        sim.apply_viscosity(delta);
+        sim.phase_timings.viscosity += start.elapsed();
+        // The pressures aren't used by PBF, but they're shown by the inspector.
+        sim.calculate_pressure_values();
+        sim.record_solver_stats(Some(iterations), sim.density_error(), None);
+    }
+}
+
+impl Simulation {
+    /// The gradient of the smoothing kernel at the given offset from the neighbor to the particle.
+    fn kernel_gradient(&self, offset: Vec2) -> Vec2 {
+        let distance = offset.length();
+        if distance <= 0.0 || distance >= self.smoothing_radius {
+            return Vec2::ZERO;
+        }
+        let value = self.smoothing_radius - distance;
+        -value * value * self.smoothing_derivative_scaling_factor * offset / distance
+    }
+
+    /// The particle's Lagrange multiplier λ: how far to move along the constraint gradient to restore its density.
+    fn constraint_lambda(&self, particle_id: usize) -> f32 {
+        let rest_density = self.rest_density(particle_id);
+        let constraint = (self.densities[particle_id] / rest_density - 1.0).max(0.0);
+        if constraint == 0.0 {
+            return 0.0;
+        }
+
+        let position = self.positions[particle_id];
+        let mut own_gradient = Vec2::ZERO;
+        let mut gradient_norms = 0.0;
+        for neighbor_id in self.neighbor_particles(particle_id) {
+            let gradient = self.kernel_gradient(position - self.positions[neighbor_id]) / rest_density;
+            own_gradient += gradient;
+            gradient_norms += gradient.length_squared() / self.particle_mass(neighbor_id);
+        }
+        // The fluid beyond the walls doesn't move, so it only contributes to the particle's own gradient. It's kept
+        // apart from the neighbors' part, which it cancels out next to a wall, where it would leave λ unbounded.
+        let wall_gradient = self.wall_density_gradient(particle_id) / rest_density;
+        gradient_norms +=
+            (own_gradient.length_squared() + wall_gradient.length_squared()) / self.particle_mass(particle_id);
+
+        -constraint / (gradient_norms + RELAXATION / (self.smoothing_radius * self.smoothing_radius))
+    }
+
+    fn position_correction(&self, particle_id: usize, lambdas: &[f32]) -> Vec2 {
+        let position = self.positions[particle_id];
+        let own_lambda = lambdas[particle_id] / self.rest_density(particle_id);
+        let mut correction =
+            lambdas[particle_id] * self.wall_density_gradient(particle_id) / self.rest_density(particle_id);
+        for neighbor_id in self.neighbor_particles(particle_id) {
+            let neighbor_lambda = lambdas[neighbor_id] / self.rest_density(neighbor_id);
+            correction += (own_lambda + neighbor_lambda) * self.kernel_gradient(position - self.positions[neighbor_id]);
+        }
+
+        (correction / self.particle_mass(particle_id)).clamp_length_max(self.smoothing_radius * MAX_CORRECTION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+    use crate::solver::SolverKind;
+
+    /// A block of fluid dropped into the tank should settle without blowing up, leaking out, or being crushed.
+    #[test]
+    fn pbf_settles_a_block_of_fluid() {
+        let mut sim = test_sim(
+            400,
+            200.0,
+            200.0,
+            Args {
+                solver: SolverKind::Pbf,
+                ..Args::default()
+            },
+        );
+        sim.step_n(1.0 / 60.0, 180);
+
+        for (i, position) in sim.positions().iter().enumerate() {
+            assert!(
+                position.is_finite() && position.abs().cmple(sim.half_bounds_size).all(),
+                "particle {i} is at {position}"
+            );
+        }
+        let mean_height = sim.positions().iter().map(|position| position.y).sum::<f32>() / 400.0;
+        assert!(mean_height < 0.0, "the fluid hasn't fallen: mean height {mean_height}");
+        let stats = sim.solver_stats;
+        assert_eq!(stats.iterations, Some(sim.pbf_iterations));
+        assert!(stats.density_error < 0.5, "density error {}", stats.density_error);
+    }
+}
@@ -0,0 +1,220 @@
+use std::time::Instant;
+
+use bevy::log::info_span;
+use bevy::math::Vec2;
+use rayon::prelude::*;
+
+use crate::sim_struct::Simulation;
+use crate::solver::Solver;
+
+/// Limit on how far the pressure can move a particle in one step, as a fraction of the smoothing radius. The pressure
+/// correction is linear, so a badly compressed spot (like the particles as they're placed) would otherwise be pushed
+/// apart so far in one step that the particles fly through each other.
+const MAX_CORRECTION: f32 = 0.1;
+/// The paper's δ only counts the particle's own pressure, but its neighbors' pressures push the same way, most of all
+/// deep in the pool, where the pressure rises with depth. Scaling the corrections down by this keeps them from
+/// overshooting, so that the iterations converge instead of oscillating.
+const RELAXATION: f32 = 0.125;
+
+/// Predictive-corrective incompressible SPH (Solenthaler & Pajarola 2009, "Predictive-Corrective Incompressible SPH"),
+/// selected with `--solver pcisph`.
+///
+/// The explicit SPH solver turns each step's density error into pressure once, so the compression left over
+/// accumulates from step to step, and only a huge `--pressure-multiplier` keeps it in check (which in turn is what
+/// makes it blow up at higher speeds). Instead, this predicts where the particles would end up with the current
+/// pressures, measures the compression there, and corrects the pressures, over and over, until the mean compression
+/// is below `--pcisph-tolerance` or `--pcisph-max-iterations` have been done. The pressure force is the same one that
+/// the SPH solver uses, so `--pressure-multiplier` isn't used at all: the correction's stiffness is derived from how
+/// much a pressure changes the density, for a particle with a full neighborhood, and the time step.
+///
+/// As with PBF, only compression is corrected (the pressures are clamped to be non-negative), and the neighbors are
+/// found once per step, at the first predicted positions.
+pub struct PcisphSolver;
+
+impl Solver for PcisphSolver {
+    fn name(&self) -> &'static str {
+        "pcisph"
+    }
+
+    fn update(&mut self, sim: &mut Simulation, delta: f32) {
+        // The velocities with everything but the pressure, which the pressure's velocity change is added to.
+        let velocities: Vec<Vec2> = (0..sim.num_particles)
+            .into_par_iter()
+            .map(|i| sim.calculate_pressure(i, Vec2::ZERO, delta))
+            .collect();
+        let positions = sim.positions.clone();
+        let mut pressure_velocities = vec![Vec2::ZERO; sim.num_particles];
+        sim.pressures = vec![0.0; sim.num_particles];
+
+        sim.velocities.clone_from(&velocities);
+        sim.predict_pressure_positions(&positions, &pressure_velocities, delta);
+        let start = Instant::now();
+        sim.update_regions();
+        sim.phase_timings.neighbor_search += start.elapsed();
+        let start = Instant::now();
+        sim.densities = sim.par_map_by_cell(|i| sim.calculate_density(i));
+        sim.phase_timings.densities += start.elapsed();
+        let stiffness = sim.pressure_stiffness(delta);
+        let mut error = sim.compression_error();
+
+        // At least one correction, since the gravity alone compresses a resting pool a little every step.
+        let max_iterations = sim.pcisph_max_iterations.max(1);
+        let max_velocity = sim.smoothing_radius * MAX_CORRECTION / (delta * sim.speed).max(f32::EPSILON);
+        let mut iterations = 0;
+        while iterations < max_iterations && (iterations == 0 || error > sim.pcisph_tolerance) {
+            let _span = info_span!("pcisph_iteration").entered();
+            let start = Instant::now();
+            sim.pressures = (0..sim.num_particles)
+                .into_par_iter()
+                .map(|i| {
+                    let excess = sim.densities[i] - sim.rest_density(i);
+                    (sim.pressures[i] + excess * sim.rest_density(i) * stiffness).max(0.0)
+                })
+                .collect();
+            pressure_velocities = sim.par_map_by_cell(|i| {
+                let force = sim.pressure_force(i) + sim.wall_pressure_force(i) + sim.body_pressure_force(i);
+                (force / sim.particle_mass(i) * delta).clamp_length_max(max_velocity)
+            });
+            sim.predict_pressure_positions(&positions, &pressure_velocities, delta);
+            sim.phase_timings.pressures += start.elapsed();
+
+            let start = Instant::now();
+            sim.densities = sim.par_map_by_cell(|i| sim.calculate_density(i));
+            sim.phase_timings.densities += start.elapsed();
+            error = sim.compression_error();
+            iterations += 1;
+        }
+
+        // Move the particles from where they started, with the pressure's velocity change, bouncing them off the walls
+        // as usual. The bodies feel the final pressures.
+        sim.positions = positions;
+        sim.velocities = velocities
+            .iter()
+            .zip(&pressure_velocities)
+            .map(|(velocity, pressure_velocity)| *velocity + *pressure_velocity)
+            .collect();
+        sim.push_bodies(delta);
+        sim.apply_velocities(delta);
+        let start = Instant::now();
+        sim.apply_viscosity(delta);
+        sim.phase_timings.viscosity += start.elapsed();
+        sim.record_solver_stats(Some(iterations), error, Some(sim.pcisph_tolerance));
+    }
+}
+
+impl Simulation {
+    /// Moves the particles to where the velocities (plus the pressure's velocity change) would take them from their
+    /// positions at the start of the step. Both positions are kept in step, so that the density calculation doesn't
+    /// depend on the prediction toggle.
+    fn predict_pressure_positions(&mut self, positions: &[Vec2], pressure_velocities: &[Vec2], delta: f32) {
+        let time_step = delta * self.speed;
+        self.positions = (0..self.num_particles)
+            .into_par_iter()
+            .map(|i| {
+                let velocity = self.velocities[i] + pressure_velocities[i];
+                self.resolve_collisions(positions[i] + velocity * time_step, velocity).0
+            })
+            .collect();
+        self.predicted_positions.clone_from(&self.positions);
+    }
+
+    /// How much a particle's pressure has to rise per unit of density in excess of its rest density, relative to the
+    /// rest density, to push its neighbors far enough away in one step to undo the excess: the paper's δ, relaxed.
+    /// As in the paper, it's worked out for a prototype particle with a full neighborhood, here the particle with the
+    /// most kernel gradient around it, which is the most conservative choice, and then used for every particle.
+    fn pressure_stiffness(&self, delta: f32) -> f32 {
+        // The density change per unit of the particle's own pressure (half of which each pair shares) is the sum
+        // over the neighbors of the kernel slope (which smoothing_kernel_derivative() doubles) times how far the
+        // pressure moves them.
+        let gradients = self.par_map_by_cell(|i| {
+            let position = self.positions[i];
+            self.neighbor_particles(i)
+                .map(|neighbor_id| {
+                    let slope = self.smoothing_kernel_derivative(self.positions[neighbor_id].distance(position));
+                    slope * slope / self.particle_mass(neighbor_id)
+                })
+                .sum::<f32>()
+        });
+        let gradients = gradients.into_iter().fold(0.0, f32::max);
+        // The pressure's force changes the velocity by delta, which moves the particles by delta * speed.
+        let time_step_squared = delta * delta * self.speed;
+        if gradients <= 0.0 || time_step_squared <= 0.0 {
+            return 0.0;
+        }
+        4.0 * RELAXATION / (time_step_squared * gradients)
+    }
+
+    /// The mean compression: the density in excess of the rest density, relative to it. The particles at the free
+    /// surface are always short of neighbors, so counting how far they are below the rest density would keep the
+    /// error from ever reaching the tolerance.
+    pub fn compression_error(&self) -> f32 {
+        self.parallel_sum(|i| (self.densities[i] / self.rest_density(i) - 1.0).max(0.0))
+            / self.num_particles.max(1) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+    use crate::sim_struct::Simulation;
+    use crate::solver::SolverKind;
+
+    /// A block of fluid dropped into the tank and left to settle.
+    fn settled_pool(solver: SolverKind, pcisph_max_iterations: u32, pcisph_tolerance: f32) -> Simulation {
+        let mut sim = test_sim(
+            400,
+            200.0,
+            200.0,
+            Args {
+                solver,
+                pcisph_max_iterations,
+                pcisph_tolerance,
+                ..Args::default()
+            },
+        );
+        sim.step_n(1.0 / 60.0, 180);
+        sim
+    }
+
+    /// The mean compression over the next second.
+    fn mean_compression(sim: &mut Simulation) -> f32 {
+        let mut total = 0.0;
+        for _ in 0..60 {
+            sim.step_n(1.0 / 60.0, 1);
+            total += sim.compression_error();
+        }
+        total / 60.0
+    }
+
+    #[test]
+    fn pcisph_iterates_the_compression_down() {
+        let mut sim = settled_pool(SolverKind::Pcisph, 8, 0.01);
+        for (i, position) in sim.positions().iter().enumerate() {
+            assert!(
+                position.is_finite() && position.abs().cmple(sim.half_bounds_size).all(),
+                "particle {i} is at {position}"
+            );
+        }
+        let mean_height = sim.positions().iter().map(|position| position.y).sum::<f32>() / 400.0;
+        assert!(mean_height < 0.0, "the fluid hasn't fallen: mean height {mean_height}");
+        let stats = sim.solver_stats;
+        assert_eq!(stats.tolerance, Some(0.01));
+        assert!(stats.iterations.is_some_and(|iterations| (1..=8).contains(&iterations)));
+        assert_eq!(stats.converged, Some(true), "compression {} after {:?}", stats.density_error, stats.iterations);
+        let pcisph = mean_compression(&mut sim);
+
+        // With a tolerance it can't reach, it runs out of iterations, and more of them compress the fluid less.
+        let mut strict = settled_pool(SolverKind::Pcisph, 8, 0.0);
+        assert_eq!(strict.solver_stats.iterations, Some(8));
+        assert_eq!(strict.solver_stats.converged, Some(false));
+        let mut single = settled_pool(SolverKind::Pcisph, 1, 0.0);
+        assert_eq!(single.solver_stats.iterations, Some(1));
+        let (strict, single) = (mean_compression(&mut strict), mean_compression(&mut single));
+        assert!(strict < single, "8 iterations {strict}, 1 iteration {single}");
+
+        // The single-pass SPH solver leaves the fluid far more compressed.
+        let sph = mean_compression(&mut settled_pool(SolverKind::Sph, 1, 0.01));
+        assert!(sph > pcisph * 10.0, "sph {sph}, pcisph {pcisph}");
+    }
+}
@@ -0,0 +1,236 @@
+use std::f32::consts::PI;
+
+use bevy::math::Vec2;
+use clap::ValueEnum;
+
+use crate::sim_impl::GRID_SCALE;
+use crate::sim_struct::Simulation;
+
+/// The angle between successive particles of the droplet's spiral, which spreads them out evenly.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// How the particles start out (`--placement`, shift+R), for reproducing the standard SPH test cases. Containers and
+/// scenarios with regions place the particles themselves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InitialPlacement {
+    /// Scattered at random over the whole tank.
+    RandomFill,
+    /// A square grid, somewhere near the bottom left of the tank.
+    #[default]
+    Grid,
+    /// A hexagonal lattice along the bottom of the tank, at the target density, so that the fluid starts at rest.
+    HexLattice,
+    /// A column of fluid against the left wall.
+    DamBreakLeft,
+    /// Columns of fluid against both walls, which collide in the middle.
+    DoubleDamBreak,
+    /// A round drop in the middle of the tank.
+    CenteredDroplet,
+    /// Scattered at random over the top of the tank, to rain down.
+    TopRain,
+}
+
+impl InitialPlacement {
+    /// The next placement, wrapping around to the first.
+    pub fn next(self) -> Self {
+        let all = Self::value_variants();
+        let index = all.iter().position(|&placement| placement == self).unwrap_or(0);
+        all[(index + 1) % all.len()]
+    }
+
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map_or_else(|| format!("{self:?}"), |value| value.get_name().to_string())
+    }
+}
+
+/// `count` points of a lattice with `cols` columns, filled row by row upwards from `origin`. The rows of a hexagonal
+/// lattice are closer together, and every other one is shifted by half the spacing.
+fn lattice(origin: Vec2, cols: usize, count: usize, spacing: f32, hex: bool) -> impl Iterator<Item = Vec2> {
+    let row_height = if hex { spacing * 3f32.sqrt() / 2.0 } else { spacing };
+    (0..count).map(move |i| {
+        let (row, col) = (i / cols, i % cols);
+        let shift = if hex && row % 2 == 1 { spacing / 2.0 } else { 0.0 };
+        origin + Vec2::new(col as f32 * spacing + shift, row as f32 * row_height)
+    })
+}
+
+impl Simulation {
+    /// Like lattice_density(), but for a hexagonal lattice.
+    pub fn hex_lattice_density(&self, spacing: f32) -> f32 {
+        if spacing <= 0.0 {
+            return 0.0;
+        }
+
+        let row_height = spacing * 3f32.sqrt() / 2.0;
+        let rows = (self.smoothing_radius / row_height).ceil() as i32;
+        let cols = (self.smoothing_radius / spacing).ceil() as i32 + 1;
+        (-rows..=rows)
+            .flat_map(|row| {
+                (-cols..=cols)
+                    .map(move |col| Vec2::new((col as f32 + row as f32 / 2.0) * spacing, row as f32 * row_height))
+            })
+            .filter(|&offset| offset != Vec2::ZERO)
+            .map(|offset| self.smoothing_kernel(offset.length()))
+            .sum()
+    }
+
+    /// The spacing of a hexagonal lattice whose density is the target density, found by bisection: the density only
+    /// goes down as the spacing goes up, and is zero once the nearest neighbors are a smoothing radius away.
+    pub fn rest_spacing(&self) -> f32 {
+        let (mut low, mut high) = (self.smoothing_radius * 0.01, self.smoothing_radius);
+        for _ in 0..40 {
+            let middle = (low + high) / 2.0;
+            if self.hex_lattice_density(middle) > self.target_density {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        (low + high) / 2.0
+    }
+
+    /// The starting positions of the particles for the placement. Where they're packed together, they're as far
+    /// apart as in the grid that the target density is derived from, which has squares of `grid_size` and `grid_cols`
+    /// columns (see subdivide_into_squares()).
+    pub fn placed_positions(&self, grid_size: f32, grid_cols: usize) -> Vec<Vec2> {
+        let spacing = grid_size * GRID_SCALE;
+        let half_size = self.half_bounds_size;
+        // Start the particles above the heightfield, if there is one.
+        let depth = self.heightfield.as_ref().map_or(0.0, |heightfield| heightfield.depth);
+        let floor = -half_size.y + depth;
+        let height = half_size.y - floor;
+        let count = self.num_particles;
+        // The number of columns that a block of the particles needs to fit under the top, and up to `max_cols`.
+        let block_cols = |count: usize, max_cols: usize| {
+            let rows = ((height / spacing) as usize).max(1);
+            count.div_ceil(rows).clamp(1, max_cols.max(1))
+        };
+        let tank_cols = ((half_size.x * 2.0 / spacing) as usize).max(1);
+        let bottom_left = Vec2::new(-half_size.x, floor) + spacing / 2.0;
+
+        match self.placement {
+            InitialPlacement::RandomFill => (0..count)
+                .map(|i| {
+                    let x = half_size.x * (self.random(i, 0) * 2.0 - 1.0);
+                    Vec2::new(x, floor + height * self.random(i, 1))
+                })
+                .collect(),
+            InitialPlacement::Grid => {
+                // The grid is smaller than the tank, and starts at a random position.
+                const MAX_GRID_OFFSET: f32 = (1.0 - GRID_SCALE) * 2.0;
+                let origin = Vec2 {
+                    x: half_size.x * (-1.0 + self.random(0, 0) * MAX_GRID_OFFSET),
+                    y: half_size.y * (-1.0 + self.random(0, 1) * MAX_GRID_OFFSET) + depth,
+                };
+                (0..count)
+                    .map(|i| {
+                        let (row, col) = (i / grid_cols, i % grid_cols);
+                        let x = origin.x + col as f32 * grid_size * GRID_SCALE;
+                        let y = origin.y + row as f32 * grid_size * GRID_SCALE;
+                        Vec2::new(x, y)
+                    })
+                    .collect()
+            }
+            InitialPlacement::HexLattice => {
+                let spacing = self.rest_spacing();
+                // Every other row is shifted by half the spacing, so it needs to fit.
+                let cols = ((half_size.x * 2.0 / spacing - 0.5) as usize).max(1);
+                let origin = Vec2::new(-half_size.x, floor) + spacing / 2.0;
+                lattice(origin, cols, count, spacing, true).collect()
+            }
+            InitialPlacement::DamBreakLeft => {
+                let cols = block_cols(count, tank_cols).max(tank_cols * 2 / 5);
+                lattice(bottom_left, cols, count, spacing, false).collect()
+            }
+            InitialPlacement::DoubleDamBreak => {
+                let left = count / 2;
+                let cols = block_cols(left.max(count - left), tank_cols / 2).max(tank_cols / 4);
+                let right_origin = Vec2::new(half_size.x - spacing / 2.0 - (cols - 1) as f32 * spacing, bottom_left.y);
+                lattice(bottom_left, cols, left, spacing, false)
+                    .chain(lattice(right_origin, cols, count - left, spacing, false))
+                    .collect()
+            }
+            InitialPlacement::CenteredDroplet => {
+                // A spiral with the same area per particle as the lattice, squeezed if it doesn't fit.
+                let radius = (spacing * (count as f32 / PI).sqrt()).min(half_size.min_element());
+                (0..count)
+                    .map(|i| {
+                        let distance = radius * ((i as f32 + 0.5) / count as f32).sqrt();
+                        Vec2::from_angle(i as f32 * GOLDEN_ANGLE) * distance
+                    })
+                    .collect()
+            }
+            InitialPlacement::TopRain => {
+                // A band along the top, twice as tall as the fluid would be at rest.
+                let band = (2.0 * count as f32 * spacing * spacing / (half_size.x * 2.0)).min(height);
+                (0..count)
+                    .map(|i| {
+                        let x = half_size.x * (self.random(i, 0) * 2.0 - 1.0);
+                        Vec2::new(x, half_size.y - band * self.random(i, 1))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use clap::ValueEnum;
+
+    use super::InitialPlacement;
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn placements_stay_in_the_tank() {
+        for &placement in InitialPlacement::value_variants() {
+            let mut sim = Simulation::new(
+                400.0,
+                300.0,
+                &Args {
+                    num_particles: 500,
+                    placement,
+                    ..Args::default()
+                },
+            );
+            sim.place_particles();
+            let limit = sim.half_bounds_size + 1e-3;
+            for position in sim.positions() {
+                assert!(position.abs().cmple(limit).all(), "{placement:?}: {position} is outside the tank");
+            }
+        }
+        assert_eq!(InitialPlacement::TopRain.next(), InitialPlacement::RandomFill);
+    }
+
+    #[test]
+    fn hex_lattice_starts_at_the_target_density() {
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 1500,
+                placement: InitialPlacement::HexLattice,
+                ..Args::default()
+            },
+        );
+        sim.place_particles();
+        assert!((sim.hex_lattice_density(sim.rest_spacing()) / sim.target_density - 1.0).abs() < 1e-3);
+
+        // A particle in the middle of the fluid, away from the walls and the surface.
+        sim.update_regions();
+        let top = sim.positions().iter().map(|p| p.y).fold(f32::MIN, f32::max);
+        let middle = Vec2::new(0.0, (top - sim.half_bounds_size.y) / 2.0);
+        let particle_id = (0..sim.num_particles)
+            .min_by(|&a, &b| {
+                sim.positions[a]
+                    .distance(middle)
+                    .total_cmp(&sim.positions[b].distance(middle))
+            })
+            .unwrap();
+        let density = sim.calculate_density(particle_id);
+        assert!((density / sim.target_density - 1.0).abs() < 0.02, "{density} vs {}", sim.target_density);
+    }
+}
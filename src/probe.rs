@@ -0,0 +1,120 @@
+use bevy::color::palettes::css::GOLD;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::window::PrimaryWindow;
+
+use crate::camera_controls::ScreenSized;
+use crate::compare::ComparisonCamera;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Offset of the probe's label from the cursor, in pixels, so that it isn't hidden under the pointer.
+const LABEL_OFFSET: Vec2 = Vec2::new(14.0, 14.0);
+
+/// The fluid's state at an arbitrary point, interpolated from the nearby particles.
+#[derive(Debug, PartialEq)]
+pub struct ProbeSample {
+    pub density: f32,
+    pub pressure: f32,
+    pub velocity: Vec2,
+}
+
+/// The label that follows the cursor in probe mode (F5).
+#[derive(Component)]
+pub struct ProbeLabel;
+
+impl Simulation {
+    /// Samples the fluid at the point. The pressure is relative to the rest density of the nearest particle's fluid.
+    pub fn probe(&self, point: Vec2) -> ProbeSample {
+        let density = self.sample_density(point);
+        let rest_density = self
+            .nearest_particle(point)
+            .map_or(self.target_density, |particle_id| self.rest_density(particle_id));
+        ProbeSample {
+            density,
+            pressure: self.pressure_from_density(density, rest_density),
+            velocity: self.sample_velocity(point),
+        }
+    }
+}
+
+pub fn spawn_probe_label(commands: &mut Commands) {
+    commands.spawn((
+        Text2d::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(GOLD.into()),
+        Anchor::BOTTOM_LEFT,
+        Visibility::Hidden,
+        ScreenSized,
+        ProbeLabel,
+    ));
+}
+
+/// In probe mode, shows the density, pressure and velocity of the fluid under the cursor next to it.
+pub fn update_probe(
+    sim: Single<&Simulation, With<MainSimulation>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    label: Single<(&mut Text2d, &mut Transform, &mut Visibility), With<ProbeLabel>>,
+) {
+    let (mut text, mut transform, mut visibility) = label.into_inner();
+    let points = windows
+        .single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .filter(|_| sim.debug.probing)
+        .and_then(|cursor_position| {
+            let (camera, camera_transform) = cameras_query.iter().next()?;
+            let point = camera.viewport_to_world_2d(camera_transform, cursor_position).ok()?;
+            // Screen y is down, world y is up.
+            let label_position = camera
+                .viewport_to_world_2d(camera_transform, cursor_position + LABEL_OFFSET * Vec2::new(1.0, -1.0))
+                .ok()?;
+            Some((point, label_position))
+        });
+    let Some((point, label_position)) = points else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+
+    let sample = sim.probe(point);
+    text.0 = format!(
+        "density:  {:.5}\npressure: {:.3}\nvelocity: ({:.1}, {:.1})",
+        sample.density, sample.pressure, sample.velocity.x, sample.velocity.y
+    );
+    transform.translation = label_position.extend(2.0);
+    visibility.set_if_neq(Visibility::Visible);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn probe_samples_the_fluid_under_the_point() {
+        let mut sim = test_sim(400, 400.0, 400.0, Args::default());
+        // Squeeze the fluid into the bottom left corner, leaving the top right one empty.
+        let half_size = sim.half_bounds_size;
+        for i in 0..sim.num_particles {
+            sim.positions[i] = -half_size + (sim.positions[i] + half_size) * 0.5;
+            sim.predicted_positions[i] = sim.positions[i];
+        }
+        sim.update_regions();
+        sim.velocities.fill(Vec2::new(3.0, -1.0));
+
+        let inside = sim.probe(sim.positions()[sim.num_particles / 2]);
+        assert!(inside.density > 0.0);
+        assert!((inside.velocity - Vec2::new(3.0, -1.0)).length() < 1e-3, "{inside:?}");
+
+        let outside = sim.probe(half_size * 0.99);
+        assert_eq!(outside.density, 0.0);
+        assert_eq!(outside.velocity, Vec2::ZERO);
+        assert!(outside.pressure < inside.pressure);
+    }
+}
@@ -0,0 +1,241 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use bevy::render::view::screenshot::{Screenshot, ScreenshotCaptured};
+
+use crate::background_writer::BackgroundWriter;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Captures the rendered frames while recording is on (`--record`, or the T key), either as numbered PNGs or piped
+/// to ffmpeg (`--record-ffmpeg`), so that videos of large runs can be made without a screen recorder.
+///
+/// The frames are timed by the fixed simulation clock rather than the display, whose rate varies (especially without
+/// vsync): each fixed step gets a frame, which is the latest rendered one, so a frame is repeated when the display
+/// falls behind the simulation, and some rendered frames are skipped when it runs ahead. The recording then plays back
+/// at the simulation's rate.
+#[derive(Resource)]
+pub struct Recorder {
+    pub dir: PathBuf,
+    /// Capture every `every`th step.
+    pub every: u32,
+    pub ffmpeg: bool,
+    recording: bool,
+    /// Fixed steps since recording was turned on, including the skipped ones.
+    frame: u32,
+    /// The fixed step count when the last frame was rendered.
+    last_step: u128,
+    /// The number of the next PNG. It carries on across recordings, so that they don't overwrite each other.
+    next_png: u32,
+    /// The number of the next video, for the same reason.
+    next_video: u32,
+    /// The ffmpeg process of the current recording, started when its first frame arrives.
+    encoder: Option<Encoder>,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf, every: u32, ffmpeg: bool) -> Self {
+        Recorder {
+            dir,
+            every: every.max(1),
+            ffmpeg,
+            recording: false,
+            frame: 0,
+            last_step: 0,
+            next_png: 0,
+            next_video: 0,
+            encoder: None,
+        }
+    }
+
+    fn stop(&mut self, writer: &mut BackgroundWriter) {
+        if !self.recording {
+            return;
+        }
+        self.recording = false;
+        self.frame = 0;
+        if self.ffmpeg {
+            self.next_video += 1;
+        }
+        // ffmpeg finishes the file once the frames queued before this are written.
+        if let Some(encoder) = self.encoder.take() {
+            writer.run(move || drop(encoder));
+        }
+        info!("Stopped recording to {}", self.dir.display());
+    }
+}
+
+/// Spawns a screenshot of the primary window for the fixed steps since the last frame that are due to be recorded.
+pub fn record_frames(
+    mut commands: Commands,
+    mut recorder: ResMut<Recorder>,
+    mut writer: ResMut<BackgroundWriter>,
+    fixed: Res<Time<Fixed>>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+) {
+    let step = fixed.elapsed().as_nanos() / fixed.timestep().as_nanos().max(1);
+    let steps = step.saturating_sub(recorder.last_step) as u32;
+    recorder.last_step = step;
+    if !sim.debug.recording {
+        recorder.stop(&mut writer);
+        return;
+    }
+    if !recorder.recording {
+        if let Err(e) = fs::create_dir_all(&recorder.dir) {
+            error!("Failed to create {}: {e}", recorder.dir.display());
+        }
+        info!("Recording to {}", recorder.dir.display());
+        recorder.recording = true;
+    }
+    let every = recorder.every;
+    let copies = (recorder.frame..recorder.frame + steps)
+        .filter(|frame| frame.is_multiple_of(every))
+        .count();
+    recorder.frame += steps;
+    if copies == 0 {
+        return;
+    }
+
+    if recorder.ffmpeg {
+        let video = recorder.next_video;
+        let fps = 1.0 / (fixed.timestep().as_secs_f32() * every as f32);
+        commands.spawn(Screenshot::primary_window()).observe(
+            move |captured: On<ScreenshotCaptured>,
+                  mut recorder: ResMut<Recorder>,
+                  mut writer: ResMut<BackgroundWriter>| {
+                // The screenshot arrives a frame or two later, by which time the recording may have been stopped.
+                if !recorder.recording || recorder.next_video != video {
+                    return;
+                }
+                let (width, height) = (captured.image.width(), captured.image.height());
+                if recorder.encoder.is_none() {
+                    let path = recorder.dir.join(format!("recording-{video:03}.mp4"));
+                    match Encoder::spawn(&path, width, height, fps) {
+                        Ok(encoder) => recorder.encoder = Some(encoder),
+                        Err(e) => error!("Failed to start ffmpeg: {e}"),
+                    }
+                }
+                if let Some(encoder) = &recorder.encoder {
+                    encoder.send(captured.image.clone(), copies, &mut writer);
+                }
+            },
+        );
+    } else {
+        let first = recorder.next_png;
+        recorder.next_png += copies as u32;
+        let paths: Vec<PathBuf> = (first..recorder.next_png)
+            .map(|png| recorder.dir.join(format!("frame-{png:05}.png")))
+            .collect();
+        commands.spawn(Screenshot::primary_window()).observe(
+            move |captured: On<ScreenshotCaptured>, mut writer: ResMut<BackgroundWriter>| {
+                let image = captured.image.clone();
+                let paths = paths.clone();
+                writer.run(move || match image.try_into_dynamic() {
+                    // The alpha channel isn't meaningful in a screenshot.
+                    Ok(image) => {
+                        if let Err(e) = image.to_rgb8().save(&paths[0]) {
+                            error!("Failed to write {}: {e}", paths[0].display());
+                            return;
+                        }
+                        for path in &paths[1..] {
+                            if let Err(e) = fs::copy(&paths[0], path) {
+                                error!("Failed to write {}: {e}", path.display());
+                            }
+                        }
+                    }
+                    Err(e) => error!("Failed to convert the frame for {}: {e}", paths[0].display()),
+                });
+            },
+        );
+    }
+}
+
+/// An ffmpeg process that encodes the raw frames it's sent into a video. The frames are converted and written to its
+/// stdin by the background writer, so that a slow encoder doesn't hold up the app.
+struct Encoder {
+    size: (u32, u32),
+    process: Arc<Mutex<FfmpegProcess>>,
+}
+
+impl Encoder {
+    fn spawn(path: &Path, width: u32, height: u32, fps: f32) -> io::Result<Self> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args([
+                "-s",
+                &format!("{width}x{height}"),
+                "-framerate",
+                &fps.to_string(),
+                "-i",
+                "-",
+            ])
+            // yuv420p is the most widely playable format, but it needs even dimensions.
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("ffmpeg's stdin is piped");
+
+        Ok(Encoder {
+            size: (width, height),
+            process: Arc::new(Mutex::new(FfmpegProcess {
+                child,
+                stdin: Some(stdin),
+                path: path.to_owned(),
+            })),
+        })
+    }
+
+    /// Queues the frame to be written `copies` times.
+    fn send(&self, image: Image, copies: usize, writer: &mut BackgroundWriter) {
+        // ffmpeg was told the frame size up front, so frames from after a window resize can't be added.
+        if (image.width(), image.height()) != self.size {
+            return;
+        }
+        let process = self.process.clone();
+        writer.run(move || {
+            let Ok(image) = image.try_into_dynamic() else {
+                return;
+            };
+            let frame = image.to_rgba8();
+            let mut process = process.lock().unwrap();
+            for _ in 0..copies {
+                process.write(&frame);
+            }
+        });
+    }
+}
+
+struct FfmpegProcess {
+    child: Child,
+    /// Taken when a write fails, so that the rest of the frames are dropped.
+    stdin: Option<ChildStdin>,
+    path: PathBuf,
+}
+
+impl FfmpegProcess {
+    fn write(&mut self, frame: &[u8]) {
+        if let Some(stdin) = &mut self.stdin
+            && let Err(e) = stdin.write_all(frame)
+        {
+            error!("Failed to write to ffmpeg: {e}");
+            self.stdin = None;
+        }
+    }
+}
+
+impl Drop for FfmpegProcess {
+    fn drop(&mut self) {
+        // Closing ffmpeg's stdin tells it to finish the file.
+        self.stdin.take();
+        match self.child.wait() {
+            Ok(status) if status.success() => info!("Wrote {}", self.path.display()),
+            Ok(status) => error!("ffmpeg failed to write {} ({status})", self.path.display()),
+            Err(e) => error!("ffmpeg failed to write {}: {e}", self.path.display()),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use bevy::color::palettes::basic::{GRAY, LIME};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::camera_controls::ScreenSized;
+use crate::compare::ComparisonCamera;
+use crate::components::{MainSimulation, Particle};
+use crate::sim_struct::Simulation;
+
+/// Number of cells drawn on each side of the focus cell, in local region grid mode.
+const LOCAL_GRID_CELLS: i32 = 3;
+const LOCAL_GRID_SIZE: usize = (2 * LOCAL_GRID_CELLS + 1) as usize;
+
+/// Displays the number of particles in one of the cells around the focus cell.
+#[derive(Component)]
+pub struct GridLabel {
+    /// Offset of the labelled cell from the focus cell.
+    offset: IVec2,
+}
+
+pub fn spawn_grid_labels(commands: &mut Commands) {
+    for index in 0..LOCAL_GRID_SIZE * LOCAL_GRID_SIZE {
+        let offset = IVec2::new((index % LOCAL_GRID_SIZE) as i32, (index / LOCAL_GRID_SIZE) as i32) - LOCAL_GRID_CELLS;
+        commands.spawn((
+            Text2d::default(),
+            TextFont {
+                font_size: 10.0,
+                ..default()
+            },
+            TextColor(GRAY.into()),
+            Visibility::Hidden,
+            GridLabel { offset },
+            ScreenSized,
+        ));
+    }
+}
+
+/// Draws the region grid (and the number of particles in each cell) only within a few cells of the watched particle,
+/// or of the cursor if no particle is being watched. With the smoothing radius display turned on, also shows the
+/// neighbor search around the particle closest to the focus: its smoothing radius and the cells that are searched.
+pub fn draw_local_region_grid(
+    mut gizmos: Gizmos,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    particle_query: Query<&Particle>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    mut labels: Query<(&GridLabel, &mut Text2d, &mut Transform, &mut Visibility)>,
+) {
+    let watched = particle_query
+        .iter()
+        .find(|particle| particle.watched && particle.id < sim.num_particles)
+        .map(|particle| particle.id);
+    let cursor_point = windows
+        .single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .and_then(|cursor_position| {
+            let (camera, camera_transform) = cameras_query.iter().next()?;
+            camera.viewport_to_world_2d(camera_transform, cursor_position).ok()
+        });
+    let focus = watched.map(|id| sim.positions[id]).or(cursor_point);
+
+    let show_grid = sim.debug.show_region_grid && sim.debug.local_region_grid;
+    let Some(focus) = focus.filter(|_| show_grid) else {
+        for (_, _, _, mut visibility) in &mut labels {
+            *visibility = Visibility::Hidden;
+        }
+        return;
+    };
+
+    let bottom_left = -sim.half_bounds_size;
+    let cell_center = |cell: IVec2| bottom_left + (cell.as_vec2() + 0.5) * sim.smoothing_radius;
+    let focus_cell = ((focus - bottom_left) / sim.smoothing_radius).floor().as_ivec2();
+    let in_grid = |cell: IVec2| {
+        cell.cmpge(IVec2::ZERO).all()
+            && cell
+                .cmplt(IVec2::new(sim.region_cols as i32, sim.region_rows as i32))
+                .all()
+    };
+
+    for (label, mut text, mut transform, mut visibility) in &mut labels {
+        let cell = focus_cell + label.offset;
+        if !in_grid(cell) {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        gizmos.rect_2d(cell_center(cell), Vec2::splat(sim.smoothing_radius), GRAY);
+        text.0 = sim.neighbor_search.cell_len(cell).to_string();
+        transform.translation = cell_center(cell).extend(1.0);
+        *visibility = Visibility::Visible;
+    }
+
+    if sim.debug.show_smoothing_radius
+        && let Some(particle_id) = watched.or_else(|| sim.nearest_particle(focus))
+    {
+        let particle_cell = sim.particle_cell(particle_id);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let cell = particle_cell + IVec2::new(dx, dy);
+                if in_grid(cell) {
+                    gizmos.rect_2d(cell_center(cell), Vec2::splat(sim.smoothing_radius * 0.96), LIME);
+                }
+            }
+        }
+        gizmos.circle_2d(sim.positions[particle_id], sim.smoothing_radius, LIME);
+    }
+}
@@ -0,0 +1,380 @@
+use bevy::math::{Rot2, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::scenario::to_world;
+use crate::sim_struct::Simulation;
+use crate::walls::apply_friction;
+
+/// How much of a floating body's speed into a wall of the tank is kept when it bounces off.
+const WALL_RESTITUTION: f32 = 0.3;
+
+/// A floating body in a scenario. The center is a fraction of the tank, and the sizes are fractions of its width.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BodySpec {
+    pub shape: Shape,
+    pub center: (f32, f32),
+    /// Relative to the fluid: below 1 floats, above 1 sinks.
+    pub density: f32,
+    /// In degrees, counterclockwise.
+    #[serde(default)]
+    pub angle: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum Shape {
+    Circle { radius: f32 },
+    Box { width: f32, height: f32 },
+}
+
+/// A rigid body floating in the fluid: the particles bounce off it, and their pressure and impulses push and turn it,
+/// so it bobs and is swept along. Its velocities are in the same units as the particles', and its mass is in particles.
+#[derive(Clone, Debug)]
+pub struct RigidBody {
+    /// The radius, or the half width and height, in pixels.
+    pub shape: Shape,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    /// In radians, counterclockwise.
+    pub angle: f32,
+    pub angular_velocity: f32,
+    pub mass: f32,
+    pub inertia: f32,
+    /// Points spread over the body at the fluid's number density, relative to its center and unrotated. They stand in
+    /// for fluid at rest in the density and pressure of the particles around the body.
+    samples: Vec<Vec2>,
+    /// The number density of the samples, in particles per square pixel.
+    sample_density: f32,
+    /// The impulse from the particles during the current step, and its moment about the center.
+    impulse: Vec2,
+    angular_impulse: f32,
+}
+
+impl RigidBody {
+    /// The body for a scenario's spec. `number_density` is the number of particles per square pixel in the fluid, which
+    /// gives the mass.
+    pub fn new(spec: &BodySpec, half_size: Vec2, number_density: f32) -> Self {
+        let width = half_size.x * 2.0;
+        let (shape, area, inertia_per_mass) = match spec.shape {
+            Shape::Circle { radius } => {
+                let radius = radius * width;
+                (Shape::Circle { radius }, std::f32::consts::PI * radius * radius, radius * radius / 2.0)
+            }
+            Shape::Box {
+                width: box_width,
+                height,
+            } => {
+                let (w, h) = (box_width * width, height * width);
+                (
+                    Shape::Box {
+                        width: w / 2.0,
+                        height: h / 2.0,
+                    },
+                    w * h,
+                    (w * w + h * h) / 12.0,
+                )
+            }
+        };
+        let mass = (spec.density * area * number_density).max(f32::EPSILON);
+        let spacing = 1.0 / number_density.max(f32::EPSILON).sqrt();
+        let extent = match shape {
+            Shape::Circle { radius } => Vec2::splat(radius),
+            Shape::Box { width, height } => Vec2::new(width, height),
+        };
+        let cells = (extent / spacing).floor().as_ivec2();
+        let mut samples: Vec<Vec2> = (-cells.y..=cells.y)
+            .flat_map(|row| (-cells.x..=cells.x).map(move |col| Vec2::new(col as f32, row as f32) * spacing))
+            .filter(|&point| match shape {
+                Shape::Circle { radius } => point.length() <= radius,
+                Shape::Box { .. } => true,
+            })
+            .collect();
+        if samples.is_empty() {
+            samples.push(Vec2::ZERO);
+        }
+        RigidBody {
+            shape,
+            position: to_world(spec.center, half_size),
+            velocity: Vec2::ZERO,
+            angle: spec.angle.to_radians(),
+            angular_velocity: 0.0,
+            mass,
+            inertia: mass * inertia_per_mass,
+            samples,
+            sample_density: number_density,
+            impulse: Vec2::ZERO,
+            angular_impulse: 0.0,
+        }
+    }
+
+    /// The point on the surface nearest to a point inside the body, and the outward normal there, or None if the
+    /// point is outside.
+    fn penetration(&self, point: Vec2) -> Option<(Vec2, Vec2)> {
+        match self.shape {
+            Shape::Circle { radius } => {
+                let offset = point - self.position;
+                let distance = offset.length();
+                if distance >= radius {
+                    return None;
+                }
+                let normal = if distance > 0.0 { offset / distance } else { Vec2::Y };
+                Some((self.position + normal * radius, normal))
+            }
+            Shape::Box { width, height } => {
+                let rotation = Rot2::radians(self.angle);
+                let local = rotation.inverse() * (point - self.position);
+                let depth = Vec2::new(width, height) - local.abs();
+                if depth.x <= 0.0 || depth.y <= 0.0 {
+                    return None;
+                }
+                let (surface, normal) = if depth.x < depth.y {
+                    (Vec2::new(width * local.x.signum(), local.y), Vec2::new(local.x.signum(), 0.0))
+                } else {
+                    (Vec2::new(local.x, height * local.y.signum()), Vec2::new(0.0, local.y.signum()))
+                };
+                Some((self.position + rotation * surface, rotation * normal))
+            }
+        }
+    }
+
+    /// Whether a point is within `range` of the body, roughly: within `range` of its bounding circle.
+    fn is_near(&self, point: Vec2, range: f32) -> bool {
+        let radius = match self.shape {
+            Shape::Circle { radius } => radius,
+            Shape::Box { width, height } => Vec2::new(width, height).length(),
+        };
+        point.distance_squared(self.position) < (radius + range) * (radius + range)
+    }
+
+    /// The body's samples, where they are now.
+    fn sample_points(&self) -> impl Iterator<Item = Vec2> + '_ {
+        let rotation = Rot2::radians(self.angle);
+        self.samples
+            .iter()
+            .map(move |&sample| self.position + rotation * sample)
+    }
+
+    /// Whether a point is on the body's surface (or inside it), where a colliding particle is left.
+    pub fn touches(&self, point: Vec2) -> bool {
+        match self.shape {
+            Shape::Circle { radius } => point.distance(self.position) <= radius * 1.0001,
+            Shape::Box { width, height } => {
+                let local = Rot2::radians(self.angle).inverse() * (point - self.position);
+                local.abs().cmple(Vec2::new(width, height) * 1.0001).all()
+            }
+        }
+    }
+
+    /// The velocity of the body at a point.
+    fn velocity_at(&self, point: Vec2) -> Vec2 {
+        self.velocity + (point - self.position).perp() * self.angular_velocity
+    }
+
+    /// Pushes a position inside the body out to its surface, and reflects the velocity off it, relative to the
+    /// velocity of the surface.
+    pub fn resolve_collision(
+        &self,
+        position: Vec2,
+        velocity: Vec2,
+        collision_damping: f32,
+        friction: f32,
+    ) -> (Vec2, Vec2) {
+        let Some((surface, normal)) = self.penetration(position) else {
+            return (position, velocity);
+        };
+
+        let surface_velocity = self.velocity_at(surface);
+        let relative = velocity - surface_velocity;
+        let normal_speed = relative.dot(normal);
+        let velocity_change =
+            if normal_speed < 0.0 { -normal * normal_speed * (1.0 + collision_damping) } else { Vec2::ZERO };
+        let relative = apply_friction(relative + velocity_change, normal, velocity_change, friction);
+
+        (surface, relative + surface_velocity)
+    }
+
+    /// Scales the body with the rest of the simulation (see Simulation::rescale()). Its mass stays the same, since the
+    /// fluid's density goes down as much as its area goes up.
+    pub fn rescale(&mut self, scale: f32, position_scale: Vec2) {
+        self.shape = match self.shape {
+            Shape::Circle { radius } => Shape::Circle { radius: radius * scale },
+            Shape::Box { width, height } => Shape::Box {
+                width: width * scale,
+                height: height * scale,
+            },
+        };
+        for sample in &mut self.samples {
+            *sample *= scale;
+        }
+        self.sample_density /= scale * scale;
+        self.position *= position_scale;
+        self.velocity *= scale;
+        self.inertia *= scale * scale;
+    }
+
+    /// Records the impulse on the body from a particle at a point, i.e. the particle's mass times the opposite of its
+    /// change in velocity.
+    pub fn add_impulse(&mut self, point: Vec2, impulse: Vec2) {
+        self.impulse += impulse;
+        self.angular_impulse += (point - self.position).perp_dot(impulse);
+    }
+
+    /// Bounces the body off a wall that a point of it has gone through, by `depth` along the wall's inward normal.
+    fn hit_wall(&mut self, point: Vec2, normal: Vec2, depth: f32) {
+        let arm = point - self.position;
+        let normal_speed = self.velocity_at(point).dot(normal);
+        self.position += normal * depth;
+        if normal_speed >= 0.0 {
+            return;
+        }
+        let arm_cross_normal = arm.perp_dot(normal);
+        let impulse = -(1.0 + WALL_RESTITUTION) * normal_speed
+            / (1.0 / self.mass + arm_cross_normal * arm_cross_normal / self.inertia);
+        self.velocity += normal * impulse / self.mass;
+        self.angular_velocity += arm_cross_normal * impulse / self.inertia;
+    }
+
+    /// The points of the body that can hit the walls: the corners of a box, or the points of a circle nearest to
+    /// each wall.
+    fn extremes(&self) -> Vec<Vec2> {
+        match self.shape {
+            Shape::Circle { radius } => [Vec2::X, Vec2::NEG_X, Vec2::Y, Vec2::NEG_Y]
+                .map(|direction| self.position + direction * radius)
+                .to_vec(),
+            Shape::Box { width, height } => {
+                let rotation = Rot2::radians(self.angle);
+                [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)]
+                    .map(|(x, y)| self.position + rotation * Vec2::new(width * x, height * y))
+                    .to_vec()
+            }
+        }
+    }
+
+    /// Applies the particles' impulses and the gravity, moves the body, and keeps it in the tank.
+    pub fn step(&mut self, gravity: Vec2, delta: f32, speed: f32, half_size: Vec2) {
+        self.velocity += self.impulse / self.mass - gravity * delta;
+        self.angular_velocity += self.angular_impulse / self.inertia;
+        self.impulse = Vec2::ZERO;
+        self.angular_impulse = 0.0;
+
+        self.position += self.velocity * delta * speed;
+        self.angle += self.angular_velocity * delta * speed;
+
+        for point in self.extremes() {
+            for (normal, depth) in [
+                (Vec2::X, -half_size.x - point.x),
+                (Vec2::NEG_X, point.x - half_size.x),
+                (Vec2::Y, -half_size.y - point.y),
+                (Vec2::NEG_Y, point.y - half_size.y),
+            ] {
+                if depth > 0.0 {
+                    self.hit_wall(point, normal, depth);
+                }
+            }
+        }
+    }
+}
+
+impl Simulation {
+    /// The density contributed by the floating bodies near a particle. Each body is filled with fluid at rest (see
+    /// RigidBody::samples), so that the particles around it aren't short of neighbors. Without it, they'd be drawn
+    /// onto the body instead of holding it up.
+    pub fn body_density(&self, particle_id: usize) -> f32 {
+        let position = self.solver_position(particle_id);
+        self.bodies
+            .iter()
+            .filter(|body| body.is_near(position, self.smoothing_radius))
+            .flat_map(|body| body.sample_points())
+            .map(|sample| self.smoothing_kernel(sample.distance(position)))
+            .sum()
+    }
+
+    /// The pressure force of a floating body on a particle, from each of its samples, and the points it acts at.
+    /// Like the walls with `--wall-density`, the fluid in the body pushes back with the particle's own pressure, but
+    /// never pulls it in.
+    fn body_forces<'a>(&'a self, body: &'a RigidBody, particle_id: usize) -> impl Iterator<Item = (Vec2, Vec2)> + 'a {
+        let position = self.solver_position(particle_id);
+        let pressure = self.pressures[particle_id].max(0.0);
+        let near = pressure > 0.0 && body.is_near(position, self.smoothing_radius);
+        near.then(|| body.sample_points())
+            .into_iter()
+            .flatten()
+            .filter_map(move |sample| {
+                let offset = position - sample;
+                let distance = offset.length();
+                (distance > 0.0 && distance < self.smoothing_radius).then(|| {
+                    let slope = self.smoothing_kernel_derivative(distance);
+                    (sample, offset / distance * pressure * slope / body.sample_density)
+                })
+            })
+    }
+
+    /// The pressure force of the floating bodies on a particle. The bodies feel the opposite force (see
+    /// push_bodies()), which is what floats them.
+    pub fn body_pressure_force(&self, particle_id: usize) -> Vec2 {
+        self.bodies
+            .iter()
+            .flat_map(|body| self.body_forces(body, particle_id))
+            .map(|(_, force)| force)
+            .sum()
+    }
+
+    /// Records the impulses of the particles' pressure on the floating bodies during a step.
+    pub fn push_bodies(&mut self, delta: f32) {
+        let mut bodies = std::mem::take(&mut self.bodies);
+        for body in &mut bodies {
+            let forces: Vec<(Vec2, Vec2)> = (0..self.num_particles)
+                .flat_map(|i| self.body_forces(body, i))
+                .collect();
+            for (point, force) in forces {
+                body.add_impulse(point, -force * delta);
+            }
+        }
+        self.bodies = bodies;
+    }
+
+    /// Advances the floating bodies by a step, once the particles' impulses on them have been recorded.
+    pub fn update_bodies(&mut self, delta: f32) {
+        let half_size = self.half_bounds_size;
+        for i in 0..self.bodies.len() {
+            let gravity = self.gravity_at(self.bodies[i].position);
+            self.bodies[i].step(gravity, delta, self.speed, half_size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::scenario::Scenario;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn light_bodies_float_and_heavy_ones_sink() {
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 900,
+                deterministic: true,
+                ..Args::default()
+            },
+        );
+        let scenario = Scenario::presets()
+            .find(|scenario| scenario.name == "Floating")
+            .unwrap();
+        sim.load_scenario(scenario);
+        assert_eq!(sim.bodies.len(), 3);
+        for position in sim.positions() {
+            assert!(sim.bodies.iter().all(|body| !body.touches(*position)), "{position} is inside a body");
+        }
+
+        sim.step_n(1.0 / 60.0, 240);
+        let floor = -sim.half_bounds_size.y;
+        let heights: Vec<f32> = sim.bodies.iter().map(|body| body.position.y - floor).collect();
+        let surface = sim.positions().iter().map(|p| p.y).fold(f32::MIN, f32::max) - floor;
+        // The box and the ball float, and the rock sinks.
+        assert!(heights[0] > surface * 0.75, "{heights:?}, surface at {surface}");
+        assert!(heights[1] > surface * 0.75, "{heights:?}, surface at {surface}");
+        assert!(heights[2] < surface * 0.3, "{heights:?}, surface at {surface}");
+    }
+}
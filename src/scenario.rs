@@ -0,0 +1,542 @@
+use std::f32::consts::TAU;
+use std::fs;
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::container::{Container, ContainerShape};
+use crate::emitter::Emitter;
+use crate::fluids::FluidType;
+use crate::motion::Motion;
+use crate::rigid_body::{BodySpec, RigidBody};
+use crate::sim_struct::Simulation;
+use crate::walls::apply_friction;
+
+/// The scenarios that are always available from the digit keys, after any `--scenario` files.
+const PRESETS: [&str; 6] = [
+    include_str!("../scenarios/dam-break.ron"),
+    include_str!("../scenarios/droplet.ron"),
+    include_str!("../scenarios/two-blocks.ron"),
+    include_str!("../scenarios/oil-and-water.ron"),
+    include_str!("../scenarios/faucet.ron"),
+    include_str!("../scenarios/floating.ron"),
+];
+
+/// A starting setup, loaded from a RON file (see scenarios/): where the particles start, circular obstacles, and
+/// overrides for some of the parameters.
+/// Positions are fractions of the tank, from (0, 0) at the bottom left to (1, 1) at the top right, and radii are
+/// fractions of the tank's width.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Scenario {
+    pub name: String,
+    /// The kinds of fluid that the regions are filled with. Without any, there's just the default fluid.
+    #[serde(default)]
+    pub fluids: Vec<FluidType>,
+    /// The particles are spread evenly over these regions, which shouldn't overlap.
+    /// Each region is filled with one of the fluids, the first one by default.
+    #[serde(default)]
+    pub regions: Vec<Region>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    /// Boxes and balls that float in the fluid, or sink, and are pushed around by it.
+    #[serde(default)]
+    pub bodies: Vec<BodySpec>,
+    /// Pours particles in at the top and drains them at the bottom.
+    pub emitter: Option<Emitter>,
+    /// Replaces the tank with a container, like --container: Circle, Hexagon, Hourglass, UTube, Funnel, or
+    /// Polygon([(x, y), ...]) with the vertices in any units, scaled to fit.
+    pub container: Option<ContainerShape>,
+    /// In units/s², like --gravity.
+    pub gravity: Option<f32>,
+    pub pressure_multiplier: Option<f32>,
+    pub viscosity_strength: Option<f32>,
+    /// Like --elasticity and --plasticity.
+    pub elasticity: Option<f32>,
+    pub plasticity: Option<f32>,
+    pub collision_damping: Option<f32>,
+    /// Left, right, bottom and top, like --wall-restitution.
+    pub wall_restitution: Option<[f32; 4]>,
+    pub wall_friction: Option<f32>,
+    /// As a multiple of the particle size, like --smoothing-radius.
+    pub smoothing_radius: Option<f32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Region {
+    Block {
+        min: (f32, f32),
+        max: (f32, f32),
+        #[serde(default)]
+        fluid: usize,
+    },
+    Droplet {
+        center: (f32, f32),
+        radius: f32,
+        #[serde(default)]
+        fluid: usize,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Obstacle {
+    pub center: (f32, f32),
+    pub radius: f32,
+    /// Moves the obstacle around, pushing the particles along.
+    #[serde(default)]
+    pub motion: Option<Motion>,
+    /// Turns per second, counterclockwise. The spinning surface drags the particles along by the wall friction, like
+    /// an impeller.
+    #[serde(default)]
+    pub spin: f32,
+}
+
+/// The parameters that a scenario can override, as they were set by the command line, so that switching from one
+/// scenario to another doesn't keep the first one's overrides. Like the scenario's own values, they're in units (see
+/// --units), and the smoothing radius is a multiple of the particle size.
+#[derive(Clone, Debug, Default)]
+pub struct BaseParams {
+    pub gravity: f32,
+    pub pressure_multiplier: f32,
+    pub viscosity_strength: f32,
+    pub elasticity: f32,
+    pub plasticity: f32,
+    pub collision_damping: f32,
+    pub wall_restitution: Option<[f32; 4]>,
+    pub wall_friction: f32,
+    pub smoothing_radius: f32,
+}
+
+impl BaseParams {
+    pub fn of(sim: &Simulation) -> Self {
+        BaseParams {
+            gravity: sim.units.to_units(sim.gravity.y),
+            pressure_multiplier: sim.units.to_units(sim.pressure_multiplier),
+            viscosity_strength: sim.viscosity_strength,
+            elasticity: sim.elasticity,
+            plasticity: sim.plasticity,
+            collision_damping: sim.collision_damping,
+            wall_restitution: sim.wall_restitution,
+            wall_friction: sim.wall_friction,
+            smoothing_radius: sim.smoothing_radius / sim.particle_size,
+        }
+    }
+}
+
+/// Converts a position from fractions of the tank to world coordinates.
+pub fn to_world((x, y): (f32, f32), half_size: Vec2) -> Vec2 {
+    (Vec2::new(x, y) * 2.0 - 1.0) * half_size
+}
+
+/// Converts a position from world coordinates to fractions of the tank.
+fn to_tank(point: Vec2, half_size: Vec2) -> (f32, f32) {
+    let fraction = (point / half_size + 1.0) / 2.0;
+    (fraction.x, fraction.y)
+}
+
+impl Scenario {
+    /// Loads a scenario file, for `--scenario`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {path}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("Couldn't parse {path}: {e}"))
+    }
+
+    /// Writes the scenario to a RON file that can be loaded with `--scenario`.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| format!("Couldn't write {path}: {e}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let scenario: Scenario = ron::from_str(text).map_err(|e| e.to_string())?;
+        let num_fluids = scenario.fluids.len().max(1);
+        if num_fluids > u8::MAX as usize + 1 {
+            return Err(format!("{}: too many fluids", scenario.name));
+        }
+        if let Some(region) = scenario.regions.iter().find(|region| region.fluid() >= num_fluids) {
+            return Err(format!("{}: {region:?} uses a fluid that isn't defined", scenario.name));
+        }
+        if let Some(container) = &scenario.container {
+            container.validate().map_err(|e| format!("{}: {e}", scenario.name))?;
+        }
+        Ok(scenario)
+    }
+
+    /// The fluid types for the simulation: the scenario's, or the default fluid if it doesn't have any.
+    pub fn fluid_types(&self) -> Vec<FluidType> {
+        if self.fluids.is_empty() { vec![FluidType::default()] } else { self.fluids.clone() }
+    }
+
+    pub fn presets() -> impl Iterator<Item = Scenario> {
+        PRESETS
+            .iter()
+            .map(|text| Self::parse(text).expect("The preset scenarios are valid"))
+    }
+
+    /// The total area of the regions, in world units.
+    pub fn area(&self, half_size: Vec2) -> f32 {
+        self.regions
+            .iter()
+            .map(|region| match *region {
+                Region::Block { min, max, .. } => {
+                    let size = to_world(max, half_size) - to_world(min, half_size);
+                    size.x * size.y
+                }
+                Region::Droplet { radius, .. } => std::f32::consts::PI * (radius * half_size.x * 2.0).powi(2),
+            })
+            .sum()
+    }
+
+    /// The points of a lattice with the given spacing that are inside the regions and the container, and outside the
+    /// obstacles and the bodies, with the fluid that each one is filled with.
+    pub fn lattice(&self, spacing: f32, half_size: Vec2) -> Vec<(Vec2, usize)> {
+        let container = self.container.as_ref().map(|shape| Container::new(shape, half_size));
+        // Only their shapes matter here, so they aren't filled with fluid.
+        let bodies: Vec<RigidBody> = self
+            .bodies
+            .iter()
+            .map(|spec| RigidBody::new(spec, half_size, 0.0))
+            .collect();
+        let mut points = Vec::new();
+        for region in &self.regions {
+            let (min, max) = region.bounds(half_size);
+            let cells = ((max - min) / spacing).ceil().as_uvec2();
+            for row in 0..cells.y {
+                for col in 0..cells.x {
+                    let point = min + (Vec2::new(col as f32, row as f32) + 0.5) * spacing;
+                    if region.contains(point, half_size)
+                        && container.as_ref().is_none_or(|container| container.contains(point))
+                        && !self
+                            .obstacles
+                            .iter()
+                            .any(|obstacle| obstacle.contains(point, half_size))
+                        && !bodies.iter().any(|body| body.touches(point))
+                    {
+                        points.push((point, region.fluid()));
+                    }
+                }
+            }
+        }
+        points
+    }
+}
+
+impl Region {
+    fn fluid(&self) -> usize {
+        match *self {
+            Region::Block { fluid, .. } | Region::Droplet { fluid, .. } => fluid,
+        }
+    }
+
+    fn bounds(&self, half_size: Vec2) -> (Vec2, Vec2) {
+        match *self {
+            Region::Block { min, max, .. } => (to_world(min, half_size), to_world(max, half_size)),
+            Region::Droplet { center, radius, .. } => {
+                let center = to_world(center, half_size);
+                let radius = radius * half_size.x * 2.0;
+                (center - radius, center + radius)
+            }
+        }
+    }
+
+    fn contains(&self, point: Vec2, half_size: Vec2) -> bool {
+        match *self {
+            // The lattice only covers the block.
+            Region::Block { .. } => true,
+            Region::Droplet { center, radius, .. } => {
+                point.distance(to_world(center, half_size)) <= radius * half_size.x * 2.0
+            }
+        }
+    }
+}
+
+impl Obstacle {
+    /// An obstacle with the given center and radius in world coordinates.
+    pub fn new(center: Vec2, radius: f32, half_size: Vec2) -> Self {
+        Obstacle {
+            center: to_tank(center, half_size),
+            radius: radius / (half_size.x * 2.0),
+            motion: None,
+            spin: 0.0,
+        }
+    }
+
+    /// The center and radius in world coordinates, before the obstacle has moved.
+    pub fn circle(&self, half_size: Vec2) -> (Vec2, f32) {
+        (to_world(self.center, half_size), self.radius * half_size.x * 2.0)
+    }
+
+    /// The center and radius in world coordinates at the given simulation time.
+    pub fn circle_at(&self, half_size: Vec2, time: f32) -> (Vec2, f32) {
+        let (center, radius) = self.circle(half_size);
+        match &self.motion {
+            Some(motion) => (center + motion.offset(center, half_size, time), radius),
+            None => (center, radius),
+        }
+    }
+
+    /// The velocity of the obstacle's surface at a point on it, in pixels per second: its motion, and its spin.
+    fn surface_velocity(&self, point: Vec2, half_size: Vec2, time: f32) -> Vec2 {
+        let (start, _) = self.circle(half_size);
+        let (center, _) = self.circle_at(half_size, time);
+        let velocity = self
+            .motion
+            .as_ref()
+            .map_or(Vec2::ZERO, |motion| motion.velocity(start, half_size, time));
+        velocity + (point - center).perp() * TAU * self.spin
+    }
+
+    fn contains(&self, point: Vec2, half_size: Vec2) -> bool {
+        let (center, radius) = self.circle(half_size);
+        point.distance(center) <= radius
+    }
+
+    /// Pushes a position inside the obstacle out to its surface, and reflects the velocity off it, relative to the
+    /// velocity of the surface, so that a moving obstacle pushes the particles along. `time` is the simulation time,
+    /// and `speed` the simulation speed, since the particles' velocities are in its time.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resolve_collision(
+        &self,
+        position: Vec2,
+        velocity: Vec2,
+        half_size: Vec2,
+        time: f32,
+        speed: f32,
+        collision_damping: f32,
+        friction: f32,
+    ) -> (Vec2, Vec2) {
+        let (center, radius) = self.circle_at(half_size, time);
+        let offset = position - center;
+        let distance = offset.length();
+        if distance >= radius {
+            return (position, velocity);
+        }
+
+        let normal = if distance > 0.0 { offset / distance } else { Vec2::Y };
+        let surface = center + normal * radius;
+        let surface_velocity = self.surface_velocity(surface, half_size, time) / speed.max(f32::EPSILON);
+        let relative = velocity - surface_velocity;
+        let normal_speed = relative.dot(normal);
+        let velocity_change =
+            if normal_speed < 0.0 { -normal * normal_speed * (1.0 + collision_damping) } else { Vec2::ZERO };
+        let relative = apply_friction(relative + velocity_change, normal, velocity_change, friction);
+
+        (surface, relative + surface_velocity)
+    }
+}
+
+impl Simulation {
+    /// Switches to a scenario: applies its parameters, and places the particles in its regions.
+    pub fn load_scenario(&mut self, scenario: Scenario) {
+        self.apply_scenario(scenario);
+        self.reset();
+    }
+
+    /// Applies the scenario's parameters, and uses its regions and obstacles from the next reset on. The parameters
+    /// that the scenario doesn't set go back to the ones from the command line.
+    pub fn apply_scenario(&mut self, scenario: Scenario) {
+        let base = self.base_params.clone();
+        // The wind tunnel is seen from above, so it has no gravity whatever the scenario says.
+        let gravity = scenario.gravity.filter(|_| self.wind_tunnel.is_none());
+        self.gravity.y = self.units.to_pixels(gravity.unwrap_or(base.gravity));
+        self.pressure_multiplier = self
+            .units
+            .to_pixels(scenario.pressure_multiplier.unwrap_or(base.pressure_multiplier));
+        self.viscosity_strength = scenario.viscosity_strength.unwrap_or(base.viscosity_strength);
+        self.elasticity = scenario.elasticity.unwrap_or(base.elasticity);
+        self.plasticity = scenario.plasticity.unwrap_or(base.plasticity);
+        self.collision_damping = scenario.collision_damping.unwrap_or(base.collision_damping);
+        self.wall_restitution = scenario.wall_restitution.or(base.wall_restitution);
+        self.wall_friction = scenario.wall_friction.unwrap_or(base.wall_friction);
+        self.set_smoothing_radius(scenario.smoothing_radius.unwrap_or(base.smoothing_radius));
+        self.fluid_types = scenario.fluid_types();
+        self.scenario = Some(scenario);
+        self.update_container();
+    }
+
+    /// The scenario as it is now, for saving: its regions and fluids, the obstacles including any that have been painted
+    /// or erased, and the current parameters.
+    pub fn current_scenario(&self) -> Scenario {
+        let scenario = self.scenario.clone().unwrap_or_else(|| Scenario {
+            name: "Untitled".into(),
+            ..Default::default()
+        });
+        Scenario {
+            gravity: Some(self.units.to_units(self.gravity.y)),
+            pressure_multiplier: Some(self.units.to_units(self.pressure_multiplier)),
+            viscosity_strength: Some(self.viscosity_strength),
+            elasticity: Some(self.elasticity),
+            plasticity: Some(self.plasticity),
+            collision_damping: Some(self.collision_damping),
+            wall_restitution: self.wall_restitution,
+            wall_friction: Some(self.wall_friction),
+            smoothing_radius: Some(self.smoothing_radius / self.particle_size),
+            ..scenario
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Args;
+
+    #[test]
+    fn preset_lattices_fill_the_regions_around_the_obstacles() {
+        let half_size = Vec2::new(300.0, 200.0);
+        for scenario in Scenario::presets() {
+            let points = scenario.lattice(10.0, half_size);
+            assert!(!points.is_empty(), "{}: no particles", scenario.name);
+            for (point, _) in points {
+                assert!(point.abs().cmple(half_size).all(), "{}: {point} is outside the tank", scenario.name);
+                assert!(
+                    !scenario
+                        .obstacles
+                        .iter()
+                        .any(|obstacle| obstacle.contains(point, half_size)),
+                    "{}: {point} is inside an obstacle",
+                    scenario.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn saved_scenarios_load_back() {
+        let half_size = Vec2::new(300.0, 200.0);
+        let mut scenario = Scenario::presets().nth(2).unwrap();
+        scenario
+            .obstacles
+            .push(Obstacle::new(Vec2::new(-150.0, 100.0), 12.0, half_size));
+        scenario.gravity = Some(10.0);
+
+        let text = ron::ser::to_string_pretty(&scenario, ron::ser::PrettyConfig::default()).unwrap();
+        let loaded = Scenario::parse(&text).unwrap();
+        assert_eq!(loaded.name, scenario.name);
+        assert_eq!(loaded.regions.len(), scenario.regions.len());
+        assert_eq!(loaded.gravity, Some(10.0));
+        let (center, radius) = loaded.obstacles.last().unwrap().circle(half_size);
+        assert!(center.distance(Vec2::new(-150.0, 100.0)) < 1e-3 && (radius - 12.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn moving_obstacles_push_the_particles_along() {
+        let half_size = Vec2::new(300.0, 200.0);
+        let scenario = Scenario::parse(include_str!("../scenarios/impeller.ron")).unwrap();
+        let [impeller, piston, _] = &scenario.obstacles[..] else {
+            panic!("Expected 3 obstacles");
+        };
+
+        // The piston is moving right at its fastest at the start, so a particle at rest on its right is pushed along.
+        let (center, radius) = piston.circle_at(half_size, 0.0);
+        let (_, velocity) =
+            piston.resolve_collision(center + Vec2::X * radius * 0.9, Vec2::ZERO, half_size, 0.0, 1.0, 0.0, 0.0);
+        assert!(velocity.x > 0.0, "{velocity}");
+
+        // The impeller turns counterclockwise, so it drags a particle landing on top of it to the left.
+        let (center, radius) = impeller.circle_at(half_size, 0.0);
+        let (_, velocity) = impeller.resolve_collision(
+            center + Vec2::Y * radius * 0.9,
+            Vec2::NEG_Y * 10.0,
+            half_size,
+            0.0,
+            1.0,
+            0.0,
+            0.5,
+        );
+        assert!(velocity.x < 0.0, "{velocity}");
+    }
+
+    #[test]
+    fn scenario_containers_replace_the_tank_and_clip_the_regions() {
+        let scenario = Scenario::parse(include_str!("../scenarios/staircase.ron")).unwrap();
+        let Some(ContainerShape::Polygon(vertices)) = &scenario.container else {
+            panic!("Expected a polygon container, got {:?}", scenario.container);
+        };
+        assert_eq!(vertices.len(), 8);
+
+        let half_size = Vec2::new(300.0, 200.0);
+        let container = Container::new(scenario.container.as_ref().unwrap(), half_size);
+        let points = scenario.lattice(10.0, half_size);
+        assert!(!points.is_empty());
+        assert!(points.iter().all(|&(point, _)| container.contains(point)));
+    }
+
+    #[test]
+    fn scenario_containers_must_have_an_inside() {
+        let text = "#![enable(implicit_some)] (name: \"Flat\", container: Polygon([%]))";
+        let parse = |vertices: &str| Scenario::parse(&text.replace('%', vertices));
+        assert!(parse("(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)").is_ok());
+        assert!(parse("").is_err());
+        assert!(parse("(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)").is_err());
+
+        // A container that holds none of the particles doesn't hang the placement: they all rain in instead.
+        let mut sim = Simulation::new(400.0, 400.0, &Args::default());
+        sim.container = Some(Container {
+            vertices: vec![],
+            fill_from_top: false,
+        });
+        sim.place_particles();
+        assert!(
+            sim.positions()
+                .iter()
+                .all(|position| position.y > sim.half_bounds_size.y * 0.5)
+        );
+    }
+
+    #[test]
+    fn regions_must_use_defined_fluids() {
+        let text = "(name: \"Test\", fluids: [(name: \"Oil\", density: 0.7)], regions: [%])";
+        let parse = |region: &str| Scenario::parse(&text.replace('%', region));
+        assert!(parse("Block(min: (0.0, 0.0), max: (1.0, 0.5))").is_ok());
+        assert!(parse("Block(min: (0.0, 0.0), max: (1.0, 0.5), fluid: 0)").is_ok());
+        assert!(parse("Droplet(center: (0.5, 0.5), radius: 0.1, fluid: 1)").is_err());
+    }
+
+    #[test]
+    fn switching_scenarios_goes_back_to_the_command_line_parameters() {
+        let mut sim = Simulation::new(400.0, 400.0, &Args::default());
+        let (gravity, viscosity_strength, smoothing_radius) =
+            (sim.gravity.y, sim.viscosity_strength, sim.smoothing_radius);
+
+        let text = "#![enable(implicit_some)] (name: \"Thick\", regions: [Block(min: (0.0, 0.0), max: (1.0, 0.5))], \
+                    gravity: 1.0, viscosity_strength: 500.0, smoothing_radius: 6.0)";
+        sim.load_scenario(Scenario::parse(text).unwrap());
+        assert_eq!(sim.viscosity_strength, 500.0);
+
+        sim.load_scenario(Scenario::presets().nth(1).unwrap());
+        assert_eq!(sim.gravity.y, gravity);
+        assert_eq!(sim.viscosity_strength, viscosity_strength);
+        assert_eq!(sim.smoothing_radius, smoothing_radius);
+
+        // The wind tunnel is seen from above, so it keeps its zero gravity.
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                wind_tunnel: true,
+                ..Args::default()
+            },
+        );
+        sim.load_scenario(Scenario::parse(text).unwrap());
+        assert_eq!(sim.gravity, Vec2::ZERO);
+        sim.load_scenario(Scenario::presets().nth(1).unwrap());
+        assert_eq!(sim.gravity, Vec2::ZERO);
+    }
+
+    #[test]
+    fn particles_that_dont_fit_the_regions_rain_in() {
+        let mut sim = Simulation::new(400.0, 400.0, &Args::default());
+        let text = "(name: \"Tiny\", regions: [Droplet(center: (0.5, 0.5), radius: 0.01)])";
+        sim.load_scenario(Scenario::parse(text).unwrap());
+
+        let center = sim
+            .positions
+            .iter()
+            .filter(|position| position.length() < sim.half_bounds_size.x * 0.05);
+        let top = sim
+            .positions
+            .iter()
+            .filter(|position| position.y > sim.half_bounds_size.y * 0.5);
+        assert_eq!(center.count() + top.count(), sim.num_particles);
+    }
+}
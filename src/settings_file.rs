@@ -0,0 +1,221 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::MessageText;
+use crate::args::Args;
+use crate::components::{MainSimulation, Notifications};
+use crate::sim_struct::Simulation;
+
+/// How often the settings file is checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Declares the options of the settings file, each named after the Args field that it sets and with the type that it
+/// has in the file, and generates both SettingsFile and SettingsFile::apply_to_args() from that one list.
+macro_rules! settings_file {
+    ($(#[$meta:meta])* pub struct $name:ident { $($field:ident: $type:ty,)* }) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+        #[serde(default, deny_unknown_fields)]
+        pub struct $name {
+            $(pub $field: Option<$type>,)*
+        }
+
+        impl $name {
+            /// Sets the options from the file, except the ones given on the command line, which `on_command_line`
+            /// picks out by their argument ids (the field names).
+            pub fn apply_to_args(&self, args: &mut Args, on_command_line: impl Fn(&str) -> bool) {
+                $(
+                    if let Some(value) = self.$field
+                        && !on_command_line(stringify!($field))
+                    {
+                        // Options whose default depends on the window (e.g. gravity) are Options in Args.
+                        args.$field = value.into();
+                    }
+                )*
+            }
+        }
+    };
+}
+
+settings_file! {
+    /// The tunable parameters, from the `--settings` file (RON), in the same units as the command line options. Any
+    /// that are left out keep their values. These are the parameters that can be applied to the running simulation
+    /// (see Simulation::apply_settings()); the rest of the options are only taken from the command line, and a file
+    /// that sets any of them is rejected.
+    pub struct SettingsFile {
+        num_particles: usize,
+        smoothing_radius: f32,
+        gravity: f32,
+        pressure_multiplier: u32,
+        viscosity_strength: f32,
+        collision_damping: f32,
+        wall_friction: f32,
+        adhesion: f32,
+        speed: f32,
+        substeps: u32,
+        interaction_input_strength: f32,
+        wind_speed: f32,
+        wind_drag: f32,
+    }
+}
+
+impl SettingsFile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {path}: {e}"))?;
+        ron::from_str(&text).map_err(|e| format!("Couldn't parse {path}: {e}"))
+    }
+}
+
+impl Simulation {
+    /// Applies the parameters from a reloaded settings file. Each of them has to be applied here by hand, since most
+    /// need converting to pixels or have something to recalculate. The smoothing radius and the number of particles
+    /// are only set when they've changed, as setting them recalculates the target density. The parameters that a
+    /// scenario can override are also the ones that switching scenarios goes back to from now on, as if they'd been on
+    /// the command line.
+    pub fn apply_settings(&mut self, settings: &SettingsFile) {
+        if let Some(num_particles) = settings.num_particles
+            && num_particles != self.num_particles
+        {
+            let preserve = !self.reset_on_count_change;
+            self.change_num_particles(num_particles, preserve);
+        }
+        if let Some(smoothing_radius) = settings.smoothing_radius {
+            if (smoothing_radius - self.smoothing_radius / self.particle_size).abs() > 1e-4 {
+                self.set_smoothing_radius(smoothing_radius);
+            }
+            self.base_params.smoothing_radius = smoothing_radius;
+        }
+        if let Some(gravity) = settings.gravity {
+            self.gravity.y = self.units.to_pixels(gravity);
+            self.base_params.gravity = gravity;
+        }
+        if let Some(pressure_multiplier) = settings.pressure_multiplier {
+            self.pressure_multiplier = self.units.to_pixels(pressure_multiplier as f32);
+            self.base_params.pressure_multiplier = pressure_multiplier as f32;
+        }
+        if let Some(viscosity_strength) = settings.viscosity_strength {
+            self.viscosity_strength = viscosity_strength;
+            self.base_params.viscosity_strength = viscosity_strength;
+        }
+        if let Some(collision_damping) = settings.collision_damping {
+            self.collision_damping = collision_damping;
+            self.base_params.collision_damping = collision_damping;
+        }
+        if let Some(wall_friction) = settings.wall_friction {
+            self.wall_friction = wall_friction;
+            self.base_params.wall_friction = wall_friction;
+        }
+        if let Some(adhesion) = settings.adhesion {
+            self.adhesion = self.units.to_pixels(adhesion);
+        }
+        if let Some(speed) = settings.speed {
+            self.speed = speed;
+        }
+        if let Some(substeps) = settings.substeps {
+            self.substeps = substeps;
+        }
+        if let Some(interaction_input_strength) = settings.interaction_input_strength {
+            self.interaction_input_strength = interaction_input_strength;
+        }
+        if let Some(wind_speed) = settings.wind_speed {
+            self.wind.speed = self.units.to_pixels(wind_speed);
+        }
+        if let Some(wind_drag) = settings.wind_drag {
+            self.wind.drag = wind_drag;
+        }
+    }
+}
+
+/// Watches the `--settings` file, to apply it to the main simulation whenever it's saved.
+#[derive(Resource)]
+pub struct SettingsWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl SettingsWatcher {
+    pub fn new(path: String) -> Self {
+        let modified = Self::modified(&path);
+        SettingsWatcher {
+            path,
+            modified,
+            last_check: Instant::now(),
+        }
+    }
+
+    fn modified(path: &str) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+/// Reloads the settings file when it changes. Everything in it is applied, including the values that the command
+/// line overrode at startup, since it's been edited since.
+pub fn reload_settings(
+    mut watcher: ResMut<SettingsWatcher>,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    mut messages: Single<&mut Notifications>,
+) {
+    if watcher.last_check.elapsed() < CHECK_INTERVAL {
+        return;
+    }
+    watcher.last_check = Instant::now();
+    let modified = SettingsWatcher::modified(&watcher.path);
+    if modified == watcher.modified {
+        return;
+    }
+    watcher.modified = modified;
+
+    let text = match SettingsFile::load(&watcher.path) {
+        Ok(settings) => {
+            sim.apply_settings(&settings);
+            info!("Reloaded {}", watcher.path);
+            format!("Reloaded {}", watcher.path)
+        }
+        Err(e) => {
+            warn!("{e}");
+            e
+        }
+    };
+    messages.messages.push(MessageText {
+        text,
+        start_time: Instant::now(),
+        duration: Duration::from_secs(2),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::SettingsFile;
+    use crate::args::Args;
+    use crate::scenario::Scenario;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn settings_file_is_overridden_by_the_command_line() {
+        let settings: SettingsFile =
+            ron::from_str("#![enable(implicit_some)]\n(gravity: 12.5, pressure_multiplier: 1000)").unwrap();
+        let mut args = Args::parse_from(["bevy-fluid-sim", "--num-particles", "100", "--gravity", "40"]);
+        settings.apply_to_args(&mut args, |id| id == "gravity");
+        assert_eq!(args.gravity, Some(40.0));
+        assert_eq!(args.pressure_multiplier, Some(1000));
+        assert!(ron::from_str::<SettingsFile>("(solver: Pbf)").is_err());
+
+        // When it's reloaded, all of it applies.
+        let mut sim = Simulation::new(400.0, 400.0, &args);
+        sim.place_particles();
+        let target_density = sim.target_density;
+        sim.apply_settings(&settings);
+        assert!((sim.units.to_units(sim.gravity.y) - 12.5).abs() < 1e-4);
+        assert_eq!(sim.target_density, target_density);
+
+        // And switching scenarios goes back to the reloaded values, rather than the ones from the command line.
+        sim.load_scenario(Scenario::presets().find(|scenario| scenario.name == "Droplet").unwrap());
+        assert!((sim.units.to_units(sim.gravity.y) - 12.5).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::Mutex;
+
+use arboard::Clipboard;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+
+use crate::args::Args;
+use crate::sim_struct::Simulation;
+
+/// Kept open once it's been used: on X11, the clipboard's contents are served by the app that copied them.
+static CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+/// The tunable parameters, in the units of the command line options, packed into a code that can be shared and passed
+/// to `--params` to start with the same settings. The short field names keep the code short.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParamsCode {
+    #[serde(rename = "n")]
+    num_particles: usize,
+    #[serde(rename = "r")]
+    smoothing_radius: f32,
+    #[serde(rename = "g")]
+    gravity: f32,
+    #[serde(rename = "p")]
+    pressure_multiplier: u32,
+    #[serde(rename = "v")]
+    viscosity_strength: f32,
+    #[serde(rename = "c")]
+    collision_damping: f32,
+    #[serde(rename = "w")]
+    wind_speed: f32,
+    #[serde(rename = "s")]
+    substeps: u32,
+    /// There's no random seed: this makes the random numbers depend only on the frame and the particle.
+    #[serde(rename = "d")]
+    deterministic: bool,
+}
+
+impl ParamsCode {
+    /// The current parameters of the simulation.
+    pub fn from_sim(sim: &Simulation) -> Self {
+        ParamsCode {
+            num_particles: sim.num_particles,
+            smoothing_radius: sim.smoothing_radius / sim.particle_size,
+            gravity: sim.units.to_units(sim.gravity.y),
+            pressure_multiplier: sim.units.to_units(sim.pressure_multiplier).round() as u32,
+            viscosity_strength: sim.viscosity_strength,
+            collision_damping: sim.collision_damping,
+            wind_speed: sim.units.to_units(sim.wind.speed),
+            substeps: sim.substeps,
+            deterministic: sim.deterministic,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let text = ron::to_string(self).expect("The parameters can always be serialized");
+        URL_SAFE_NO_PAD.encode(text)
+    }
+
+    /// Parses a code made by `encode`, for `--params`.
+    pub fn decode(code: &str) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(code.trim())
+            .map_err(|e| format!("Invalid parameters code: {e}"))?;
+        let text = String::from_utf8(bytes).map_err(|e| format!("Invalid parameters code: {e}"))?;
+        ron::from_str(&text).map_err(|e| format!("Invalid parameters code: {e}"))
+    }
+
+    /// Overrides the options with the parameters.
+    pub fn apply(&self, args: &mut Args) {
+        args.num_particles = self.num_particles;
+        args.smoothing_radius = self.smoothing_radius;
+        args.gravity = Some(self.gravity);
+        args.pressure_multiplier = Some(self.pressure_multiplier);
+        args.viscosity_strength = self.viscosity_strength;
+        args.collision_damping = self.collision_damping;
+        args.wind_speed = Some(self.wind_speed);
+        args.substeps = self.substeps;
+        args.deterministic |= self.deterministic;
+    }
+}
+
+pub fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    let mut clipboard = CLIPBOARD.lock().map_err(|e| e.to_string())?;
+    if clipboard.is_none() {
+        *clipboard = Some(Clipboard::new().map_err(|e| e.to_string())?);
+    }
+    clipboard
+        .as_mut()
+        .expect("The clipboard was just opened")
+        .set_text(text)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParamsCode;
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn params_code_restores_the_parameters() {
+        let args = Args {
+            num_particles: 300,
+            ..Args::default()
+        };
+        let mut sim = Simulation::new(400.0, 400.0, &args);
+        sim.place_particles();
+        sim.adj_gravity(true);
+        sim.adj_viscosity(false);
+        sim.adj_collision_damping(true);
+
+        let code = ParamsCode::from_sim(&sim).encode();
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'), "{code}");
+        let mut restored_args = Args::default();
+        ParamsCode::decode(&code).unwrap().apply(&mut restored_args);
+        let mut restored = Simulation::new(400.0, 400.0, &restored_args);
+        restored.place_particles();
+        assert_eq!(restored.num_particles, 300);
+        assert!((restored.gravity.y - sim.gravity.y).abs() < 1e-3);
+        assert_eq!(restored.viscosity_strength, sim.viscosity_strength);
+        assert_eq!(restored.collision_damping, sim.collision_damping);
+        assert!((restored.smoothing_radius - sim.smoothing_radius).abs() < 1e-3);
+
+        assert!(ParamsCode::decode("not a code").is_err());
+    }
+}
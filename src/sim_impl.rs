@@ -1,83 +1,232 @@
 use std::f32::consts::PI;
+use std::time::Instant;
 
 use bevy::prelude::*;
 use rand::random;
 use rayon::prelude::*;
 
 use crate::Particle;
-use crate::args::Args;
+use crate::args::{
+    Args, DEFAULT_EXPLOSION_RADIUS, DEFAULT_EXPLOSION_STRENGTH, DEFAULT_GRAVITY, DEFAULT_INFLOW_SPEED,
+    DEFAULT_INTERACTION_INPUT_RADIUS, DEFAULT_INTERACTION_SLOW_THRESHOLD, DEFAULT_OBSTACLE_RADIUS,
+    DEFAULT_PADDLE_AMPLITUDE, DEFAULT_PRESSURE_MULTIPLIER, DEFAULT_WIND_SPEED,
+};
+use crate::boundary::WallKernel;
+use crate::convection::Convection;
+use crate::dye::{Dye, UNDYED};
+use crate::elasticity::Springs;
+use crate::fluids::FluidType;
+use crate::foam::Foam;
+use crate::heightfield::Heightfield;
+use crate::interaction::InteractionMode;
+use crate::metrics::PhaseTimings;
+use crate::paddle::Paddle;
+use crate::rigid_body::RigidBody;
+use crate::scenario::{BaseParams, Scenario};
 use crate::sim_struct::{DebugParams, Simulation};
+use crate::solver::{SolverStats, SphSolver};
+use crate::time_step::TimeStep;
+use crate::units::Units;
+use crate::wall_impulse::WallImpulses;
+use crate::walls::{BOTTOM, LEFT, RIGHT, TOP, apply_friction};
+use crate::wind::{FlowField, Wind};
+use crate::wind_tunnel::WindTunnel;
 
-const OFFSETS_2D: [(i32, i32); 9] = [
-    (-1, 1),
-    (0, 1),
-    (1, 1),
-    (-1, 0),
-    (0, 0),
-    (1, 0),
-    (-1, -1),
-    (0, -1),
-    (1, -1),
-];
+/// Chunk size for parallel reductions in deterministic mode.
+const REDUCTION_CHUNK_SIZE: usize = 1024;
+
+/// We'll place the particles in a grid that's smaller than the window, so this is the spacing of the initial grid as a
+/// fraction of the spacing that would fill the window.
+pub const GRID_SCALE: f32 = 0.8;
+
+/// The target density as a fraction of the density of the initial particle grid, so that the particles spread out a
+/// bit from their tightly-packed starting positions.
+const TARGET_DENSITY_FRACTION: f32 = 0.7;
+
+/// How far ahead the predicted positions are, as a fraction of a step.
+const PREDICTION_FACTOR: f32 = 0.5;
+
+/// How much the area of the tank can grow or shrink, as a ratio, before the particle size is re-derived from it.
+const RESCALE_AREA_RATIO: f32 = 1.5;
+
+/// How fast the gravity spins when spinning is turned on without `--gravity-spin`, in degrees per second.
+const DEFAULT_GRAVITY_SPIN: f32 = 20.0;
 
 impl Simulation {
     pub fn new(window_width: f32, window_height: f32, args: &Args) -> Simulation {
         let window_area = window_width * window_height;
         // Pick a particle size (in pixels) relative to the window size.
         let particle_size = (window_area * 0.5 / args.num_particles as f32).sqrt();
+        let units = Units::new(args.units, particle_size, window_width, args.world_width, args.rest_density);
 
         // Preallocate the vectors.
         let positions = vec![Vec2::default(); args.num_particles];
         let predicted_positions = vec![Vec2::default(); args.num_particles];
         let velocities = vec![Vec2::default(); args.num_particles];
         let densities = vec![0f32; args.num_particles];
+        let pressures = vec![0f32; args.num_particles];
 
         let mut sim = Simulation {
             smoothing_radius: 0.0,
             smoothing_scaling_factor: 0.0,
             smoothing_derivative_scaling_factor: 0.0,
             viscosity_scaling_factor: 0.0,
+            viscosity_laplacian_scaling_factor: 0.0,
             num_particles: args.num_particles,
+            reset_on_count_change: args.reset_on_count_change,
+            totals_changed: false,
             particle_size,
+            units,
             sprite_size: args.sprite_size,
             half_bounds_size: Vec2::new(window_width, window_height) / 2.0 - particle_size / 2.0,
-            gravity: Vec2::new(0.0, args.gravity * particle_size),
+            sized_for: Vec2::new(window_width, window_height),
+            gravity: Vec2::new(0.0, units.to_pixels_or(args.gravity, DEFAULT_GRAVITY)),
+            gravity_angle: 0.0,
+            gravity_spin_speed: args.gravity_spin.unwrap_or(DEFAULT_GRAVITY_SPIN).to_radians(),
+            gravity_spinning: args.gravity_spin.is_some(),
+            point_gravity: args.point_gravity.is_some(),
+            gravity_center: args
+                .point_gravity
+                .map_or(Vec2::ZERO, |center| center * units.pixels_per_unit),
             target_density: 0.0,
-            pressure_multiplier: args.pressure_multiplier as f32 * particle_size,
+            target_density_override: args.target_density,
+            deterministic: args.deterministic,
+            pressure_multiplier: units
+                .to_pixels_or(args.pressure_multiplier.map(|value| value as f32), DEFAULT_PRESSURE_MULTIPLIER as f32),
             collision_damping: args.collision_damping,
+            wall_restitution: args.wall_restitution,
+            wall_friction: args.wall_friction,
+            bottomless: args.bottomless,
+            adhesion: units.to_pixels(args.adhesion),
+            surface_tension: units.to_pixels(args.surface_tension),
+            surface: Vec::new(),
+            wall_density: args.wall_density,
+            wall_kernel: WallKernel::default(),
             speed: args.speed,
+            substeps: args.substeps,
+            time_step: TimeStep::new(args.cfl, args.max_dt),
+            solver: args.solver.create(),
+            pbf_iterations: args.pbf_iterations,
+            pcisph_max_iterations: args.pcisph_max_iterations,
+            pcisph_tolerance: args.pcisph_tolerance,
 
             viscosity_strength: args.viscosity_strength,
+            elasticity: args.elasticity,
+            plasticity: args.plasticity,
+            springs: Springs::default(),
             interaction_input_strength: args.interaction_input_strength,
-            interaction_input_radius: args.interaction_input_radius as f32 * particle_size,
+            interaction_input_radius: units
+                .to_pixels_or(args.interaction_input_radius, DEFAULT_INTERACTION_INPUT_RADIUS),
+            explosion_radius: units.to_pixels_or(args.explosion_radius, DEFAULT_EXPLOSION_RADIUS),
+            explosion_strength: units.to_pixels_or(args.explosion_strength, DEFAULT_EXPLOSION_STRENGTH),
             interaction_input_point: None,
+            interaction_mode: InteractionMode::Normal,
+            pinch_anchor: None,
+            pinch_point: None,
+            interaction_slow_threshold: units
+                .to_pixels_or(args.interaction_slow_threshold, DEFAULT_INTERACTION_SLOW_THRESHOLD),
+            cursor_wake_strength: args.cursor_wake,
+            cursor_wake: None,
+            grab_constraints: Vec::new(),
+            grab_point: None,
+            flow_lines: Vec::new(),
+            wind: Wind {
+                field: args.wind.clone().unwrap_or(FlowField::Uniform),
+                speed: units.to_pixels_or(args.wind_speed, DEFAULT_WIND_SPEED),
+                drag: args.wind_drag,
+                enabled: args.wind.is_some(),
+            },
+            heightfield: None,
+            wall_impulses: WallImpulses::new(Vec2::ZERO, 1.0),
+            wind_tunnel: None,
+            paddle: args.paddle.then(|| Paddle {
+                amplitude: units.to_pixels_or(args.paddle_amplitude, DEFAULT_PADDLE_AMPLITUDE),
+                frequency: args.paddle_frequency,
+            }),
+            bodies: Vec::new(),
+            convection: args.convection.then(|| Convection::new(args.num_particles)),
+            dye: args.dye.then(|| Dye::new(args.num_particles)),
+            foam: args.foam.then(Foam::default),
+            container_shape: args.container.clone(),
+            placement: args.placement,
+            container: None,
+            scenario: None,
+            base_params: BaseParams::default(),
+            scenarios: args.scenario.iter().cloned().chain(Scenario::presets()).collect(),
 
             positions,
+            previous_positions: predicted_positions.clone(),
             predicted_positions,
             velocities,
             densities,
+            pressures,
+            fluid_types: vec![FluidType::default()],
+            fluids: vec![0; args.num_particles],
             region_rows: 0,
             region_cols: 0,
-            regions: vec![],
+            neighbor_search: args.neighbor_search.create(),
+            gpu: None,
             min_velocity: f32::MAX,
             max_velocity: 0.0,
             min_density: f32::MAX,
             max_density: 0.0,
+            solver_stats: SolverStats::default(),
+            phase_timings: PhaseTimings::default(),
+            sim_time: 0.0,
 
             debug: DebugParams {
                 current_frame: 0,
-                frames_to_show: u32::MAX,
                 log_frame: u32::MAX,
                 show_fps: false,
                 show_smoothing_radius: false,
                 show_region_grid: false,
-                density_heatmap: false,
+                local_region_grid: args.local_grid,
+                color_field: args.color_field,
+                color_values: Vec::new(),
+                colormap: args.colormap,
+                show_wall_impulses: args.wall_impulses,
                 show_arrows: false,
-                use_predicted_positions: false,
+                show_streamlines: args.streamlines,
+                show_surface: args.surface_outline,
+                probing: false,
+                show_energy: args.energy_stats,
+                annotating: false,
+                density_scaled_sprites: args.density_sprites,
+                density_background: args.density_background,
+                use_predicted_positions: !args.no_prediction,
+                cull_offscreen: !args.no_culling,
+                particle_mesh: args.particle_mesh,
+                recording: args.record,
+                offscreen_particles: 0,
+                watched_neighbors: Vec::new(),
             },
         };
 
         sim.set_smoothing_radius(args.smoothing_radius);
+        if args.wind_tunnel {
+            // The tunnel is seen from above, so there's no gravity.
+            sim.gravity = Vec2::ZERO;
+            sim.wind_tunnel = Some(WindTunnel::new(
+                units.to_pixels_or(args.inflow_speed, DEFAULT_INFLOW_SPEED),
+                sim.smoothing_radius,
+                units.to_pixels_or(args.obstacle_radius, DEFAULT_OBSTACLE_RADIUS),
+            ));
+        }
+        sim.base_params = BaseParams::of(&sim);
+        sim.wall_impulses = WallImpulses::new(sim.half_bounds_size, sim.smoothing_radius);
+        sim.update_container();
+
+        if args.heightfield_depth > 0.0 {
+            sim.heightfield = Some(Heightfield::new(
+                sim.half_bounds_size.x * 2.0,
+                sim.half_bounds_size.y * 2.0 * args.heightfield_depth,
+                sim.smoothing_radius,
+            ));
+        }
+
+        if let Some(scenario) = args.scenario.first() {
+            sim.apply_scenario(scenario.clone());
+        }
 
         sim
     }
@@ -101,103 +250,480 @@ impl Simulation {
         self.smoothing_scaling_factor = 10.0 / (PI * smoothing_radius.powf(5.0));
         self.smoothing_derivative_scaling_factor = 30.0 / (PI * smoothing_radius.powf(5.0));
         self.viscosity_scaling_factor = 6.0 / (PI * smoothing_radius.powf(4.0));
+        // The Laplacian of Müller et al.'s viscosity kernel, which is 40 / (π h^5) (h - r) in 2D.
+        self.viscosity_laplacian_scaling_factor = 40.0 / (PI * smoothing_radius.powf(5.0));
+        self.wall_kernel = WallKernel::new(
+            smoothing_radius,
+            |distance| self.smoothing_kernel(distance),
+            |distance| self.smoothing_kernel_derivative(distance),
+        );
+
+        self.update_target_density();
+    }
+
+    /// Derives the target density from the spacing of the initial particle grid and the smoothing kernel, unless
+    /// one was set by hand (see target_density_override).
+    /// Must be called whenever the smoothing radius or the number of particles changes, otherwise the fluid will
+    /// either collapse or explode.
+    pub fn update_target_density(&mut self) {
+        self.target_density = match self.target_density_override {
+            Some(density) => density / (self.units.pixels_per_unit * self.units.pixels_per_unit),
+            None => {
+                let (grid_size, _, _) = self.subdivide_into_squares();
+                self.lattice_density(grid_size * GRID_SCALE) * TARGET_DENSITY_FRACTION
+            }
+        };
+        self.totals_changed = true;
+    }
+
+    /// Sets the target density by hand, in particles per square unit, so that it's kept from then on instead of being
+    /// derived.
+    pub fn set_target_density(&mut self, density: f32) {
+        self.target_density_override = Some(density);
+        self.update_target_density();
+    }
+
+    /// The density of a particle in the middle of a square grid of particles with the given spacing, i.e. the sum of
+    /// the kernel weights of the other grid points within the smoothing radius. Like calculate_density(), this leaves
+    /// out the particle itself.
+    pub fn lattice_density(&self, spacing: f32) -> f32 {
+        if spacing <= 0.0 {
+            return 0.0;
+        }
+
+        let n = (self.smoothing_radius / spacing).ceil() as i32;
+        (-n..=n)
+            .flat_map(|row| (-n..=n).map(move |col| Vec2::new(col as f32, row as f32) * spacing))
+            .filter(|&offset| offset != Vec2::ZERO)
+            .map(|offset| self.smoothing_kernel(offset.length().max(0.000000001)))
+            .sum()
+    }
+
+    /// Returns a random number in [0, 1).
+    /// In deterministic mode, the number is instead derived from the current frame and the two given values, so that
+    /// replays are bit-exact.
+    pub fn random(&self, a: usize, b: usize) -> f32 {
+        if !self.deterministic {
+            return random::<f32>();
+        }
+
+        // SplitMix64 finalizer.
+        let mut x = ((self.debug.current_frame as u64) << 40) ^ ((a as u64) << 20) ^ b as u64;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+        x ^= x >> 31;
+        (x >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Sums a per-particle value in parallel.
+    /// Floating-point addition isn't associative, and rayon splits the work differently from run to run, so in
+    /// deterministic mode the particles are summed in fixed-size chunks which are then combined in order.
+    pub fn parallel_sum<F>(&self, f: F) -> f32
+    where
+        F: Fn(usize) -> f32 + Sync + Send,
+    {
+        if self.deterministic {
+            let chunk_sums: Vec<f32> = (0..self.num_particles.div_ceil(REDUCTION_CHUNK_SIZE))
+                .into_par_iter()
+                .map(|chunk| {
+                    let start = chunk * REDUCTION_CHUNK_SIZE;
+                    let end = (start + REDUCTION_CHUNK_SIZE).min(self.num_particles);
+                    (start..end).map(&f).sum::<f32>()
+                })
+                .collect();
+            chunk_sums.iter().sum()
+        } else {
+            (0..self.num_particles).into_par_iter().map(f).sum()
+        }
+    }
+
+    /// Changes the number of particles, without changing the particle size.
+    pub fn set_num_particles(&mut self, num_particles: usize) {
+        self.num_particles = num_particles;
+        self.positions.resize(num_particles, Vec2::ZERO);
+        self.predicted_positions.resize(num_particles, Vec2::ZERO);
+        self.previous_positions.resize(num_particles, Vec2::ZERO);
+        self.velocities.resize(num_particles, Vec2::ZERO);
+        self.densities.resize(num_particles, 0.0);
+        self.pressures.resize(num_particles, 0.0);
+        self.fluids.resize(num_particles, 0);
+        if let Some(convection) = &mut self.convection {
+            convection.temperatures.resize(num_particles, 0.0);
+        }
+        if let Some(dye) = &mut self.dye {
+            dye.colors.resize(num_particles, UNDYED);
+        }
+        self.grab_constraints
+            .retain(|constraint| constraint.particle_id < num_particles);
+    }
+
+    /// Changes the number of particles at runtime.
+    /// If `preserve` is true, the existing particles keep their positions and velocities: extra particles are removed,
+    /// and new particles rain in from the top of the window. Otherwise, all the particles are placed from scratch.
+    pub fn change_num_particles(&mut self, num_particles: usize, preserve: bool) {
+        let old_num_particles = self.num_particles;
+        self.set_num_particles(num_particles.max(1));
+        self.update_target_density();
+        self.totals_changed = true;
+
+        if !preserve {
+            self.reset();
+            return;
+        }
+
+        for i in old_num_particles..self.num_particles {
+            self.rain_in(i);
+        }
+        self.update_regions();
+    }
+
+    /// Places a particle at a random point along the top of the tank, to fall in.
+    fn rain_in(&mut self, particle_id: usize) {
+        let x = self.half_bounds_size.x * (self.random(particle_id, 0) * 2.0 - 1.0);
+        let y = self.half_bounds_size.y - self.random(particle_id, 1) * self.smoothing_radius;
+        self.positions[particle_id] = Vec2::new(x, y);
+        self.predicted_positions[particle_id] = self.positions[particle_id];
     }
 
-    pub fn spawn_particles(&mut self, commands: &mut Commands) {
+    /// Places the particles, and spawns their entities for the Simulation entity `sim` (which can be spawned empty
+    /// first, and have the simulation inserted afterwards).
+    pub fn spawn_particles(&mut self, commands: &mut Commands, sim: Entity) {
         self.place_particles();
+        if self.debug.particle_mesh {
+            return;
+        }
 
         for i in 0..self.num_particles {
-            let particle = Particle { id: i, watched: false };
+            let particle = Particle {
+                sim,
+                id: i,
+                watched: false,
+            };
 
             commands.spawn((Sprite::default(), particle));
         }
     }
 
     pub fn place_particles(&mut self) {
-        let (grid_size, cols, rows) = self.subdivide_into_squares();
+        let (grid_size, cols, _) = self.subdivide_into_squares();
+        self.fluids.fill(0);
 
-        // We'll place the particles in a grid that's smaller than the window, and with a random starting position.
-        const GRID_SCALE: f32 = 0.8;
-        const MAX_GRID_OFFSET: f32 = (1.0 - GRID_SCALE) * 2.0;
+        let positions = self.placed_positions(grid_size, cols);
+        for (i, position) in positions.into_iter().enumerate() {
+            self.positions[i] = position;
+            self.predicted_positions[i] = position;
+            self.velocities[i] = Vec2::ZERO;
+        }
+        if let Some(container) = &self.container {
+            // Fill the container from the bottom up, packing the particles more tightly if they don't fit.
+            let mut spacing = grid_size * GRID_SCALE;
+            while container.lattice(spacing).count() < self.num_particles && spacing > self.particle_size * 0.1 {
+                spacing *= 0.9;
+            }
+            let lattice: Vec<Vec2> = container.lattice(spacing).take(self.num_particles).collect();
+            let placed = lattice.len();
+            for (i, position) in lattice.into_iter().enumerate() {
+                self.positions[i] = position;
+                self.predicted_positions[i] = position;
+            }
+            // As with the scenario's regions below, the ones that don't fit even at the closest spacing rain in.
+            if placed < self.num_particles {
+                warn!(
+                    target: "sim::placement",
+                    "Only {placed} particles fit in the container, the other {} rain in from the top",
+                    self.num_particles - placed
+                );
+                for i in placed..self.num_particles {
+                    self.rain_in(i);
+                }
+            }
+        }
+        if let Some(scenario) = &self.scenario
+            && !scenario.regions.is_empty()
+        {
+            // Spread the particles evenly over the regions, packing them more tightly if the lattice doesn't fit
+            // them all (some of it is cut off by the edges of the regions, by the container and by the obstacles).
+            let mut spacing = (scenario.area(self.half_bounds_size) / self.num_particles.max(1) as f32).sqrt();
+            let mut lattice = scenario.lattice(spacing, self.half_bounds_size);
+            while lattice.len() < self.num_particles && spacing > self.particle_size * 0.1 {
+                spacing *= 0.95;
+                lattice = scenario.lattice(spacing, self.half_bounds_size);
+            }
+            let placed = lattice.len().min(self.num_particles);
+            for (i, (position, fluid)) in lattice.into_iter().take(self.num_particles).enumerate() {
+                self.positions[i] = position;
+                self.predicted_positions[i] = position;
+                self.fluids[i] = fluid as u8;
+            }
+            // The regions are too small (or too covered by obstacles) for the rest even at the closest spacing, so
+            // they rain in from the top, like particles added at runtime, rather than being left wherever the
+            // placement above put them.
+            if placed < self.num_particles {
+                warn!(
+                    target: "sim::placement",
+                    "{}: only {placed} particles fit in the regions, the other {} rain in from the top",
+                    scenario.name,
+                    self.num_particles - placed
+                );
+                for i in placed..self.num_particles {
+                    self.rain_in(i);
+                }
+            }
+        }
+        if let Some(convection) = &mut self.convection {
+            convection.temperatures.fill(0.0);
+        }
+        self.reset_dye();
+        if let Some(foam) = &mut self.foam {
+            foam.particles.clear();
+        }
+        self.release_grab();
+        self.springs.rest_lengths.clear();
+        self.previous_positions.clone_from(&self.positions);
+        self.solver_stats = SolverStats::default();
+        self.sim_time = 0.0;
 
-        let start_pos = Vec2 {
-            x: self.half_bounds_size.x * (-1.0 + random::<f32>() * MAX_GRID_OFFSET),
-            y: self.half_bounds_size.y * (-1.0 + random::<f32>() * MAX_GRID_OFFSET),
+        self.update_regions();
+        self.update_target_density();
+        // The bodies' masses follow the density that the particles are packed at: under gravity, the fluid settles
+        // well above the target density.
+        let number_density = match &self.scenario {
+            Some(scenario) if !scenario.regions.is_empty() => {
+                self.num_particles as f32 / scenario.area(self.half_bounds_size).max(f32::EPSILON)
+            }
+            _ => self.target_density,
         };
+        self.bodies = self
+            .scenario
+            .iter()
+            .flat_map(|scenario| &scenario.bodies)
+            .map(|spec| RigidBody::new(spec, self.half_bounds_size, number_density))
+            .collect();
+    }
 
-        for i in 0..self.num_particles {
-            let row = i / cols;
-            let col = i % cols;
+    /// Recalculates the densities and pressures without moving the particles, e.g. while paused.
+    pub fn refresh(&mut self, delta: f32) {
+        // Nothing to interpolate the rendered positions from.
+        self.previous_positions.clone_from(&self.positions);
+        self.predict_positions(delta);
+        self.update_regions();
+        self.calculate_densities();
+        self.calculate_pressure_values();
+    }
 
-            let x = start_pos.x + col as f32 * grid_size * GRID_SCALE;
-            let y = start_pos.y + row as f32 * grid_size * GRID_SCALE;
-            self.positions[i] = Vec2::new(x, y);
-            self.predicted_positions[i] = self.positions[i];
-            self.velocities[i] = Vec2::ZERO;
+    pub fn update_particles(&mut self, delta: f32) {
+        let _span = info_span!("update_particles").entered();
+        // The measurement lines count the particles crossing between the positions before and after the step.
+        let before = (!self.flow_lines.is_empty()).then(|| self.positions.clone());
+
+        let start = Instant::now();
+        self.update_surface();
+        // The solver is taken out for the step, so that it can borrow the simulation mutably.
+        let mut solver = std::mem::replace(&mut self.solver, Box::new(SphSolver));
+        solver.update(self, delta);
+        self.solver = solver;
+
+        self.update_heightfield(delta);
+        self.update_temperatures(delta);
+        self.update_dye(delta);
+        self.wall_impulses.decay(delta * self.speed);
+        self.sim_time += delta;
+        self.spin_gravity(delta);
+        if let Some(before) = before {
+            self.measure_flow(&before);
         }
+        self.update_emitter(delta);
+        self.update_bodies(delta);
+        self.update_foam(delta);
+        self.phase_timings.step += start.elapsed();
 
-        self.update_regions();
+        let mut min_velocity = f32::MAX;
+        let mut max_velocity = 0f32;
+        let mut min_density = f32::MAX;
+        let mut max_density = 0f32;
 
-        // Set the target density based on the current density of the center particle.
-        if self.target_density == 0.0 {
-            self.target_density = self.calculate_density((rows / 2) * cols + (cols / 2)) * 0.7;
+        for i in 0..self.num_particles {
+            min_density = min_density.min(self.densities[i]);
+            max_density = max_density.max(self.densities[i]);
+            min_velocity = min_velocity.min(self.velocities[i].length());
+            max_velocity = max_velocity.max(self.velocities[i].length());
         }
-    }
 
-    pub fn update_particles(&mut self, delta: f32) {
-        self.update_regions();
+        // Slowly bring the mins and maxes toward the recent values.
+        const ADJUST_RATE: f32 = 0.01;
+        if min_velocity < self.min_velocity {
+            self.min_velocity = min_velocity;
+        } else {
+            self.min_velocity += (min_velocity - self.min_velocity) * ADJUST_RATE;
+        }
+        if max_velocity > self.max_velocity {
+            self.max_velocity = max_velocity;
+        } else {
+            self.max_velocity -= (self.max_velocity - max_velocity) * ADJUST_RATE;
+        }
+        if min_density < self.min_density {
+            self.min_density = min_density;
+        } else {
+            self.min_density += (min_density - self.min_density) * ADJUST_RATE;
+        }
+        if max_density > self.max_density {
+            self.max_density = max_density;
+        } else {
+            self.max_density -= (self.max_density - max_density) * ADJUST_RATE;
+        }
+    }
 
+    /// Predicts where the particles will be a fraction of a step ahead, for the density and pressure calculations.
+    pub fn predict_positions(&mut self, delta: f32) {
+        let _span = info_span!("predict_positions").entered();
         self.predicted_positions = (0..self.num_particles)
             .into_par_iter()
-            .map(|i| self.positions[i] + self.velocities[i] * delta * self.speed)
+            .map(|i| self.positions[i] + self.velocities[i] * delta * self.speed * PREDICTION_FACTOR)
             .collect();
+    }
 
-        self.calculate_densities();
+    pub fn on_resize(&mut self, window_width: f32, window_height: f32) {
+        let size = Vec2::new(window_width, window_height);
+        let area_ratio = size.element_product() / self.sized_for.element_product();
+        if !(1.0 / RESCALE_AREA_RATIO..=RESCALE_AREA_RATIO).contains(&area_ratio) {
+            self.rescale(size);
+        }
+        self.half_bounds_size = size / 2.0 - self.particle_size / 2.0;
 
-        if self.frames_to_advance() > 0 {
-            self.calculate_pressures(delta);
-            self.apply_velocities(delta);
-            self.apply_viscosity();
-
-            let mut min_velocity = f32::MAX;
-            let mut max_velocity = 0f32;
-            let mut min_density = f32::MAX;
-            let mut max_density = 0f32;
-
-            for i in 0..self.num_particles {
-                min_density = min_density.min(self.densities[i]);
-                max_density = max_density.max(self.densities[i]);
-                min_velocity = min_velocity.min(self.velocities[i].length());
-                max_velocity = max_velocity.max(self.velocities[i].length());
-            }
+        if let Some(heightfield) = &self.heightfield {
+            self.heightfield =
+                Some(Heightfield::new(self.half_bounds_size.x * 2.0, heightfield.depth, self.smoothing_radius));
+        }
+        self.wall_impulses = WallImpulses::new(self.half_bounds_size, self.smoothing_radius);
+        self.update_container();
+    }
 
-            // Slowly bring the mins and maxes toward the recent values.
-            const ADJUST_RATE: f32 = 0.01;
-            if min_velocity < self.min_velocity {
-                self.min_velocity = min_velocity;
-            } else {
-                self.min_velocity += (min_velocity - self.min_velocity) * ADJUST_RATE;
-            }
-            if max_velocity > self.max_velocity {
-                self.max_velocity = max_velocity;
-            } else {
-                self.max_velocity -= (self.max_velocity - max_velocity) * ADJUST_RATE;
-            }
-            if min_density < self.min_density {
-                self.min_density = min_density;
-            } else {
-                self.min_density += (min_density - self.min_density) * ADJUST_RATE;
+    /// Scales the whole simulation to a tank of the given size, as if it had been started in it: the particle size,
+    /// and with it every length in pixels, follows the square root of the area, so the fluid looks and behaves the
+    /// same. The particles are spread out (or squeezed) in proportion to the tank's width and height.
+    fn rescale(&mut self, size: Vec2) {
+        let scale = (size.element_product() / self.sized_for.element_product()).sqrt();
+        let old_half_size = self.sized_for / 2.0 - self.particle_size / 2.0;
+        let smoothing_radius = self.smoothing_radius / self.particle_size;
+        info!(
+            target: "sim::resize",
+            "Rescaling the simulation by {scale:.2} for a {}x{} tank", size.x, size.y
+        );
+
+        self.sized_for = size;
+        self.particle_size *= scale;
+        self.units.pixels_per_unit *= scale;
+        self.units.particle_size = self.particle_size;
+        let position_scale = (size / 2.0 - self.particle_size / 2.0) / old_half_size;
+        for position in self
+            .positions
+            .iter_mut()
+            .chain(&mut self.predicted_positions)
+            .chain(&mut self.previous_positions)
+        {
+            *position *= position_scale;
+        }
+        for velocity in &mut self.velocities {
+            *velocity *= scale;
+        }
+        self.gravity *= scale;
+        self.gravity_center *= position_scale;
+        self.pressure_multiplier *= scale;
+        self.adhesion *= scale;
+        self.surface_tension *= scale;
+        self.interaction_input_radius *= scale;
+        self.explosion_radius *= scale;
+        self.explosion_strength *= scale;
+        self.interaction_slow_threshold *= scale;
+        self.wind.speed *= scale;
+        if let Some(wind_tunnel) = &mut self.wind_tunnel {
+            wind_tunnel.inflow_speed *= scale;
+            wind_tunnel.inflow_width *= scale;
+            wind_tunnel.obstacle_center *= position_scale;
+            wind_tunnel.obstacle_radius *= scale;
+        }
+        if let Some(paddle) = &mut self.paddle {
+            paddle.amplitude *= scale;
+        }
+        for body in &mut self.bodies {
+            body.rescale(scale, position_scale);
+        }
+        if let Some(foam) = &mut self.foam {
+            for particle in &mut foam.particles {
+                particle.position *= position_scale;
+                particle.velocity *= scale;
             }
-            if max_density > self.max_density {
-                self.max_density = max_density;
-            } else {
-                self.max_density -= (self.max_density - max_density) * ADJUST_RATE;
+        }
+
+        // The target density is derived from the particle grid that fills the bounds, so they're needed first.
+        self.half_bounds_size = size / 2.0 - self.particle_size / 2.0;
+        self.set_smoothing_radius(smoothing_radius);
+        self.update_regions();
+    }
+
+    /// The y coordinate of the floor at x: either the bottom of the window, or the heightfield surface.
+    pub fn floor(&self, x: f32) -> f32 {
+        match &self.heightfield {
+            Some(heightfield) => -self.half_bounds_size.y + heightfield.surface_height(x),
+            None => -self.half_bounds_size.y,
+        }
+    }
+
+    /// Advances the heightfield, loading each column with the weight of the particles resting on its surface.
+    fn update_heightfield(&mut self, delta: f32) {
+        let _span = info_span!("update_heightfield").entered();
+        let Some(heightfield) = &self.heightfield else {
+            return;
+        };
+
+        // Each particle represents roughly twice its own area of water (see Simulation::new()).
+        let particle_height = 2.0 * self.particle_size * self.particle_size / heightfield.column_width;
+        let mut loads = vec![0f32; heightfield.heights.len()];
+        for i in 0..self.num_particles {
+            let position = self.positions[i];
+            if position.y - self.floor(position.x) < self.smoothing_radius {
+                loads[heightfield.column(position.x)] += particle_height;
             }
         }
+
+        // Positions are advanced by velocity * speed, so the same goes for the heightfield.
+        let gravity = self.gravity.y * self.speed;
+        if let Some(heightfield) = &mut self.heightfield {
+            heightfield.step(delta, gravity, &loads);
+        }
     }
 
-    pub fn on_resize(&mut self, window_width: f32, window_height: f32) {
-        self.half_bounds_size = Vec2::new(window_width, window_height) / 2.0 - self.particle_size / 2.0;
+    /// Advances the simulation by `n` steps of `delta` seconds each, with no ECS or rendering involved.
+    /// Intended for tests and benchmarks.
+    pub fn step_n(&mut self, delta: f32, n: usize) {
+        for _ in 0..n {
+            self.update_particles(delta);
+            self.end_frame();
+        }
+    }
+
+    /// The position of a particle a fraction `alpha` of the way from its position before the last step to its
+    /// current one. Particles that jumped (e.g. were wrapped around in the wind tunnel) aren't interpolated.
+    pub fn render_position(&self, particle_id: usize, alpha: f32) -> Vec2 {
+        let previous = self.previous_positions[particle_id];
+        let current = self.positions[particle_id];
+        if previous.distance_squared(current) > self.smoothing_radius * self.smoothing_radius {
+            current
+        } else {
+            previous.lerp(current, alpha)
+        }
+    }
+
+    pub fn positions(&self) -> &[Vec2] {
+        &self.positions[..self.num_particles]
+    }
+
+    pub fn velocities(&self) -> &[Vec2] {
+        &self.velocities[..self.num_particles]
+    }
+
+    pub fn densities(&self) -> &[f32] {
+        &self.densities[..self.num_particles]
     }
 
     pub fn end_frame(&mut self) {
@@ -206,15 +732,13 @@ impl Simulation {
         }
 
         self.debug.current_frame += 1;
-        if self.debug.frames_to_show > 0 {
-            self.debug.frames_to_show -= 1;
-        }
     }
 
+    /// Logs the state of the simulation for the frame requested with L, under the sim::frame, sim::density,
+    /// sim::velocity and sim::regions targets, so that e.g. `RUST_LOG=sim::density=info` narrows it down.
     fn log_stats(&mut self) {
-        println!("{self:?}");
+        debug!(target: "sim::frame", "Frame {}: {self:?}", self.debug.current_frame);
 
-        println!();
         let lowest_density = self.densities.iter().cloned().reduce(f32::min).unwrap();
         let highest_density = self.densities.iter().cloned().reduce(f32::max).unwrap();
         let average_density = self.densities.iter().cloned().sum::<f32>() / self.num_particles as f32;
@@ -222,27 +746,41 @@ impl Simulation {
         let lowest_velocity = self.velocities.iter().map(|v| v.length()).reduce(f32::min).unwrap();
         let highest_velocity = self.velocities.iter().map(|v| v.length()).reduce(f32::max).unwrap();
         let average_velocity = self.velocities.iter().map(|v| v.length()).sum::<f32>() / self.num_particles as f32;
-        println!("density:  min:     {}", self.min_density);
-        println!("          lowest:  {lowest_density}");
-        println!("          highest: {highest_density}");
-        println!("          max:     {}", self.max_density);
-        println!("          avg:     {average_density}");
-        println!("velocity: min:     {}", self.min_velocity);
-        println!("          lowest:  {lowest_velocity}");
-        println!("          highest: {highest_velocity}");
-        println!("          max:     {}", self.max_velocity);
-        println!("          avg:     {average_velocity}");
+        info!(
+            target: "sim::density",
+            "min: {}, lowest: {lowest_density}, highest: {highest_density}, max: {}, avg: {average_density}",
+            self.min_density,
+            self.max_density
+        );
+        info!(
+            target: "sim::velocity",
+            "min: {}, lowest: {lowest_velocity}, highest: {highest_velocity}, max: {}, avg: {average_velocity}",
+            self.min_velocity,
+            self.max_velocity
+        );
+        if let Some(wind_tunnel) = &self.wind_tunnel {
+            let mass = self.units.particle_mass(self.target_density);
+            info!(target: "sim::frame", "drag avg: {}", self.units.to_units(wind_tunnel.mean_drag()) * mass);
+        }
+        if self.debug.cull_offscreen {
+            info!(
+                target: "sim::frame",
+                "off-screen particles: {} of {}",
+                self.debug.offscreen_particles,
+                self.num_particles
+            );
+        }
 
         let mut min_region = usize::MAX;
         let mut max_region = 0usize;
-        for region_row in &self.regions {
-            for region_cell in region_row {
-                min_region = min_region.min(region_cell.len());
-                max_region = max_region.max(region_cell.len());
+        for row in 0..self.region_rows {
+            for col in 0..self.region_cols {
+                let region_len = self.neighbor_search.cell_len(IVec2::new(col as i32, row as i32));
+                min_region = min_region.min(region_len);
+                max_region = max_region.max(region_len);
             }
         }
-        println!();
-        println!("Region min: {min_region}, max: {max_region}");
+        info!(target: "sim::regions", "particles per region: min: {min_region}, max: {max_region}");
     }
 
     fn subdivide_into_squares(&self) -> (f32, usize, usize) {
@@ -250,8 +788,8 @@ impl Simulation {
         let height = self.half_bounds_size.y * 2.0;
         let target_area = (width * height) / self.num_particles as f32;
         let side_length = target_area.sqrt();
-        let columns = (width / side_length) as usize;
-        let rows = (self.num_particles as f32 / columns as f32) as usize;
+        let columns = ((width / side_length) as usize).max(1);
+        let rows = ((self.num_particles as f32 / columns as f32) as usize).max(1);
 
         // Adjust the final side length to fit evenly
         let side_length = f32::min(width / columns as f32, height / rows as f32);
@@ -259,130 +797,275 @@ impl Simulation {
         (side_length, columns, rows)
     }
 
-    /// This is my simplistic alternative to the funky "spatial hash" code.
-    /// I just divide the space up into regions the size of the smoothing hash, and
-    /// keep track of the particles in each region. Wasteful of memory, but it's simple and
-    /// it works.
-    fn update_regions(&mut self) {
+    /// Rebuilds the spatial index used to find each particle's neighbors.
+    /// The space is divided into regions the size of the smoothing radius.
+    pub fn update_regions(&mut self) {
+        let _span = info_span!("update_regions").entered();
         let width = self.half_bounds_size.x * 2.0;
         let height = self.half_bounds_size.y * 2.0;
-        let cols = (width / self.smoothing_radius) as usize + 1;
-        let rows = (height / self.smoothing_radius) as usize + 1;
-        let num_regions = rows * cols;
-
-        // If window size or smoothing radius has changed, need to resize the regions vector.
-        if self.region_rows != rows || self.region_cols != cols {
-            self.region_rows = rows;
-            self.region_cols = cols;
-            self.regions.clear();
-            for row in 0..rows {
-                self.regions.push(vec![]);
-                for _ in 0..cols {
-                    let region = Vec::with_capacity(self.num_particles / num_regions * 4);
-                    self.regions[row].push(region);
-                }
-            }
-        } else {
-            for row in 0..rows {
-                for col in 0..cols {
-                    self.regions[row][col].clear();
-                }
-            }
+        self.region_cols = (width / self.smoothing_radius) as usize + 1;
+        self.region_rows = (height / self.smoothing_radius) as usize + 1;
+
+        let positions = if self.debug.use_predicted_positions { &self.predicted_positions } else { &self.positions };
+        self.neighbor_search.update(
+            positions,
+            -self.half_bounds_size,
+            UVec2::new(self.region_cols as u32, self.region_rows as u32),
+            self.smoothing_radius,
+        );
+    }
+
+    pub fn calculate_densities(&mut self) {
+        let _span = info_span!("calculate_densities").entered();
+        if let Some(densities) = self.gpu_densities() {
+            self.densities = (0..self.num_particles)
+                .into_par_iter()
+                .map(|i| densities[i] + self.wall_density(i) + self.body_density(i))
+                .collect();
+            return;
         }
+        self.densities = self.par_map_by_cell(|i| self.calculate_density(i));
+    }
 
-        let left = -self.half_bounds_size.x;
-        let bottom = -self.half_bounds_size.y;
-        for i in 0..self.num_particles {
-            let col = ((self.positions[i].x - left) / self.smoothing_radius) as usize;
-            let row = ((self.positions[i].y - bottom) / self.smoothing_radius) as usize;
-            // While the window is being resized, some particles can be temporarily
-            // outside the window.
-            let col = col.clamp(0, cols - 1);
-            let row = row.clamp(0, rows - 1);
-            self.regions[row][col].push(i);
+    /// Computes a value for every particle in parallel, one region at a time.
+    /// The particles are far from evenly distributed (the regions at the bottom of a settled tank are much denser than
+    /// the ones at the top), so splitting the particle indices into equal chunks leaves some threads idle. Instead,
+    /// each region is a separate task, and the most expensive regions are started first, so that rayon's work
+    /// stealing can balance the cheap ones around them.
+    pub fn par_map_by_cell<T, F>(&self, f: F) -> Vec<T>
+    where
+        T: Copy + Default + Send,
+        F: Fn(usize) -> T + Sync + Send,
+    {
+        let results: Vec<(usize, T)> = self
+            .cells_by_cost()
+            .par_iter()
+            .with_max_len(1)
+            .flat_map_iter(|&cell| self.neighbor_search.cell_particles(cell).map(|i| (i, f(i))))
+            .collect();
+
+        let mut values = vec![T::default(); self.num_particles];
+        for (i, value) in results {
+            values[i] = value;
         }
+        values
     }
 
-    fn calculate_densities(&mut self) {
-        self.densities = (0..self.num_particles)
+    /// The non-empty regions, most expensive first. The cost of a region is roughly the number of particles in it
+    /// times the number of neighbor candidates that each of them has to check.
+    fn cells_by_cost(&self) -> Vec<IVec2> {
+        let mut cells: Vec<(usize, IVec2)> = (0..self.region_rows as i32)
+            .flat_map(|row| (0..self.region_cols as i32).map(move |col| IVec2::new(col, row)))
+            .filter_map(|cell| {
+                let len = self.neighbor_search.cell_len(cell);
+                if len == 0 {
+                    return None;
+                }
+                let candidates: usize = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| cell + IVec2::new(dx, dy)))
+                    .map(|neighbor| self.neighbor_search.cell_len(neighbor))
+                    .sum();
+                Some((len * candidates, cell))
+            })
+            .collect();
+        cells.sort_unstable_by_key(|&(cost, _)| std::cmp::Reverse(cost));
+
+        cells.into_iter().map(|(_, cell)| cell).collect()
+    }
+
+    /// Converts each particle's density into a pressure once per frame, so that the force pass doesn't need to
+    /// recompute it for every neighbor pair.
+    pub fn calculate_pressure_values(&mut self) {
+        let _span = info_span!("calculate_pressure_values").entered();
+        self.pressures = (0..self.num_particles)
             .into_par_iter()
-            .map(|i| self.calculate_density(i))
+            .map(|i| self.pressure_from_density(self.densities[i], self.rest_density(i)))
             .collect();
     }
 
-    fn calculate_density(&self, particle_id: usize) -> f32 {
-        let position = if self.debug.use_predicted_positions {
-            self.predicted_positions[particle_id]
-        } else {
-            self.positions[particle_id]
-        };
+    pub fn pressure_from_density(&self, density: f32, rest_density: f32) -> f32 {
+        (density - rest_density) * self.pressure_multiplier
+    }
+
+    pub fn calculate_density(&self, particle_id: usize) -> f32 {
+        let position = self.solver_position(particle_id);
         let mut density = 0.0;
 
         for neighbor_id in self.neighbor_particles(particle_id) {
-            let neighbor_pos = if self.debug.use_predicted_positions {
-                self.predicted_positions[neighbor_id]
-            } else {
-                self.positions[neighbor_id]
-            };
+            let neighbor_pos = self.solver_position(neighbor_id);
             let distance = (neighbor_pos - position).length().max(0.000000001);
             let influence = self.smoothing_kernel(distance);
             density += influence;
         }
 
-        density
+        density + self.wall_density(particle_id) + self.body_density(particle_id)
     }
 
-    fn calculate_pressures(&mut self, delta: f32) {
-        self.velocities = (0..self.num_particles)
-            .into_par_iter()
-            .map(|i| self.calculate_pressure(i, delta))
-            .collect();
+    pub fn calculate_pressures(&mut self, delta: f32) {
+        let _span = info_span!("calculate_pressures").entered();
+        let gpu_pressure_forces = self.gpu_pressure_forces();
+        self.velocities = self.par_map_by_cell(|i| {
+            let pressure_force = gpu_pressure_forces
+                .as_ref()
+                .map_or_else(|| self.pressure_force(i), |forces| forces[i])
+                + self.wall_pressure_force(i)
+                + self.body_pressure_force(i);
+            self.calculate_pressure(i, pressure_force, delta)
+        });
+        self.push_bodies(delta);
     }
 
-    fn calculate_pressure(&self, particle_id: usize, delta: f32) -> Vec2 {
+    pub fn calculate_pressure(&self, particle_id: usize, pressure_force: Vec2, delta: f32) -> Vec2 {
         self.velocities[particle_id]
-            + self.pressure_force(particle_id) * delta
+            + pressure_force / self.particle_mass(particle_id) * delta
             + self.gravity_force(particle_id) * delta
+            + self.adhesion_force(particle_id) * delta
+            + self.surface_tension_force(particle_id) * delta
+            + self.buoyancy_force(particle_id) * delta
+            + self.cursor_wake_force(particle_id) * delta
+            + self.wind_force(particle_id) * delta
+            + self.grab_force(particle_id) * delta
     }
 
-    fn neighbor_particles(&self, particle_id: usize) -> impl Iterator<Item = usize> + '_ {
-        let particle_row = ((self.positions[particle_id].y - -self.half_bounds_size.y) / self.smoothing_radius) as i32;
-        let particle_col = ((self.positions[particle_id].x - -self.half_bounds_size.x) / self.smoothing_radius) as i32;
+    /// The density at an arbitrary point, interpolated from the nearby particles.
+    pub fn sample_density(&self, point: Vec2) -> f32 {
+        self.neighbor_search
+            .candidates(point)
+            .map(|neighbor_id| self.smoothing_kernel((self.positions[neighbor_id] - point).length()))
+            .sum()
+    }
 
-        OFFSETS_2D.iter().flat_map(move |offset| {
-            let region_row = particle_row + offset.0;
-            let region_col = particle_col + offset.1;
-            let in_bounds = region_row >= 0
-                && (region_row as usize) < self.region_rows
-                && region_col >= 0
-                && (region_col as usize) < self.region_cols;
-            if in_bounds { self.regions[region_row as usize][region_col as usize].as_slice() } else { &[] }
-                .iter()
-                .copied()
-                .filter(move |&neighbor_id| neighbor_id != particle_id)
+    /// The velocity at an arbitrary point: the kernel-weighted average of the nearby particles' velocities.
+    pub fn sample_velocity(&self, point: Vec2) -> Vec2 {
+        let mut velocity = Vec2::ZERO;
+        let mut total_weight = 0.0;
+        for neighbor_id in self.neighbor_search.candidates(point) {
+            let weight = self.smoothing_kernel((self.positions[neighbor_id] - point).length());
+            velocity += self.velocities[neighbor_id] * weight;
+            total_weight += weight;
+        }
+
+        if total_weight > 0.0 { velocity / total_weight } else { Vec2::ZERO }
+    }
+
+    /// The particle closest to the given point, if there are any particles.
+    pub fn nearest_particle(&self, point: Vec2) -> Option<usize> {
+        (0..self.num_particles).into_par_iter().min_by(|&a, &b| {
+            let distance_a = self.positions[a].distance_squared(point);
+            let distance_b = self.positions[b].distance_squared(point);
+            distance_a.total_cmp(&distance_b)
         })
     }
 
-    fn apply_velocities(&mut self, delta: f32) {
-        (self.positions, self.velocities) = (0..self.num_particles)
+    /// The (column, row) of the region containing the particle.
+    pub fn particle_cell(&self, particle_id: usize) -> IVec2 {
+        let cell = ((self.positions[particle_id] + self.half_bounds_size) / self.smoothing_radius).floor();
+        cell.as_ivec2()
+            .clamp(IVec2::ZERO, IVec2::new(self.region_cols as i32 - 1, self.region_rows as i32 - 1).max(IVec2::ZERO))
+    }
+
+    /// The number of other particles within the smoothing radius of the particle.
+    pub fn neighbor_count(&self, particle_id: usize) -> usize {
+        self.neighbors_within_radius(particle_id).count()
+    }
+
+    /// The particles, from the neighbor search, that are within the smoothing radius of the particle.
+    pub fn neighbors_within_radius(&self, particle_id: usize) -> impl Iterator<Item = usize> + '_ {
+        let position = self.positions[particle_id];
+        self.neighbor_particles(particle_id)
+            .filter(move |&neighbor_id| (self.positions[neighbor_id] - position).length() < self.smoothing_radius)
+    }
+
+    /// The position used for the density and pressure calculations: the position predicted a fraction of a step
+    /// ahead, unless prediction has been turned off (O key) for comparison.
+    pub fn solver_position(&self, particle_id: usize) -> Vec2 {
+        if self.debug.use_predicted_positions {
+            self.predicted_positions[particle_id]
+        } else {
+            self.positions[particle_id]
+        }
+    }
+
+    pub fn neighbor_particles(&self, particle_id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.neighbor_search
+            .candidates(self.solver_position(particle_id))
+            .filter(move |&neighbor_id| neighbor_id != particle_id)
+    }
+
+    pub fn apply_velocities(&mut self, delta: f32) {
+        let _span = info_span!("apply_velocities").entered();
+        let velocities: Vec<Vec2>;
+        (self.positions, velocities) = (0..self.num_particles)
             .into_par_iter()
             .map(|particle_id| self.apply_velocity(particle_id, delta))
             .unzip();
+
+        // The only thing that changes the velocity here is a collision (or the wind tunnel inflow), so the change is
+        // the collision impulse.
+        for (i, velocity) in velocities.iter().enumerate() {
+            let velocity_change = *velocity - self.velocities[i];
+            if velocity_change == Vec2::ZERO {
+                continue;
+            }
+            // A particle that bounced off a floating body pushes it the other way.
+            let (position, mass) = (self.positions[i], self.particle_mass(i));
+            if let Some(body) = self.bodies.iter_mut().find(|body| body.touches(position)) {
+                body.add_impulse(position, -velocity_change * mass);
+                continue;
+            }
+            match &mut self.wind_tunnel {
+                Some(wind_tunnel) if wind_tunnel.is_on_obstacle(self.positions[i]) => {
+                    wind_tunnel.add_impulse(velocity_change)
+                }
+                _ => self
+                    .wall_impulses
+                    .add(self.positions[i], self.half_bounds_size, velocity_change.length()),
+            }
+        }
+        self.velocities = velocities;
+        if let Some(wind_tunnel) = &mut self.wind_tunnel {
+            wind_tunnel.end_step(delta * self.speed);
+        }
     }
 
-    fn apply_viscosity(&mut self) {
+    /// Applies the viscosity, and the viscoelastic springs if they're on, which share its loop over the neighbors.
+    /// The particles have already moved by now, so the distances are measured between the solver positions that the
+    /// neighbor grid was built from, rather than the new positions, which the grid would miss neighbors of.
+    pub fn apply_viscosity(&mut self, delta: f32) {
+        let _span = info_span!("apply_viscosity").entered();
         self.velocities = (0..self.num_particles)
             .into_par_iter()
-            .map(|particle_id| self.apply_viscosity_to_particle(particle_id))
+            .map(|particle_id| self.apply_viscosity_to_particle(particle_id, delta))
             .collect();
+        self.update_springs(delta);
     }
 
     fn apply_velocity(&self, particle_id: usize, delta: f32) -> (Vec2, Vec2) {
-        let position = self.positions[particle_id] + self.velocities[particle_id] * delta * self.speed;
-        self.resolve_collisions(position, self.velocities[particle_id])
+        let mut position = self.positions[particle_id] + self.velocities[particle_id] * delta * self.speed;
+        let mut velocity = self.velocities[particle_id];
+
+        // In the wind tunnel, particles leaving one end re-enter at the other, and are pushed along by the inflow.
+        if let Some(wind_tunnel) = &self.wind_tunnel {
+            if position.x > self.half_bounds_size.x {
+                position.x -= self.half_bounds_size.x * 2.0;
+            } else if position.x < -self.half_bounds_size.x {
+                position.x += self.half_bounds_size.x * 2.0;
+            }
+            if position.x < -self.half_bounds_size.x + wind_tunnel.inflow_width {
+                velocity = Vec2::new(wind_tunnel.inflow_speed, velocity.y);
+            }
+        }
+
+        // In bottomless mode, particles that fall out of the bottom are recycled to the top.
+        if self.bottomless && self.heightfield.is_none() && position.y < -self.half_bounds_size.y {
+            let x = self.half_bounds_size.x * (self.random(particle_id, 0) * 2.0 - 1.0);
+            position = Vec2::new(x, self.half_bounds_size.y);
+        }
+
+        self.resolve_collisions(position, velocity)
     }
 
-    fn smoothing_kernel(&self, distance: f32) -> f32 {
+    pub fn smoothing_kernel(&self, distance: f32) -> f32 {
         if distance >= self.smoothing_radius {
             0.0
         } else {
@@ -391,7 +1074,7 @@ impl Simulation {
         }
     }
 
-    fn smoothing_kernel_derivative(&self, distance: f32) -> f32 {
+    pub fn smoothing_kernel_derivative(&self, distance: f32) -> f32 {
         if distance >= self.smoothing_radius {
             0.0
         } else {
@@ -401,7 +1084,7 @@ impl Simulation {
         }
     }
 
-    fn viscosity_kernel(&self, distance: f32) -> f32 {
+    pub fn viscosity_kernel(&self, distance: f32) -> f32 {
         if distance >= self.smoothing_radius {
             0.0
         } else {
@@ -409,42 +1092,99 @@ impl Simulation {
         }
     }
 
-    fn shared_pressure(&self, density1: f32, density2: f32) -> f32 {
-        let density_error1 = density1 - self.target_density;
-        let density_error2 = density2 - self.target_density;
-        (density_error1 + density_error2) * self.pressure_multiplier / 2.0
+    /// The Laplacian of the viscosity kernel (Müller et al. 2003, "Particle-Based Fluid Simulation for Interactive
+    /// Applications"), which is positive everywhere inside the smoothing radius, unlike that of the smoothing kernel.
+    pub fn viscosity_laplacian(&self, distance: f32) -> f32 {
+        if distance >= self.smoothing_radius {
+            0.0
+        } else {
+            (self.smoothing_radius - distance) * self.viscosity_laplacian_scaling_factor
+        }
     }
 
-    fn resolve_collisions(&self, mut position: Vec2, mut velocity: Vec2) -> (Vec2, Vec2) {
-        if position.x.abs() > self.half_bounds_size.x {
+    fn shared_pressure(&self, particle_id: usize, neighbor_id: usize) -> f32 {
+        (self.pressures[particle_id] + self.pressures[neighbor_id]) / 2.0
+    }
+
+    pub fn resolve_collisions(&self, mut position: Vec2, mut velocity: Vec2) -> (Vec2, Vec2) {
+        let friction = self.wall_friction;
+        if let Some(wind_tunnel) = &self.wind_tunnel {
+            (position, velocity) = wind_tunnel.resolve_collision(position, velocity, self.collision_damping, friction);
+        } else if position.x.abs() > self.half_bounds_size.x {
+            let restitution = self.wall_restitution(if position.x < 0.0 { LEFT } else { RIGHT });
+            let before = velocity;
             position.x = self.half_bounds_size.x * position.x.signum();
-            velocity.x = (velocity.x * self.collision_damping).abs() * -position.x.signum();
+            velocity.x = (velocity.x * restitution).abs() * -position.x.signum();
+            velocity = apply_friction(velocity, Vec2::X, velocity - before, friction);
         }
-        if position.y.abs() > self.half_bounds_size.y {
+        if let Some(paddle) = &self.paddle {
+            (position, velocity) = paddle.resolve_collision(
+                position,
+                velocity,
+                -self.half_bounds_size.x,
+                self.sim_time,
+                self.speed,
+                self.wall_restitution(LEFT),
+                friction,
+            );
+        }
+        let open_bottom = self.bottomless && self.heightfield.is_none() && position.y < 0.0;
+        if position.y.abs() > self.half_bounds_size.y && !open_bottom {
+            let restitution = self.wall_restitution(if position.y < 0.0 { BOTTOM } else { TOP });
+            let before = velocity;
             position.y = self.half_bounds_size.y * position.y.signum();
-            velocity.y = (velocity.y * self.collision_damping).abs() * -position.y.signum();
+            velocity.y = (velocity.y * restitution).abs() * -position.y.signum();
+            velocity = apply_friction(velocity, Vec2::Y, velocity - before, friction);
+        }
+        if let Some(container) = &self.container {
+            (position, velocity) = container.resolve_collision(position, velocity, self.collision_damping, friction);
+        }
+        for obstacle in self.scenario.iter().flat_map(|scenario| &scenario.obstacles) {
+            (position, velocity) = obstacle.resolve_collision(
+                position,
+                velocity,
+                self.half_bounds_size,
+                self.sim_time,
+                self.speed,
+                self.collision_damping,
+                friction,
+            );
+        }
+        for body in &self.bodies {
+            (position, velocity) = body.resolve_collision(position, velocity, self.collision_damping, friction);
+        }
+        if let Some(heightfield) = &self.heightfield {
+            let floor = self.floor(position.x);
+            if position.y < floor {
+                // Bounce off the surface, but also get carried up by it if it's rising.
+                let surface_velocity = heightfield.surface_velocity(position.x) / self.speed.max(f32::EPSILON);
+                let before = velocity;
+                position.y = floor;
+                velocity.y = (velocity.y * self.wall_restitution(BOTTOM)).abs().max(surface_velocity);
+                velocity = apply_friction(velocity, Vec2::Y, velocity - before, friction);
+            }
         }
 
         (position, velocity)
     }
 
-    fn pressure_force(&self, particle_id: usize) -> Vec2 {
+    pub fn pressure_force(&self, particle_id: usize) -> Vec2 {
         let mut pressure_force = Vec2::default();
-        let position = self.positions[particle_id];
-        let density = self.densities[particle_id];
+        let position = self.solver_position(particle_id);
 
         for neighbor_id in self.neighbor_particles(particle_id) {
-            let offset = self.positions[neighbor_id] - position;
+            let offset = self.solver_position(neighbor_id) - position;
             let distance = offset.length();
             if distance < self.smoothing_radius {
                 if distance > 0.0 {
                     let direction = -(offset / distance);
                     let slope = self.smoothing_kernel_derivative(distance);
-                    let pressure = self.shared_pressure(density, self.densities[neighbor_id]);
+                    let pressure = self.shared_pressure(particle_id, neighbor_id);
                     pressure_force += pressure * direction * slope / self.densities[neighbor_id];
                 } else {
                     // Move randomly toward the interior.
-                    let inward = (Vec2::ZERO - position) * Vec2::new(random::<f32>(), random::<f32>());
+                    let inward = (Vec2::ZERO - position)
+                        * Vec2::new(self.random(particle_id, neighbor_id), self.random(neighbor_id, particle_id));
                     // Make it a unit vector.
                     let inward = inward / inward.length();
                     pressure_force += inward * self.particle_size;
@@ -459,49 +1199,163 @@ impl Simulation {
         let pos = self.positions[particle_id];
         let velocity = self.velocities[particle_id];
 
-        // Mouse buttons generate pseudo gravity/repulsion at mouse location.
+        // Mouse buttons generate pseudo gravity/repulsion at mouse location (and at the other point of a pinch).
+        // Pulling the two points apart stretches a filament of fluid between them.
         if let Some(interaction_input_point) = self.interaction_input_point {
-            let input_point_offset = interaction_input_point - pos;
-            let distance = input_point_offset.length();
-            if distance < self.interaction_input_radius {
-                let distance_ratio = distance / self.interaction_input_radius;
-                let center = 1.0 - distance_ratio;
-                let dir_to_centre = input_point_offset / distance;
-
-                let gravity_weight = 1.0 - (center * (self.interaction_input_strength / 10.0).clamp(0.0, 1.0));
-                let mut accel =
-                    -self.gravity * gravity_weight + dir_to_centre * center * self.interaction_input_strength;
-                accel -= velocity * center;
-                return accel;
+            for point in std::iter::once(interaction_input_point).chain(self.pinch_point) {
+                if let Some(accel) = self.interaction_acceleration(
+                    point,
+                    self.interaction_mode,
+                    self.interaction_input_strength,
+                    pos,
+                    velocity,
+                ) {
+                    return accel;
+                }
             }
         }
 
-        -self.gravity
+        -self.gravity_at(pos)
     }
 
-    fn apply_viscosity_to_particle(&self, particle_id: usize) -> Vec2 {
-        let velocity = self.velocities[particle_id];
+    /// The acceleration (including gravity) of a particle at `pos` due to an interaction at `input_point`, or None if
+    /// the particle isn't affected by it.
+    pub fn interaction_acceleration(
+        &self,
+        input_point: Vec2,
+        mode: InteractionMode,
+        strength: f32,
+        pos: Vec2,
+        velocity: Vec2,
+    ) -> Option<Vec2> {
+        let input_point_offset = input_point - pos;
+        let distance = input_point_offset.length();
+        let affected = match mode {
+            InteractionMode::SlowOnly => velocity.length() < self.interaction_slow_threshold,
+            _ => true,
+        };
+        if distance >= self.interaction_input_radius || distance <= 0.0 || !affected {
+            return None;
+        }
+
+        let distance_ratio = distance / self.interaction_input_radius;
+        let center = 1.0 - distance_ratio;
+        let dir_to_centre = input_point_offset / distance;
+        let strength = match mode {
+            InteractionMode::Double => strength * 2.0,
+            _ => strength,
+        };
+
+        if mode == InteractionMode::Vortex {
+            // Push the particles around the cursor rather than toward it.
+            return Some(-self.gravity_at(pos) + dir_to_centre.perp() * center * strength - velocity * center * 0.1);
+        }
+
+        let gravity_weight = 1.0 - (center * (strength / 10.0).clamp(0.0, 1.0));
+        let mut accel = -self.gravity_at(pos) * gravity_weight + dir_to_centre * center * strength;
+        accel -= velocity * center;
+        Some(accel)
+    }
+
+    /// Drags the particles near a moving cursor along with it, in proportion to the difference between the cursor's
+    /// velocity and the particle's.
+    fn cursor_wake_force(&self, particle_id: usize) -> Vec2 {
+        let Some((cursor_position, cursor_velocity)) = self.cursor_wake else {
+            return Vec2::ZERO;
+        };
+        let radius = self.smoothing_radius * 2.0;
+        let distance = self.positions[particle_id].distance(cursor_position);
+        if distance >= radius {
+            return Vec2::ZERO;
+        }
+
+        (cursor_velocity - self.velocities[particle_id]) * (1.0 - distance / radius) * self.cursor_wake_strength
+    }
+
+    /// Attraction (or repulsion, if adhesion is negative) toward any walls within the smoothing radius, falling off
+    /// with the square of the distance from the wall, like the viscosity kernel.
+    fn adhesion_force(&self, particle_id: usize) -> Vec2 {
+        if self.adhesion == 0.0 {
+            return Vec2::ZERO;
+        }
+
         let position = self.positions[particle_id];
-        let mut viscosity = Vec2::default();
+        let mut force = Vec2::ZERO;
+        for (distance, direction) in [
+            (self.half_bounds_size.x - position.x, Vec2::X),
+            (self.half_bounds_size.x + position.x, Vec2::NEG_X),
+            (self.half_bounds_size.y - position.y, Vec2::Y),
+            (self.half_bounds_size.y + position.y, Vec2::NEG_Y),
+        ] {
+            if distance < self.smoothing_radius {
+                let falloff = 1.0 - distance.max(0.0) / self.smoothing_radius;
+                force += direction * falloff * falloff * self.adhesion;
+            }
+        }
+
+        force
+    }
+
+    /// The viscosity's acceleration is the kinematic viscosity times the Laplacian of the velocity, which is the sum
+    /// over the neighbors of their volume (the inverse of their density) times their velocity relative to the particle
+    /// times the Laplacian of the viscosity kernel.
+    fn apply_viscosity_to_particle(&self, particle_id: usize, delta: f32) -> Vec2 {
+        let velocity = self.velocities[particle_id];
+        let position = self.solver_position(particle_id);
+        let viscosity = self.viscosity_strength * self.particle_size * self.particle_size;
+        let mut laplacian = Vec2::default();
+        let mut total_weight = 0.0;
+        let mut springs = Vec2::default();
+        let mut num_springs = 0;
 
         for neighbor_id in self.neighbor_particles(particle_id) {
-            let offset = self.positions[neighbor_id] - position;
+            let offset = self.solver_position(neighbor_id) - position;
             let distance = offset.length().max(0.00000001);
             if distance < self.smoothing_radius {
-                let influence = self.viscosity_kernel(distance);
-                viscosity += (self.velocities[neighbor_id] - velocity) * influence;
+                // A splashing particle has few neighbors, which would make each of them count as a huge volume and
+                // lock the pair's velocities together, so the volume is at most the one at rest.
+                let volume = 1.0 / self.densities[neighbor_id].max(self.rest_density(neighbor_id));
+                let weight = volume * self.viscosity_laplacian(distance);
+                laplacian += (self.velocities[neighbor_id] - velocity) * weight;
+                total_weight += weight;
+                if let Some(spring) = self.spring_acceleration(particle_id, neighbor_id, offset, distance) {
+                    springs += spring;
+                    num_springs += 1;
+                }
             }
         }
 
-        velocity + viscosity * self.viscosity_strength
+        // Explicit viscosity overshoots, and blows up, once a step would take the particle past the weighted average
+        // of its neighbors' velocities, so a step goes no further than that, however viscous the fluid.
+        let time_step = (viscosity * delta).min(1.0 / total_weight.max(f32::EPSILON));
+        velocity + laplacian * time_step + springs / num_springs.max(1) as f32 * delta
     }
 }
 
+/// A deterministic simulation for the tests, with the given number of particles placed in a tank of the given size.
+/// The other arguments are taken from `args`, which is usually `Args::default()` with a few fields changed.
+#[cfg(test)]
+pub(crate) fn test_sim(num_particles: usize, width: f32, height: f32, args: Args) -> Simulation {
+    let mut sim = Simulation::new(
+        width,
+        height,
+        &Args {
+            num_particles,
+            deterministic: true,
+            ..args
+        },
+    );
+    sim.place_particles();
+    sim
+}
+
 #[cfg(test)]
 mod tests {
     use bevy::math::Vec2;
+    use clap::ValueEnum;
 
     use super::*;
+    use crate::neighbor_search::NeighborSearchKind;
 
     /// Place particles in an evenly-spaced grid and verify that the
     /// density of the center particle is approximately the same across
@@ -523,17 +1377,18 @@ mod tests {
                 win_width,
                 win_height,
                 &Args {
-                    win: "".to_string(),
+                    win: (800, 900),
                     num_particles,
                     smoothing_radius: 0.0,
-                    gravity: 0.0,
+                    gravity: Some(0.0),
                     speed: 0.0,
-                    pressure_multiplier: 100000,
+                    pressure_multiplier: Some(100000),
                     viscosity_strength: 0.0,
                     collision_damping: 0.0,
-                    interaction_input_radius: 0,
+                    interaction_input_radius: Some(0.0),
                     interaction_input_strength: 0.0,
                     sprite_size: 0.0,
+                    ..Args::default()
                 },
             );
             let spacing = sim.particle_size * 1.5;
@@ -563,7 +1418,10 @@ mod tests {
                 println!("    density={density:.4}");
 
                 sim.calculate_densities();
-                let pressure = sim.calculate_pressure(center_particle, 1.0 / 120.0) / sim.particle_size;
+                sim.calculate_pressure_values();
+                let pressure =
+                    sim.calculate_pressure(center_particle, sim.pressure_force(center_particle), 1.0 / 120.0)
+                        / sim.particle_size;
                 assert_ne!(Vec2::ZERO, pressure);
                 pressures.push(pressure);
                 println!("    pressure={pressure:.4}");
@@ -581,4 +1439,207 @@ mod tests {
             assert!(max_diff < 0.05);
         }
     }
+
+    /// The target density is derived from an ideal grid of particles, so it should match the density of the center
+    /// particle of an actual grid with the same spacing.
+    #[test]
+    fn lattice_density_matches_particle_grid() {
+        let rows = 30;
+        let cols = 30;
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: rows * cols,
+                ..Args::default()
+            },
+        );
+
+        for (spacing_scale, smoothing_radius) in [(1.0, 3.0), (1.5, 5.0), (0.8, 8.0)] {
+            let spacing = sim.particle_size * spacing_scale;
+            for row in 0..rows {
+                for col in 0..cols {
+                    let particle_id = row * cols + col;
+                    sim.positions[particle_id] = Vec2::new(col as f32, row as f32) * spacing - 150.0;
+                    sim.predicted_positions[particle_id] = sim.positions[particle_id];
+                }
+            }
+            sim.set_smoothing_radius(smoothing_radius);
+            sim.update_regions();
+
+            let center_particle = (rows / 2) * cols + (cols / 2);
+            let density = sim.calculate_density(center_particle);
+            let lattice_density = sim.lattice_density(spacing);
+            assert!(
+                (density - lattice_density).abs() / density < 1e-3,
+                "spacing {spacing}: {density} != {lattice_density}"
+            );
+        }
+    }
+
+    /// The viscosity is a force, so it slows a shear flow down by as much in one step as in two steps of half the
+    /// time, rather than once per step.
+    #[test]
+    fn viscosity_does_not_depend_on_the_step_size() {
+        let mean_speed = |delta: f32, steps: usize| {
+            let mut sim = test_sim(
+                400,
+                200.0,
+                200.0,
+                Args {
+                    gravity: Some(0.0),
+                    pressure_multiplier: Some(0),
+                    viscosity_strength: 200.0,
+                    ..Args::default()
+                },
+            );
+            for i in 0..sim.num_particles {
+                sim.velocities[i] = Vec2::new(sim.positions[i].y.signum() * 10.0, 0.0);
+            }
+            sim.step_n(delta, steps);
+            sim.velocities().iter().map(|velocity| velocity.length()).sum::<f32>() / sim.num_particles as f32
+        };
+
+        let (coarse, fine) = (mean_speed(1.0 / 60.0, 10), mean_speed(1.0 / 120.0, 20));
+        assert!(coarse < 9.0, "the viscosity hasn't slowed the shear down: {coarse}");
+        assert!((coarse - fine).abs() < 0.05 * fine, "one step {coarse}, two half steps {fine}");
+    }
+
+    /// A target density given by hand is kept when the particles are reset, the smoothing radius changes or the
+    /// number of particles changes, instead of being derived again.
+    #[test]
+    fn target_density_override_is_kept() {
+        let mut sim = test_sim(
+            400,
+            400.0,
+            400.0,
+            Args {
+                target_density: Some(0.5),
+                ..Args::default()
+            },
+        );
+        let target_density = 0.5 / (sim.units.pixels_per_unit * sim.units.pixels_per_unit);
+        assert_eq!(sim.target_density, target_density);
+        sim.set_smoothing_radius(6.0);
+        sim.reset();
+        sim.change_num_particles(500, true);
+        assert_eq!(sim.target_density, target_density);
+
+        sim.target_density_override = None;
+        sim.update_target_density();
+        assert_ne!(sim.target_density, target_density);
+
+        // Adjusting it with F7 sets it by hand too.
+        sim.adj_target_density(true);
+        let adjusted = sim.target_density;
+        sim.set_smoothing_radius(8.0);
+        sim.place_particles();
+        assert_eq!(sim.target_density, adjusted);
+    }
+
+    /// The neighbors highlighted around a watched particle should be exactly the particles within its smoothing
+    /// radius, whichever neighbor search finds them.
+    #[test]
+    fn neighbors_within_radius_match_brute_force() {
+        for neighbor_search in NeighborSearchKind::value_variants() {
+            let mut sim = test_sim(
+                400,
+                400.0,
+                400.0,
+                Args {
+                    neighbor_search: *neighbor_search,
+                    ..Args::default()
+                },
+            );
+            sim.step_n(1.0 / 60.0, 30);
+
+            for particle_id in [0, 123, 399] {
+                let position = sim.positions[particle_id];
+                let mut found: Vec<usize> = sim
+                    .neighbors_within_radius(particle_id)
+                    .filter(|&i| i != particle_id)
+                    .collect();
+                found.sort_unstable();
+                found.dedup();
+                let expected: Vec<usize> = (0..sim.num_particles)
+                    .filter(|&i| i != particle_id && (sim.positions[i] - position).length() < sim.smoothing_radius)
+                    .collect();
+                assert_eq!(found, expected, "{neighbor_search:?}: particle {particle_id}");
+            }
+        }
+    }
+
+    /// Runs a small deterministic simulation and compares the particle positions against the golden file, so that
+    /// refactors of the solver can be checked for unchanged behavior. Every neighbor search must produce the same
+    /// result. Run with `UPDATE_GOLDEN=1` to regenerate the golden files after an intentional change: they're written
+    /// from the default neighbor search, which comes first, and the others are still checked against them.
+    #[test]
+    fn positions_match_golden_state() {
+        const STEPS: usize = 120;
+        const TOLERANCE: f32 = 1e-2;
+
+        for (name, viscosity_strength, gravity) in [("still", 0.0, 0.0), ("viscous-drop", 50.0, 30.0)] {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/golden/{name}.txt"));
+
+            for neighbor_search in NeighborSearchKind::value_variants() {
+                let mut sim = test_sim(
+                    400,
+                    200.0,
+                    200.0,
+                    Args {
+                        viscosity_strength,
+                        gravity: Some(gravity),
+                        neighbor_search: *neighbor_search,
+                        ..Args::default()
+                    },
+                );
+                sim.step_n(1.0 / 60.0, STEPS);
+
+                let actual: String = sim
+                    .positions()
+                    .iter()
+                    .map(|p| format!("{:.4} {:.4}\n", p.x, p.y))
+                    .collect();
+                if std::env::var_os("UPDATE_GOLDEN").is_some() && *neighbor_search == NeighborSearchKind::default() {
+                    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    std::fs::write(&path, &actual).unwrap();
+                }
+
+                let expected = std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("{}: {e} (run with UPDATE_GOLDEN=1 to create it)", path.display()));
+                let parse = |line: &str| -> Vec2 {
+                    let (x, y) = line.split_once(' ').unwrap();
+                    Vec2::new(x.parse().unwrap(), y.parse().unwrap())
+                };
+                assert_eq!(expected.lines().count(), actual.lines().count(), "{name}: particle count");
+                for (i, (expected, actual)) in expected.lines().zip(actual.lines()).enumerate() {
+                    let (expected, actual) = (parse(expected), parse(actual));
+                    assert!(
+                        expected.distance(actual) < TOLERANCE,
+                        "{name} ({neighbor_search:?}): particle {i} is at {actual}, expected {expected}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn big_resize_rescales_the_simulation() {
+        let mut sim = test_sim(100, 200.0, 200.0, Args::default());
+        let (particle_size, target_density, gravity) = (sim.particle_size, sim.target_density, sim.gravity);
+
+        // A small resize only moves the walls.
+        sim.on_resize(220.0, 200.0);
+        assert_eq!(sim.particle_size, particle_size);
+
+        sim.on_resize(400.0, 400.0);
+        assert!((sim.particle_size - particle_size * 2.0).abs() < 1e-3);
+        assert!((sim.target_density - target_density / 4.0).abs() < target_density * 1e-3);
+        assert!((sim.gravity.y - gravity.y * 2.0).abs() < 1e-3);
+        assert!(
+            sim.positions()
+                .iter()
+                .all(|p| p.abs().cmple(sim.half_bounds_size).all())
+        );
+    }
 }
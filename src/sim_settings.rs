@@ -1,5 +1,7 @@
 use bevy::prelude::Vec2;
 
+use crate::color_field::ColorField;
+use crate::foam::Foam;
 use crate::sim_struct::Simulation;
 
 impl Simulation {
@@ -12,12 +14,11 @@ impl Simulation {
         self.max_density = 0f32;
     }
 
-    pub fn frames_to_advance(&self) -> u32 {
-        self.debug.frames_to_show
-    }
-
-    pub fn set_frames_to_show(&mut self, val: u32) {
-        self.debug.frames_to_show = val;
+    /// Places the particles from scratch, but each particle keeps its velocity.
+    pub fn reset_keeping_velocities(&mut self) {
+        let velocities = self.velocities.clone();
+        self.reset();
+        self.velocities = velocities;
     }
 
     pub fn toggle_smoothing_radius(&mut self) {
@@ -32,8 +33,52 @@ impl Simulation {
         self.debug.show_fps = !self.debug.show_fps;
     }
 
-    pub fn toggle_heatmap(&mut self) {
-        self.debug.density_heatmap = !self.debug.density_heatmap;
+    pub fn toggle_recording(&mut self) {
+        self.debug.recording = !self.debug.recording;
+    }
+
+    pub fn toggle_bottomless(&mut self) {
+        self.bottomless = !self.bottomless;
+    }
+
+    pub fn toggle_wall_impulses(&mut self) {
+        self.debug.show_wall_impulses = !self.debug.show_wall_impulses;
+    }
+
+    pub fn next_color_field(&mut self) {
+        self.debug.color_field = self.debug.color_field.next();
+    }
+
+    pub fn toggle_annotating(&mut self) {
+        self.debug.annotating = !self.debug.annotating;
+    }
+
+    pub fn toggle_density_scaled_sprites(&mut self) {
+        self.debug.density_scaled_sprites = !self.debug.density_scaled_sprites;
+    }
+
+    pub fn toggle_density_background(&mut self) {
+        self.debug.density_background = !self.debug.density_background;
+    }
+
+    pub fn toggle_streamlines(&mut self) {
+        self.debug.show_streamlines = !self.debug.show_streamlines;
+    }
+
+    pub fn toggle_surface(&mut self) {
+        self.debug.show_surface = !self.debug.show_surface;
+    }
+
+    pub fn toggle_foam(&mut self) {
+        self.foam = if self.foam.is_some() { None } else { Some(Foam::default()) };
+    }
+
+    pub fn toggle_probing(&mut self) {
+        self.debug.probing = !self.debug.probing;
+    }
+
+    pub fn toggle_energy_stats(&mut self) {
+        self.debug.show_energy = !self.debug.show_energy;
     }
 
     pub fn reset_inertia(&mut self) {
@@ -58,6 +103,26 @@ impl Simulation {
         self.set_smoothing_radius((smoothing_radius + increment).max(increment.abs()));
     }
 
+    /// Picks random values, within ranges that keep the simulation stable, for a few of the parameters that make the
+    /// most visible difference. Returns a description of the chosen values.
+    pub fn randomize(&mut self) -> String {
+        let pick = |index: usize, min: f32, max: f32| min + self.random(usize::MAX, index) * (max - min);
+        let gravity = pick(0, 5.0, 60.0);
+        let viscosity_strength = pick(1, 0.0, 200.0);
+        let interaction_input_strength = pick(2, 200.0, 2000.0);
+        let density_heatmap = pick(3, 0.0, 1.0) < 0.5;
+
+        self.gravity.y = self.units.to_pixels(gravity);
+        self.viscosity_strength = viscosity_strength;
+        self.interaction_input_strength = interaction_input_strength;
+        self.debug.color_field = if density_heatmap { ColorField::Density } else { ColorField::Speed };
+
+        format!(
+            "Gravity: {gravity:.1}\nViscosity: {viscosity_strength:.1}\nInteraction strength: {interaction_input_strength:.0}\nColors: {}",
+            if density_heatmap { "density" } else { "velocity" }
+        )
+    }
+
     pub fn adj_gravity(&mut self, increase: bool) {
         self.gravity.y = if increase { self.gravity.y * 1.10 } else { self.gravity.y / 1.10 };
     }
@@ -68,7 +133,26 @@ impl Simulation {
     }
 
     pub fn adj_viscosity(&mut self, increase: bool) {
-        self.viscosity_strength =
-            if increase { self.viscosity_strength * 1.10 } else { self.viscosity_strength / 1.10 };
+        // Below the minimum, the viscosity is off, so that it can be turned back on from zero.
+        const MIN_VISCOSITY: f32 = 0.1;
+        self.viscosity_strength = if increase {
+            (self.viscosity_strength * 1.10).max(MIN_VISCOSITY)
+        } else if self.viscosity_strength / 1.10 < MIN_VISCOSITY {
+            0.0
+        } else {
+            self.viscosity_strength / 1.10
+        };
+    }
+
+    /// The adjusted target density is kept from then on, rather than derived again when the smoothing radius changes
+    /// or the particles are reset.
+    pub fn adj_target_density(&mut self, increase: bool) {
+        let target_density = self.target_density * self.units.pixels_per_unit * self.units.pixels_per_unit;
+        self.set_target_density(if increase { target_density * 1.10 } else { target_density / 1.10 });
+    }
+
+    pub fn adj_collision_damping(&mut self, increase: bool) {
+        let step = if increase { 0.05 } else { -0.05 };
+        self.collision_damping = (self.collision_damping + step).clamp(0.0, 1.0);
     }
 }
@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::digit_keys::key_number;
+use crate::key_bindings::KeyBindings;
+use crate::sim_struct::Simulation;
+
+/// How often holding 1 advances another step.
+const STEP_REPEAT: Duration = Duration::from_millis(50);
+
+/// Whether the simulations are running. The stepping systems only run while the state is advancing (see
+/// `advancing`), and the others can check it with `in_state`.
+#[derive(States, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SimState {
+    #[default]
+    Running,
+    Paused,
+    /// Advancing this many more steps, and then pausing.
+    SingleStep(u32),
+}
+
+impl SimState {
+    /// The state once any pending transition is applied. FixedUpdate can run several times in a frame, before the
+    /// state transitions are, so the single steps are counted against the pending state.
+    fn current(state: &State<SimState>, next: &NextState<SimState>) -> SimState {
+        match next {
+            NextState::Pending(pending) | NextState::PendingIfNeq(pending) => *pending,
+            NextState::Unchanged => *state.get(),
+        }
+    }
+}
+
+/// Run condition for the systems that step the simulations.
+pub fn advancing(state: Res<State<SimState>>, next: Res<NextState<SimState>>) -> bool {
+    SimState::current(&state, &next) != SimState::Paused
+}
+
+/// Counts down the single steps, after each step.
+pub fn count_single_steps(state: Res<State<SimState>>, mut next: ResMut<NextState<SimState>>) {
+    if let SimState::SingleStep(steps) = SimState::current(&state, &next) {
+        next.set(if steps > 1 { SimState::SingleStep(steps - 1) } else { SimState::Paused });
+    }
+}
+
+/// While paused, keeps the densities (and so the colors and the inspector) up to date.
+pub fn refresh_paused(mut sims: Query<&mut Simulation>, time: Res<Time<Fixed>>) {
+    for mut sim in &mut sims {
+        let delta = time.timestep().as_secs_f32() / sim.substeps.max(1) as f32;
+        sim.refresh(delta);
+    }
+}
+
+/// Space (or its rebinding) pauses and resumes, and the digits 1 to 9 advance that many steps and pause (1 repeats while it's held).
+/// Shift+digit loads a scenario instead (see keyboard.rs).
+pub fn control_sim_state(
+    kb: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    state: Res<State<SimState>>,
+    mut next: ResMut<NextState<SimState>>,
+    mut last_step: Local<Option<Instant>>,
+) {
+    if kb.just_pressed(bindings.pause_key()) {
+        next.set(match state.get() {
+            SimState::Paused => SimState::Running,
+            SimState::Running | SimState::SingleStep(_) => SimState::Paused,
+        });
+        return;
+    }
+
+    if kb.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        return;
+    }
+    let repeating = kb.pressed(KeyCode::Digit1) && last_step.is_some_and(|time| time.elapsed() >= STEP_REPEAT);
+    let steps = kb
+        .get_just_pressed()
+        .find_map(|&key| key_number(key))
+        .or(repeating.then_some(1));
+    if let Some(steps) = steps {
+        next.set(SimState::SingleStep(steps));
+        *last_step = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::prelude::*;
+
+    use super::{SimState, advancing, count_single_steps};
+
+    #[test]
+    fn single_steps_count_down_before_the_transition_is_applied() {
+        let mut world = World::new();
+        world.insert_resource(State::new(SimState::SingleStep(2)));
+        world.insert_resource(NextState::<SimState>::Unchanged);
+
+        // FixedUpdate can step more than once before the state transitions run.
+        let mut steps = 0;
+        for _ in 0..5 {
+            if world.run_system_once(advancing).unwrap() {
+                steps += 1;
+                world.run_system_once(count_single_steps).unwrap();
+            }
+        }
+        assert_eq!(steps, 2);
+        assert!(matches!(world.resource::<NextState<SimState>>(), NextState::Pending(SimState::Paused)));
+    }
+}
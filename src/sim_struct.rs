@@ -3,40 +3,173 @@ use std::fmt::{Debug, Formatter};
 use bevy::math::Vec2;
 use bevy::prelude::Component;
 
+use crate::boundary::WallKernel;
+use crate::color_field::ColorField;
+use crate::colormap::Colormap;
+use crate::container::{Container, ContainerShape};
+use crate::convection::Convection;
+use crate::dye::Dye;
+use crate::elasticity::Springs;
+use crate::flow_meter::FlowLine;
+use crate::fluids::FluidType;
+use crate::foam::Foam;
+use crate::gpu::GpuBackend;
+use crate::grab::GrabConstraint;
+use crate::heightfield::Heightfield;
+use crate::interaction::InteractionMode;
+use crate::metrics::PhaseTimings;
+use crate::neighbor_search::NeighborSearch;
+use crate::paddle::Paddle;
+use crate::placement::InitialPlacement;
+use crate::rigid_body::RigidBody;
+use crate::scenario::{BaseParams, Scenario};
+use crate::solver::{Solver, SolverStats};
+use crate::time_step::TimeStep;
+use crate::units::Units;
+use crate::wall_impulse::WallImpulses;
+use crate::wind::Wind;
+use crate::wind_tunnel::WindTunnel;
+
 #[derive(Component)]
 pub struct Simulation {
     pub smoothing_radius: f32,
     pub smoothing_scaling_factor: f32,
     pub smoothing_derivative_scaling_factor: f32,
     pub viscosity_scaling_factor: f32,
+    pub viscosity_laplacian_scaling_factor: f32,
     pub num_particles: usize,
+    pub reset_on_count_change: bool,
+    /// Set when the particle count or the particle mass is changed deliberately, so that the change isn't reported as
+    /// a conservation error.
+    pub totals_changed: bool,
     pub particle_size: f32,
+    pub units: Units,
     pub half_bounds_size: Vec2,
+    /// The size of the tank that the particle size was derived from. When the tank gets much bigger or smaller than
+    /// this, the whole simulation is rescaled (see on_resize()).
+    pub sized_for: Vec2,
     pub target_density: f32,
+    /// A target density set by hand, per square unit, which update_target_density() keeps instead of deriving one.
+    pub target_density_override: Option<f32>,
+    pub deterministic: bool,
 
     // Adjustable parameters
     pub gravity: Vec2,
+    /// How far the gravity is tilted from straight down, in radians (Ctrl+Left/Right). See gravity_vector().
+    pub gravity_angle: f32,
+    /// How fast the gravity turns while it's spinning, in radians per second.
+    pub gravity_spin_speed: f32,
+    pub gravity_spinning: bool,
+    /// Point gravity (planet) mode: the gravity pulls toward gravity_center instead of down (M). See gravity_at().
+    pub point_gravity: bool,
+    pub gravity_center: Vec2,
     pub pressure_multiplier: f32,
+    /// The kinematic viscosity, in particle sizes²/s.
     pub viscosity_strength: f32,
+    /// The spring constant and plasticity of the viscoelastic springs (see Springs).
+    pub elasticity: f32,
+    pub plasticity: f32,
+    pub springs: Springs,
     pub collision_damping: f32,
+    /// Restitution of the left, right, bottom and top sides of the tank, if they differ from the collision damping.
+    pub wall_restitution: Option<[f32; 4]>,
+    /// Coulomb friction coefficient of all the walls, including the container and the obstacles.
+    pub wall_friction: f32,
+    pub bottomless: bool,
+    pub adhesion: f32,
+    /// Acceleration of the surface particles toward their neighbors (see surface_tension_force()).
+    pub surface_tension: f32,
+    /// Whether each particle is at the free surface, as of the start of the step, or empty when nothing needs it (see
+    /// update_surface()).
+    pub surface: Vec<bool>,
+    /// Whether the wall density correction is on (see WallKernel).
+    pub wall_density: bool,
+    pub wall_kernel: WallKernel,
     pub speed: f32,
+    /// Minimum number of substeps per fixed step.
+    pub substeps: u32,
+    /// The CFL limit on the substeps, and how the last frame was split.
+    pub time_step: TimeStep,
+    pub solver: Box<dyn Solver>,
+    /// Number of constraint projection iterations per substep, with the PBF solver.
+    pub pbf_iterations: u32,
+    /// Most pressure-correction iterations per substep, and the compression they stop at, with the PCISPH solver.
+    pub pcisph_max_iterations: u32,
+    pub pcisph_tolerance: f32,
     pub sprite_size: f32,
     pub interaction_input_strength: f32,
     pub interaction_input_radius: f32,
+    /// Radius of the blast of a double-click, in pixels.
+    pub explosion_radius: f32,
+    /// The speed that a double-click gives the particles at the center of the blast, in pixels per second.
+    pub explosion_strength: f32,
+    pub interaction_slow_threshold: f32,
+    pub cursor_wake_strength: f32,
 
     // Particle information:
     pub positions: Vec<Vec2>,
     pub predicted_positions: Vec<Vec2>,
+    /// The positions before the last fixed step, for interpolating the rendered positions.
+    pub previous_positions: Vec<Vec2>,
     pub velocities: Vec<Vec2>,
     pub densities: Vec<f32>,
+    pub pressures: Vec<f32>,
+    /// The kinds of fluid in the simulation. There's only one, unless a scenario defines more.
+    pub fluid_types: Vec<FluidType>,
+    /// The index into fluid_types of each particle's fluid.
+    pub fluids: Vec<u8>,
     pub region_rows: usize,
     pub region_cols: usize,
-    pub regions: Vec<Vec<Vec<usize>>>,
+    pub neighbor_search: Box<dyn NeighborSearch>,
+    /// Computes the densities and pressure forces with compute shaders, with `--backend gpu`.
+    pub gpu: Option<GpuBackend>,
     pub interaction_input_point: Option<Vec2>,
+    pub interaction_mode: InteractionMode,
+    /// A second point that's placed with the J key, and attracts or repels along with the cursor while clicking.
+    pub pinch_anchor: Option<Vec2>,
+    /// The second point of a two-point pinch while interacting: the pinch anchor, or the second touch.
+    pub pinch_point: Option<Vec2>,
+    /// The particles held by the grab tool, in order of particle id.
+    pub grab_constraints: Vec<GrabConstraint>,
+    /// The point that the grabbed particles are attached to, and its velocity.
+    pub grab_point: Option<(Vec2, Vec2)>,
+    /// Lines that count the particles flowing across them (see flow_meter.rs).
+    pub flow_lines: Vec<FlowLine>,
+    /// The cursor position and velocity, while the cursor is moving over the window with no buttons pressed.
+    pub cursor_wake: Option<(Vec2, Vec2)>,
+    /// The background current (`--wind`, U).
+    pub wind: Wind,
+    pub heightfield: Option<Heightfield>,
+    pub wall_impulses: WallImpulses,
+    pub wind_tunnel: Option<WindTunnel>,
+    /// The moving left wall (`--paddle`).
+    pub paddle: Option<Paddle>,
+    /// The scenario's floating bodies, as they are now.
+    pub bodies: Vec<RigidBody>,
+    pub convection: Option<Convection>,
+    /// The particles' dye colors (`--dye`).
+    pub dye: Option<Dye>,
+    /// The splashes' secondary particles (`--foam`).
+    pub foam: Option<Foam>,
+    pub container_shape: Option<ContainerShape>,
+    pub placement: InitialPlacement,
+    /// The container_shape, fitted to the window.
+    pub container: Option<Container>,
+    /// The scenario that the particles are placed for, and whose obstacles are in the tank.
+    pub scenario: Option<Scenario>,
+    /// The parameters that a scenario can override, as they were before any scenario was applied.
+    pub base_params: BaseParams,
+    /// The scenarios that can be switched to with Shift+digit: the --scenario files, then the presets.
+    pub scenarios: Vec<Scenario>,
     pub min_velocity: f32,
     pub max_velocity: f32,
     pub min_density: f32,
     pub max_density: f32,
+    pub solver_stats: SolverStats,
+    /// Time spent in each phase of the steps since the last metrics export (see metrics.rs).
+    pub phase_timings: PhaseTimings,
+    /// Simulated time, in seconds, since the particles were last placed.
+    pub sim_time: f32,
 
     pub debug: DebugParams,
 }
@@ -51,28 +184,80 @@ impl Debug for Simulation {
             self.smoothing_radius / self.particle_size
         )?;
         writeln!(f, "    num_particles: {}", self.num_particles)?;
+        writeln!(f, "    neighbor_search: {}", self.neighbor_search.name())?;
+        writeln!(f, "    solver: {}", self.solver.name())?;
+        writeln!(f, "    backend: {}", if self.gpu.is_some() { "gpu" } else { "cpu" })?;
         writeln!(f, "    particle_size: {}", self.particle_size)?;
-        writeln!(f, "    gravity: {} ({})", self.gravity.y, self.gravity.y / self.particle_size)?;
-        writeln!(f, "    target_density: {}", self.target_density)?;
+        writeln!(
+            f,
+            "    units: {:?} ({} pixels per {})",
+            self.units.system,
+            self.units.pixels_per_unit,
+            self.units.length_name()
+        )?;
+        writeln!(f, "    gravity: {} ({})", self.gravity.y, self.units.to_units(self.gravity.y))?;
+        writeln!(
+            f,
+            "    target_density: {} ({} kg/m², particle mass {} kg)",
+            self.target_density,
+            self.units.density(self.target_density, self.target_density),
+            self.units.particle_mass(self.target_density)
+        )?;
         writeln!(
             f,
             "    pressure_multiplier: {} ({})",
             self.pressure_multiplier,
-            self.pressure_multiplier / self.particle_size
+            self.units.to_units(self.pressure_multiplier)
         )?;
         writeln!(f, "    viscosity_strength: {}", self.viscosity_strength)?;
-        writeln!(f, "    collision_damping: {}", self.collision_damping)
+        writeln!(f, "    elasticity: {}", self.elasticity)?;
+        writeln!(f, "    plasticity: {}", self.plasticity)?;
+        writeln!(f, "    collision_damping: {}", self.collision_damping)?;
+        writeln!(f, "    wall_restitution: {:?}", self.wall_restitution)?;
+        writeln!(f, "    wall_friction: {}", self.wall_friction)?;
+        writeln!(f, "    adhesion: {}", self.units.to_units(self.adhesion))?;
+        writeln!(f, "    surface_tension: {}", self.units.to_units(self.surface_tension))
     }
 }
 
 pub struct DebugParams {
     pub current_frame: u32,
-    pub frames_to_show: u32,
     pub log_frame: u32,
     pub show_fps: bool,
     pub show_smoothing_radius: bool,
     pub show_region_grid: bool,
-    pub density_heatmap: bool,
+    /// Draw the region grid only around the cursor or the watched particle, with the particle count of each cell.
+    pub local_region_grid: bool,
+    pub color_field: ColorField,
+    /// The color field for each particle, scaled to 0..1, for the fields that the simulation doesn't already track.
+    pub color_values: Vec<f32>,
+    pub colormap: Colormap,
+    pub show_wall_impulses: bool,
     pub show_arrows: bool,
+    /// Draw streamlines through the velocity field (see streamlines.rs).
+    pub show_streamlines: bool,
+    /// Outline the particles at the free surface (see surface.rs).
+    pub show_surface: bool,
+    /// Show the fluid's state under the cursor (see probe.rs).
+    pub probing: bool,
+    /// Display the energy and momentum, and warn when the energy grows (see conservation.rs).
+    pub show_energy: bool,
+    /// The mouse draws annotations instead of interacting with the fluid.
+    pub annotating: bool,
+    /// Scale each sprite by the area its particle occupies, so that splashes look less gappy and compressed regions
+    /// less blobby. Only affects the rendering.
+    pub density_scaled_sprites: bool,
+    /// Draw the continuous density field behind the particles (see density_background.rs).
+    pub density_background: bool,
     pub use_predicted_positions: bool,
+    /// Skip updating the sprites of particles that are off-screen.
+    pub cull_offscreen: bool,
+    /// Draw the particles as one mesh, rather than as Particle entities.
+    pub particle_mesh: bool,
+    /// Capture the rendered frames (see recorder.rs).
+    pub recording: bool,
+    /// The number of particles that were skipped in the last frame because they were off-screen.
+    pub offscreen_particles: usize,
+    /// The neighbors of the watched particles, sorted, which are highlighted to check the neighbor search.
+    pub watched_neighbors: Vec<usize>,
 }
@@ -0,0 +1,188 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use bevy::app::AppExit;
+use bevy::prelude::{MessageWriter, ResMut, Resource, Single, With, error, info};
+
+use crate::args::DEFAULT_SOAK_MAX_ENERGY;
+use crate::components::MainSimulation;
+use crate::conservation::Conservation;
+use crate::sim_struct::Simulation;
+
+/// How often the invariants are checked while soak testing.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// State for the unattended soak test (`--soak hours`).
+/// The simulation runs until the requested time has elapsed, and every CHECK_INTERVAL the solver invariants are
+/// checked. Any violations are logged, along with a snapshot of the simulation state, so that slow-developing
+/// instabilities can be investigated after the fact.
+#[derive(Resource)]
+pub struct SoakTest {
+    pub end_time: Instant,
+    pub next_check: Instant,
+    /// In units²/s², or the default, in particle sizes²/s², if it's not given.
+    pub max_energy: Option<f32>,
+    /// The total mass that the fluid should have: the one at the start, or after the last deliberate change of the
+    /// particle count or mass (see Simulation::totals_changed).
+    pub expected_mass: Option<f32>,
+    pub violation_count: usize,
+}
+
+impl SoakTest {
+    pub fn new(hours: f32, max_energy: Option<f32>) -> Self {
+        let now = Instant::now();
+        SoakTest {
+            end_time: now + Duration::from_secs_f32(hours * 3600.0),
+            next_check: now + CHECK_INTERVAL,
+            max_energy,
+            expected_mass: None,
+            violation_count: 0,
+        }
+    }
+}
+
+impl Simulation {
+    /// Total mass of the fluid, relative to the mass of a particle of the default fluid.
+    pub fn total_mass(&self) -> f32 {
+        self.fluids
+            .iter()
+            .map(|&fluid| self.fluid_types[fluid as usize].mass())
+            .sum()
+    }
+
+    /// Average kinetic energy per unit mass, with velocities expressed in units per second (see --units), so that with
+    /// particle units the value doesn't depend on the window size or particle count.
+    pub fn mean_kinetic_energy(&self) -> f32 {
+        let total = self.parallel_sum(|i| 0.5 * (self.velocities[i] / self.units.pixels_per_unit).length_squared());
+        total / self.num_particles.max(1) as f32
+    }
+
+    /// Checks the solver invariants, returning a description of each one that was violated.
+    pub fn check_invariants(&self, expected_mass: f32, max_energy: f32) -> Vec<String> {
+        let mut violations = vec![];
+
+        let nan_positions = self.positions().iter().filter(|p| !p.is_finite()).count();
+        let nan_velocities = self.velocities().iter().filter(|v| !v.is_finite()).count();
+        let nan_densities = self.densities().iter().filter(|d| !d.is_finite()).count();
+        if nan_positions + nan_velocities + nan_densities > 0 {
+            violations.push(format!(
+                "non-finite values: {nan_positions} positions, {nan_velocities} velocities, {nan_densities} densities"
+            ));
+        }
+
+        let mass = self.total_mass();
+        if (mass - expected_mass).abs() > expected_mass * Conservation::MASS_TOLERANCE {
+            violations.push(format!("total mass changed: expected {expected_mass}, found {mass}"));
+        }
+
+        // Allow a little slack, since particles can be outside the bounds while the window is being resized.
+        let limit = self.half_bounds_size + self.particle_size;
+        let outside = self
+            .positions
+            .iter()
+            .filter(|p| p.x.abs() > limit.x || p.y.abs() > limit.y)
+            .count();
+        if outside > 0 {
+            violations.push(format!("{outside} particles outside the bounds"));
+        }
+
+        let energy = self.mean_kinetic_energy();
+        if energy > max_energy {
+            violations.push(format!("mean kinetic energy {energy} exceeds {max_energy}"));
+        }
+
+        violations
+    }
+
+    /// Writes the parameters and the full particle state to a file, returning the file name.
+    pub fn write_snapshot(&self, prefix: &str) -> std::io::Result<String> {
+        let file_name = format!("{prefix}-frame-{}.txt", self.debug.current_frame);
+        let mut contents = format!("{self:?}\n");
+        for i in 0..self.num_particles {
+            let _ = writeln!(
+                contents,
+                "{i}: pos={:?} vel={:?} density={} pressure={}",
+                self.positions[i], self.velocities[i], self.densities[i], self.pressures[i]
+            );
+        }
+        fs::write(&file_name, contents)?;
+
+        Ok(file_name)
+    }
+}
+
+/// Periodically checks the simulation invariants during a soak test, and exits once the test duration has elapsed.
+pub fn run_soak_test(
+    mut soak: ResMut<SoakTest>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut app_exit: MessageWriter<AppExit>,
+) {
+    let now = Instant::now();
+    let expected_mass = match soak.expected_mass {
+        Some(mass) if !sim.totals_changed => mass,
+        _ => sim.total_mass(),
+    };
+    soak.expected_mass = Some(expected_mass);
+
+    if now >= soak.next_check {
+        soak.next_check = now + CHECK_INTERVAL;
+
+        let max_energy = soak.max_energy.unwrap_or_else(|| {
+            let particle_size = sim.units.to_units(sim.units.particle_size);
+            DEFAULT_SOAK_MAX_ENERGY * particle_size * particle_size
+        });
+        let violations = sim.check_invariants(expected_mass, max_energy);
+        if !violations.is_empty() {
+            soak.violation_count += 1;
+            error!(
+                "Soak test: invariant violation at frame {}:\n    {}",
+                sim.debug.current_frame,
+                violations.join("\n    ")
+            );
+            match sim.write_snapshot("soak-snapshot") {
+                Ok(file_name) => info!("State snapshot written to {file_name}"),
+                Err(e) => error!("Failed to write state snapshot: {e}"),
+            }
+        }
+    }
+
+    if now >= soak.end_time {
+        info!(
+            "Soak test finished after {} frames with {} violation(s).",
+            sim.debug.current_frame, soak.violation_count
+        );
+        app_exit.write(if soak.violation_count == 0 { AppExit::Success } else { AppExit::error() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn mass_is_checked_with_a_tolerance() {
+        let mut sim = Simulation::new(
+            400.0,
+            400.0,
+            &Args {
+                num_particles: 100,
+                ..Args::default()
+            },
+        );
+        let mass = sim.total_mass();
+        let mass_violations = |sim: &Simulation, expected_mass: f32| {
+            sim.check_invariants(expected_mass, f32::INFINITY)
+                .iter()
+                .filter(|violation| violation.starts_with("total mass"))
+                .count()
+        };
+        assert_eq!(mass_violations(&sim, mass * (1.0 + 1e-5)), 0);
+
+        sim.change_num_particles(110, false);
+        assert!(sim.totals_changed);
+        assert_eq!(mass_violations(&sim, mass), 1);
+        assert_eq!(mass_violations(&sim, sim.total_mass()), 0);
+    }
+}
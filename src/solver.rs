@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use clap::ValueEnum;
+
+use crate::components::{MainSimulation, Notifications};
+use crate::messages::MessageText;
+use crate::pbf::PbfSolver;
+use crate::pcisph::PcisphSolver;
+use crate::sim_struct::Simulation;
+
+/// Advances the particles by one substep: applies the forces, keeps the fluid incompressible, and moves the particles.
+/// The solvers share the neighbor search, the forces and the collisions (all in Simulation), so a new one only needs
+/// to implement its own way of dealing with the density.
+pub trait Solver: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Advances the simulation by `delta` seconds. This also updates the densities and the solver stats.
+    fn update(&mut self, sim: &mut Simulation, delta: f32);
+}
+
+/// The available Solver implementations, selectable with `--solver`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SolverKind {
+    /// Explicit SPH: pressure forces from the density error, in a single pass.
+    #[default]
+    Sph,
+    /// Position-Based Fluids (Macklin & Müller 2013): the positions are projected onto the density constraints over
+    /// several iterations (see pbf.rs).
+    Pbf,
+    /// Predictive-corrective SPH (Solenthaler & Pajarola 2009): the pressures are corrected over several iterations,
+    /// until the predicted compression is small enough (see pcisph.rs).
+    Pcisph,
+}
+
+impl SolverKind {
+    pub fn create(self) -> Box<dyn Solver> {
+        match self {
+            SolverKind::Sph => Box::new(SphSolver),
+            SolverKind::Pbf => Box::new(PbfSolver),
+            SolverKind::Pcisph => Box::new(PcisphSolver),
+        }
+    }
+}
+
+/// Explicit SPH, as in Sebastian Lague's video: the pressure forces push the particles apart in proportion to how far
+/// their densities are from the target density. The densities are computed at the predicted positions.
+pub struct SphSolver;
+
+impl Solver for SphSolver {
+    fn name(&self) -> &'static str {
+        "sph"
+    }
+
+    fn update(&mut self, sim: &mut Simulation, delta: f32) {
+        sim.predict_positions(delta);
+        let start = Instant::now();
+        sim.update_regions();
+        sim.phase_timings.neighbor_search += start.elapsed();
+
+        let start = Instant::now();
+        sim.calculate_densities();
+        sim.phase_timings.densities += start.elapsed();
+        let start = Instant::now();
+        sim.calculate_pressure_values();
+        sim.calculate_pressures(delta);
+        sim.phase_timings.pressures += start.elapsed();
+        sim.record_solver_stats(None, sim.density_error(), None);
+        sim.apply_velocities(delta);
+        let start = Instant::now();
+        sim.apply_viscosity(delta);
+        sim.phase_timings.viscosity += start.elapsed();
+    }
+}
+
+/// Per-frame diagnostics of the pressure solver, shown with the FPS display.
+///
+/// The SPH solver is a single explicit pass, so it only records the density error. The PBF solver also records its
+/// number of constraint iterations, which is fixed. The PCISPH solver records its pressure-correction iterations, the
+/// compression that they stop at and its tolerance, and fails to converge when it runs out of iterations first.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SolverStats {
+    /// The number of iterations, for the solvers that iterate.
+    pub iterations: Option<u32>,
+    /// Mean of |density - target density| / target density over all the particles, or just of the compression (the
+    /// density in excess of the target) with PCISPH.
+    pub density_error: f32,
+    /// The density error that the solver iterates down to, if it does.
+    pub tolerance: Option<f32>,
+    /// Whether the density error is within the tolerance, for the solvers that have one.
+    pub converged: Option<bool>,
+    /// Number of frames that have failed to converge since the particles were placed.
+    pub failures: u32,
+}
+
+/// Notable things that happen in the simulation, for other systems (and tests) to react to.
+#[derive(Message, Debug)]
+pub enum SimEvent {
+    /// The solver failed to converge in this frame, after not failing in the previous one.
+    SolverFailed {
+        frame: u32,
+        iterations: u32,
+        density_error: f32,
+    },
+    /// The CFL condition needed more substeps than the most allowed in this frame, after not needing them in the
+    /// previous one, so the simulation fell behind real time.
+    Throttled {
+        frame: u32,
+        substeps: u32,
+        simulated_fraction: f32,
+    },
+}
+
+impl Simulation {
+    /// The mean of |density - target density| / target density over all the particles.
+    pub fn density_error(&self) -> f32 {
+        let target_density = self.target_density.max(f32::EPSILON);
+        self.parallel_sum(|i| (self.densities[i] - self.target_density).abs() / target_density)
+            / self.num_particles.max(1) as f32
+    }
+
+    /// Records the solver's iterations (None if it doesn't iterate), its measure of the density error, and the
+    /// tolerance that it iterates down to (None if it doesn't have one, in which case it can't fail to converge).
+    pub fn record_solver_stats(&mut self, iterations: Option<u32>, density_error: f32, tolerance: Option<f32>) {
+        let converged = tolerance.map(|tolerance| density_error <= tolerance);
+        self.solver_stats = SolverStats {
+            iterations,
+            density_error,
+            tolerance,
+            converged,
+            failures: self.solver_stats.failures + (converged == Some(false)) as u32,
+        };
+    }
+}
+
+/// Emits a SimEvent when the solver starts failing to converge.
+pub fn emit_solver_events(
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut events: MessageWriter<SimEvent>,
+    mut failed: Local<bool>,
+) {
+    let stats = sim.solver_stats;
+    let failing = stats.converged == Some(false);
+    if failing && !*failed {
+        events.write(SimEvent::SolverFailed {
+            frame: sim.debug.current_frame,
+            iterations: stats.iterations.unwrap_or_default(),
+            density_error: stats.density_error,
+        });
+    }
+    *failed = failing;
+}
+
+pub fn log_sim_events(mut events: MessageReader<SimEvent>, mut messages: Single<&mut Notifications>) {
+    for event in events.read() {
+        let text = match event {
+            SimEvent::SolverFailed {
+                frame,
+                iterations,
+                density_error,
+            } => {
+                let text = format!(
+                    "Frame {frame}: the solver failed to converge after {iterations} iteration(s), density error \
+                     {:.1}%",
+                    density_error * 100.0
+                );
+                warn!(target: "sim::pressure", "{text}");
+                text
+            }
+            SimEvent::Throttled {
+                frame,
+                substeps,
+                simulated_fraction,
+            } => {
+                let text = format!(
+                    "Frame {frame}: the particles are too fast for {substeps} substeps per frame, so the simulation \
+                     is slowed to {:.0}% of real time (see --cfl)",
+                    simulated_fraction * 100.0
+                );
+                warn!(target: "sim::time_step", "{text}");
+                text
+            }
+        };
+        messages.messages.push(MessageText {
+            text,
+            start_time: Instant::now(),
+            duration: Duration::from_secs(2),
+        });
+    }
+}
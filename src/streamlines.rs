@@ -0,0 +1,100 @@
+use bevy::color::palettes::css::{AQUA, WHITE};
+use bevy::prelude::*;
+
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// Spacing of the grid of tracer points that the streamlines start from, in pixels.
+const SEED_SPACING: f32 = 40.0;
+/// The most integration steps per streamline.
+const MAX_STEPS: usize = 16;
+/// Length of each integration step, as a fraction of the smoothing radius.
+const STEP_LENGTH: f32 = 0.5;
+/// The streamlines stop where the fluid is slower than this, as a fraction of the maximum velocity.
+const MIN_SPEED: f32 = 0.01;
+
+impl Simulation {
+    /// Traces the streamline through the interpolated velocity field from the seed point, in steps of equal length
+    /// (midpoint method), until it leaves the fluid or the tank, or the flow is too slow to have a direction.
+    pub fn streamline(&self, seed: Vec2) -> Vec<Vec2> {
+        let step_length = self.smoothing_radius * STEP_LENGTH;
+        let min_speed = self.max_velocity * MIN_SPEED;
+        let direction = |point: Vec2| {
+            let velocity = self.sample_velocity(point);
+            (velocity.length() > min_speed).then(|| velocity.normalize())
+        };
+
+        let mut points = vec![seed];
+        let mut point = seed;
+        for _ in 0..MAX_STEPS {
+            let Some(midpoint_direction) =
+                direction(point).and_then(|start| direction(point + start * step_length * 0.5))
+            else {
+                break;
+            };
+            point += midpoint_direction * step_length;
+            if point.abs().cmpgt(self.half_bounds_size).any() {
+                break;
+            }
+            points.push(point);
+        }
+        points
+    }
+}
+
+/// Draws streamlines from a grid of tracer points over the tank while they're turned on (F4), fading in along the
+/// direction of the flow, so that the vortices and jets stand out even when the particle colors are noisy.
+pub fn draw_streamlines(mut gizmos: Gizmos, sim: Single<&Simulation, With<MainSimulation>>) {
+    if !sim.debug.show_streamlines {
+        return;
+    }
+
+    let cols = (sim.half_bounds_size.x * 2.0 / SEED_SPACING).floor().max(1.0) as usize;
+    let rows = (sim.half_bounds_size.y * 2.0 / SEED_SPACING).floor().max(1.0) as usize;
+    let spacing = sim.half_bounds_size * 2.0 / Vec2::new(cols as f32, rows as f32);
+    for row in 0..rows {
+        for col in 0..cols {
+            let seed = -sim.half_bounds_size + (Vec2::new(col as f32, row as f32) + 0.5) * spacing;
+            let points = sim.streamline(seed);
+            if points.len() < 2 {
+                continue;
+            }
+            let last = (points.len() - 1) as f32;
+            gizmos.linestrip_gradient_2d(
+                points
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, point)| (point, Color::from(AQUA.mix(&WHITE, i as f32 / last)))),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn streamlines_follow_the_flow() {
+        let mut sim = test_sim(400, 400.0, 400.0, Args::default());
+        sim.update_regions();
+        let seed = sim.positions()[sim.num_particles / 2];
+
+        sim.velocities.fill(Vec2::X);
+        sim.max_velocity = 1.0;
+        let points = sim.streamline(seed);
+        assert!(points.len() > 2, "{points:?}");
+        assert!(
+            points
+                .windows(2)
+                .all(|pair| pair[1].x > pair[0].x && (pair[1].y - pair[0].y).abs() < 0.001)
+        );
+
+        // No flow, no direction.
+        sim.velocities.fill(Vec2::ZERO);
+        assert_eq!(sim.streamline(seed), vec![seed]);
+    }
+}
@@ -0,0 +1,140 @@
+use bevy::color::palettes::css::WHITE;
+use bevy::log::info_span;
+use bevy::prelude::*;
+
+use crate::color_field::ColorField;
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+
+/// A particle is at the free surface if the gradient of the color field (which is 1 inside the fluid and 0 outside)
+/// is steeper than this, times the smoothing radius...
+const SURFACE_GRADIENT: f32 = 1.0;
+/// ...or if it has fewer than this fraction of the average number of neighbors, as spray does.
+const SPARSE_NEIGHBORS: f32 = 0.5;
+
+impl Simulation {
+    /// Whether the surface needs classifying: for the surface tension, the outline, or the color field.
+    fn needs_surface(&self) -> bool {
+        self.surface_tension != 0.0 || self.debug.show_surface || self.debug.color_field == ColorField::Surface
+    }
+
+    /// The gradient of the color field at the particle: it points into the fluid, and is steep at the surface and
+    /// close to zero inside. The walls count as fluid, so that the particles along them aren't taken for surface.
+    pub fn color_gradient(&self, particle_id: usize) -> Vec2 {
+        let position = self.solver_position(particle_id);
+        let particles: Vec2 = self
+            .neighbor_particles(particle_id)
+            .filter(|&neighbor_id| self.densities[neighbor_id] > 0.0)
+            .map(|neighbor_id| {
+                let offset = self.solver_position(neighbor_id) - position;
+                let distance = offset.length();
+                if distance <= 0.0 || distance >= self.smoothing_radius {
+                    return Vec2::ZERO;
+                }
+                // smoothing_kernel_derivative() is the kernel's slope, negated and doubled, so this is the true
+                // gradient, which the wall term is too.
+                offset / distance * self.smoothing_kernel_derivative(distance) / 2.0 / self.densities[neighbor_id]
+            })
+            .sum();
+        particles + self.wall_fraction_gradient(position)
+    }
+
+    /// Classifies the particles at the free surface, once per step, before the solver moves them, so that the
+    /// surface tension and the renderer see the same surface. The classification is left empty when nothing needs it.
+    pub fn update_surface(&mut self) {
+        if !self.needs_surface() {
+            self.surface.clear();
+            return;
+        }
+        let _span = info_span!("update_surface").entered();
+
+        let neighbor_counts: Vec<usize> = self.par_map_by_cell(|i| self.neighbor_count(i));
+        let mean_neighbors = neighbor_counts.iter().sum::<usize>() as f32 / self.num_particles.max(1) as f32;
+        self.surface = self.par_map_by_cell(|i| {
+            (neighbor_counts[i] as f32) < mean_neighbors * SPARSE_NEIGHBORS
+                || self.color_gradient(i).length() * self.smoothing_radius > SURFACE_GRADIENT
+        });
+    }
+
+    /// Whether the particle was at the free surface at the start of the step.
+    pub fn is_surface(&self, particle_id: usize) -> bool {
+        self.surface.get(particle_id).copied().unwrap_or(false)
+    }
+
+    /// Surface tension, as cohesion that only acts at the free surface: each surface particle is pulled toward the
+    /// kernel-weighted center of its neighbors, which rounds off the surface's bumps and pulls drops together.
+    pub fn surface_tension_force(&self, particle_id: usize) -> Vec2 {
+        if self.surface_tension == 0.0 || !self.is_surface(particle_id) {
+            return Vec2::ZERO;
+        }
+
+        let position = self.positions[particle_id];
+        let mut center = Vec2::ZERO;
+        let mut total_weight = 0.0;
+        for neighbor_id in self.neighbors_within_radius(particle_id) {
+            let weight = self.smoothing_kernel(self.positions[neighbor_id].distance(position));
+            center += (self.positions[neighbor_id] - position) * weight;
+            total_weight += weight;
+        }
+        if total_weight <= 0.0 {
+            return Vec2::ZERO;
+        }
+        center / total_weight / self.smoothing_radius * self.surface_tension
+    }
+}
+
+/// Outlines the free surface while it's turned on (F12), with a ring around each surface particle.
+pub fn draw_surface(mut gizmos: Gizmos, sim: Single<&Simulation, With<MainSimulation>>) {
+    if !sim.debug.show_surface {
+        return;
+    }
+
+    let radius = sim.particle_size * sim.sprite_size * 0.75;
+    for (position, _) in sim.positions.iter().zip(&sim.surface).filter(|(_, surface)| **surface) {
+        gizmos.circle_2d(*position, radius, WHITE).resolution(12);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::args::Args;
+    use crate::color_field::ColorField;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn surface_particles_are_at_the_top_of_a_pool() {
+        let mut sim = test_sim(
+            900,
+            400.0,
+            400.0,
+            Args {
+                smoothing_radius: 4.0,
+                ..Args::default()
+            },
+        );
+        // Nothing needs the surface yet.
+        sim.step_n(1.0 / 60.0, 300);
+        assert!(sim.surface.is_empty());
+
+        sim.debug.color_field = ColorField::Surface;
+        sim.step_n(1.0 / 60.0, 1);
+        let top = sim.positions.iter().map(|position| position.y).fold(f32::MIN, f32::max);
+        let (surface, interior): (Vec<usize>, Vec<usize>) = (0..sim.num_particles).partition(|&i| sim.is_surface(i));
+        assert!(!surface.is_empty() && surface.len() < sim.num_particles / 2, "{} at the surface", surface.len());
+        // The highest particle is at the surface, and the particles deep in the pool, away from the walls, aren't.
+        let highest = (0..sim.num_particles).find(|&i| sim.positions[i].y == top).unwrap();
+        assert!(sim.is_surface(highest));
+        let half_size = sim.half_bounds_size;
+        assert!(
+            interior
+                .iter()
+                .any(|&i| sim.positions[i].y < top - sim.smoothing_radius * 2.0)
+        );
+        for i in 0..sim.num_particles {
+            let deep = sim.positions[i].y < top - sim.smoothing_radius * 2.0
+                && sim.positions[i].y > -half_size.y + sim.smoothing_radius
+                && sim.positions[i].x.abs() < half_size.x - sim.smoothing_radius;
+            assert!(!deep || !sim.is_surface(i), "particle {i} at {}", sim.positions[i]);
+        }
+    }
+}
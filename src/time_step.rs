@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::components::MainSimulation;
+use crate::sim_struct::Simulation;
+use crate::solver::SimEvent;
+
+/// The most substeps a frame is split into, however fast the particles are. Past this, the simulation falls behind
+/// real time instead.
+pub const MAX_SUBSTEPS: u32 = 16;
+
+/// Adaptive time stepping: each fixed step is split into as many substeps as the CFL condition needs, so that no
+/// particle moves more than a fraction (`--cfl`) of the smoothing radius in one substep, which is what lets the
+/// fastest particles tunnel through their neighbors and blow the fluid apart.
+pub struct TimeStep {
+    /// The most of the smoothing radius that a particle may move in a substep, or 0 for fixed substeps.
+    pub cfl: f32,
+    /// The longest substep, in seconds of frame time (before the speed multiplier).
+    pub max_dt: f32,
+    /// The number of substeps in the last frame.
+    pub substeps: u32,
+    /// The fraction of the last frame's time that was simulated: less than 1 when the CFL condition needed more than
+    /// MAX_SUBSTEPS, and the simulation was throttled.
+    pub simulated_fraction: f32,
+}
+
+impl TimeStep {
+    pub fn new(cfl: f32, max_dt: f32) -> Self {
+        TimeStep {
+            cfl,
+            max_dt,
+            substeps: 1,
+            simulated_fraction: 1.0,
+        }
+    }
+
+    pub fn throttled(&self) -> bool {
+        self.simulated_fraction < 1.0
+    }
+}
+
+impl Simulation {
+    /// The longest step, in seconds of frame time, that keeps the fastest particle within the CFL limit.
+    pub fn cfl_time_step(&self) -> f32 {
+        let max_speed = self
+            .velocities
+            .par_iter()
+            .map(|velocity| velocity.length())
+            .reduce(|| 0.0, f32::max);
+        // Positions advance by velocity * delta * speed.
+        let max_speed = max_speed * self.speed;
+        if self.time_step.cfl <= 0.0 || max_speed <= 0.0 {
+            return f32::INFINITY;
+        }
+        self.time_step.cfl * self.smoothing_radius / max_speed
+    }
+
+    /// Advances the simulation by a frame of `frame_delta` seconds, with `step` performing each substep: at least
+    /// `--substeps` of them, more if the max dt or the CFL condition call for it, up to MAX_SUBSTEPS. If even that
+    /// isn't enough, the substeps stay at the CFL limit and the rest of the frame is dropped, so the simulation runs
+    /// slower than real time rather than exploding.
+    pub fn step_frame(&mut self, frame_delta: f32, mut step: impl FnMut(&mut Simulation, f32)) {
+        let min_substeps = self.substeps.max(1);
+        let longest = self.cfl_time_step().min(self.time_step.max_dt);
+        // Less a little, so that rounding doesn't add a substep when the frame is exactly the max dt.
+        let needed = if longest > 0.0 { (frame_delta / longest - 0.001).ceil() as u32 } else { u32::MAX };
+        let needed = needed.max(min_substeps);
+        let substeps = needed.min(MAX_SUBSTEPS.max(min_substeps));
+
+        let delta = frame_delta / needed as f32;
+        for _ in 0..substeps {
+            step(self, delta);
+        }
+        self.time_step.substeps = substeps;
+        self.time_step.simulated_fraction = substeps as f32 / needed as f32;
+    }
+}
+
+/// Reports when the main simulation starts being throttled by the CFL condition, so that a slowdown doesn't look like
+/// a hang.
+pub fn emit_throttle_events(
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut events: MessageWriter<SimEvent>,
+    mut throttled: Local<bool>,
+) {
+    let time_step = &sim.time_step;
+    if time_step.throttled() && !*throttled {
+        events.write(SimEvent::Throttled {
+            frame: sim.debug.current_frame,
+            substeps: time_step.substeps,
+            simulated_fraction: time_step.simulated_fraction,
+        });
+    }
+    *throttled = time_step.throttled();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::MAX_SUBSTEPS;
+    use crate::args::Args;
+    use crate::sim_impl::test_sim;
+
+    #[test]
+    fn fast_particles_take_more_substeps() {
+        let mut sim = test_sim(100, 400.0, 400.0, Args::default());
+        let frame = 1.0 / 60.0;
+        let mut deltas = Vec::new();
+
+        // At rest, a frame is one step.
+        sim.step_frame(frame, |_, delta| deltas.push(delta));
+        assert_eq!(deltas, [frame]);
+        assert!(!sim.time_step.throttled());
+
+        // A particle that would cross a smoothing radius in a frame needs at least 1 / cfl substeps.
+        sim.velocities[0] = Vec2::X * sim.smoothing_radius / frame / sim.speed;
+        deltas.clear();
+        sim.step_frame(frame, |_, delta| deltas.push(delta));
+        let expected = (1.0 / sim.time_step.cfl).ceil() as usize;
+        assert_eq!(deltas.len(), expected);
+        assert!((deltas.iter().sum::<f32>() - frame).abs() < 1e-6);
+        assert!(!sim.time_step.throttled());
+
+        // Much faster than that, the simulation is throttled.
+        sim.velocities[0] *= 100.0;
+        deltas.clear();
+        sim.step_frame(frame, |_, delta| deltas.push(delta));
+        assert_eq!(deltas.len(), MAX_SUBSTEPS as usize);
+        assert!(sim.time_step.throttled());
+        assert!(deltas[0] * sim.velocities[0].length() * sim.speed <= sim.time_step.cfl * sim.smoothing_radius * 1.001);
+    }
+}
@@ -0,0 +1,108 @@
+use clap::ValueEnum;
+
+/// How the physical parameters (gravity, pressure, interaction radius, etc.) are interpreted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum UnitSystem {
+    /// Lengths are measured in particle sizes, so the behavior doesn't depend on the window size or particle count.
+    /// This is what the defaults are tuned for.
+    #[default]
+    Particle,
+    /// Lengths are measured in meters, time in seconds and mass in kilograms. The width of the window is
+    /// `--world-width` meters, so e.g. `--gravity 9.81` is real-world gravity. The parameters that aren't given keep
+    /// their defaults in particle sizes, converted to meters.
+    Physical,
+}
+
+/// The mapping between simulation units and screen space.
+///
+/// The simulation itself works in pixels: positions are pixels, velocities are pixels per second, and so on.
+/// Every length-based parameter from the command line is multiplied by `pixels_per_unit` on the way in, and divided
+/// by it on the way out (e.g. when displayed).
+///
+/// Densities are the sum of the smoothing kernel over the neighbors, i.e. particles per square pixel. To express them
+/// in kg/m², each particle is given a mass such that the target density corresponds to `rest_density`.
+#[derive(Clone, Copy, Debug)]
+pub struct Units {
+    pub system: UnitSystem,
+    pub pixels_per_unit: f32,
+    /// The size of a particle in pixels, which the parameters' defaults are measured in.
+    pub particle_size: f32,
+    /// Rest density of the fluid, in kg/m² (since the simulation is 2D).
+    pub rest_density: f32,
+}
+
+impl Units {
+    pub fn new(system: UnitSystem, particle_size: f32, window_width: f32, world_width: f32, rest_density: f32) -> Self {
+        let pixels_per_unit = match system {
+            UnitSystem::Particle => particle_size,
+            UnitSystem::Physical => window_width / world_width,
+        };
+
+        Units {
+            system,
+            pixels_per_unit,
+            particle_size,
+            rest_density,
+        }
+    }
+
+    /// Converts a value in units (or units/s, units/s², ...) to pixels.
+    pub fn to_pixels(self, value: f32) -> f32 {
+        value * self.pixels_per_unit
+    }
+
+    /// Converts a parameter to pixels: the value given in units, or else the default, which is in particle sizes
+    /// (or particle sizes/s, ...) in either unit system, so that the defaults behave the same in both.
+    pub fn to_pixels_or(self, value: Option<f32>, default: f32) -> f32 {
+        value.map_or(default * self.particle_size, |value| self.to_pixels(value))
+    }
+
+    /// Converts a value in pixels (or pixels/s, pixels/s², ...) to units.
+    pub fn to_units(self, value: f32) -> f32 {
+        value / self.pixels_per_unit
+    }
+
+    pub fn length_name(&self) -> &'static str {
+        match self.system {
+            UnitSystem::Particle => "particle sizes",
+            UnitSystem::Physical => "m",
+        }
+    }
+
+    /// The mass of each particle, in kg, such that a particle at the target density is at the rest density.
+    /// (With particle units, this treats one particle size as a meter.)
+    pub fn particle_mass(&self, target_density: f32) -> f32 {
+        let target_density_per_unit2 = target_density * self.pixels_per_unit * self.pixels_per_unit;
+        self.rest_density / target_density_per_unit2.max(f32::EPSILON)
+    }
+
+    /// Converts a simulation density (particles per square pixel) to kg/m².
+    pub fn density(&self, density: f32, target_density: f32) -> f32 {
+        self.rest_density * density / target_density.max(f32::EPSILON)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::args::Args;
+    use crate::sim_struct::Simulation;
+
+    #[test]
+    fn defaults_are_in_particle_sizes_in_either_unit_system() {
+        let sim = |args: &[&str]| {
+            let args = Args::parse_from([&["bevy-fluid-sim", "--num-particles", "100"], args].concat());
+            Simulation::new(400.0, 400.0, &args)
+        };
+        let particle = sim(&[]);
+        let physical = sim(&["--units", "physical"]);
+        assert_eq!(physical.interaction_input_radius, particle.interaction_input_radius);
+        assert_eq!(physical.gravity, particle.gravity);
+        assert_eq!(physical.wind.speed, particle.wind.speed);
+
+        // The values that are given are in meters: the window is 1 m wide.
+        let given = sim(&["--units", "physical", "--interaction-input-radius", "0.1"]);
+        assert_eq!(given.interaction_input_radius, 40.0);
+    }
+}
@@ -1,72 +1,230 @@
+use std::collections::HashSet;
+use std::f32::consts::TAU;
 use std::ops::{Deref, DerefMut};
 use std::sync::Mutex;
 
+use bevy::camera::{Camera, visibility::ViewVisibility};
 use bevy::color::Color;
-use bevy::color::palettes::basic::{GRAY, LIME, YELLOW};
-use bevy::math::{Vec2, Vec3, Vec3Swizzles};
-use bevy::prelude::{Commands, Entity, Gizmos, Query, Res, Single, Sprite, Text, Time, Transform};
+use bevy::color::palettes::basic::{BLUE, GRAY, LIME, NAVY, WHITE, YELLOW};
+use bevy::math::{Isometry2d, Rect, Rot2, Vec2, Vec3};
+use bevy::prelude::{
+    Commands, Entity, Fixed, Gizmos, GlobalTransform, Has, Local, Query, Res, Single, Sprite, Text, Time, Transform,
+    With, Without,
+};
 use once_cell::sync::Lazy;
 
 use crate::SpriteImage;
+use crate::color_field::ColorField;
+use crate::colormap::Colormap;
+use crate::compare::ComparisonCamera;
 use crate::components::*;
+use crate::convection::Convection;
+use crate::crash_dump::CrashDump;
+use crate::lod::Lod;
+use crate::rigid_body::Shape;
 use crate::sim_struct::Simulation;
+use crate::wind_tunnel::WindTunnel;
 
 // Some color definitions for blending.
-const COLD: Vec3 = Vec3::new(0.0, 0.0, 0.6);
-const HOT: Vec3 = Vec3::new(1.0, 0.2, 0.2);
+pub const COLD: Vec3 = Vec3::new(0.0, 0.0, 0.6);
+pub const HOT: Vec3 = Vec3::new(1.0, 0.2, 0.2);
 
-const STOPPED: Vec3 = Vec3::new(0.1, 0.1, 0.5);
-const FAST: Vec3 = Vec3::new(0.9, 1.0, 0.0);
+pub const STOPPED: Vec3 = Vec3::new(0.1, 0.1, 0.5);
+pub const FAST: Vec3 = Vec3::new(0.9, 1.0, 0.0);
+
+// The color of the watched particles' neighbors.
+const NEIGHBOR: Vec3 = Vec3::new(0.0, 0.8, 0.8);
+
+// Bounds on the sprite scale for density-scaled sprites.
+const MIN_SPRITE_SCALE: f32 = 0.8;
+const MAX_SPRITE_SCALE: f32 = 1.4;
+// The most velocity arrows to draw. With more particles than this, the arrows are thinned out to one per cell of a
+// grid over the tank, so that the dense parts of the fluid don't get more than their share.
+const MAX_ARROWS: usize = 2000;
 
 static TOT_FPS: Lazy<Mutex<f32>> = Lazy::new(|| Mutex::new(0.0));
 
-/// Performs one step of the simulation, and draws the particles.
+/// Performs one step of each simulation, in FixedUpdate, split into substeps (see step_frame()).
+pub fn step_simulation(mut sims: Query<&mut Simulation>, time: Res<Time<Fixed>>, mut crash_dump: Local<CrashDump>) {
+    // The step is fixed (see the Time<Fixed> resource in main) rather than following the framerate, to avoid the
+    // chaos that can arise from sudden framerate pauses.
+    for mut sim in &mut sims {
+        let Simulation {
+            positions,
+            previous_positions,
+            ..
+        } = &mut *sim;
+        previous_positions.clone_from(positions);
+        sim.step_frame(time.timestep().as_secs_f32(), |sim, delta| crash_dump.update_or_dump(sim, delta));
+        sim.end_frame();
+    }
+}
+
+/// Draws the particles, interpolated between the last two simulation steps so that the motion is smooth even when
+/// the framerate doesn't match the simulation rate.
 /// Chooses a color based on the particle's density or velocity, depending on the settings.
+/// Particles that are off-screen, and were already off-screen in the previous frame, aren't updated at all.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)] // ECS gonna ECS.
 pub fn update_particles(
-    mut commands: Commands,
-    mut particle_query: Query<(Entity, &mut Transform, &mut Particle)>,
-    // time: Res<Time>,
-    mut sim: Single<&mut Simulation>,
+    mut particle_query: Query<(&mut Transform, &mut Sprite, &Particle, &ViewVisibility)>,
+    time: Res<Time<Fixed>>,
+    mut sims: Query<(Entity, &mut Simulation, Option<&TankOffset>, Has<MainSimulation>)>,
     sprite_image: Single<&SpriteImage>,
+    cameras_query: Query<(&Camera, &GlobalTransform), Without<ComparisonCamera>>,
+    lod: Res<Lod>,
 ) {
-    // I'm using a fixed delta of 1/60th of a second rather than relying on time.delta_secs()), to avoid the
-    // chaos that can arise from sudden framerate pauses.
-    sim.update_particles(1.0 / 60.0); // time.delta_secs());
-
-    let custom_size = Some(Vec2::splat(sim.particle_size * sim.sprite_size));
-
-    particle_query.iter_mut().for_each(|(entity, mut transform, particle)| {
-        transform.translation.x = sim.positions[particle.id].x;
-        transform.translation.y = sim.positions[particle.id].y;
-
-        let color = if sim.debug.show_arrows {
-            Color::linear_rgba(0.0, 0.0, 0.0, 0.)
-        } else if particle.watched {
-            Color::linear_rgb(1.0, 1.0, 0.0)
-        } else if sim.debug.density_heatmap {
-            let density_ratio = (sim.densities[particle.id] - sim.min_density) / (sim.max_density - sim.min_density);
-            let density_scale = density_ratio.powf(2.0);
-            let rgb = COLD + density_scale * (HOT - COLD);
-            Color::linear_rgb(rgb.x, rgb.y, rgb.z)
-        } else {
-            let speed_ratio = sim.velocities[particle.id].length() / sim.max_velocity;
-            let speed_scale = speed_ratio.powf(1.0 / 4.0);
-            let rgb = STOPPED + speed_scale * (FAST - STOPPED);
-            Color::linear_rgb(rgb.x, rgb.y, rgb.z)
-        };
+    let alpha = time.overstep_fraction();
 
-        commands.entity(entity).insert(Sprite {
-            image: sprite_image.handle.clone(),
-            custom_size,
-            color,
-            ..Default::default()
-        });
+    // The visible part of the world, as seen by the main camera.
+    let visible_rect = cameras_query.iter().next().and_then(|(camera, camera_transform)| {
+        let viewport_size = camera.logical_viewport_size()?;
+        let corner_a = camera.viewport_to_world_2d(camera_transform, Vec2::ZERO).ok()?;
+        let corner_b = camera.viewport_to_world_2d(camera_transform, viewport_size).ok()?;
+        Some(Rect::from_corners(corner_a, corner_b))
     });
 
-    sim.end_frame();
+    for (sim_entity, mut sim, offset, is_main) in &mut sims {
+        // When the particles are being drawn as LOD splats, there's no point in updating them.
+        if is_main && lod.active {
+            continue;
+        }
+        let offset = offset.map_or(Vec2::ZERO, |offset| offset.0);
+        // The tanks drawn elsewhere are shown by their own cameras, which follow the main one (see compare.rs).
+        // Expanded by a particle so that the ones straddling the edges aren't culled.
+        let visible_rect = visible_rect
+            .map(|rect| Rect::from_center_size(rect.center() + offset, rect.size()))
+            .map(|rect| rect.inflate(sim.particle_size * sim.sprite_size))
+            .filter(|_| sim.debug.cull_offscreen);
+        let mut offscreen_particles = 0;
+
+        particle_query
+            .iter_mut()
+            .filter(|(_, _, particle, _)| particle.sim == sim_entity)
+            .for_each(|(mut transform, mut sprite, particle, view_visibility)| {
+                // The particle count has just been reduced, and this entity hasn't been despawned yet.
+                if particle.id >= sim.num_particles {
+                    return;
+                }
+
+                // Sprites that were visible last frame still need to be moved off-screen.
+                let position = sim.render_position(particle.id, alpha) + offset;
+                if let Some(visible_rect) = visible_rect
+                    && !visible_rect.contains(position)
+                    && !view_visibility.get()
+                {
+                    offscreen_particles += 1;
+                    return;
+                }
+
+                transform.translation.x = position.x;
+                transform.translation.y = position.y;
+
+                let color = particle_color(&sim, particle.id, particle.watched);
+                let custom_size = Some(particle_sprite_size(&sim, particle.id));
+
+                // Update the existing sprite rather than inserting a new one, which would queue a command for every
+                // particle. The image and size rarely change, so only touch them (and the sprite's change tick) if
+                // they do.
+                if sprite.image != sprite_image.handle {
+                    sprite.image = sprite_image.handle.clone();
+                }
+                if sprite.custom_size != custom_size {
+                    sprite.custom_size = custom_size;
+                }
+                sprite.color = color;
+            });
+
+        sim.debug.offscreen_particles = offscreen_particles;
+    }
+}
+
+/// The color of a particle, based on its dye, its temperature or the color field, depending on the settings.
+pub fn particle_color(sim: &Simulation, particle_id: usize, watched: bool) -> Color {
+    if sim.debug.show_arrows {
+        Color::linear_rgba(0.0, 0.0, 0.0, 0.)
+    } else if watched {
+        Color::linear_rgb(1.0, 1.0, 0.0)
+    } else if sim.debug.watched_neighbors.binary_search(&particle_id).is_ok() {
+        Color::linear_rgb(NEIGHBOR.x, NEIGHBOR.y, NEIGHBOR.z)
+    } else if let Some(dye) = &sim.dye {
+        let rgb = dye.colors[particle_id];
+        Color::linear_rgb(rgb.x, rgb.y, rgb.z)
+    } else if let Some(convection) = &sim.convection {
+        let rgb = sim
+            .debug
+            .colormap
+            .color((convection.temperatures[particle_id] + 1.0) / 2.0, COLD, HOT);
+        Color::linear_rgb(rgb.x, rgb.y, rgb.z)
+    } else if let Some(&value) = sim.debug.color_values.get(particle_id) {
+        let rgb = sim.debug.colormap.color(value, COLD, HOT);
+        Color::linear_rgb(rgb.x, rgb.y, rgb.z)
+    } else if sim.debug.color_field == ColorField::Density {
+        let density_ratio = (sim.densities[particle_id] - sim.min_density) / (sim.max_density - sim.min_density);
+        let density_scale = density_ratio.powf(2.0);
+        let rgb = sim.debug.colormap.color(density_scale, COLD, HOT);
+        Color::linear_rgb(rgb.x, rgb.y, rgb.z)
+    } else {
+        let speed_ratio = sim.velocities[particle_id].length() / sim.max_velocity;
+        let speed_scale = speed_ratio.powf(1.0 / 4.0);
+        let rgb = match sim.debug.colormap {
+            Colormap::Classic => sim.fluid_type(particle_id).color(speed_scale),
+            colormap => colormap.color(speed_scale, STOPPED, FAST),
+        };
+        Color::linear_rgb(rgb.x, rgb.y, rgb.z)
+    }
+}
+
+/// The size of a particle's sprite, which is scaled by its density if density-scaled sprites are turned on.
+pub fn particle_sprite_size(sim: &Simulation, particle_id: usize) -> Vec2 {
+    let size = Vec2::splat(sim.particle_size * sim.sprite_size);
+    if sim.debug.density_scaled_sprites && sim.densities[particle_id] > 0.0 {
+        // A particle's share of the area is mass / density, so its diameter goes as the square root.
+        let scale = (sim.target_density / sim.densities[particle_id]).sqrt();
+        size * scale.clamp(MIN_SPRITE_SCALE, MAX_SPRITE_SCALE)
+    } else {
+        size
+    }
+}
+
+/// Spawns or despawns particle entities when the number of particles in a simulation changes.
+pub fn sync_particle_entities(
+    mut commands: Commands,
+    sims: Query<(Entity, &Simulation)>,
+    particle_query: Query<(Entity, &Particle)>,
+) {
+    for (sim_entity, sim) in &sims {
+        // The particle mesh draws all the particles without any entities.
+        let num_particles = if sim.debug.particle_mesh { 0 } else { sim.num_particles };
+        let particles = || particle_query.iter().filter(|(_, particle)| particle.sim == sim_entity);
+        let num_entities = particles().count();
+        if num_entities == num_particles {
+            continue;
+        }
+
+        for (entity, particle) in particles() {
+            if particle.id >= num_particles {
+                commands.entity(entity).despawn();
+            }
+        }
+        for id in num_entities..num_particles {
+            commands.spawn((
+                Sprite::default(),
+                Particle {
+                    sim: sim_entity,
+                    id,
+                    watched: false,
+                },
+            ));
+        }
+    }
 }
 
-pub fn update_fps(mut query: Query<(&mut Text, &FpsText)>, time: Res<Time>, sim: Single<&Simulation>) {
+pub fn update_fps(
+    mut query: Query<(&mut Text, &FpsText)>,
+    time: Res<Time>,
+    sim: Single<&Simulation, With<MainSimulation>>,
+    mut frames: Local<u32>,
+) {
     for (mut span, _) in &mut query {
         if time.delta_secs() == 0.0 {
             return;
@@ -76,31 +234,159 @@ pub fn update_fps(mut query: Query<(&mut Text, &FpsText)>, time: Res<Time>, sim:
         let mut tot_fps = TOT_FPS.lock().unwrap();
 
         *tot_fps.deref_mut() += cur_fps;
+        *frames += 1;
         if sim.debug.show_fps {
-            **span = format!("FPS: {:5.1} / avg {:.1}", cur_fps, tot_fps.deref() / (sim.debug.current_frame as f32));
+            let solver = sim.solver_stats;
+            // Only the solvers that iterate have iterations, and only the ones with a tolerance can fail.
+            let iterations = solver
+                .iterations
+                .map_or(String::new(), |iterations| format!("{iterations} iteration(s), "));
+            let tolerance = solver.tolerance.map_or(String::new(), |tolerance| {
+                format!(" (tolerance {:.1}%), {} failure(s)", tolerance * 100.0, solver.failures)
+            });
+            **span = format!(
+                "FPS: {:5.1} / avg {:.1}\nSolver: {iterations}density error {:.1}%{tolerance}",
+                cur_fps,
+                tot_fps.deref() / (*frames as f32),
+                solver.density_error * 100.0,
+            );
         } else if !span.is_empty() {
             span.clear();
         }
     }
 }
 
-pub fn draw_debug_info(
+/// Finds the neighbors of the watched particles, for particle_color() to highlight, and draws lines to them, to check
+/// that the neighbor search returns the particles within the smoothing radius.
+pub fn highlight_watched_neighbors(
     mut gizmos: Gizmos,
-    sim: Single<&Simulation>,
-    particle_query: Query<(&mut Transform, &mut Particle)>,
+    mut sim: Single<&mut Simulation, With<MainSimulation>>,
+    particle_query: Query<&Particle>,
 ) {
+    let mut neighbors = Vec::new();
+    for particle in particle_query.iter().filter(|particle| particle.watched) {
+        if particle.id >= sim.num_particles {
+            continue;
+        }
+        let position = sim.positions[particle.id];
+        for neighbor_id in sim.neighbors_within_radius(particle.id) {
+            if neighbor_id != particle.id {
+                gizmos.line_2d(
+                    position,
+                    sim.positions[neighbor_id],
+                    Color::linear_rgb(NEIGHBOR.x, NEIGHBOR.y, NEIGHBOR.z),
+                );
+                neighbors.push(neighbor_id);
+            }
+        }
+    }
+    neighbors.sort_unstable();
+    neighbors.dedup();
+    sim.debug.watched_neighbors = neighbors;
+}
+
+pub fn draw_debug_info(mut gizmos: Gizmos, sim: Single<&Simulation, With<MainSimulation>>) {
     if sim.debug.show_arrows {
-        particle_query.iter().for_each(|(transform, particle)| {
-            let arrow_end = transform.translation.xy() + sim.velocities[particle.id] * 1. / 60. * sim.speed;
+        let cell_size = if sim.num_particles > MAX_ARROWS {
+            (sim.half_bounds_size.x * sim.half_bounds_size.y * 4.0 / MAX_ARROWS as f32).sqrt()
+        } else {
+            0.0
+        };
+        let mut cells = HashSet::new();
+        for (position, velocity) in sim.positions().iter().zip(&sim.velocities) {
+            if cell_size > 0.0 && !cells.insert((*position / cell_size).floor().as_ivec2()) {
+                continue;
+            }
+            let arrow_end = *position + *velocity * 1. / 60. * sim.speed;
             gizmos
-                .arrow(transform.translation.xy().extend(0.0), arrow_end.extend(0.0), YELLOW)
+                .arrow_2d(*position, arrow_end, YELLOW)
                 .with_tip_length(sim.particle_size);
-        });
+        }
+    }
+    if let Some(heightfield) = &sim.heightfield {
+        // Fill each column of the heightfield, and outline the surface.
+        let bottom = -sim.half_bounds_size.y;
+        for (column, height) in heightfield.heights.iter().enumerate() {
+            let x = heightfield.left + (column as f32 + 0.5) * heightfield.column_width;
+            gizmos.rect_2d(Vec2::new(x, bottom + height / 2.0), Vec2::new(heightfield.column_width, *height), NAVY);
+        }
+        gizmos.linestrip_2d(
+            (0..heightfield.heights.len()).map(|column| {
+                let x = heightfield.left + (column as f32 + 0.5) * heightfield.column_width;
+                Vec2::new(x, sim.floor(x))
+            }),
+            BLUE,
+        );
+    }
+    for obstacle in sim.scenario.iter().flat_map(|scenario| &scenario.obstacles) {
+        let (center, radius) = obstacle.circle_at(sim.half_bounds_size, sim.sim_time);
+        gizmos.circle_2d(center, radius, WHITE);
+        if obstacle.spin != 0.0 {
+            // A spoke, to show it turning.
+            let spoke = Vec2::from_angle(TAU * obstacle.spin * sim.sim_time) * radius;
+            gizmos.line_2d(center, center + spoke, WHITE);
+        }
+    }
+    for body in &sim.bodies {
+        let isometry = Isometry2d::new(body.position, Rot2::radians(body.angle));
+        match body.shape {
+            Shape::Circle { radius } => {
+                gizmos.circle_2d(isometry, radius, WHITE);
+                gizmos.line_2d(body.position, body.position + Vec2::from_angle(body.angle) * radius, WHITE);
+            }
+            Shape::Box { width, height } => gizmos.rect_2d(isometry, Vec2::new(width, height) * 2.0, WHITE),
+        }
+    }
+    if let Some(paddle) = &sim.paddle {
+        let x = -sim.half_bounds_size.x + paddle.offset(sim.sim_time);
+        gizmos.line_2d(Vec2::new(x, -sim.half_bounds_size.y), Vec2::new(x, sim.half_bounds_size.y), WHITE);
+    }
+    if let Some(wind_tunnel) = &sim.wind_tunnel {
+        gizmos.circle_2d(wind_tunnel.obstacle_center, wind_tunnel.obstacle_radius, WHITE);
+
+        // Plot the drag over time in the top right corner.
+        let size = sim.half_bounds_size * Vec2::new(0.6, 0.3);
+        let origin = sim.half_bounds_size - size - sim.particle_size;
+        let max_drag = wind_tunnel
+            .drag_history
+            .iter()
+            .map(|drag| drag.abs())
+            .fold(f32::EPSILON, f32::max);
+        gizmos.rect_2d(origin + size / 2.0, size, GRAY);
+        gizmos.line_2d(origin + Vec2::new(0.0, size.y / 2.0), origin + Vec2::new(size.x, size.y / 2.0), GRAY);
+        gizmos.linestrip_2d(
+            wind_tunnel.drag_history.iter().enumerate().map(|(i, drag)| {
+                origin
+                    + Vec2::new(
+                        i as f32 / WindTunnel::HISTORY_LEN as f32 * size.x,
+                        (0.5 + drag / max_drag / 2.0) * size.y,
+                    )
+            }),
+            YELLOW,
+        );
+    }
+    if let Some(pinch_anchor) = sim.pinch_anchor {
+        gizmos.circle_2d(pinch_anchor, sim.interaction_input_radius, LIME);
+    }
+    if sim.convection.is_some() {
+        for (center, radius, temperature) in Convection::sources(sim.half_bounds_size) {
+            let color = if temperature > 0.0 { HOT } else { COLD };
+            gizmos.circle_2d(center, radius, Color::linear_rgb(color.x, color.y, color.z));
+        }
+    }
+    if sim.debug.show_wall_impulses {
+        // Color each wall segment by the impulse the fluid has applied to it recently.
+        let max_impulse = sim.wall_impulses.max().max(f32::EPSILON);
+        for (start, end, impulse) in sim.wall_impulses.segments(sim.half_bounds_size) {
+            let color = COLD.lerp(HOT, impulse / max_impulse);
+            gizmos.line_2d(start, end, Color::linear_rgb(color.x, color.y, color.z));
+        }
     }
-    if sim.debug.show_smoothing_radius {
+    // In local grid mode, these are drawn by draw_local_region_grid instead.
+    if sim.debug.show_smoothing_radius && !sim.debug.local_region_grid {
         gizmos.circle_2d(sim.positions[0], sim.smoothing_radius, LIME);
     }
-    if sim.debug.show_region_grid {
+    if sim.debug.show_region_grid && !sim.debug.local_region_grid {
         let bottom = -sim.half_bounds_size.y;
         let left = -sim.half_bounds_size.x;
         for row in 0..sim.region_rows {
@@ -0,0 +1,88 @@
+use bevy::math::Vec2;
+
+/// The impulse that the particles have applied to each segment of the container walls, averaged over a short window.
+/// Used to visualize where the fluid is hammering the container.
+///
+/// Impulses are measured as the change in velocity caused by each collision, i.e. per unit of particle mass.
+pub struct WallImpulses {
+    /// Impulse per segment for the left, right, bottom and top walls. The segments run from left to right, or bottom
+    /// to top.
+    pub walls: [Vec<f32>; 4],
+}
+
+impl WallImpulses {
+    /// Time constant of the exponential moving average, in seconds.
+    const WINDOW: f32 = 0.25;
+
+    pub fn new(half_bounds_size: Vec2, segment_length: f32) -> Self {
+        let segments = |half_length: f32| {
+            // The smoothing radius can be zero before it's been set.
+            let count = if segment_length > 0.0 { (half_length * 2.0 / segment_length) as usize } else { 1 };
+            vec![0.0; count.max(1)]
+        };
+
+        WallImpulses {
+            walls: [
+                segments(half_bounds_size.y),
+                segments(half_bounds_size.y),
+                segments(half_bounds_size.x),
+                segments(half_bounds_size.x),
+            ],
+        }
+    }
+
+    /// Records a collision at `position`, which must be on (or outside) one of the walls.
+    pub fn add(&mut self, position: Vec2, half_bounds_size: Vec2, impulse: f32) {
+        // How far along the wall the collision was, from 0 to 1.
+        let (wall, fraction) = if position.x <= -half_bounds_size.x {
+            (0, position.y / half_bounds_size.y * 0.5 + 0.5)
+        } else if position.x >= half_bounds_size.x {
+            (1, position.y / half_bounds_size.y * 0.5 + 0.5)
+        } else if position.y <= -half_bounds_size.y {
+            (2, position.x / half_bounds_size.x * 0.5 + 0.5)
+        } else if position.y >= half_bounds_size.y {
+            (3, position.x / half_bounds_size.x * 0.5 + 0.5)
+        } else {
+            return;
+        };
+
+        let segments = &mut self.walls[wall];
+        let segment = ((fraction.max(0.0) * segments.len() as f32) as usize).min(segments.len() - 1);
+        segments[segment] += impulse / Self::WINDOW;
+    }
+
+    /// Fades out the older impulses, so that the values are a moving average over the last `WINDOW` seconds.
+    pub fn decay(&mut self, delta: f32) {
+        let retained = (-delta / Self::WINDOW).exp();
+        self.walls.iter_mut().flatten().for_each(|impulse| *impulse *= retained);
+    }
+
+    pub fn max(&self) -> f32 {
+        self.walls.iter().flatten().copied().fold(0.0, f32::max)
+    }
+
+    /// The start and end points of each segment, along with its impulse.
+    pub fn segments(&self, half_bounds_size: Vec2) -> impl Iterator<Item = (Vec2, Vec2, f32)> + '_ {
+        let corners = [
+            (Vec2::new(-half_bounds_size.x, -half_bounds_size.y), Vec2::Y),
+            (Vec2::new(half_bounds_size.x, -half_bounds_size.y), Vec2::Y),
+            (Vec2::new(-half_bounds_size.x, -half_bounds_size.y), Vec2::X),
+            (Vec2::new(-half_bounds_size.x, half_bounds_size.y), Vec2::X),
+        ];
+
+        self.walls
+            .iter()
+            .zip(corners)
+            .flat_map(move |(segments, (start, direction))| {
+                let wall_length = if direction == Vec2::X { half_bounds_size.x } else { half_bounds_size.y } * 2.0;
+                let segment_length = wall_length / segments.len() as f32;
+                segments.iter().enumerate().map(move |(i, &impulse)| {
+                    (
+                        start + direction * i as f32 * segment_length,
+                        start + direction * (i + 1) as f32 * segment_length,
+                        impulse,
+                    )
+                })
+            })
+    }
+}
@@ -0,0 +1,62 @@
+use bevy::math::Vec2;
+
+use crate::sim_struct::Simulation;
+
+/// The sides of the tank, in the same order as WallImpulses and `--wall-restitution`.
+pub const LEFT: usize = 0;
+pub const RIGHT: usize = 1;
+pub const BOTTOM: usize = 2;
+pub const TOP: usize = 3;
+
+/// Parses `--wall-restitution`: either one value for all four sides of the tank, or four values for the left, right,
+/// bottom and top walls.
+pub fn parse_restitution(arg: &str) -> Result<[f32; 4], String> {
+    let values = arg
+        .split(',')
+        .map(|value| value.trim().parse::<f32>().map_err(|e| format!("{value}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [all] => Ok([all; 4]),
+        [left, right, bottom, top] => Ok([left, right, bottom, top]),
+        _ => Err(format!("Expected 1 or 4 values, got {}", values.len())),
+    }
+}
+
+/// Coulomb friction: slows down the particle along a wall it has bounced off, by `friction` times the change in its
+/// velocity across the wall, but never so much that it's turned around.
+/// `normal` is the wall's normal, and `velocity_change` is the change caused by the bounce.
+pub fn apply_friction(velocity: Vec2, normal: Vec2, velocity_change: Vec2, friction: f32) -> Vec2 {
+    if friction <= 0.0 {
+        return velocity;
+    }
+
+    let tangential = velocity - normal * velocity.dot(normal);
+    let speed = tangential.length();
+    if speed == 0.0 {
+        return velocity;
+    }
+    let slowdown = (friction * velocity_change.dot(normal).abs()).min(speed);
+    velocity - tangential / speed * slowdown
+}
+
+impl Simulation {
+    /// The restitution of one side of the tank: `--wall-restitution` (or the scenario's), or else the collision
+    /// damping, which also applies to the container and the obstacles.
+    pub fn wall_restitution(&self, side: usize) -> f32 {
+        self.wall_restitution
+            .map_or(self.collision_damping, |restitution| restitution[side])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn friction_slows_but_never_reverses_the_tangential_velocity() {
+        let bounce = Vec2::new(0.0, 2.0);
+        assert_eq!(apply_friction(Vec2::new(3.0, 1.0), Vec2::Y, bounce, 0.0), Vec2::new(3.0, 1.0));
+        assert_eq!(apply_friction(Vec2::new(3.0, 1.0), Vec2::Y, bounce, 0.5), Vec2::new(2.0, 1.0));
+        assert_eq!(apply_friction(Vec2::new(-3.0, 1.0), Vec2::Y, bounce, 10.0), Vec2::new(0.0, 1.0));
+    }
+}
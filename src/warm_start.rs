@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::math::Vec2;
+use bevy::prelude::Resource;
+
+use crate::args::Args;
+use crate::sim_struct::Simulation;
+
+/// The particle positions and velocities of a settled tank, saved by `--settle` and loaded by `--warm-start`, so that
+/// the simulation can start from calm water instead of the initial collapse.
+///
+/// The file is little-endian: a magic number, the number of particles, the half bounds size, and then the position
+/// and velocity of each particle, all as u32 or f32.
+#[derive(Resource, Debug, Default, PartialEq)]
+pub struct SettledState {
+    pub half_bounds_size: Vec2,
+    pub positions: Vec<Vec2>,
+    pub velocities: Vec<Vec2>,
+}
+
+impl SettledState {
+    const MAGIC: &'static [u8; 4] = b"BFS1";
+
+    pub fn from_sim(sim: &Simulation) -> Self {
+        SettledState {
+            half_bounds_size: sim.half_bounds_size,
+            positions: sim.positions().to_vec(),
+            velocities: sim.velocities().to_vec(),
+        }
+    }
+
+    /// Overwrites this state with the simulation's, reusing the buffers.
+    pub fn copy_from(&mut self, sim: &Simulation) {
+        self.half_bounds_size = sim.half_bounds_size;
+        self.positions.clear();
+        self.positions.extend_from_slice(sim.positions());
+        self.velocities.clear();
+        self.velocities.extend_from_slice(sim.velocities());
+    }
+
+    /// Copies the particles into the simulation. If the window is a different size than when the state was saved,
+    /// the positions are scaled to fit.
+    pub fn apply(&self, sim: &mut Simulation) {
+        let scale = sim.half_bounds_size / self.half_bounds_size;
+        sim.set_num_particles(self.positions.len());
+        for (i, (&position, &velocity)) in self.positions.iter().zip(&self.velocities).enumerate() {
+            sim.positions[i] = position * scale;
+            sim.predicted_positions[i] = sim.positions[i];
+            sim.velocities[i] = velocity;
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(16 + self.positions.len() * 16);
+        bytes.extend_from_slice(Self::MAGIC);
+        bytes.extend_from_slice(&(self.positions.len() as u32).to_le_bytes());
+        let vectors = std::iter::once(&self.half_bounds_size).chain(
+            self.positions
+                .iter()
+                .zip(&self.velocities)
+                .flat_map(|(position, velocity)| [position, velocity]),
+        );
+        for vector in vectors {
+            bytes.extend_from_slice(&vector.x.to_le_bytes());
+            bytes.extend_from_slice(&vector.y.to_le_bytes());
+        }
+
+        fs::write(path, bytes)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {message}", path.display()));
+        if bytes.len() < 8 || &bytes[..4] != Self::MAGIC {
+            return Err(invalid("not a settled state file"));
+        }
+
+        let word = |index: usize| u32::from_le_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap());
+        let num_particles = word(1) as usize;
+        if bytes.len() != 16 + num_particles * 16 {
+            return Err(invalid("wrong length"));
+        }
+        let vector = |index: usize| Vec2::new(f32::from_bits(word(index)), f32::from_bits(word(index + 1)));
+
+        Ok(SettledState {
+            half_bounds_size: vector(2),
+            positions: (0..num_particles).map(|i| vector(4 + i * 4)).collect(),
+            velocities: (0..num_particles).map(|i| vector(6 + i * 4)).collect(),
+        })
+    }
+}
+
+/// Runs the simulation headlessly for the given number of frames, so that the fluid can settle.
+pub fn settle(args: &Args, window_width: f32, window_height: f32, frames: u32) -> SettledState {
+    let mut sim = Simulation::new(window_width, window_height, args);
+    sim.place_particles();
+
+    const FRAMES_PER_LOG: u32 = 600;
+    for frame in (0..frames).step_by(FRAMES_PER_LOG as usize) {
+        println!("Settling: frame {frame} of {frames}");
+        sim.step_n(1.0 / 60.0, FRAMES_PER_LOG.min(frames - frame) as usize);
+    }
+
+    SettledState::from_sim(&sim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let state = SettledState {
+            half_bounds_size: Vec2::new(400.0, 450.0),
+            positions: (0..100).map(|i| Vec2::new(i as f32, -(i as f32) / 3.0)).collect(),
+            velocities: (0..100)
+                .map(|i| Vec2::new((i as f32).sin(), 1.0 / (i as f32 + 1.0)))
+                .collect(),
+        };
+        let path = std::env::temp_dir().join(format!("bevy-fluid-sim-settled-{}.bin", std::process::id()));
+
+        state.save(&path).unwrap();
+        let loaded = SettledState::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), state);
+    }
+}
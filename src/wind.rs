@@ -0,0 +1,182 @@
+use std::f32::consts::TAU;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bevy::math::Vec2;
+
+use crate::sim_struct::Simulation;
+
+/// How long it takes a gust to pass, in seconds of simulation time.
+const GUST_PERIOD: f32 = 4.0;
+
+/// The shape of the background flow (`--wind`). The flow velocities are relative to the wind speed, so that the
+/// keyboard can make any of them stronger or weaker.
+#[derive(Clone)]
+pub enum FlowField {
+    /// The same flow everywhere: the wind speed, to the right.
+    Uniform,
+    /// Gusts that sweep across the tank from the left, with the wind rising from nothing to twice the wind speed and
+    /// dying down again.
+    Gusts,
+    /// Flow vectors on a grid spanning the tank, loaded from a file.
+    Grid(FlowGrid),
+    /// Any flow, as a function of the position (in pixels, from the middle of the window) and the simulation time.
+    Custom(Arc<dyn Fn(Vec2, f32) -> Vec2 + Send + Sync>),
+}
+
+/// The custom flow is a closure, which can't be printed.
+impl fmt::Debug for FlowField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlowField::Uniform => write!(f, "Uniform"),
+            FlowField::Gusts => write!(f, "Gusts"),
+            FlowField::Grid(grid) => f.debug_tuple("Grid").field(grid).finish(),
+            FlowField::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl FromStr for FlowField {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        match spec.to_lowercase().as_str() {
+            "uniform" => Ok(FlowField::Uniform),
+            "gusts" => Ok(FlowField::Gusts),
+            _ => {
+                let text = fs::read_to_string(spec).map_err(|e| format!("{spec}: {e}"))?;
+                FlowGrid::parse(&text)
+                    .map(FlowField::Grid)
+                    .map_err(|e| format!("{spec}: {e}"))
+            }
+        }
+    }
+}
+
+/// A grid of flow vectors. The first row is the top of the tank, and the corners of the grid are the corners of the
+/// tank. In between, the flow is interpolated.
+#[derive(Clone, Debug)]
+pub struct FlowGrid {
+    columns: usize,
+    rows: usize,
+    vectors: Vec<Vec2>,
+}
+
+impl FlowGrid {
+    /// Parses a flow file: one line per row, each with the same number of `x,y` vectors separated by whitespace.
+    /// Blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let rows = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.split_whitespace().map(parse_vector).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let columns = rows.first().map_or(0, Vec::len);
+        if rows.len() < 2 || columns < 2 {
+            return Err("Expected at least 2 rows of at least 2 vectors".into());
+        }
+        if let Some(row) = rows.iter().position(|row| row.len() != columns) {
+            return Err(format!("Row {} has {} vectors, expected {columns}", row + 1, rows[row].len()));
+        }
+
+        Ok(FlowGrid {
+            columns,
+            rows: rows.len(),
+            vectors: rows.concat(),
+        })
+    }
+
+    /// The flow at a position given as fractions of the tank's size, from 0,0 at the bottom left to 1,1 at the top
+    /// right.
+    fn sample(&self, fraction: Vec2) -> Vec2 {
+        let max = Vec2::new((self.columns - 1) as f32, (self.rows - 1) as f32);
+        // Rows run from the top down.
+        let position = Vec2::new(fraction.x, 1.0 - fraction.y).clamp(Vec2::ZERO, Vec2::ONE) * max;
+        let corner = position.floor().min(max - 1.0);
+        let weight = position - corner;
+        let vector = |column: f32, row: f32| self.vectors[row as usize * self.columns + column as usize];
+
+        let top = vector(corner.x, corner.y).lerp(vector(corner.x + 1.0, corner.y), weight.x);
+        let bottom = vector(corner.x, corner.y + 1.0).lerp(vector(corner.x + 1.0, corner.y + 1.0), weight.x);
+        top.lerp(bottom, weight.y)
+    }
+}
+
+fn parse_vector(text: &str) -> Result<Vec2, String> {
+    let (x, y) = text.split_once(',').ok_or_else(|| format!("{text}: expected x,y"))?;
+    let parse = |value: &str| value.parse::<f32>().map_err(|e| format!("{text}: {e}"));
+    Ok(Vec2::new(parse(x)?, parse(y)?))
+}
+
+/// A background current that drags the particles along with it: each particle is accelerated toward the flow's
+/// velocity, in proportion to how much slower (or faster) it's moving.
+pub struct Wind {
+    pub field: FlowField,
+    /// Wind speed, in pixels per second. The flow field's vectors are multiples of this.
+    pub speed: f32,
+    /// How quickly the particles are brought up to the flow's velocity, per second.
+    pub drag: f32,
+    pub enabled: bool,
+}
+
+impl Wind {
+    /// The flow's velocity at a position, at the given simulation time.
+    pub fn velocity_at(&self, position: Vec2, time: f32, half_bounds_size: Vec2) -> Vec2 {
+        let flow = match &self.field {
+            FlowField::Uniform => Vec2::X,
+            FlowField::Gusts => {
+                let phase = time / GUST_PERIOD - (position.x / half_bounds_size.x + 1.0) / 4.0;
+                Vec2::X * (1.0 - (phase * TAU).cos())
+            }
+            FlowField::Grid(grid) => grid.sample((position / half_bounds_size + 1.0) / 2.0),
+            FlowField::Custom(flow) => flow(position, time),
+        };
+        flow * self.speed
+    }
+}
+
+impl Simulation {
+    /// The wind's drag on a particle, if the wind is on.
+    pub fn wind_force(&self, particle_id: usize) -> Vec2 {
+        if !self.wind.enabled {
+            return Vec2::ZERO;
+        }
+
+        let flow = self
+            .wind
+            .velocity_at(self.positions[particle_id], self.sim_time, self.half_bounds_size);
+        (flow - self.velocities[particle_id]) * self.wind.drag
+    }
+
+    pub fn toggle_wind(&mut self) {
+        self.wind.enabled = !self.wind.enabled;
+    }
+
+    pub fn adj_wind(&mut self, increase: bool) {
+        self.wind.speed = if increase { self.wind.speed * 1.10 } else { self.wind.speed / 1.10 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::FlowGrid;
+
+    #[test]
+    fn flow_grid_interpolates_between_the_vectors() {
+        let grid = FlowGrid::parse("# top row first\n0,1 2,1\n\n0,0 2,0\n").unwrap();
+        assert_eq!(grid.sample(Vec2::new(0.0, 1.0)), Vec2::new(0.0, 1.0));
+        assert_eq!(grid.sample(Vec2::new(1.0, 0.0)), Vec2::new(2.0, 0.0));
+        assert_eq!(grid.sample(Vec2::new(0.5, 0.5)), Vec2::new(1.0, 0.5));
+        // Outside the tank, the flow at the nearest edge.
+        assert_eq!(grid.sample(Vec2::new(2.0, -1.0)), Vec2::new(2.0, 0.0));
+
+        assert!(FlowGrid::parse("0,0 1,0\n0,0\n").is_err());
+        assert!(FlowGrid::parse("0,0 1\n0,0 1,0\n").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+
+use bevy::math::Vec2;
+
+use crate::walls::apply_friction;
+
+/// A wind tunnel: the left and right walls are replaced by a periodic boundary (particles leaving on the right
+/// re-enter on the left), the particles entering on the left are given the inflow velocity, and there's a circular
+/// obstacle in the middle of the tunnel. The drag on the obstacle is measured every frame.
+pub struct WindTunnel {
+    /// Inflow speed, in pixels per second.
+    pub inflow_speed: f32,
+    /// Width of the region at the left edge where the inflow velocity is imposed, in pixels.
+    pub inflow_width: f32,
+    pub obstacle_center: Vec2,
+    pub obstacle_radius: f32,
+    /// Impulse applied to the obstacle by the particles during the current frame.
+    impulse: Vec2,
+    /// Drag force (the horizontal component of the force on the obstacle) for the most recent frames.
+    pub drag_history: VecDeque<f32>,
+}
+
+impl WindTunnel {
+    /// Number of frames of drag history to keep for the graph.
+    pub const HISTORY_LEN: usize = 600;
+
+    pub fn new(inflow_speed: f32, inflow_width: f32, obstacle_radius: f32) -> Self {
+        WindTunnel {
+            inflow_speed,
+            inflow_width,
+            obstacle_center: Vec2::ZERO,
+            obstacle_radius,
+            impulse: Vec2::ZERO,
+            drag_history: VecDeque::with_capacity(Self::HISTORY_LEN),
+        }
+    }
+
+    /// Pushes a position inside the obstacle out to its surface, and reflects the velocity off it.
+    pub fn resolve_collision(
+        &self,
+        position: Vec2,
+        velocity: Vec2,
+        collision_damping: f32,
+        friction: f32,
+    ) -> (Vec2, Vec2) {
+        let offset = position - self.obstacle_center;
+        let distance = offset.length();
+        if distance >= self.obstacle_radius {
+            return (position, velocity);
+        }
+
+        let normal = if distance > 0.0 { offset / distance } else { Vec2::Y };
+        let normal_speed = velocity.dot(normal);
+        let velocity_change =
+            if normal_speed < 0.0 { -normal * normal_speed * (1.0 + collision_damping) } else { Vec2::ZERO };
+        let velocity = apply_friction(velocity + velocity_change, normal, velocity_change, friction);
+
+        (self.obstacle_center + normal * self.obstacle_radius, velocity)
+    }
+
+    pub fn is_on_obstacle(&self, position: Vec2) -> bool {
+        position.distance(self.obstacle_center) <= self.obstacle_radius * 1.0001
+    }
+
+    /// Records the change in a particle's velocity due to a collision with the obstacle.
+    /// The obstacle receives the opposite impulse.
+    pub fn add_impulse(&mut self, velocity_change: Vec2) {
+        self.impulse -= velocity_change;
+    }
+
+    /// Converts this frame's impulse to a force, and adds it to the history.
+    pub fn end_step(&mut self, delta: f32) {
+        if self.drag_history.len() == Self::HISTORY_LEN {
+            self.drag_history.pop_front();
+        }
+        self.drag_history.push_back(self.impulse.x / delta.max(f32::EPSILON));
+        self.impulse = Vec2::ZERO;
+    }
+
+    /// Average drag over the recorded history.
+    pub fn mean_drag(&self) -> f32 {
+        self.drag_history.iter().sum::<f32>() / self.drag_history.len().max(1) as f32
+    }
+}
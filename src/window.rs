@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PresentMode, VideoModeSelection, WindowMode, WindowResolution};
+use clap::ValueEnum;
+
+use crate::args::Args;
+
+/// Whether to wait for the display's vertical sync (`--vsync`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Vsync {
+    On,
+    /// Draw as fast as possible, which shows the real frame rate of the simulation.
+    #[default]
+    Off,
+}
+
+/// Parses a width and height, as width,height, for `--win` and `--domain`.
+pub fn parse_size(arg: &str) -> Result<(u32, u32), String> {
+    let values = arg
+        .split(',')
+        .map(|value| value.trim().parse::<u32>().map_err(|e| format!("{value}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [width, height] if width > 0 && height > 0 => Ok((width, height)),
+        [_, _] => Err("The width and height must be more than 0".into()),
+        _ => Err(format!("Expected width,height, got {} values", values.len())),
+    }
+}
+
+impl Args {
+    /// The primary window, as configured by `--win`, `--fullscreen`, `--borderless`, `--monitor` and `--vsync`.
+    pub fn window(&self) -> Window {
+        let (width, height) = self.win;
+        let monitor = self.monitor.map_or(MonitorSelection::Current, MonitorSelection::Index);
+        let mode = if self.fullscreen {
+            WindowMode::Fullscreen(monitor, VideoModeSelection::Current)
+        } else if self.borderless {
+            WindowMode::BorderlessFullscreen(monitor)
+        } else {
+            WindowMode::Windowed
+        };
+        Window {
+            present_mode: match self.vsync {
+                Vsync::On => PresentMode::AutoVsync,
+                Vsync::Off => PresentMode::AutoNoVsync,
+            },
+            resolution: WindowResolution::new(width, height),
+            mode,
+            position: match self.monitor {
+                Some(index) => WindowPosition::Centered(MonitorSelection::Index(index)),
+                None => WindowPosition::Automatic,
+            },
+            ..default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_size;
+
+    #[test]
+    fn size_is_a_pair_of_positive_numbers() {
+        assert_eq!(parse_size("600, 800"), Ok((600, 800)));
+        assert!(parse_size("600").is_err());
+        assert!(parse_size("600x800").is_err());
+        assert!(parse_size("0,800").is_err());
+    }
+}